@@ -0,0 +1,41 @@
+/* C interface of the brainfuck_vm library, built with the `ffi` feature. */
+#ifndef BRAINFUCK_VM_H
+#define BRAINFUCK_VM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BF_OK 0
+#define BF_ERR_NULL 1
+#define BF_ERR_ENCODING 2
+#define BF_ERR_PARSE 3
+#define BF_ERR_RUNTIME 4
+#define BF_ERR_IO 5
+#define BF_ERR_PANIC 6
+
+typedef struct BfProgram bf_program;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} bf_output;
+
+/* Compiles the NUL-terminated source into a program freed with bf_free_program. */
+int bf_compile(const char *src, bf_program **out);
+
+/* Runs a program on `len` bytes of input; free the output with bf_free_output. */
+int bf_run(const bf_program *program, const uint8_t *input, size_t len, bf_output *out);
+
+void bf_free_program(bf_program *program);
+
+void bf_free_output(bf_output *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,200 @@
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::{Command, DEFAULT_MAX_DEPTH, ParsingError};
+
+/// Structured representation of a Brainfuck program.
+///
+/// Runs of identical commands are grouped, and loops own their bodies, which
+/// makes the tree easier to analyze and transform than the flat command list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ast {
+    /// A run of `+`.
+    Inc(u32),
+    /// A run of `-`.
+    Dec(u32),
+    /// A run of `>` (positive) or `<` (negative).
+    Move(i32),
+    /// `.`
+    Output,
+    /// `,`
+    Input,
+    /// `[` ... `]`
+    Loop(Vec<Ast>),
+    /// A sequence of nodes, used for the program itself.
+    Block(Vec<Ast>),
+}
+
+/// Parses Brainfuck source code into a [`Ast::Block`].
+///
+/// Errors are reported exactly like in [`compile`](crate::compile).
+pub fn parse_ast(text: &str) -> Result<Ast, ParsingError> {
+    // Each open loop keeps the body of its parent and the offset of its `[`.
+    let mut open_loops: Vec<(Vec<Ast>, usize)> = Vec::new();
+    let mut current = Vec::new();
+
+    // Every command is ASCII, and UTF-8 never uses ASCII bytes inside a
+    // longer character, so the bytes of comments can be skipped one by one.
+    for (offset, byte) in text.bytes().enumerate() {
+        match byte {
+            b'+' => push_run(&mut current, Ast::Inc(1)),
+            b'-' => push_run(&mut current, Ast::Dec(1)),
+            b'>' => push_run(&mut current, Ast::Move(1)),
+            b'<' => push_run(&mut current, Ast::Move(-1)),
+            b'.' => current.push(Ast::Output),
+            b',' => current.push(Ast::Input),
+            b'[' if open_loops.len() == DEFAULT_MAX_DEPTH => {
+                return Err(ParsingError::NestingTooDeep {
+                    offset,
+                    depth: DEFAULT_MAX_DEPTH + 1,
+                });
+            }
+            b'[' => open_loops.push((mem::take(&mut current), offset)),
+            b']' => {
+                let Some((outer, _)) = open_loops.pop() else {
+                    return Err(ParsingError::UnmatchedBracket {
+                        offset,
+                        bracket: ']',
+                    });
+                };
+                let body = mem::replace(&mut current, outer);
+                current.push(Ast::Loop(body));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(&(_, offset)) = open_loops.first() {
+        return Err(ParsingError::UnmatchedBracket {
+            offset,
+            bracket: '[',
+        });
+    }
+
+    Ok(Ast::Block(current))
+}
+
+/// Appends a node, merging it into a preceding run of the same kind and
+/// direction. Empty runs are dropped.
+pub(crate) fn push_run(nodes: &mut Vec<Ast>, node: Ast) {
+    match (nodes.last_mut(), node) {
+        (_, Ast::Inc(0) | Ast::Dec(0) | Ast::Move(0)) => {}
+        (Some(Ast::Inc(n)), Ast::Inc(m)) | (Some(Ast::Dec(n)), Ast::Dec(m)) => *n += m,
+        (Some(Ast::Move(n)), Ast::Move(m)) if (*n > 0) == (m > 0) => *n += m,
+        (_, node) => nodes.push(node),
+    }
+}
+
+/// Flattens a tree into the command list that [`compile`](crate::compile)
+/// produces for the same source.
+pub fn lower(ast: &Ast) -> Vec<Command> {
+    let mut commands = Vec::new();
+    lower_into(ast, &mut commands);
+    commands
+}
+
+fn lower_into(ast: &Ast, commands: &mut Vec<Command>) {
+    use self::Command as C;
+
+    match ast {
+        Ast::Inc(n) => commands.extend((0..*n).map(|_| C::Increment)),
+        Ast::Dec(n) => commands.extend((0..*n).map(|_| C::Decrement)),
+        Ast::Move(n) if *n >= 0 => commands.extend((0..*n).map(|_| C::IncrementDataPointer)),
+        Ast::Move(n) => commands.extend((0..-*n).map(|_| C::DecrementDataPointer)),
+        Ast::Output => commands.push(C::WriteByte),
+        Ast::Input => commands.push(C::ReadByte),
+        Ast::Loop(body) => {
+            let start = commands.len();
+            commands.push(C::JumpForwardIfZero(0));
+            for node in body {
+                lower_into(node, commands);
+            }
+            let end = commands.len();
+            commands.push(C::JumpBackwardIfNonZero(start));
+            commands[start] = C::JumpForwardIfZero(end);
+        }
+        Ast::Block(nodes) => {
+            for node in nodes {
+                lower_into(node, commands);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, eval};
+
+    /// Test the tree built for a small program with a nested loop.
+    #[test]
+    fn test_parse_nested() {
+        let ast = parse_ast("++>[-<+>>[.,]]<<<").unwrap();
+
+        assert_eq!(
+            ast,
+            Ast::Block(vec![
+                Ast::Inc(2),
+                Ast::Move(1),
+                Ast::Loop(vec![
+                    Ast::Dec(1),
+                    Ast::Move(-1),
+                    Ast::Inc(1),
+                    Ast::Move(2),
+                    Ast::Loop(vec![Ast::Output, Ast::Input]),
+                ]),
+                Ast::Move(-3),
+            ])
+        );
+    }
+
+    /// Test that opposite directions are not merged into one run.
+    #[test]
+    fn test_parse_direction_changes() {
+        let ast = parse_ast("+-><").unwrap();
+
+        assert_eq!(
+            ast,
+            Ast::Block(vec![Ast::Inc(1), Ast::Dec(1), Ast::Move(1), Ast::Move(-1)])
+        );
+    }
+
+    /// Test that unmatched brackets are reported at the same index as `compile`.
+    #[test]
+    fn test_parse_unmatched() {
+        for source in ["+]", "[[]", "[]]", "a[b", "ü→[😀"] {
+            assert_eq!(parse_ast(source).unwrap_err(), compile(source).unwrap_err());
+        }
+    }
+
+    /// Test that multi-byte characters are skipped like any comment.
+    #[test]
+    fn test_parse_unicode() {
+        let ast = parse_ast("ü+→+😀[-]").unwrap();
+        assert_eq!(
+            ast,
+            Ast::Block(vec![Ast::Inc(2), Ast::Loop(vec![Ast::Dec(1)])])
+        );
+        assert_eq!(
+            parse_ast("→]").unwrap_err(),
+            ParsingError::UnmatchedBracket {
+                offset: 3,
+                bracket: ']'
+            }
+        );
+    }
+
+    /// Test that hello world survives parse followed by lower.
+    #[test]
+    fn test_lower_hello_world() {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        let program = lower(&parse_ast(source_code).unwrap());
+        assert_eq!(program, compile(source_code).unwrap());
+
+        let mut writer = Vec::new();
+        eval(&program, &[][..], &mut writer).unwrap();
+        assert_eq!(writer, b"Hello World!\n");
+    }
+}
@@ -0,0 +1,97 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{Command, Error, ExecutionReport, Interpreter, Status};
+
+/// Number of commands [`eval_async`] executes before yielding to the runtime.
+pub const YIELD_INTERVAL: u64 = 10_000;
+
+/// Same as [`eval`](crate::eval), but awaits on `,` and `.` instead of
+/// blocking.
+///
+/// Every [`YIELD_INTERVAL`] commands control is handed back to the runtime,
+/// so a long-running program does not monopolize a worker thread. Nothing is
+/// spawned: dropping the future stops the program.
+pub async fn eval_async<R, W>(
+    commands: &[Command],
+    mut reader: R,
+    mut writer: W,
+) -> Result<ExecutionReport, Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut vm = Interpreter::default().vm(commands);
+    let mut next_yield = YIELD_INTERVAL;
+
+    loop {
+        let fuel = next_yield.saturating_sub(vm.report().steps);
+        match vm.run_for(fuel)? {
+            Status::Running | Status::DebugDump => {}
+            Status::OutOfFuel => {
+                tokio::task::yield_now().await;
+                next_yield = vm.report().steps + YIELD_INTERVAL;
+            }
+            Status::NeedsInput => match reader.read_u8().await {
+                Ok(byte) => vm.provide_input(byte),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => vm.provide_eof(),
+                Err(e) => return Err(e.into()),
+            },
+            Status::ProducedOutput(byte) => writer.write_u8(byte).await?,
+            Status::Halted => {
+                writer.flush().await?;
+                return Ok(*vm.report());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io;
+
+    use super::*;
+    use crate::compile;
+
+    /// Test the cat program over an in-memory duplex stream.
+    #[tokio::test]
+    async fn test_cat_over_duplex() {
+        let program = compile(",[.,]").unwrap();
+        let (mut client, server) = io::duplex(64);
+        let (server_reader, server_writer) = io::split(server);
+
+        let client_side = async {
+            client.write_all(b"over the wire").await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut echoed = Vec::new();
+            client.read_to_end(&mut echoed).await.unwrap();
+            echoed
+        };
+        let server_side = async {
+            let mut writer = server_writer;
+            let report = eval_async(&program, server_reader, &mut writer).await;
+            writer.shutdown().await.unwrap();
+            report
+        };
+        let (echoed, report) = tokio::join!(client_side, server_side);
+
+        assert_eq!(echoed, b"over the wire");
+        assert_eq!(report.unwrap().bytes_read, 13);
+    }
+
+    /// Test that an endless loop yields, so a timeout can cancel it even on
+    /// a single-threaded runtime.
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_cancel_endless_loop() {
+        let program = compile("+[]").unwrap();
+
+        let result = tokio::time::timeout(
+            Duration::from_millis(20),
+            eval_async(&program, io::empty(), io::sink()),
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{Command, Error, Interpreter};
+
+/// Runs one compiled program over many inputs in parallel.
+///
+/// Every input gets a fresh tape from the default [`Interpreter`], and
+/// results are returned in the order of `inputs`. With `max_steps`, a run
+/// that does not halt in time fails with
+/// [`RuntimeError::StepLimitExceeded`](crate::RuntimeError::StepLimitExceeded)
+/// without holding up the others. `threads == 0` uses one thread per
+/// available core.
+pub fn run_batch(
+    commands: &[Command],
+    inputs: &[Vec<u8>],
+    threads: usize,
+    max_steps: Option<u64>,
+) -> Vec<Result<Vec<u8>, Error>> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        n => n,
+    };
+    let next = AtomicUsize::new(0);
+    let mut interpreter = Interpreter::builder();
+    if let Some(max_steps) = max_steps {
+        interpreter = interpreter.max_steps(max_steps);
+    }
+    let interpreter = interpreter.build().expect("the default tape is valid");
+
+    let run = |input: &[u8]| {
+        let mut output = Vec::new();
+        interpreter.run(commands, input, &mut output)?;
+        Ok(output)
+    };
+
+    let mut results: Vec<(usize, Result<Vec<u8>, Error>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(inputs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(input) = inputs.get(index) else {
+                            return done;
+                        };
+                        done.push((index, run(input)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    results.sort_unstable_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RuntimeError, compile};
+
+    /// Test the cat program over 100 inputs on 4 threads.
+    #[test]
+    fn test_cat_batch() {
+        let program = compile(",[.,]").unwrap();
+        let inputs: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("input number {i}").into_bytes())
+            .collect();
+
+        let results = run_batch(&program, &inputs, 4, None);
+
+        assert_eq!(results.len(), inputs.len());
+        for (result, input) in results.into_iter().zip(&inputs) {
+            assert_eq!(&result.unwrap(), input);
+        }
+    }
+
+    /// Test that a run hitting the step cap does not affect the others.
+    #[test]
+    fn test_step_cap() {
+        // Loops forever unless the first input byte is zero.
+        let program = compile(",[]").unwrap();
+        let inputs = vec![vec![0], vec![1], vec![0]];
+
+        let results = run_batch(&program, &inputs, 2, Some(10_000));
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(Error::Runtime(RuntimeError::StepLimitExceeded {
+                steps: 10_000,
+                ..
+            }))
+        ));
+        assert!(results[2].is_ok());
+    }
+}
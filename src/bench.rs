@@ -0,0 +1,132 @@
+//! Timing of repeated runs of a program.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Wall times of the timed runs of `bench`, and how fast they went.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub runs: usize,
+    /// Runs before the timed ones, which are left out of the rest.
+    pub warmup: usize,
+    /// Commands executed by a run, on average.
+    pub steps: u64,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    /// Sample standard deviation, zero for a single run.
+    pub stddev: Duration,
+    /// Commands executed per second over all timed runs.
+    pub steps_per_second: f64,
+}
+
+impl Summary {
+    /// Summarizes the timed runs, each with its wall time and the number of
+    /// commands it executed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no runs.
+    pub fn new(runs: &[(Duration, u64)], warmup: usize) -> Summary {
+        assert!(!runs.is_empty(), "there is nothing to summarize");
+        let mut times: Vec<_> = runs.iter().map(|&(time, _)| time).collect();
+        times.sort();
+        let total: Duration = times.iter().sum();
+        let total_steps: u64 = runs.iter().map(|&(_, steps)| steps).sum();
+
+        let count = times.len();
+        let middle = count / 2;
+        let median = match count % 2 {
+            1 => times[middle],
+            _ => (times[middle - 1] + times[middle]) / 2,
+        };
+        let mean = total / count as u32;
+        let variance = times
+            .iter()
+            .map(|time| (time.as_secs_f64() - mean.as_secs_f64()).powi(2))
+            .sum::<f64>()
+            / (count - 1).max(1) as f64;
+        let steps_per_second = match total.as_secs_f64() {
+            0.0 => 0.0,
+            seconds => total_steps as f64 / seconds,
+        };
+        Summary {
+            runs: count,
+            warmup,
+            steps: total_steps / count as u64,
+            min: times[0],
+            median,
+            mean,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            steps_per_second,
+        }
+    }
+
+    /// The summary as one JSON object, with the times in nanoseconds.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"runs\":{},\"warmup\":{},\"steps\":{},\"min_ns\":{},\"median_ns\":{},\
+             \"mean_ns\":{},\"stddev_ns\":{},\"steps_per_second\":{:.0}}}",
+            self.runs,
+            self.warmup,
+            self.steps,
+            self.min.as_nanos(),
+            self.median.as_nanos(),
+            self.mean.as_nanos(),
+            self.stddev.as_nanos(),
+            self.steps_per_second
+        )
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "runs    {} (after {} warmup)", self.runs, self.warmup)?;
+        writeln!(f, "steps   {} per run", self.steps)?;
+        writeln!(f, "min     {:.2?}", self.min)?;
+        writeln!(f, "median  {:.2?}", self.median)?;
+        writeln!(f, "mean    {:.2?}", self.mean)?;
+        writeln!(f, "stddev  {:.2?}", self.stddev)?;
+        write!(f, "speed   {:.0} steps/s", self.steps_per_second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test the statistics of a few known times.
+    #[test]
+    fn test_summary() {
+        let ms = Duration::from_millis;
+        let runs = [(ms(4), 100), (ms(1), 100), (ms(3), 100), (ms(2), 100)];
+        let summary = Summary::new(&runs, 2);
+        assert_eq!((summary.runs, summary.warmup, summary.steps), (4, 2, 100));
+        assert_eq!(
+            (summary.min, summary.median, summary.mean),
+            (ms(1), ms(2) + ms(1) / 2, ms(2) + ms(1) / 2)
+        );
+        // The sample variance of 1, 2, 3, and 4 is 5/3.
+        let stddev = summary.stddev.as_secs_f64() * 1000.0;
+        assert!((stddev - (5.0_f64 / 3.0).sqrt()).abs() < 1e-6, "{stddev}");
+        assert!((summary.steps_per_second - 40_000.0).abs() < 1e-6);
+
+        let single = Summary::new(&[(ms(5), 7)], 0);
+        assert_eq!((single.median, single.stddev), (ms(5), Duration::ZERO));
+        assert_eq!(
+            Summary::new(&[(Duration::ZERO, 7)], 0).steps_per_second,
+            0.0
+        );
+    }
+
+    /// Test that the JSON holds every field, with the times in nanoseconds.
+    #[test]
+    fn test_json() {
+        let summary = Summary::new(&[(Duration::from_micros(3), 30)], 1);
+        assert_eq!(
+            summary.to_json(),
+            "{\"runs\":1,\"warmup\":1,\"steps\":30,\"min_ns\":3000,\"median_ns\":3000,\
+             \"mean_ns\":3000,\"stddev_ns\":0,\"steps_per_second\":10000000}"
+        );
+    }
+}
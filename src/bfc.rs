@@ -0,0 +1,328 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bytes::{ByteSink, ByteSource, IoError};
+use crate::{Command, JumpError, OptLevel, Program};
+
+/// First bytes of every `.bfc` file.
+pub const BFC_MAGIC: [u8; 4] = *b"\x7fBFC";
+
+/// Version of the `.bfc` format that [`Program::save`] writes and
+/// [`Program::load`] reads.
+pub const BFC_VERSION: u16 = 1;
+
+/// Most commands reserved up front, however many a file claims to hold.
+const MAX_RESERVED: usize = 1 << 16;
+
+/// Settings a `.bfc` file was compiled against, kept so that it is not run
+/// with others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BfcHeader {
+    /// Width of the cells in bits: 8, 16, or 32.
+    pub cell_bits: u8,
+    /// Whether the cells are signed.
+    pub signed_cells: bool,
+    /// Passes the program went through.
+    pub opt_level: OptLevel,
+}
+
+impl Default for BfcHeader {
+    /// Unsigned bytes and every pass, like a run without flags.
+    fn default() -> Self {
+        BfcHeader {
+            cell_bits: 8,
+            signed_cells: false,
+            opt_level: OptLevel::default(),
+        }
+    }
+}
+
+/// Enum for `.bfc` files that cannot be loaded.
+#[derive(Debug)]
+pub enum BfcError {
+    /// Reading the file failed.
+    Io(IoError),
+    /// The file does not start with [`BFC_MAGIC`].
+    BadMagic,
+    /// The file was written in another version of the format.
+    UnsupportedVersion(u16),
+    /// The header names a cell type or optimization level that does not
+    /// exist.
+    InvalidHeader,
+    /// The file ends before the command it announces.
+    Truncated,
+    /// The command at `address` has an opcode that does not exist.
+    UnknownOpcode { address: usize, opcode: u8 },
+    /// The commands do not form a valid program.
+    Jump(JumpError),
+}
+
+impl fmt::Display for BfcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BfcError::Io(e) => write!(f, "cannot read the program: {e}"),
+            BfcError::BadMagic => write!(f, "not a .bfc file"),
+            BfcError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported .bfc version {version}, expected {BFC_VERSION}"
+            ),
+            BfcError::InvalidHeader => write!(f, "invalid .bfc header"),
+            BfcError::Truncated => write!(f, "the .bfc file is cut short"),
+            BfcError::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {opcode} for command {address}")
+            }
+            BfcError::Jump(e) => write!(f, "invalid program: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for BfcError {}
+
+impl From<IoError> for BfcError {
+    fn from(e: IoError) -> Self {
+        BfcError::Io(e)
+    }
+}
+
+impl From<JumpError> for BfcError {
+    fn from(e: JumpError) -> Self {
+        BfcError::Jump(e)
+    }
+}
+
+impl Program {
+    /// Writes the program in the `.bfc` format: [`BFC_MAGIC`], the format
+    /// version, `header`, the number of commands, and every command as an
+    /// opcode byte followed by its operands, all little endian.
+    ///
+    /// Bytes are written one at a time, so `sink` should be buffered.
+    pub fn save(&self, header: &BfcHeader, sink: &mut impl ByteSink) -> Result<(), IoError> {
+        use self::Command as C;
+
+        let mut bytes = Vec::with_capacity(16 + self.len() * 2);
+        bytes.extend_from_slice(&BFC_MAGIC);
+        bytes.extend_from_slice(&BFC_VERSION.to_le_bytes());
+        bytes.push(header.cell_bits);
+        bytes.push(header.signed_cells.into());
+        bytes.push(match header.opt_level {
+            OptLevel::O0 => 0,
+            OptLevel::O1 => 1,
+            OptLevel::O2 => 2,
+        });
+        bytes.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        let wide = |operand: usize| (operand as u64).to_le_bytes();
+        for command in self.iter() {
+            let (opcode, operands): (u8, &[&[u8]]) = match *command {
+                C::IncrementDataPointer => (0, &[]),
+                C::DecrementDataPointer => (1, &[]),
+                C::Increment => (2, &[]),
+                C::Decrement => (3, &[]),
+                C::WriteByte => (4, &[]),
+                C::ReadByte => (5, &[]),
+                C::JumpForwardIfZero(target) => (6, &[&wide(target)]),
+                C::JumpBackwardIfNonZero(target) => (7, &[&wide(target)]),
+                C::DebugDump => (8, &[]),
+                C::BeginProc(target) => (9, &[&wide(target)]),
+                C::EndProc(target) => (10, &[&wide(target)]),
+                C::Call => (11, &[]),
+                C::Random => (12, &[]),
+                C::Add(delta) => (13, &[&delta.to_le_bytes()]),
+                C::MovePointer(offset) => (14, &[&offset.to_le_bytes()]),
+                C::Set(value) => (15, &[&[value]]),
+                C::MulAdd { offset, factor } => {
+                    (16, &[&offset.to_le_bytes(), &factor.to_le_bytes()])
+                }
+                C::ScanRight(stride) => (17, &[&wide(stride)]),
+                C::ScanLeft(stride) => (18, &[&wide(stride)]),
+                C::AddAt { offset, value } => (19, &[&offset.to_le_bytes(), &value.to_le_bytes()]),
+                C::SetAt { offset, value } => (20, &[&offset.to_le_bytes(), &[value]]),
+                C::OutputAt(offset) => (21, &[&offset.to_le_bytes()]),
+                C::InputAt(offset) => (22, &[&offset.to_le_bytes()]),
+                C::ClearRange { len } => (23, &[&len.to_le_bytes()]),
+            };
+            bytes.push(opcode);
+            for operand in operands {
+                bytes.extend_from_slice(operand);
+            }
+        }
+        bytes
+            .into_iter()
+            .try_for_each(|byte| sink.write_byte(byte))?;
+        sink.flush()
+    }
+
+    /// Reads a program written by [`Program::save`], together with the
+    /// header it was saved with.
+    ///
+    /// The program is [validated](crate::validate) like every other one, so
+    /// a file that was tampered with fails here instead of when it runs.
+    pub fn load(source: &mut impl ByteSource) -> Result<(BfcHeader, Program), BfcError> {
+        use self::Command as C;
+
+        fn take<const N: usize>(source: &mut impl ByteSource) -> Result<[u8; N], BfcError> {
+            let mut bytes = [0; N];
+            for byte in &mut bytes {
+                *byte = source.read_byte()?.ok_or(BfcError::Truncated)?;
+            }
+            Ok(bytes)
+        }
+        fn read_address(source: &mut impl ByteSource) -> Result<usize, BfcError> {
+            // An address that does not fit cannot point into the program.
+            let address = u64::from_le_bytes(take(source)?);
+            Ok(usize::try_from(address).unwrap_or(usize::MAX))
+        }
+        let i32 = |source: &mut _| take(source).map(i32::from_le_bytes);
+        let i16 = |source: &mut _| take(source).map(i16::from_le_bytes);
+        let u8 = |source: &mut _| take::<1>(source).map(|[byte]| byte);
+
+        if take(source)? != BFC_MAGIC {
+            return Err(BfcError::BadMagic);
+        }
+        let version = u16::from_le_bytes(take(source)?);
+        if version != BFC_VERSION {
+            return Err(BfcError::UnsupportedVersion(version));
+        }
+        let [cell_bits, signed_cells, opt_level] = take(source)?;
+        let header = BfcHeader {
+            cell_bits: match (cell_bits, signed_cells) {
+                (8 | 16 | 32, 0) | (8, 1) => cell_bits,
+                _ => return Err(BfcError::InvalidHeader),
+            },
+            signed_cells: signed_cells == 1,
+            opt_level: match opt_level {
+                0 => OptLevel::O0,
+                1 => OptLevel::O1,
+                2 => OptLevel::O2,
+                _ => return Err(BfcError::InvalidHeader),
+            },
+        };
+
+        let len = u64::from_le_bytes(take(source)?);
+        let len = usize::try_from(len).map_err(|_| BfcError::Truncated)?;
+        let mut commands = Vec::with_capacity(len.min(MAX_RESERVED));
+        for address in 0..len {
+            let [opcode] = take(source)?;
+            commands.push(match opcode {
+                0 => C::IncrementDataPointer,
+                1 => C::DecrementDataPointer,
+                2 => C::Increment,
+                3 => C::Decrement,
+                4 => C::WriteByte,
+                5 => C::ReadByte,
+                6 => C::JumpForwardIfZero(read_address(source)?),
+                7 => C::JumpBackwardIfNonZero(read_address(source)?),
+                8 => C::DebugDump,
+                9 => C::BeginProc(read_address(source)?),
+                10 => C::EndProc(read_address(source)?),
+                11 => C::Call,
+                12 => C::Random,
+                13 => C::Add(i16(source)?),
+                14 => C::MovePointer(i32(source)?),
+                15 => C::Set(u8(source)?),
+                16 => C::MulAdd {
+                    offset: i32(source)?,
+                    factor: i16(source)?,
+                },
+                17 => C::ScanRight(read_address(source)?),
+                18 => C::ScanLeft(read_address(source)?),
+                19 => C::AddAt {
+                    offset: i32(source)?,
+                    value: i16(source)?,
+                },
+                20 => C::SetAt {
+                    offset: i32(source)?,
+                    value: u8(source)?,
+                },
+                21 => C::OutputAt(i32(source)?),
+                22 => C::InputAt(i32(source)?),
+                23 => C::ClearRange { len: i32(source)? },
+                _ => return Err(BfcError::UnknownOpcode { address, opcode }),
+            });
+        }
+        Ok((header, Program::new(commands)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OverflowPolicy, compile, compile_pbrain, optimize};
+
+    fn saved(program: &Program, header: &BfcHeader) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        program.save(header, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Test that programs with every kind of command load as they were saved.
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            compile("+[->+<]>.,").unwrap(),
+            optimize(
+                &compile("++[->>+++<<]>>[>]<<[<]>[-]>>+<<<,.[-]<[-]<[-]").unwrap(),
+                OverflowPolicy::Wrap,
+            ),
+            compile_pbrain("+(-:)>+:").unwrap(),
+            Vec::new(),
+        ];
+        let header = BfcHeader {
+            cell_bits: 16,
+            signed_cells: false,
+            opt_level: OptLevel::O1,
+        };
+        for commands in sources {
+            let program = Program::new(commands).unwrap();
+            let bytes = saved(&program, &header);
+            assert_eq!(bytes[..4], BFC_MAGIC);
+            let (loaded_header, loaded) = Program::load(&mut &bytes[..]).unwrap();
+            assert_eq!((loaded_header, loaded), (header, program));
+        }
+    }
+
+    /// Test that damaged files are refused instead of loaded.
+    #[test]
+    fn test_corrupted() {
+        let program = Program::new(compile("+[-]>[<+>-]").unwrap()).unwrap();
+        let bytes = saved(&program, &BfcHeader::default());
+        let load = |bytes: &[u8]| Program::load(&mut &bytes[..]).unwrap_err();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'B';
+        assert!(matches!(load(&bad_magic), BfcError::BadMagic));
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 9;
+        assert!(matches!(
+            load(&bad_version),
+            BfcError::UnsupportedVersion(9)
+        ));
+        let mut bad_cells = bytes.clone();
+        bad_cells[6] = 12;
+        assert!(matches!(load(&bad_cells), BfcError::InvalidHeader));
+        for len in 0..bytes.len() {
+            let error = load(&bytes[..len]);
+            assert!(
+                matches!(error, BfcError::Truncated | BfcError::BadMagic),
+                "{len}: {error:?}"
+            );
+        }
+
+        // The 17-byte header is followed by `+` and then `[`, whose target
+        // starts at byte 19.
+        let mut bad_target = bytes.clone();
+        bad_target[19] = 200;
+        assert!(matches!(
+            load(&bad_target),
+            BfcError::Jump(JumpError::OutOfRange { address: 1, .. })
+        ));
+        let mut bad_opcode = bytes;
+        bad_opcode[17] = 99;
+        assert!(matches!(
+            load(&bad_opcode),
+            BfcError::UnknownOpcode {
+                address: 0,
+                opcode: 99
+            }
+        ));
+    }
+}
@@ -0,0 +1,174 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Command;
+
+/// What a straight-line block does to one cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CellChange {
+    /// Offset of the cell from where the pointer was at the start.
+    pub(crate) offset: i32,
+    /// Value the cell is set to first, if any.
+    pub(crate) set: Option<u8>,
+    /// Amount added to the cell after that, wrapping around.
+    pub(crate) add: i64,
+}
+
+/// Run of commands that only change cells and move the pointer, so that
+/// it can run as a single step, see `Vm::run_block`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Block {
+    /// Number of commands in the block.
+    pub(crate) len: usize,
+    /// Where the changes of the block start and end in [`Blocks::changes`].
+    pub(crate) changes: (usize, usize),
+    /// Offset the pointer ends up at.
+    pub(crate) shift: i32,
+    /// Lowest offset the pointer or a change reaches.
+    pub(crate) low: i32,
+    /// Highest offset the pointer or a change reaches.
+    pub(crate) high: i32,
+}
+
+/// Every block of a program, by the address of its first command.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Blocks {
+    /// The block starting at every address, or nothing if no block of two
+    /// or more commands is in the program.
+    pub(crate) at: Vec<Option<Block>>,
+    pub(crate) changes: Vec<CellChange>,
+}
+
+/// Finds every maximal run of `+`, `-`, `>`, `<`, and the commands the
+/// optimizer merges them into, outside of which every command, a bracket
+/// or I/O above all, runs on its own.
+pub(crate) fn find_blocks(commands: &[Command]) -> Blocks {
+    use self::Command as C;
+
+    let mut blocks = Blocks::default();
+    let mut start = 0;
+    while start < commands.len() {
+        let mut offset = 0_i64;
+        let (mut low, mut high) = (0, 0);
+        // Index of the change to every cell in `blocks.changes`.
+        let mut changed = BTreeMap::new();
+        let first_change = blocks.changes.len();
+        let mut end = start;
+        let mut change = |offset: i64, set: Option<u8>, add: i64| {
+            let index = *changed.entry(offset).or_insert_with(|| {
+                blocks.changes.push(CellChange {
+                    offset: offset as i32,
+                    set: None,
+                    add: 0,
+                });
+                blocks.changes.len() - 1
+            });
+            let change = &mut blocks.changes[index];
+            if set.is_some() {
+                *change = CellChange {
+                    set,
+                    add: 0,
+                    ..*change
+                };
+            }
+            change.add = change.add.wrapping_add(add);
+        };
+        while let Some(command) = commands.get(end) {
+            let touched = match *command {
+                C::AddAt { offset: delta, .. } | C::SetAt { offset: delta, .. } => {
+                    offset + i64::from(delta)
+                }
+                _ => offset,
+            };
+            match *command {
+                C::Increment => change(offset, None, 1),
+                C::Decrement => change(offset, None, -1),
+                C::Add(delta) => change(offset, None, delta.into()),
+                C::Set(value) => change(offset, Some(value), 0),
+                C::AddAt { value, .. } => change(touched, None, value.into()),
+                C::SetAt { value, .. } => change(touched, Some(value), 0),
+                C::IncrementDataPointer => offset += 1,
+                C::DecrementDataPointer => offset -= 1,
+                C::MovePointer(delta) => offset += i64::from(delta),
+                _ => break,
+            }
+            low = low.min(touched).min(offset);
+            high = high.max(touched).max(offset);
+            end += 1;
+        }
+        let fits = i32::try_from(low).is_ok() && i32::try_from(high).is_ok();
+        if end - start >= 2 && fits {
+            if blocks.at.is_empty() {
+                blocks.at = vec![None; commands.len()];
+            }
+            blocks.at[start] = Some(Block {
+                len: end - start,
+                changes: (first_change, blocks.changes.len()),
+                shift: offset as i32,
+                low: low as i32,
+                high: high as i32,
+            });
+        } else {
+            blocks.changes.truncate(first_change);
+        }
+        start = end.max(start + 1);
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OverflowPolicy, compile, optimize};
+
+    /// Test the changes of a block that moves back and forth.
+    #[test]
+    fn test_find_blocks() {
+        let program = compile("+>++<-->>>+++++[-]<.").unwrap();
+        let blocks = find_blocks(&program);
+        let block = blocks.at[0].unwrap();
+        assert_eq!(
+            block,
+            Block {
+                len: 15,
+                changes: (0, 3),
+                shift: 3,
+                low: 0,
+                high: 3,
+            }
+        );
+        let added: Vec<_> = blocks.changes.iter().map(|c| (c.offset, c.add)).collect();
+        assert_eq!(added, [(0, -1), (1, 2), (3, 5)]);
+        // The loop and `.` stop blocks, and a lone `<` is not one.
+        assert!(blocks.at[1..].iter().all(Option::is_none));
+    }
+
+    /// Test that blocks take the offsets and sets of the optimizer in.
+    #[test]
+    fn test_optimized_blocks() {
+        let program = optimize(
+            &compile(">>+++<<[-]>[-]+++>-.").unwrap(),
+            OverflowPolicy::Wrap,
+        );
+        let blocks = find_blocks(&program);
+        let (address, block) = blocks
+            .at
+            .iter()
+            .enumerate()
+            .find_map(|(address, block)| Some((address, (*block)?)))
+            .unwrap();
+        assert_eq!(address, 0);
+        let changes = &blocks.changes[block.changes.0..block.changes.1];
+        let effect: Vec<_> = changes
+            .iter()
+            .map(|change| (change.offset, change.set, change.add))
+            .collect();
+        assert_eq!(effect, [(2, None, 2), (0, Some(0), 0), (1, Some(3), 0)]);
+        assert_eq!(
+            (block.len, block.shift, block.low, block.high),
+            (4, 0, 0, 2)
+        );
+        assert!(find_blocks(&compile("+.+").unwrap()).at.is_empty());
+    }
+}
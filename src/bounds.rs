@@ -0,0 +1,170 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Command;
+
+/// Lowest and highest offset from the pointer at `[` that a loop touches.
+pub(crate) type LoopBounds = (i32, i32);
+
+/// Cells every balanced loop touches, at both of its brackets.
+///
+/// A loop is balanced when each pass through its body, inner loops
+/// included, ends on the cell it started on and only moves the pointer
+/// and changes cells, so every pass stays within the same cells around
+/// the `[`. Loops with I/O, scans, or pbrain procedures, and loops whose
+/// brackets do not point at each other, have no bounds. Returns an empty
+/// list if no loop is balanced.
+pub(crate) fn loop_bounds(commands: &[Command]) -> Vec<Option<LoopBounds>> {
+    use self::Command as C;
+
+    /// Loop being walked through: where it starts, where the pointer is
+    /// relative to that, and the cells touched so far.
+    struct Frame {
+        start: usize,
+        offset: i64,
+        low: i64,
+        high: i64,
+        balanced: bool,
+    }
+
+    impl Frame {
+        fn touch(&mut self, offset: i64) {
+            self.low = self.low.min(offset);
+            self.high = self.high.max(offset);
+        }
+    }
+
+    let mut bounds = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    for (address, command) in commands.iter().enumerate() {
+        let Some(frame) = stack.last_mut() else {
+            if let C::JumpForwardIfZero(_) = command {
+                stack.push(Frame {
+                    start: address,
+                    offset: 0,
+                    low: 0,
+                    high: 0,
+                    balanced: true,
+                });
+            }
+            continue;
+        };
+        match command {
+            C::IncrementDataPointer => frame.offset += 1,
+            C::DecrementDataPointer => frame.offset -= 1,
+            C::MovePointer(offset) => frame.offset += i64::from(*offset),
+            C::MulAdd { offset, .. } | C::AddAt { offset, .. } | C::SetAt { offset, .. } => {
+                let offset = frame.offset + i64::from(*offset);
+                frame.touch(offset);
+            }
+            C::Increment | C::Decrement | C::Add(_) | C::Set(_) => {}
+            C::ClearRange { len } => {
+                let offset = frame.offset;
+                frame.touch(offset);
+                frame.offset += i64::from(*len) - i64::from(len.signum());
+            }
+            C::JumpForwardIfZero(_) => {
+                stack.push(Frame {
+                    start: address,
+                    offset: 0,
+                    low: 0,
+                    high: 0,
+                    balanced: true,
+                });
+                continue;
+            }
+            C::JumpBackwardIfNonZero(target) => {
+                let frame = stack.pop().expect("a frame is on the stack");
+                let matched = *target == frame.start
+                    && commands[frame.start] == C::JumpForwardIfZero(address);
+                let range = (i32::try_from(frame.low), i32::try_from(frame.high));
+                let loop_bounds = match range {
+                    (Ok(low), Ok(high)) if matched && frame.balanced && frame.offset == 0 => {
+                        (low, high)
+                    }
+                    _ => {
+                        if let Some(parent) = stack.last_mut() {
+                            parent.balanced = false;
+                        }
+                        continue;
+                    }
+                };
+                if bounds.is_empty() {
+                    bounds = vec![None; commands.len()];
+                }
+                bounds[frame.start] = Some(loop_bounds);
+                bounds[address] = Some(loop_bounds);
+                if let Some(parent) = stack.last_mut() {
+                    parent.touch(parent.offset + frame.low);
+                    parent.touch(parent.offset + frame.high);
+                }
+                continue;
+            }
+            C::WriteByte
+            | C::ReadByte
+            | C::OutputAt(_)
+            | C::InputAt(_)
+            | C::ScanRight(_)
+            | C::ScanLeft(_)
+            | C::DebugDump
+            | C::BeginProc(_)
+            | C::EndProc(_)
+            | C::Call
+            | C::Random => frame.balanced = false,
+        }
+        let offset = frame.offset;
+        frame.touch(offset);
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, optimize};
+
+    /// Test the bounds of nested, unbalanced, and optimized loops.
+    #[test]
+    fn test_loop_bounds() {
+        let commands = compile("+[>>+<[-<+>]<-]").unwrap();
+        let bounds = loop_bounds(&commands);
+        assert_eq!(bounds.len(), commands.len());
+        assert_eq!(bounds[1], Some((0, 2)));
+        assert_eq!(bounds[14], Some((0, 2)));
+        assert_eq!(bounds[6], Some((-1, 0)));
+        assert_eq!(bounds[11], Some((-1, 0)));
+        assert_eq!(bounds.iter().flatten().count(), 4);
+
+        // The inner loop moves, so the outer one cannot be bounded either.
+        let commands = compile("+[>[>]+<-]").unwrap();
+        assert!(loop_bounds(&commands).is_empty());
+        let commands = compile("+[[>+<-].-]").unwrap();
+        let bounds = loop_bounds(&commands);
+        assert_eq!(bounds[1], None);
+        assert_eq!(bounds[2], Some((0, 1)));
+
+        let commands = optimize(
+            &compile("+[->>+<<[-]<<+>>]").unwrap(),
+            crate::OverflowPolicy::Wrap,
+        );
+        let bounds = loop_bounds(&commands);
+        assert!(bounds.contains(&Some((-2, 2))), "{commands:?}");
+        let commands = optimize(
+            &compile("+[[-]>[-]>[-]<<-]").unwrap(),
+            crate::OverflowPolicy::Wrap,
+        );
+        assert!(commands.contains(&Command::ClearRange { len: 3 }));
+        assert!(
+            loop_bounds(&commands).contains(&Some((0, 2))),
+            "{commands:?}"
+        );
+
+        // Brackets that do not point at each other are left alone.
+        let commands = [
+            Command::JumpForwardIfZero(2),
+            Command::Increment,
+            Command::JumpBackwardIfNonZero(1),
+        ];
+        assert!(loop_bounds(&commands).is_empty());
+    }
+}
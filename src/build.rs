@@ -0,0 +1,108 @@
+//! Executables built from the C translation with the system C compiler.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory the generated C is kept in, so that a program that did not
+/// change is not compiled again.
+pub const CACHE_DIR: &str = "target/bf";
+
+/// Compilers tried in order when `CC` is not set.
+const COMPILERS: [&str; 3] = ["cc", "clang", "gcc"];
+
+/// Compiles `code`, a C translation, into the executable `output`.
+///
+/// The C is written to [`CACHE_DIR`], named after the executable, with the
+/// compiler command in its first line. If the file already holds the same
+/// text and the executable is newer, the executable is left as it is.
+pub fn build(code: &str, output: &Path, optimize: bool) -> Result<(), String> {
+    let compiler = find_compiler()?;
+    let mut flags = vec!["-std=c99"];
+    if optimize {
+        flags.push("-O2");
+    }
+    let name = output
+        .file_name()
+        .ok_or_else(|| format!("'{}' is not a file name", output.display()))?;
+    let source = Path::new(CACHE_DIR).join(name).with_extension("c");
+    let text = format!("/* {} {} */\n{code}", compiler, flags.join(" "));
+
+    let cached = fs::read_to_string(&source).is_ok_and(|cached| cached == text);
+    if cached && is_newer(output, &source) {
+        return Ok(());
+    }
+    fs::create_dir_all(CACHE_DIR).map_err(|e| format!("cannot create '{CACHE_DIR}': {e}"))?;
+    fs::write(&source, &text).map_err(|e| format!("cannot write '{}': {e}", source.display()))?;
+
+    let result = Command::new(&compiler)
+        .args(&flags)
+        .arg("-o")
+        .arg(output)
+        .arg(&source)
+        .output()
+        .map_err(|e| format!("cannot run {compiler}: {e}"))?;
+    if result.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let mut message = format!("{compiler} failed on '{}':\n{stderr}", source.display());
+    for line in offending_lines(&stderr, &source) {
+        let generated = text.lines().nth(line - 1).unwrap_or_default();
+        message.push_str(&format!("generated line {line}: {generated}\n"));
+    }
+    Err(message.trim_end().to_string())
+}
+
+/// `CC`, or the first of [`COMPILERS`] that runs.
+fn find_compiler() -> Result<String, String> {
+    if let Some(compiler) = std::env::var("CC").ok().filter(|cc| !cc.is_empty()) {
+        return Ok(compiler);
+    }
+    COMPILERS
+        .iter()
+        .find(|compiler| {
+            Command::new(compiler)
+                .arg("--version")
+                .output()
+                .is_ok_and(|output| output.status.success())
+        })
+        .map(|compiler| compiler.to_string())
+        .ok_or_else(|| {
+            format!(
+                "no C compiler found, tried {}; set CC",
+                COMPILERS.join(", ")
+            )
+        })
+}
+
+/// Whether `path` exists and was modified after `than`.
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(path), modified(than)) {
+        (Ok(path), Ok(than)) => path >= than,
+        _ => false,
+    }
+}
+
+/// Lines of `source` that the compiler complained about in `stderr`, in
+/// the `file:line:` form GCC and Clang use, without repeats.
+fn offending_lines(stderr: &str, source: &Path) -> Vec<usize> {
+    let prefix = format!("{}:", PathBuf::from(source).display());
+    let mut lines = Vec::new();
+    for message in stderr.lines() {
+        let Some(rest) = message.strip_prefix(&prefix) else {
+            continue;
+        };
+        let digits = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if let Ok(line) = rest[..digits].parse::<usize>()
+            && line > 0
+            && !lines.contains(&line)
+        {
+            lines.push(line);
+        }
+    }
+    lines
+}
@@ -0,0 +1,142 @@
+//! Byte-level I/O traits that work with and without `std`.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+
+/// Error produced by a [`ByteSource`] or [`ByteSink`].
+/// With the `std` feature this is [`std::io::Error`].
+#[cfg(feature = "std")]
+pub use std::io::Error as IoError;
+
+/// Error produced by a [`ByteSource`] or [`ByteSink`].
+/// With the `std` feature this is [`std::io::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(not(feature = "std"))]
+pub struct IoError {
+    message: &'static str,
+}
+
+#[cfg(not(feature = "std"))]
+impl IoError {
+    pub const fn new(message: &'static str) -> Self {
+        IoError { message }
+    }
+
+    pub fn message(&self) -> &'static str {
+        self.message
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl core::error::Error for IoError {}
+
+/// Error for input that does not have the expected format.
+pub(crate) fn invalid_data(message: &'static str) -> IoError {
+    #[cfg(feature = "std")]
+    return IoError::new(std::io::ErrorKind::InvalidData, message);
+    #[cfg(not(feature = "std"))]
+    return IoError::new(message);
+}
+
+/// Where `,` gets its bytes from.
+///
+/// With the `std` feature this is implemented for every [`std::io::Read`].
+pub trait ByteSource {
+    /// Reads the next byte, or `None` at the end of input.
+    fn read_byte(&mut self) -> Result<Option<u8>, IoError>;
+}
+
+/// Where `.` puts its bytes.
+///
+/// With the `std` feature this is implemented for every [`std::io::Write`].
+pub trait ByteSink {
+    /// Writes a single byte.
+    fn write_byte(&mut self, byte: u8) -> Result<(), IoError>;
+
+    /// Delivers buffered bytes. Called before the program waits on input.
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> ByteSource for R {
+    fn read_byte(&mut self) -> Result<Option<u8>, IoError> {
+        let mut buf = [0];
+        match self.read_exact(&mut buf) {
+            Ok(()) => Ok(Some(buf[0])),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> ByteSink for W {
+    fn write_byte(&mut self, byte: u8) -> Result<(), IoError> {
+        self.write_all(&[byte])
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        std::io::Write::flush(self)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<S: ByteSource + ?Sized> ByteSource for &mut S {
+    fn read_byte(&mut self) -> Result<Option<u8>, IoError> {
+        (**self).read_byte()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ByteSource for &[u8] {
+    fn read_byte(&mut self) -> Result<Option<u8>, IoError> {
+        let Some((&first, rest)) = self.split_first() else {
+            return Ok(None);
+        };
+        *self = rest;
+        Ok(Some(first))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<S: ByteSink + ?Sized> ByteSink for &mut S {
+    fn write_byte(&mut self, byte: u8) -> Result<(), IoError> {
+        (**self).write_byte(byte)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        (**self).flush()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ByteSink for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) -> Result<(), IoError> {
+        self.push(byte);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ByteSink for &mut [u8] {
+    /// Fills the slice from the front, failing once it is full.
+    fn write_byte(&mut self, byte: u8) -> Result<(), IoError> {
+        let Some((first, rest)) = core::mem::take(self).split_first_mut() else {
+            return Err(IoError::new("failed to write whole buffer"));
+        };
+        *first = byte;
+        *self = rest;
+        Ok(())
+    }
+}
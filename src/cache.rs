@@ -0,0 +1,240 @@
+//! Compiled programs, and the output of programs that read no input, kept
+//! between runs.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use brainfuck_vm::{BfcHeader, Command, Interpreter, Program};
+
+use crate::options::Options;
+
+/// Part of every key, to be bumped whenever a change to the interpreter
+/// could change the output of a program.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Largest output that is kept; longer ones are not worth the disk space.
+const MAX_CACHED_OUTPUT: usize = 16 << 20;
+
+/// Entry for the output of one program run with one set of options.
+pub struct OutputCache {
+    path: PathBuf,
+}
+
+impl OutputCache {
+    /// Entry for running `program` under `interpreter`, or `None` if the
+    /// run may not be cached: with `--no-cache`, without a cache directory,
+    /// when the program reads input, draws random bytes, or dumps the tape,
+    /// and when limits, the exit code, or `--stats` depend on more than the
+    /// output.
+    pub fn for_run(
+        options: &Options,
+        program: &[Command],
+        interpreter: &Interpreter,
+    ) -> Option<OutputCache> {
+        let limited = options.max_steps.is_some()
+            || options.max_output.is_some()
+            || options.timeout.is_some()
+            || options.max_memory.is_some();
+        let uncacheable = program.iter().any(|command| {
+            matches!(
+                command,
+                Command::ReadByte | Command::InputAt(_) | Command::Random | Command::DebugDump
+            )
+        });
+        if options.no_cache
+            || limited
+            || options.exit_cell
+            || options.stats.is_some()
+            || options.random_ext
+            || uncacheable
+        {
+            return None;
+        }
+
+        let mut key = Fnv::default();
+        fmt::Write::write_fmt(
+            &mut key,
+            format_args!(
+                "{CACHE_VERSION} {} {program:?} {interpreter:?} {:?} {}",
+                env!("CARGO_PKG_VERSION"),
+                options.cell_size,
+                options.sparse_tape,
+            ),
+        )
+        .expect("hashing cannot fail");
+        let path = directory()?.join(format!("v{CACHE_VERSION}-{:016x}.out", key.0));
+        Some(OutputCache { path })
+    }
+
+    /// Output stored by an earlier run, unless there is none or it was cut
+    /// short.
+    pub fn load(&self) -> Option<Vec<u8>> {
+        read_entry(&self.path)
+    }
+
+    /// Stores `output`.
+    pub fn store(&self, output: &[u8]) -> io::Result<()> {
+        write_entry(&self.path, output)
+    }
+}
+
+/// Entry for a program compiled from one source with one set of options.
+pub struct ProgramCache {
+    path: PathBuf,
+}
+
+impl ProgramCache {
+    /// Entry for compiling `source_code` as `options` ask, or `None` with
+    /// `--no-cache`, without a cache directory, and for programs that are
+    /// loaded compiled already.
+    pub fn for_source(options: &Options, source_code: &str) -> Option<ProgramCache> {
+        if options.no_cache || options.compiled.is_some() {
+            return None;
+        }
+        // Everything `load_program` looks at.
+        let mut key = Fnv::default();
+        fmt::Write::write_fmt(
+            &mut key,
+            format_args!(
+                "{CACHE_VERSION} {} {:?} {:?} {} {} {} {:?} {} {:?} {:?} {:?} {}\n",
+                env!("CARGO_PKG_VERSION"),
+                options.dialect,
+                options.token_map,
+                options.strict,
+                options.debug_ext,
+                options.random_ext,
+                options.comments,
+                options.macros,
+                options.opt_level,
+                options.disabled_passes,
+                options.overflow_policy,
+                options.tape_init.is_none(),
+            ),
+        )
+        .expect("hashing cannot fail");
+        key.write_bytes(source_code.as_bytes());
+        let path = directory()?.join(format!("v{CACHE_VERSION}-{:016x}.prog", key.0));
+        Some(ProgramCache { path })
+    }
+
+    /// Program stored by an earlier run, unless there is none or it does
+    /// not load as a valid program.
+    pub fn load(&self) -> Option<Vec<Command>> {
+        let entry = read_entry(&self.path)?;
+        let (_, program) = Program::load(&mut entry.as_slice()).ok()?;
+        Some(program.into_commands())
+    }
+
+    /// Stores `program` in the `.bfc` format; the key already covers the
+    /// header.
+    pub fn store(&self, program: &[Command]) -> io::Result<()> {
+        let program = Program::new(program.to_vec()).map_err(io::Error::other)?;
+        let mut entry = Vec::new();
+        program.save(&BfcHeader::default(), &mut entry)?;
+        write_entry(&self.path, &entry)
+    }
+}
+
+/// Contents of the entry at `path`, unless there is none or it was cut
+/// short.
+fn read_entry(path: &Path) -> Option<Vec<u8>> {
+    let entry = fs::read(path).ok()?;
+    let (len, contents) = entry.split_first_chunk::<8>()?;
+    (u64::from_le_bytes(*len) == contents.len() as u64).then(|| contents.to_vec())
+}
+
+/// Stores `contents` at `path`, prefixed with their length so that a partly
+/// written entry is never taken for a whole one.
+fn write_entry(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    // Readers only ever see the old entry or the complete new one.
+    let temporary = path.with_extension(format!("tmp{}", std::process::id()));
+    let mut entry = Vec::with_capacity(8 + contents.len());
+    entry.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+    entry.extend_from_slice(contents);
+    fs::write(&temporary, entry)?;
+    fs::rename(&temporary, path)
+}
+
+/// Directory of the cache: `BRAINFUCK_VM_CACHE_DIR`, or `brainfuck_vm` in
+/// the user's cache directory.
+fn directory() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    if let Some(directory) = var("BRAINFUCK_VM_CACHE_DIR") {
+        return Some(directory.into());
+    }
+    let cache = var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache.join("brainfuck_vm"))
+}
+
+/// Writer that keeps a copy of what passes through it, up to
+/// [`MAX_CACHED_OUTPUT`] bytes.
+pub struct Recorder<W> {
+    inner: W,
+    /// Everything written so far, or `None` if it is not recorded.
+    recorded: Option<Vec<u8>>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Passes writes on to `inner`, and records them if `record` is set.
+    pub fn new(inner: W, record: bool) -> Self {
+        Recorder {
+            inner,
+            recorded: record.then(Vec::new),
+        }
+    }
+
+    /// Everything written, if it was recorded in full.
+    pub fn recorded(&self) -> Option<&[u8]> {
+        self.recorded.as_deref()
+    }
+}
+
+impl<W: Write> Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(recorded) = &mut self.recorded {
+            if recorded.len() + n > MAX_CACHED_OUTPUT {
+                self.recorded = None;
+            } else {
+                recorded.extend_from_slice(&buf[..n]);
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the standard hasher stays the same across
+/// Rust releases.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl fmt::Write for Fnv {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use core::fmt;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for i8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// Integer type stored in a tape cell: `u8`, `u16`, `u32`, or `i8`.
+///
+/// Wider cells hold larger values, but I/O stays byte-sized: `,` stores the
+/// input byte zero-extended, and `.` writes only the low byte of the cell.
+///
+/// Signed `i8` cells wrap between -128 and 127 and only change how values
+/// are shown, e.g. in `Debug` output of the tape. Their I/O uses the
+/// two's-complement byte, so a cell holding -1 is written as 0xFF.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait Cell: Copy + Eq + Default + fmt::Debug + fmt::Display + sealed::Sealed {
+    /// Value of a fresh cell.
+    const ZERO: Self;
+    /// Value of -1, which is the largest value for unsigned cells.
+    const MINUS_ONE: Self;
+
+    /// Converts an input byte into a cell value.
+    fn from_byte(byte: u8) -> Self;
+
+    /// Keeps the low bits of `bits`, i.e. reduces it modulo the cell size.
+    fn from_bits(bits: u64) -> Self;
+
+    /// Lowest eight bits of the value, which is what `.` writes.
+    fn low_byte(self) -> u8;
+
+    /// The value itself, which every cell type fits into.
+    fn to_i64(self) -> i64;
+
+    fn wrapping_inc(self) -> Self;
+    fn wrapping_dec(self) -> Self;
+    fn saturating_inc(self) -> Self;
+    fn saturating_dec(self) -> Self;
+    fn checked_inc(self) -> Option<Self>;
+    fn checked_dec(self) -> Option<Self>;
+
+    /// Adds `delta` modulo the cell size, like `delta` times `+` or `-`
+    /// with wrapping.
+    #[inline]
+    fn wrapping_add_signed(self, delta: i64) -> Self {
+        Self::from_bits(self.to_i64().wrapping_add(delta) as u64)
+    }
+
+    /// Index of the first zero in `cells`.
+    #[inline]
+    fn position_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().position(|&cell| cell == Self::ZERO)
+    }
+
+    /// Index of the last zero in `cells`.
+    #[inline]
+    fn rposition_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().rposition(|&cell| cell == Self::ZERO)
+    }
+}
+
+macro_rules! impl_cell {
+    ($($ty:ty $(=> $find:path, $rfind:path)?);*) => {$(
+        impl Cell for $ty {
+            const ZERO: Self = 0;
+            const MINUS_ONE: Self = (0 as $ty).wrapping_sub(1);
+
+            #[inline]
+            fn from_byte(byte: u8) -> Self {
+                byte as $ty
+            }
+
+            #[inline]
+            fn from_bits(bits: u64) -> Self {
+                bits as $ty
+            }
+
+            #[inline]
+            fn low_byte(self) -> u8 {
+                self as u8
+            }
+
+            #[inline]
+            fn to_i64(self) -> i64 {
+                i64::from(self)
+            }
+
+            #[inline]
+            fn wrapping_inc(self) -> Self {
+                self.wrapping_add(1)
+            }
+
+            #[inline]
+            fn wrapping_dec(self) -> Self {
+                self.wrapping_sub(1)
+            }
+
+            #[inline]
+            fn saturating_inc(self) -> Self {
+                self.saturating_add(1)
+            }
+
+            #[inline]
+            fn saturating_dec(self) -> Self {
+                self.saturating_sub(1)
+            }
+
+            #[inline]
+            fn checked_inc(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+
+            #[inline]
+            fn checked_dec(self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+            $(
+                #[inline]
+                fn position_zero(cells: &[Self]) -> Option<usize> {
+                    $find(0, cells)
+                }
+
+                #[inline]
+                fn rposition_zero(cells: &[Self]) -> Option<usize> {
+                    $rfind(0, cells)
+                }
+            )?
+        }
+    )*};
+}
+
+// Byte cells are searched with the SIMD routines of `memchr`.
+impl_cell!(u8 => memchr::memchr, memchr::memrchr; u16; u32; i8);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EofBehavior, Interpreter, OverflowPolicy, RuntimeError, Streams, Vm, compile, eval_on_tape,
+    };
+
+    /// Test a counter that prints every value until its cell wraps to zero.
+    #[test]
+    fn test_counter_width() {
+        let program = compile("+[+.]").unwrap();
+        let mut narrow = Vec::new();
+        let mut wide = Vec::new();
+
+        eval_on_tape(&program, vec![0u8], 0, &[][..], &mut narrow).unwrap();
+        eval_on_tape(&program, vec![0u16], 0, &[][..], &mut wide).unwrap();
+
+        assert_eq!(narrow.len(), 255);
+        assert_eq!(narrow[254], 0);
+        assert_eq!(wide.len(), 65_535);
+        assert_eq!(wide[253..256], [255, 0, 1]);
+    }
+
+    /// Test that input is zero-extended and output is the low byte.
+    #[test]
+    fn test_wide_io() {
+        let program = compile(",-.>,.").unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, vec![0u32; 2], 0);
+        vm.run_with(Streams::new(&[0, 200][..], &mut output))
+            .unwrap();
+
+        assert_eq!(output, [255, 200]);
+        assert_eq!(vm.tape(), &[u32::MAX, 200]);
+    }
+
+    /// Test that a signed cell shows -1 but is still written as 0xFF.
+    #[test]
+    fn test_signed_cell() {
+        let program = compile("-.>,.>+[+]").unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, vec![0i8; 3], 0);
+        vm.run_with(Streams::new(&[200][..], &mut output)).unwrap();
+
+        assert_eq!(output, [0xFF, 200]);
+        assert_eq!(format!("{:?}", vm.tape()), "[-1, -56, 0]");
+    }
+
+    /// Test the signed range under the overflow policies and EOF.
+    #[test]
+    fn test_signed_limits() {
+        let program = compile("-,").unwrap();
+        let interpreter = Interpreter::builder()
+            .tape_len(1)
+            .overflow_policy(OverflowPolicy::Saturate)
+            .eof_behavior(EofBehavior::Unchanged)
+            .build()
+            .unwrap();
+        let mut vm = interpreter.vm_with_cells::<i8>(&program);
+        vm.run_with(Streams::new(&[][..], Vec::new())).unwrap();
+        assert_eq!(vm.tape(), &[-1]);
+
+        let program = compile(&"+".repeat(200)).unwrap();
+        let mut vm = interpreter.vm_with_cells::<i8>(&program);
+        vm.run().unwrap();
+        assert_eq!(vm.tape(), &[127]);
+
+        let interpreter = Interpreter::builder()
+            .tape_len(1)
+            .overflow_policy(OverflowPolicy::Error)
+            .eof_behavior(EofBehavior::SetMinusOne)
+            .build()
+            .unwrap();
+        let mut vm = interpreter.vm_with_cells::<i8>(&program);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::CellOverflow {
+                instruction_index: 127
+            })
+        );
+        let program = compile(",").unwrap();
+        let mut vm = interpreter.vm_with_cells::<i8>(&program);
+        vm.run_with(Streams::new(&[][..], Vec::new())).unwrap();
+        assert_eq!(vm.tape(), &[-1]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Reports of `check --json`, with every error found in the programs.
+
+use std::path::Path;
+
+use brainfuck_vm::ParsingError;
+
+use crate::source::Location;
+
+/// One error `check` found.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    /// File the error is in, unless the program was given inline.
+    pub file: Option<String>,
+    /// Offset, line, and column of errors in the source code.
+    pub position: Option<(usize, usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// The diagnostic for an error of the source code.
+    pub fn parse(location: Location<'_>) -> Diagnostic {
+        Diagnostic {
+            file: location.path.map(|path| path.display().to_string()),
+            position: Some((location.error.offset(), location.line, location.column)),
+            message: message(&location.error),
+        }
+    }
+
+    /// The diagnostic for an error that points nowhere in the source, like
+    /// a file that cannot be read.
+    pub fn other(path: Option<&Path>, message: String) -> Diagnostic {
+        Diagnostic {
+            file: path.map(|path| path.display().to_string()),
+            position: None,
+            message,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let file = self.file.as_deref().map_or("null".into(), json_string);
+        let (offset, line, column) = match self.position {
+            Some((offset, line, column)) => {
+                (offset.to_string(), line.to_string(), column.to_string())
+            }
+            None => ("null".into(), "null".into(), "null".into()),
+        };
+        format!(
+            "{{\"file\":{file},\"offset\":{offset},\"line\":{line},\"column\":{column},\
+             \"message\":{}}}",
+            json_string(&self.message)
+        )
+    }
+}
+
+/// Every diagnostic as one JSON array, empty if no program has an error.
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let objects: Vec<_> = diagnostics.iter().map(Diagnostic::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// What `error` says, without the offset that the JSON has on its own.
+fn message(error: &ParsingError) -> String {
+    match *error {
+        ParsingError::UnmatchedBracket { bracket, .. } => format!("unmatched '{bracket}'"),
+        ParsingError::UnexpectedCharacter { ch, .. } => {
+            format!("unexpected character '{ch}' (U+{:04X})", u32::from(ch))
+        }
+        ParsingError::NestingTooDeep { depth, .. } => {
+            format!("'[' nests loops {depth} deep, more than the limit")
+        }
+    }
+}
+
+/// `text` as a JSON string, with quotes.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            ch if u32::from(ch) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(ch))),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test the fields of both kinds of diagnostics, with `null` where
+    /// nothing is known.
+    #[test]
+    fn test_json() {
+        let diagnostics = [
+            Diagnostic::parse(Location {
+                path: Some(Path::new("dir/a \"b\".b")),
+                error: ParsingError::UnexpectedCharacter { offset: 7, ch: '"' },
+                line: 2,
+                column: 3,
+            }),
+            Diagnostic::other(None, "cannot read\tstdin".into()),
+        ];
+        assert_eq!(
+            to_json(&diagnostics),
+            "[{\"file\":\"dir/a \\\"b\\\".b\",\"offset\":7,\"line\":2,\"column\":3,\
+             \"message\":\"unexpected character '\\\"' (U+0022)\"},\
+             {\"file\":null,\"offset\":null,\"line\":null,\"column\":null,\
+             \"message\":\"cannot read\\u0009stdin\"}]"
+        );
+        assert_eq!(to_json(&[]), "[]");
+    }
+}
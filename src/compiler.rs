@@ -0,0 +1,658 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{ErrorKind, Read};
+
+#[cfg(feature = "std")]
+use crate::Error;
+use crate::{Command, ParsingError};
+
+/// Deepest loop nesting that [`compile`](crate::compile) accepts.
+///
+/// Deeper programs are almost certainly machine-generated garbage, and
+/// would overflow the stack of recursive passes over the [`Ast`](crate::Ast).
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Compiler state that survives between pieces of source code.
+///
+/// Commands are emitted as they arrive. A `[` is written with a placeholder
+/// address that is patched once its `]` shows up, so only the open brackets
+/// need to be remembered.
+#[derive(Debug)]
+pub(crate) struct Compiler {
+    commands: Vec<Command>,
+    /// Index and source offset of every `[` or `(` that is still open.
+    open_brackets: Vec<(usize, usize)>,
+    /// Offset of the next byte in the source.
+    offset: usize,
+    /// Number of loops that may be open at the same time.
+    max_depth: usize,
+    /// Whether `#` compiles to [`Command::DebugDump`].
+    debug_dumps: bool,
+    /// Whether `(`, `)`, and `:` compile to pbrain procedure commands.
+    procedures: bool,
+    /// Whether `?` compiles to [`Command::Random`].
+    random: bool,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::with_max_depth(DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl Compiler {
+    pub(crate) fn with_max_depth(max_depth: usize) -> Self {
+        Compiler {
+            commands: Vec::new(),
+            open_brackets: Vec::new(),
+            offset: 0,
+            max_depth,
+            debug_dumps: false,
+            procedures: false,
+            random: false,
+        }
+    }
+
+    /// Compiles `bytes`, which continue the source pushed so far.
+    ///
+    /// Commands are ASCII, and UTF-8 never uses ASCII bytes inside a longer
+    /// character, so the bytes of comments are skipped one by one, and a
+    /// source can be split anywhere, even inside a character.
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Result<(), ParsingError> {
+        use self::Command as C;
+
+        let start_offset = self.offset;
+        self.offset += bytes.len();
+
+        for (i, &byte) in bytes.iter().enumerate() {
+            let offset = start_offset + i;
+
+            let command = match byte {
+                b'>' => C::IncrementDataPointer,
+                b'<' => C::DecrementDataPointer,
+                b'+' => C::Increment,
+                b'-' => C::Decrement,
+                b'.' => C::WriteByte,
+                b',' => C::ReadByte,
+                b'#' if self.debug_dumps => C::DebugDump,
+                b':' if self.procedures => C::Call,
+                b'?' if self.random => C::Random,
+                b'[' => {
+                    self.open(offset)?;
+                    C::JumpForwardIfZero(0)
+                }
+                b'(' if self.procedures => {
+                    self.open(offset)?;
+                    C::BeginProc(0)
+                }
+                b']' => {
+                    let start = self.close(offset, ']')?;
+                    self.commands[start] = C::JumpForwardIfZero(self.commands.len());
+                    C::JumpBackwardIfNonZero(start)
+                }
+                b')' if self.procedures => {
+                    let start = self.close(offset, ')')?;
+                    self.commands[start] = C::BeginProc(self.commands.len());
+                    C::EndProc(start)
+                }
+                _ => continue,
+            };
+            self.commands.push(command);
+        }
+
+        Ok(())
+    }
+
+    /// Remembers the `[` or `(` at `offset`, which is compiled next.
+    fn open(&mut self, offset: usize) -> Result<(), ParsingError> {
+        if self.open_brackets.len() == self.max_depth {
+            return Err(ParsingError::NestingTooDeep {
+                offset,
+                depth: self.max_depth + 1,
+            });
+        }
+        self.open_brackets.push((self.commands.len(), offset));
+        Ok(())
+    }
+
+    /// Returns the index of the innermost open bracket, if it is the
+    /// partner of the `bracket` at `offset`.
+    fn close(&mut self, offset: usize, bracket: char) -> Result<usize, ParsingError> {
+        let partner = match bracket {
+            ']' => Command::JumpForwardIfZero(0),
+            _ => Command::BeginProc(0),
+        };
+        match self.open_brackets.last() {
+            Some(&(start, _)) if self.commands[start] == partner => {
+                self.open_brackets.pop();
+                Ok(start)
+            }
+            _ => Err(ParsingError::UnmatchedBracket { offset, bracket }),
+        }
+    }
+
+    /// Skips `len` bytes of source that contain no commands.
+    pub(crate) fn skip(&mut self, len: usize) {
+        self.offset += len;
+    }
+
+    /// Returns the program, or the first `[` that was never closed.
+    pub(crate) fn finish(self) -> Result<Vec<Command>, ParsingError> {
+        if let Some(&(start, offset)) = self.open_brackets.first() {
+            let bracket = match self.commands[start] {
+                Command::BeginProc(_) => '(',
+                _ => '[',
+            };
+            return Err(ParsingError::UnmatchedBracket { offset, bracket });
+        }
+        // Every `[` was patched when its `]` arrived, so the jumps must
+        // already point at their partners.
+        debug_assert_eq!(crate::validate(&self.commands), Ok(()));
+        Ok(self.commands)
+    }
+}
+
+/// Same as [`compile`](crate::compile), but allows loops to be nested
+/// `max_depth` deep instead of [`DEFAULT_MAX_DEPTH`].
+pub fn compile_with_max_depth(text: &str, max_depth: usize) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler::with_max_depth(max_depth);
+    compiler.push(text.as_bytes())?;
+    compiler.finish()
+}
+
+/// Same as [`compile`](crate::compile), but keeps every `#` as a
+/// [`Command::DebugDump`] instead of skipping it.
+pub fn compile_with_debug_dumps(text: &str) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler {
+        debug_dumps: true,
+        ..Compiler::default()
+    };
+    compiler.push(text.as_bytes())?;
+    compiler.finish()
+}
+
+/// Same as [`compile`](crate::compile), but keeps every `?` as a
+/// [`Command::Random`] instead of skipping it.
+pub fn compile_with_random(text: &str) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler {
+        random: true,
+        ..Compiler::default()
+    };
+    compiler.push(text.as_bytes())?;
+    compiler.finish()
+}
+
+/// Compiles source code in the pbrain dialect, where `(` and `)` define a
+/// procedure and `:` calls one, see [`Command::BeginProc`].
+///
+/// Procedures and loops must nest within each other, so `[(])` is rejected
+/// like a mismatched bracket.
+pub fn compile_pbrain(text: &str) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler {
+        procedures: true,
+        ..Compiler::default()
+    };
+    compiler.push(text.as_bytes())?;
+    compiler.finish()
+}
+
+/// Same as [`compile`](crate::compile), but rejects every character that is
+/// not a command, whitespace, or part of a comment, with
+/// [`ParsingError::UnexpectedCharacter`].
+///
+/// Comments run from `#` to the end of the line. They may contain any text,
+/// and command characters in them are skipped as well.
+pub fn compile_strict(text: &str) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler::default();
+    let mut line_offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let code = line.split('#').next().unwrap_or_default();
+        let unexpected = code
+            .char_indices()
+            .find(|&(_, ch)| !"><+-.,[]".contains(ch) && !ch.is_whitespace());
+        if let Some((i, ch)) = unexpected {
+            return Err(ParsingError::UnexpectedCharacter {
+                offset: line_offset + i,
+                ch,
+            });
+        }
+
+        compiler.push(code.as_bytes())?;
+        compiler.skip(line.len() - code.len());
+        line_offset += line.len();
+    }
+
+    compiler.finish()
+}
+
+/// Every error [`compile`](crate::compile) finds in `text`, or
+/// [`compile_strict`] with `strict`, instead of only the first, by offset.
+///
+/// Compiling stops at the first error, so that an editor or a linter would
+/// only show one problem at a time. Here an unmatched `]` is skipped, every
+/// `[` left open at the end is reported, and loops nested too deep are
+/// reported once for the `[` that goes past the limit.
+pub fn find_errors(text: &str, strict: bool) -> Vec<ParsingError> {
+    let mut errors = Vec::new();
+    let mut open_brackets = Vec::new();
+    let mut line_offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let code = match strict {
+            true => line.split('#').next().unwrap_or_default(),
+            false => line,
+        };
+        for (i, ch) in code.char_indices() {
+            let offset = line_offset + i;
+            match ch {
+                '[' => {
+                    open_brackets.push(offset);
+                    if open_brackets.len() == DEFAULT_MAX_DEPTH + 1 {
+                        errors.push(ParsingError::NestingTooDeep {
+                            offset,
+                            depth: open_brackets.len(),
+                        });
+                    }
+                }
+                ']' if open_brackets.pop().is_none() => {
+                    errors.push(ParsingError::UnmatchedBracket {
+                        offset,
+                        bracket: ']',
+                    });
+                }
+                _ if strict && !"><+-.,[]".contains(ch) && !ch.is_whitespace() => {
+                    errors.push(ParsingError::UnexpectedCharacter { offset, ch });
+                }
+                _ => {}
+            }
+        }
+        line_offset += line.len();
+    }
+
+    errors.extend(
+        open_brackets
+            .into_iter()
+            .map(|offset| ParsingError::UnmatchedBracket {
+                offset,
+                bracket: '[',
+            }),
+    );
+    errors.sort_by_key(ParsingError::offset);
+    errors
+}
+
+/// Result of [`IncrementalCompiler::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushResult {
+    /// Some `[` are still open, so the program cannot be finished yet.
+    NeedsMore { open_brackets: usize },
+    /// Every bracket is matched; [`IncrementalCompiler::finish`] will succeed.
+    Complete,
+}
+
+/// Compiles source code that arrives in fragments, e.g. lines typed into
+/// a REPL.
+///
+/// Offsets in errors count bytes from the start of the first fragment.
+#[derive(Debug, Default)]
+pub struct IncrementalCompiler {
+    compiler: Compiler,
+}
+
+impl IncrementalCompiler {
+    /// Creates a compiler that has not seen any source yet.
+    pub fn new() -> Self {
+        IncrementalCompiler::default()
+    }
+
+    /// Same as [`IncrementalCompiler::new`], but allows loops to be nested
+    /// `max_depth` deep instead of [`DEFAULT_MAX_DEPTH`].
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        IncrementalCompiler {
+            compiler: Compiler::with_max_depth(max_depth),
+        }
+    }
+
+    /// Compiles the next fragment and reports whether the program so far is
+    /// complete.
+    ///
+    /// A `]` without a partner or a loop nested too deep is reported right
+    /// away. The fragment is then
+    /// discarded, leaving the compiler as it was before the call.
+    pub fn push(&mut self, fragment: &str) -> Result<PushResult, ParsingError> {
+        let compiler = &mut self.compiler;
+        let len = compiler.commands.len();
+        let open_brackets = compiler.open_brackets.clone();
+
+        if let Err(e) = compiler.push(fragment.as_bytes()) {
+            compiler.offset -= fragment.len();
+            compiler.commands.truncate(len);
+            for &(start, _) in &open_brackets {
+                compiler.commands[start] = Command::JumpForwardIfZero(0);
+            }
+            compiler.open_brackets = open_brackets;
+            return Err(e);
+        }
+
+        Ok(match compiler.open_brackets.len() {
+            0 => PushResult::Complete,
+            open_brackets => PushResult::NeedsMore { open_brackets },
+        })
+    }
+
+    /// Returns the compiled program, or the first `[` that was never closed.
+    pub fn finish(self) -> Result<Vec<Command>, ParsingError> {
+        self.compiler.finish()
+    }
+}
+
+#[cfg(feature = "std")]
+/// Same as [`compile`](crate::compile), but reads the source incrementally,
+/// so it never has to be in memory as a whole.
+///
+/// Bytes other than the eight commands are skipped, and offsets in
+/// [`ParsingError`]s count bytes from the start of the stream.
+pub fn compile_from_reader<R: Read>(mut reader: R) -> Result<Vec<Command>, Error> {
+    let mut compiler = Compiler::default();
+    let mut buf = [0; 64 * 1024];
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        compiler.push(&buf[..n])?;
+    }
+
+    Ok(compiler.finish()?)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{compile, eval};
+
+    /// Test that streaming produces the same commands and errors as `compile`.
+    #[test]
+    fn test_same_as_compile() {
+        let sources = [
+            ">,[>,]<[<]>[.>]",
+            "++[>++[>+<-]<-] with a comment",
+            "+]",
+            "[[]",
+            "a[b",
+        ];
+        for source in sources {
+            let streamed = compile_from_reader(source.as_bytes()).map_err(|e| e.to_string());
+            let compiled = compile(source).map_err(|e| Error::from(e).to_string());
+            assert_eq!(streamed, compiled, "{source}");
+        }
+    }
+
+    /// Test a multi-megabyte source that is mostly comments.
+    #[test]
+    fn test_large_source() {
+        let mut source = Vec::new();
+        for _ in 0..100_000 {
+            source.extend_from_slice(b"padding text without commands ");
+            source.extend_from_slice(b"+[-]");
+        }
+        source.push(b']');
+        let len = source.len();
+        assert!(len > 3_000_000);
+
+        let error = compile_from_reader(Cursor::new(&source)).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Parse(ParsingError::UnmatchedBracket { offset, bracket: ']' }) if offset == len - 1
+        ));
+
+        source.pop();
+        let commands = compile_from_reader(Cursor::new(source)).unwrap();
+        assert_eq!(commands.len(), 400_000);
+    }
+
+    /// Test a loop split over two fragments.
+    #[test]
+    fn test_incremental_fragments() {
+        let mut compiler = IncrementalCompiler::new();
+
+        assert_eq!(
+            compiler.push("+++[").unwrap(),
+            PushResult::NeedsMore { open_brackets: 1 }
+        );
+        assert_eq!(compiler.push(">++<-]>.").unwrap(), PushResult::Complete);
+
+        let program = compiler.finish().unwrap();
+        assert_eq!(program, compile("+++[>++<-]>.").unwrap());
+        let mut output = Vec::new();
+        eval(&program, &[][..], &mut output).unwrap();
+        assert_eq!(output, [6]);
+    }
+
+    /// Test that offsets are global and a bad fragment is discarded.
+    #[test]
+    fn test_incremental_errors() {
+        let mut compiler = IncrementalCompiler::new();
+        compiler.push("+[").unwrap();
+
+        assert_eq!(
+            compiler.push("-]]").unwrap_err(),
+            ParsingError::UnmatchedBracket {
+                offset: 4,
+                bracket: ']',
+            }
+        );
+        assert_eq!(
+            compiler.push("[-").unwrap(),
+            PushResult::NeedsMore { open_brackets: 2 }
+        );
+        assert_eq!(
+            compiler.finish().unwrap_err(),
+            ParsingError::UnmatchedBracket {
+                offset: 1,
+                bracket: '[',
+            }
+        );
+    }
+
+    /// Test that the `[` past the nesting limit is reported.
+    #[test]
+    fn test_nesting_limit() {
+        let too_deep = "[".repeat(20_000);
+        let error = ParsingError::NestingTooDeep {
+            offset: 10_000,
+            depth: 10_001,
+        };
+        assert_eq!(compile(&too_deep), Err(error.clone()));
+        assert_eq!(crate::parse_ast(&too_deep), Err(error));
+
+        let deepest = "[".repeat(DEFAULT_MAX_DEPTH) + &"]".repeat(DEFAULT_MAX_DEPTH);
+        assert_eq!(compile(&deepest).unwrap().len(), 20_000);
+
+        assert_eq!(
+            compile_with_max_depth("+[>[-]<[[-]]]", 2),
+            Err(ParsingError::NestingTooDeep {
+                offset: 8,
+                depth: 3
+            })
+        );
+        let mut compiler = IncrementalCompiler::with_max_depth(2);
+        assert!(compiler.push("[[").is_ok());
+        assert!(compiler.push("[").is_err());
+        assert!(compiler.push("]]").is_ok());
+    }
+
+    /// Test that each bracket is compiled to its own jump, pointing at its
+    /// partner.
+    #[test]
+    fn test_nested_loops() {
+        use crate::Command as C;
+
+        assert_eq!(
+            compile("+[>[-]<[>+<-]]").unwrap(),
+            [
+                C::Increment,
+                C::JumpForwardIfZero(13),
+                C::IncrementDataPointer,
+                C::JumpForwardIfZero(5),
+                C::Decrement,
+                C::JumpBackwardIfNonZero(3),
+                C::DecrementDataPointer,
+                C::JumpForwardIfZero(12),
+                C::IncrementDataPointer,
+                C::Increment,
+                C::DecrementDataPointer,
+                C::Decrement,
+                C::JumpBackwardIfNonZero(7),
+                C::JumpBackwardIfNonZero(1),
+            ]
+        );
+    }
+
+    /// Test that the jumps of random balanced programs point at their
+    /// partners, also when the source arrives in pieces.
+    #[test]
+    fn test_random_brackets() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % bound
+        };
+
+        for _ in 0..200 {
+            let mut source = String::new();
+            let mut depth = 0;
+            for _ in 0..next(200) {
+                match next(4) {
+                    0 if depth < 20 => {
+                        source.push('[');
+                        depth += 1;
+                    }
+                    1 if depth > 0 => {
+                        source.push(']');
+                        depth -= 1;
+                    }
+                    _ => source.push(b"+-<>.,x"[next(7) as usize] as char),
+                }
+            }
+            source.extend(core::iter::repeat_n(']', depth));
+
+            let commands = compile(&source).unwrap();
+            assert_eq!(crate::validate(&commands), Ok(()), "{source}");
+
+            let mut compiler = IncrementalCompiler::new();
+            let split = source.len() / 2;
+            compiler.push(&source[..split]).unwrap();
+            compiler.push(&source[split..]).unwrap();
+            assert_eq!(compiler.finish().unwrap(), commands, "{source}");
+        }
+    }
+
+    /// Test that procedures pair up with their `)` and nest with loops.
+    #[test]
+    fn test_pbrain_brackets() {
+        use crate::Command as C;
+
+        assert_eq!(
+            compile_pbrain("+([-]):").unwrap(),
+            [
+                C::Increment,
+                C::BeginProc(5),
+                C::JumpForwardIfZero(4),
+                C::Decrement,
+                C::JumpBackwardIfNonZero(2),
+                C::EndProc(1),
+                C::Call,
+            ]
+        );
+        assert_eq!(compile("+([-]):").unwrap().len(), 4);
+
+        let unmatched = |offset, bracket| Err(ParsingError::UnmatchedBracket { offset, bracket });
+        assert_eq!(compile_pbrain("[(])"), unmatched(2, ']'));
+        assert_eq!(compile_pbrain("([)]"), unmatched(2, ')'));
+        assert_eq!(compile_pbrain("+(:"), unmatched(1, '('));
+        assert_eq!(compile_pbrain(":)"), unmatched(1, ')'));
+    }
+
+    /// Test that prose is only a comment outside of strict mode.
+    #[test]
+    fn test_strict_comments() {
+        let source_code = "Add two numbers: ++>+++[<+>-]";
+        assert!(compile(source_code).is_ok());
+        assert_eq!(
+            compile_strict(source_code),
+            Err(ParsingError::UnexpectedCharacter { offset: 0, ch: 'A' })
+        );
+
+        let strict = "# Add two numbers, e.g. 2 + 3.\n++>+++ # here\n[<+>-]\n";
+        assert_eq!(compile_strict(strict), compile("++>+++[<+>-]"));
+    }
+
+    /// Test that every error is found, with the first one that of `compile`.
+    #[test]
+    fn test_find_errors() {
+        let source_code = "]+[[-]x\n# [ is a comment\n]";
+        let errors = find_errors(source_code, false);
+        let offsets: Vec<_> = errors.iter().map(ParsingError::offset).collect();
+        assert_eq!(offsets, [0, 2]);
+        assert_eq!(compile(source_code).unwrap_err(), errors[0]);
+
+        // The `[` in the comment does not take the last `]`.
+        assert_eq!(
+            find_errors(source_code, true),
+            [
+                ParsingError::UnmatchedBracket {
+                    offset: 0,
+                    bracket: ']'
+                },
+                ParsingError::UnexpectedCharacter { offset: 6, ch: 'x' },
+            ]
+        );
+        assert!(find_errors("+[-] # ]", true).is_empty());
+        let deep = "[".repeat(DEFAULT_MAX_DEPTH + 2) + &"]".repeat(DEFAULT_MAX_DEPTH + 2);
+        assert_eq!(
+            find_errors(&deep, false),
+            [ParsingError::NestingTooDeep {
+                offset: DEFAULT_MAX_DEPTH,
+                depth: DEFAULT_MAX_DEPTH + 1
+            }]
+        );
+    }
+
+    /// Test that a lookalike of `>` is reported at its offset in the source.
+    #[test]
+    fn test_strict_lookalike() {
+        let source_code = "# move right\n+≻+[-]";
+        let error = compile_strict(source_code).unwrap_err();
+
+        assert_eq!(
+            error,
+            ParsingError::UnexpectedCharacter {
+                offset: 14,
+                ch: '≻'
+            }
+        );
+        assert_eq!(&source_code[14..], "≻+[-]");
+        assert_eq!(
+            error.to_string(),
+            "unexpected character '≻' (U+227B) at offset 14"
+        );
+
+        // Bracket errors still count comment bytes.
+        assert_eq!(
+            compile_strict("# ≻ [\n]"),
+            Err(ParsingError::UnmatchedBracket {
+                offset: 8,
+                bracket: ']'
+            })
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use alloc::string::String;
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::{Command, JumpError, validate};
+
+/// Turns a command list back into Brainfuck source code.
+///
+/// The jump addresses are checked with [`validate`] first, so hand-built
+/// programs with inconsistent brackets are rejected instead of producing
+/// source that compiles to something else. Commands folded by
+/// [`optimize`](crate::optimize) turn back into runs and loops that do the
+/// same, though not always the ones they came from.
+pub fn to_source(commands: &[Command]) -> Result<String, JumpError> {
+    use self::Command as C;
+
+    validate(commands)?;
+
+    let mut source = String::with_capacity(commands.len());
+    for (address, command) in commands.iter().enumerate() {
+        match *command {
+            C::IncrementDataPointer => source.push('>'),
+            C::DecrementDataPointer => source.push('<'),
+            C::Increment => source.push('+'),
+            C::Decrement => source.push('-'),
+            C::WriteByte => source.push('.'),
+            C::ReadByte => source.push(','),
+            C::JumpForwardIfZero(_) => source.push('['),
+            C::JumpBackwardIfNonZero(_) => source.push(']'),
+            C::DebugDump => source.push('#'),
+            C::Random => source.push('?'),
+            C::BeginProc(_) => source.push('('),
+            C::EndProc(_) => source.push(')'),
+            C::Call => source.push(':'),
+            C::Add(delta) => push_run(&mut source, '+', '-', delta.into()),
+            C::MovePointer(offset) => push_run(&mut source, '>', '<', offset.into()),
+            C::Set(value) => {
+                source.push_str("[-]");
+                push_run(&mut source, '+', '-', value.into());
+            }
+            C::AddAt { offset, value } => push_at(&mut source, offset, |source| {
+                push_run(source, '+', '-', value.into());
+            }),
+            C::SetAt { offset, value } => push_at(&mut source, offset, |source| {
+                source.push_str("[-]");
+                push_run(source, '+', '-', value.into());
+            }),
+            C::OutputAt(offset) => push_at(&mut source, offset, |source| source.push('.')),
+            C::InputAt(offset) => push_at(&mut source, offset, |source| source.push(',')),
+            C::ClearRange { len } => {
+                let step = if len < 0 { '<' } else { '>' };
+                for cell in 0..len.unsigned_abs() {
+                    if cell > 0 {
+                        source.push(step);
+                    }
+                    source.push_str("[-]");
+                }
+            }
+            C::ScanRight(stride) => push_loop(&mut source, '>', stride),
+            C::ScanLeft(stride) => push_loop(&mut source, '<', stride),
+            // Consecutive multiplications share one loop.
+            C::MulAdd { offset, factor } => {
+                let is_mul_add =
+                    |command: Option<&Command>| matches!(command, Some(C::MulAdd { .. }));
+                if address == 0 || !is_mul_add(commands.get(address - 1)) {
+                    source.push('[');
+                }
+                push_run(&mut source, '>', '<', offset.into());
+                push_run(&mut source, '+', '-', factor.into());
+                push_run(&mut source, '<', '>', offset.into());
+                if !is_mul_add(commands.get(address + 1)) {
+                    source.push_str("-]");
+                }
+            }
+        }
+    }
+
+    Ok(source)
+}
+
+/// Lists a command list one command per line, in a text form that stays
+/// the same across releases, for reading what the optimizer made of a
+/// program and for comparing it with an earlier listing.
+///
+/// Every line holds the address of the command, a colon, its mnemonic,
+/// and its operands, e.g. `3: add_at +2 -1` for [`Command::AddAt`] with
+/// offset 2 and value -1. Offsets carry a sign; jumps name the address of
+/// their other bracket.
+pub fn to_ir(commands: &[Command]) -> String {
+    let mut ir = String::with_capacity(8 * commands.len());
+    for (address, command) in commands.iter().enumerate() {
+        let _ = write!(ir, "{address}: ");
+        push_instruction(&mut ir, command);
+        ir.push('\n');
+    }
+    ir
+}
+
+/// Lists the commands at the addresses in `range` like [`to_ir`], but
+/// indented by how deeply they are nested, for reading the structure of a
+/// program.
+///
+/// Every line holds the address, right-aligned, and the instruction as
+/// [`to_ir`] writes it, indented by two spaces for every loop or pbrain
+/// procedure around it, e.g. `  3    dec` for a `-` in a nested loop. With
+/// `offsets`, the source offset of every command, each line also holds
+/// its offset after an `@`. Addresses in `range` past the end are left out.
+pub fn to_listing(commands: &[Command], range: Range<usize>, offsets: Option<&[usize]>) -> String {
+    use self::Command as C;
+
+    let end = range.end.min(commands.len());
+    let start = range.start.min(end);
+    let width = digits(end.saturating_sub(1));
+    let offset_width = offsets.map_or(0, |offsets| {
+        let listed = offsets.get(start..end).unwrap_or_default();
+        listed.iter().max().map_or(0, |&offset| digits(offset))
+    });
+
+    let opens = |command: &Command| matches!(command, C::JumpForwardIfZero(_) | C::BeginProc(_));
+    let closes = |command: &Command| matches!(command, C::JumpBackwardIfNonZero(_) | C::EndProc(_));
+    let mut depth = commands[..start].iter().fold(0usize, |depth, command| {
+        if opens(command) {
+            depth + 1
+        } else if closes(command) {
+            depth.saturating_sub(1)
+        } else {
+            depth
+        }
+    });
+
+    let mut listing = String::with_capacity(16 * (end - start));
+    for (address, command) in commands.iter().enumerate().take(end).skip(start) {
+        if closes(command) {
+            depth = depth.saturating_sub(1);
+        }
+        let _ = write!(listing, "{address:>width$}  ");
+        if let Some(offsets) = offsets {
+            let offset = offsets.get(address).copied().unwrap_or_default();
+            let _ = write!(listing, "@{offset:<offset_width$}  ");
+        }
+        listing.extend(core::iter::repeat_n("  ", depth));
+        push_instruction(&mut listing, command);
+        listing.push('\n');
+        if opens(command) {
+            depth += 1;
+        }
+    }
+    listing
+}
+
+/// Number of decimal digits of `n`.
+fn digits(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+/// Appends the mnemonic and the operands of `command`.
+fn push_instruction(out: &mut String, command: &Command) {
+    use self::Command as C;
+
+    let _ = match *command {
+        C::IncrementDataPointer => write!(out, "right"),
+        C::DecrementDataPointer => write!(out, "left"),
+        C::Increment => write!(out, "inc"),
+        C::Decrement => write!(out, "dec"),
+        C::WriteByte => write!(out, "out"),
+        C::ReadByte => write!(out, "in"),
+        C::JumpForwardIfZero(target) => write!(out, "jz {target}"),
+        C::JumpBackwardIfNonZero(target) => write!(out, "jnz {target}"),
+        C::DebugDump => write!(out, "dump"),
+        C::Random => write!(out, "random"),
+        C::BeginProc(end) => write!(out, "proc {end}"),
+        C::EndProc(start) => write!(out, "ret {start}"),
+        C::Call => write!(out, "call"),
+        C::Add(delta) => write!(out, "add {delta}"),
+        C::MovePointer(offset) => write!(out, "move {offset:+}"),
+        C::Set(value) => write!(out, "set {value}"),
+        C::MulAdd { offset, factor } => write!(out, "mul_add {offset:+} {factor}"),
+        C::ScanRight(stride) => write!(out, "scan_right {stride}"),
+        C::ScanLeft(stride) => write!(out, "scan_left {stride}"),
+        C::AddAt { offset, value } => write!(out, "add_at {offset:+} {value}"),
+        C::SetAt { offset, value } => write!(out, "set_at {offset:+} {value}"),
+        C::OutputAt(offset) => write!(out, "out_at {offset:+}"),
+        C::InputAt(offset) => write!(out, "in_at {offset:+}"),
+        C::ClearRange { len } => write!(out, "clear_range {len:+}"),
+    };
+}
+
+/// Appends what `push` appends, between moves to the cell `offset` away and
+/// back.
+fn push_at(source: &mut String, offset: i32, push: impl FnOnce(&mut String)) {
+    push_run(source, '>', '<', offset.into());
+    push(source);
+    push_run(source, '<', '>', offset.into());
+}
+
+/// Appends a loop of `count` times `ch`.
+fn push_loop(source: &mut String, ch: char, count: usize) {
+    source.push('[');
+    source.extend(core::iter::repeat_n(ch, count));
+    source.push(']');
+}
+
+/// Appends `count` times `up`, or `down` for a negative `count`.
+fn push_run(source: &mut String, up: char, down: char, count: i64) {
+    let ch = if count < 0 { down } else { up };
+    source.extend(core::iter::repeat_n(ch, count.unsigned_abs() as usize));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OverflowPolicy, compile, optimize, run_to_bytes};
+
+    const PROGRAMS: [(&str, &[u8]); 4] = [
+        (
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+            b"",
+        ),
+        (">,[>,]<[<]>[.>]", b"round trip\0"),
+        (
+            // Prints the digits from 0 to 9.
+            ">++++++[<++++++++>-]>++++++++++[<<.+>>-]",
+            b"",
+        ),
+        (
+            "[This comment is skipped, the cell is still zero.] ,[.,]",
+            b"echo",
+        ),
+    ];
+
+    /// Test that decompiled programs compile to the same commands, and that
+    /// optimized ones still do the same.
+    #[test]
+    fn test_round_trip() {
+        for (source_code, input) in PROGRAMS {
+            let program = compile(source_code).unwrap();
+            let source = to_source(&program).unwrap();
+
+            assert_eq!(compile(&source).unwrap(), program);
+            let expected = run_to_bytes(source_code, input).unwrap();
+            assert_eq!(run_to_bytes(&source, input).unwrap(), expected);
+
+            let optimized = to_source(&optimize(&program, OverflowPolicy::Wrap)).unwrap();
+            assert_eq!(run_to_bytes(&optimized, input).unwrap(), expected);
+        }
+    }
+
+    /// Test that hand-built programs are decompiled by their jump addresses.
+    #[test]
+    fn test_hand_built() {
+        use self::Command as C;
+
+        let commands = [
+            C::JumpForwardIfZero(5),
+            C::Decrement,
+            C::IncrementDataPointer,
+            C::Increment,
+            C::DecrementDataPointer,
+            C::JumpBackwardIfNonZero(0),
+        ];
+        assert_eq!(to_source(&commands).unwrap(), "[->+<]");
+        let commands = [C::ClearRange { len: 2 }, C::ClearRange { len: -3 }];
+        assert_eq!(to_source(&commands).unwrap(), "[-]>[-][-]<[-]<[-]");
+
+        let broken = [C::JumpForwardIfZero(3), C::JumpBackwardIfNonZero(0)];
+        assert_eq!(
+            to_source(&broken),
+            Err(JumpError::OutOfRange {
+                address: 0,
+                target: 3
+            })
+        );
+    }
+
+    /// Test the operands of the listing, signs and jumps included.
+    #[test]
+    fn test_ir() {
+        use self::Command as C;
+
+        let commands = [
+            C::ReadByte,
+            C::JumpForwardIfZero(4),
+            C::MulAdd {
+                offset: -1,
+                factor: 3,
+            },
+            C::Set(0),
+            C::JumpBackwardIfNonZero(1),
+            C::AddAt {
+                offset: 2,
+                value: -1,
+            },
+            C::MovePointer(-4),
+            C::ClearRange { len: -3 },
+        ];
+        assert_eq!(
+            to_ir(&commands),
+            "0: in\n1: jz 4\n2: mul_add -1 3\n3: set 0\n4: jnz 1\n5: add_at +2 -1\n6: move -4\n7: clear_range -3\n"
+        );
+        assert_eq!(to_ir(&[]), "");
+    }
+
+    /// Test the indentation, the alignment, and the windows of the listing.
+    #[test]
+    fn test_listing() {
+        let commands = compile("+[>[-]<]").unwrap();
+        let all = 0..usize::MAX;
+        assert_eq!(
+            to_listing(&commands, all.clone(), None),
+            "0  inc\n1  jz 7\n2    right\n3    jz 5\n4      dec\n5    jnz 3\n6    left\n7  jnz 1\n"
+        );
+        assert_eq!(
+            to_listing(&commands, 4..6, None),
+            "4      dec\n5    jnz 3\n"
+        );
+        assert_eq!(to_listing(&commands, 9..12, None), "");
+        let offsets = [0, 10, 11, 12, 13, 14, 15, 16];
+        assert_eq!(
+            to_listing(&commands, 0..2, Some(&offsets)),
+            "0  @0   inc\n1  @10  jz 7\n"
+        );
+
+        let commands = compile(&"+".repeat(11)).unwrap();
+        assert!(to_listing(&commands, all, None).starts_with(" 0  inc\n"));
+    }
+}
@@ -0,0 +1,321 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+
+/// Enum for source code that cannot be translated to Brainfuck.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialectError {
+    /// The program ends in the middle of the pair starting at `token_index`.
+    IncompletePair { token_index: usize },
+    /// The pair starting at `token_index` is not one of the eight commands.
+    UnknownPair { token_index: usize },
+    /// The text at byte `offset` is not a token; only reported by
+    /// [`TokenMap::translate_strict`].
+    UnmatchedText { offset: usize },
+    /// Line `line` of a [`TokenMap`] file, counted from one, is malformed,
+    /// maps to something other than a command, or repeats a token.
+    InvalidMap { line: usize },
+}
+
+impl fmt::Display for DialectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialectError::IncompletePair { token_index } => {
+                write!(f, "incomplete pair starting at token {token_index}")
+            }
+            DialectError::UnknownPair { token_index } => {
+                write!(f, "unknown pair starting at token {token_index}")
+            }
+            DialectError::UnmatchedText { offset } => {
+                write!(f, "text at offset {offset} is not a token of the dialect")
+            }
+            DialectError::InvalidMap { line } => {
+                write!(f, "invalid token map entry on line {line}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DialectError {}
+
+/// Translates an `Ook!` program to Brainfuck, to be passed on to
+/// [`compile`](crate::compile).
+///
+/// Every `Ook` followed by `.`, `?`, or `!` is a token, and every two tokens
+/// make one command, e.g. `Ook. Ook?` for `>` or `Ook! Ook?` for `[`. Text
+/// between the tokens is skipped, stray punctuation included. An `Ook`
+/// without punctuation makes its pair unknown. Tokens are counted from zero.
+pub fn from_ook(source: &str) -> Result<String, DialectError> {
+    let mut marks = source
+        .match_indices("Ook")
+        .map(|(offset, ook)| source[offset + ook.len()..].chars().next());
+    let mut brainfuck = String::new();
+    let mut token_index = 0;
+
+    while let Some(first) = marks.next() {
+        let Some(second) = marks.next() else {
+            return Err(DialectError::IncompletePair { token_index });
+        };
+        let command = match (first, second) {
+            (Some('.'), Some('?')) => '>',
+            (Some('?'), Some('.')) => '<',
+            (Some('.'), Some('.')) => '+',
+            (Some('!'), Some('!')) => '-',
+            (Some('!'), Some('.')) => '.',
+            (Some('.'), Some('!')) => ',',
+            (Some('!'), Some('?')) => '[',
+            (Some('?'), Some('!')) => ']',
+            _ => return Err(DialectError::UnknownPair { token_index }),
+        };
+        brainfuck.push(command);
+        token_index += 2;
+    }
+
+    Ok(brainfuck)
+}
+
+/// Spelling of the eight commands in a dialect that only substitutes them,
+/// like Blub or the many emoji variants.
+///
+/// Source code is split into tokens by longest match, so a token may
+/// start with another one. Text that is not a token is skipped like a
+/// comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenMap {
+    /// Tokens and their commands, longest tokens first.
+    tokens: Vec<(String, char)>,
+}
+
+impl TokenMap {
+    /// Reads a map from a small subset of TOML: one entry per line, with a
+    /// command as the key and a token or an array of tokens as the value.
+    ///
+    /// ```toml
+    /// # Comments and blank lines are allowed.
+    /// "+" = "👍"
+    /// "-" = ["👎", "🙁"]
+    /// ```
+    ///
+    /// Keys and tokens are basic or literal strings on a single line.
+    /// Commands without an entry cannot be written in the dialect.
+    pub fn from_toml(text: &str) -> Result<TokenMap, DialectError> {
+        let mut map = TokenMap::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let invalid = DialectError::InvalidMap { line: i + 1 };
+            let Some(entry) = parse_entry(line) else {
+                return Err(invalid);
+            };
+            let Some((command, tokens)) = entry else {
+                continue;
+            };
+            for token in tokens {
+                if token.is_empty() || map.tokens.iter().any(|(known, _)| *known == token) {
+                    return Err(invalid);
+                }
+                map.tokens.push((token, command));
+            }
+        }
+
+        map.tokens.sort_by_key(|(token, _)| Reverse(token.len()));
+        Ok(map)
+    }
+
+    /// Translates `source` to Brainfuck, to be passed on to
+    /// [`compile`](crate::compile).
+    pub fn translate(&self, source: &str) -> Result<String, DialectError> {
+        self.translate_with(source, false)
+    }
+
+    /// Same as [`TokenMap::translate`], but allows nothing except tokens and
+    /// whitespace, or fails with [`DialectError::UnmatchedText`].
+    pub fn translate_strict(&self, source: &str) -> Result<String, DialectError> {
+        self.translate_with(source, true)
+    }
+
+    fn translate_with(&self, source: &str, strict: bool) -> Result<String, DialectError> {
+        let mut brainfuck = String::new();
+        let mut rest = source;
+
+        while let Some(ch) = rest.chars().next() {
+            let token = self
+                .tokens
+                .iter()
+                .find(|(token, _)| rest.starts_with(token.as_str()));
+            match token {
+                Some((token, command)) => {
+                    brainfuck.push(*command);
+                    rest = &rest[token.len()..];
+                }
+                None if strict && !ch.is_whitespace() => {
+                    let offset = source.len() - rest.len();
+                    return Err(DialectError::UnmatchedText { offset });
+                }
+                None => rest = &rest[ch.len_utf8()..],
+            }
+        }
+
+        Ok(brainfuck)
+    }
+}
+
+/// Parses one line of a [`TokenMap`] file into a command and its tokens.
+/// Returns `Some(None)` for a blank line and `None` for a malformed one.
+fn parse_entry(line: &str) -> Option<Option<(char, Vec<String>)>> {
+    let rest = line.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        return Some(None);
+    }
+
+    let (key, rest) = parse_string(rest)?;
+    let mut key_chars = key.chars();
+    let command = key_chars.next().filter(|ch| "><+-.,[]".contains(*ch))?;
+    if key_chars.next().is_some() {
+        return None;
+    }
+
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let (tokens, rest) = match rest.strip_prefix('[') {
+        Some(mut rest) => {
+            let mut tokens = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    break (tokens, after);
+                }
+                let (token, after) = parse_string(rest)?;
+                tokens.push(token);
+                rest = after.trim_start();
+                match rest.strip_prefix(',') {
+                    Some(after) => rest = after,
+                    None => break (tokens, rest.strip_prefix(']')?),
+                }
+            }
+        }
+        None => {
+            let (token, rest) = parse_string(rest)?;
+            (alloc::vec![token], rest)
+        }
+    };
+
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then_some(Some((command, tokens)))
+}
+
+/// Parses the TOML string at the start of `text` and returns it with the
+/// text after it. Basic strings in double quotes may contain the escapes
+/// `\"`, `\\`, `\n`, `\t`, `\uXXXX`, and `\UXXXXXXXX`; literal strings in
+/// single quotes are taken as they are.
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&ch| ch == '"' || ch == '\'')?;
+    let body = &text[1..];
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+
+    while let Some((i, ch)) = chars.next() {
+        if ch == quote {
+            return Some((value, &body[i + 1..]));
+        }
+        if ch != '\\' || quote == '\'' {
+            value.push(ch);
+            continue;
+        }
+        let escaped = match chars.next()?.1 {
+            '"' => '"',
+            '\\' => '\\',
+            'n' => '\n',
+            't' => '\t',
+            'u' => parse_scalar(&mut chars, 4)?,
+            'U' => parse_scalar(&mut chars, 8)?,
+            _ => return None,
+        };
+        value.push(escaped);
+    }
+
+    None
+}
+
+/// Reads `len` hex digits of a Unicode scalar value.
+fn parse_scalar(chars: &mut impl Iterator<Item = (usize, char)>, len: usize) -> Option<char> {
+    let mut value = 0;
+    for _ in 0..len {
+        value = value * 16 + chars.next()?.1.to_digit(16)?;
+    }
+    char::from_u32(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that punctuation and text between tokens is skipped.
+    #[test]
+    fn test_ook_stray_text() {
+        assert_eq!(
+            from_ook("Ook. Ook? ... Ook!Ook!\nmonkey ?! Ook? Ook!").unwrap(),
+            ">-]"
+        );
+        assert_eq!(from_ook("no ook here").unwrap(), "");
+    }
+
+    /// Test that broken pairs report the index of their first token.
+    #[test]
+    fn test_ook_errors() {
+        assert_eq!(
+            from_ook("Ook. Ook. Ook!"),
+            Err(DialectError::IncompletePair { token_index: 2 })
+        );
+        assert_eq!(
+            from_ook("Ook. Ook. Ook? Ook?"),
+            Err(DialectError::UnknownPair { token_index: 2 })
+        );
+        assert_eq!(
+            from_ook("Ook. Ook. Ook. Ook"),
+            Err(DialectError::UnknownPair { token_index: 2 })
+        );
+    }
+
+    /// Test that the longest token wins, and that strict mode only allows
+    /// tokens and whitespace.
+    #[test]
+    fn test_token_map_longest_match() {
+        let map = TokenMap::from_toml(
+            r#"
+            # Blub, with two spellings of '-'
+            "+" = 'Blub.'
+            "-" = ["Blub!", "Blub!!"]
+
+            "." = "Blub\u0021."  # Blub!.
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(map.translate("Blub. Blub!! Blub!.").unwrap(), "+-.");
+        assert_eq!(map.translate("Blub!Blub!!!").unwrap(), "--");
+        assert_eq!(map.translate("Blub? Blub.").unwrap(), "+");
+        assert_eq!(
+            map.translate_strict("Blub.\n Blub? Blub."),
+            Err(DialectError::UnmatchedText { offset: 7 })
+        );
+    }
+
+    /// Test that malformed entries report their line.
+    #[test]
+    fn test_token_map_errors() {
+        for (text, line) in [
+            ("\"+\" = \"a\"\n\"x\" = \"b\"", 2),
+            ("\"+\" = \"a\"\n\"-\" = \"a\"", 2),
+            ("\"+\" = \"\"", 1),
+            ("\"+\" = [\"a\", \"b\"", 1),
+            ("\n\n\"+\" = \"a\" trailing", 3),
+            ("\"+-\" = \"a\"", 1),
+        ] {
+            assert_eq!(
+                TokenMap::from_toml(text),
+                Err(DialectError::InvalidMap { line }),
+                "{text}"
+            );
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use alloc::string::FromUtf8Error;
+use core::fmt;
+use core::time::Duration;
+
+use crate::{ConfigError, DialectError, IoError, MacroError, ParsingError};
+
+/// Enum for everything that can go wrong between source code and output.
+#[derive(Debug)]
+pub enum Error {
+    /// The source code could not be compiled.
+    Parse(ParsingError),
+    /// The source code could not be translated from another dialect, e.g.
+    /// by [`from_ook`](crate::from_ook).
+    Dialect(DialectError),
+    /// The macros in the source code could not be expanded by
+    /// [`expand_macros`](crate::expand_macros).
+    Macro(MacroError),
+    /// The interpreter settings are inconsistent.
+    Config(ConfigError),
+    /// The program did something the interpreter does not allow.
+    Runtime(RuntimeError),
+    /// Reading input or writing output failed.
+    Io(IoError),
+    /// The program ran, but its output is not valid UTF-8.
+    /// The raw output is available through [`FromUtf8Error::into_bytes`].
+    Utf8(FromUtf8Error),
+}
+
+/// Enum for failures while a program is running.
+/// Every variant records the index of the command that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuntimeError {
+    /// The data pointer was moved to `pointer`, which is outside the tape.
+    PointerOutOfBounds {
+        instruction_index: usize,
+        pointer: isize,
+    },
+    /// The tape would need room for `requested_cells` cells, which is more
+    /// than its cell or memory limit allows.
+    MemoryLimitExceeded {
+        instruction_index: usize,
+        requested_cells: usize,
+    },
+    /// A `+` or `-` left the cell range under [`OverflowPolicy::Error`](crate::OverflowPolicy::Error).
+    CellOverflow { instruction_index: usize },
+    /// The program was still running after executing `steps` commands.
+    StepLimitExceeded {
+        instruction_index: usize,
+        steps: u64,
+    },
+    /// The command at `instruction_index` would have written more than
+    /// `limit` bytes.
+    OutputLimitExceeded {
+        instruction_index: usize,
+        limit: u64,
+    },
+    /// The program was still running after `elapsed` time, in which it
+    /// executed `steps` commands.
+    Timeout {
+        instruction_index: usize,
+        elapsed: Duration,
+        steps: u64,
+    },
+    /// A `.` under [`IoMode::Utf8`](crate::IoMode::Utf8) found `value` in the
+    /// cell, which is a surrogate or above U+10FFFF.
+    InvalidScalarValue {
+        instruction_index: usize,
+        value: i64,
+    },
+    /// A pbrain `:` called `procedure`, which no `(` has defined yet.
+    UndefinedProcedure {
+        instruction_index: usize,
+        procedure: i64,
+    },
+    /// A pbrain `:` would have nested more than `limit` procedure calls.
+    CallStackOverflow {
+        instruction_index: usize,
+        limit: usize,
+    },
+}
+
+impl RuntimeError {
+    /// Index of the command that failed.
+    pub fn instruction_index(&self) -> usize {
+        match self {
+            RuntimeError::PointerOutOfBounds {
+                instruction_index, ..
+            }
+            | RuntimeError::MemoryLimitExceeded {
+                instruction_index, ..
+            }
+            | RuntimeError::CellOverflow { instruction_index }
+            | RuntimeError::StepLimitExceeded {
+                instruction_index, ..
+            }
+            | RuntimeError::OutputLimitExceeded {
+                instruction_index, ..
+            }
+            | RuntimeError::Timeout {
+                instruction_index, ..
+            }
+            | RuntimeError::InvalidScalarValue {
+                instruction_index, ..
+            }
+            | RuntimeError::UndefinedProcedure {
+                instruction_index, ..
+            }
+            | RuntimeError::CallStackOverflow {
+                instruction_index, ..
+            } => *instruction_index,
+        }
+    }
+}
+
+impl ParsingError {
+    /// Byte offset in the source that the error points at.
+    pub fn offset(&self) -> usize {
+        match *self {
+            ParsingError::UnmatchedBracket { offset, .. }
+            | ParsingError::UnexpectedCharacter { offset, .. }
+            | ParsingError::NestingTooDeep { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsingError::UnmatchedBracket { offset, bracket } => {
+                write!(f, "unmatched '{bracket}' at offset {offset}")
+            }
+            ParsingError::UnexpectedCharacter { offset, ch } => write!(
+                f,
+                "unexpected character '{ch}' (U+{:04X}) at offset {offset}",
+                u32::from(*ch)
+            ),
+            ParsingError::NestingTooDeep { offset, depth } => write!(
+                f,
+                "'[' at offset {offset} nests loops {depth} deep, more than the limit"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::EmptyTape => write!(f, "the tape must have at least one cell"),
+            ConfigError::DataPointerOutOfRange {
+                data_pointer,
+                tape_len,
+            } => write!(
+                f,
+                "data pointer {data_pointer} is outside of the {tape_len}-cell tape"
+            ),
+            ConfigError::TapeInitTooLong { end, tape_len } => write!(
+                f,
+                "initial tape data needs {end} cells, but the tape has {tape_len}"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::PointerOutOfBounds {
+                instruction_index,
+                pointer,
+            } => write!(
+                f,
+                "data pointer moved out of the tape to cell {pointer} at instruction {instruction_index}"
+            ),
+            RuntimeError::MemoryLimitExceeded {
+                instruction_index,
+                requested_cells,
+            } => write!(
+                f,
+                "tape would need {requested_cells} cells, more than its memory limit allows, at instruction {instruction_index}"
+            ),
+            RuntimeError::CellOverflow { instruction_index } => {
+                write!(f, "cell overflowed at instruction {instruction_index}")
+            }
+            RuntimeError::StepLimitExceeded {
+                instruction_index,
+                steps,
+            } => write!(
+                f,
+                "step limit of {steps} exceeded at instruction {instruction_index}"
+            ),
+            RuntimeError::OutputLimitExceeded {
+                instruction_index,
+                limit,
+            } => write!(
+                f,
+                "output limit of {limit} bytes exceeded at instruction {instruction_index}"
+            ),
+            RuntimeError::Timeout {
+                instruction_index,
+                elapsed,
+                steps,
+            } => write!(
+                f,
+                "timed out after {elapsed:?} and {steps} steps at instruction {instruction_index}"
+            ),
+            RuntimeError::InvalidScalarValue {
+                instruction_index,
+                value,
+            } => write!(
+                f,
+                "cell value {value} is not a Unicode scalar value at instruction {instruction_index}"
+            ),
+            RuntimeError::UndefinedProcedure {
+                instruction_index,
+                procedure,
+            } => write!(
+                f,
+                "procedure {procedure} is not defined at instruction {instruction_index}"
+            ),
+            RuntimeError::CallStackOverflow {
+                instruction_index,
+                limit,
+            } => write!(
+                f,
+                "call depth limit of {limit} exceeded at instruction {instruction_index}"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "parse error: {e}"),
+            Error::Dialect(e) => write!(f, "parse error: {e}"),
+            Error::Macro(e) => write!(f, "parse error: {e}"),
+            Error::Config(e) => write!(f, "invalid configuration: {e}"),
+            Error::Runtime(e) => write!(f, "runtime error: {e}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Utf8(e) => write!(f, "output is not valid UTF-8: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for ParsingError {}
+
+impl core::error::Error for ConfigError {}
+
+impl core::error::Error for RuntimeError {}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::Dialect(e) => Some(e),
+            Error::Macro(e) => Some(e),
+            Error::Config(e) => Some(e),
+            Error::Runtime(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParsingError> for Error {
+    fn from(e: ParsingError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<DialectError> for Error {
+    fn from(e: DialectError) -> Self {
+        Error::Dialect(e)
+    }
+}
+
+impl From<MacroError> for Error {
+    fn from(e: MacroError) -> Self {
+        Error::Macro(e)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Error::Runtime(e)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(e: IoError) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::Utf8(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<RuntimeError> for std::io::Error {
+    /// Lets runtime errors travel through `Read` and `Write` implementations.
+    fn from(e: RuntimeError) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, compile};
+
+    /// Test the messages users see for each kind of error.
+    #[test]
+    fn test_messages() {
+        let error = Error::from(compile("+[.].]").unwrap_err());
+        assert_eq!(error.to_string(), "parse error: unmatched ']' at offset 5");
+
+        let error = Error::from(compile("  [+").unwrap_err());
+        assert_eq!(error.to_string(), "parse error: unmatched '[' at offset 2");
+
+        let error = Error::from(Interpreter::builder().tape_len(0).build().unwrap_err());
+        assert_eq!(
+            error.to_string(),
+            "invalid configuration: the tape must have at least one cell"
+        );
+
+        let error = Error::from(RuntimeError::PointerOutOfBounds {
+            instruction_index: 3,
+            pointer: -1,
+        });
+        assert_eq!(
+            error.to_string(),
+            "runtime error: data pointer moved out of the tape to cell -1 at instruction 3"
+        );
+    }
+
+    /// Test that `?` converts every error kind.
+    #[test]
+    fn test_question_mark() {
+        fn run(source: &str, tape_len: usize) -> Result<(), Error> {
+            let interpreter = Interpreter::builder()
+                .tape_len(tape_len)
+                .data_pointer(0)
+                .build()?;
+            let program = compile(source)?;
+            interpreter.run(&program, std::io::empty(), std::io::sink())?;
+            Ok(())
+        }
+
+        assert!(matches!(run("[", 1), Err(Error::Parse(_))));
+        assert!(matches!(run("", 0), Err(Error::Config(_))));
+        assert!(matches!(run(">>", 2), Err(Error::Runtime(_))));
+        assert!(run(">", 2).is_ok());
+    }
+}
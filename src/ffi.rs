@@ -0,0 +1,213 @@
+//! C interface for embedding the interpreter in other languages.
+//!
+//! Every function returns one of the `BF_*` status codes. Panics never cross
+//! the boundary; they are reported as [`BF_ERR_PANIC`]. The matching header
+//! is `include/brainfuck_vm.h`.
+
+use std::ffi::{CStr, c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::{Command, Error, compile, eval};
+
+/// The call succeeded.
+pub const BF_OK: c_int = 0;
+/// A required pointer argument was null.
+pub const BF_ERR_NULL: c_int = 1;
+/// The source code is not valid UTF-8.
+pub const BF_ERR_ENCODING: c_int = 2;
+/// The source code has unmatched brackets.
+pub const BF_ERR_PARSE: c_int = 3;
+/// The program failed while running.
+pub const BF_ERR_RUNTIME: c_int = 4;
+/// Reading input or writing output failed.
+pub const BF_ERR_IO: c_int = 5;
+/// The interpreter panicked.
+pub const BF_ERR_PANIC: c_int = 6;
+
+/// Compiled program, opaque to C callers.
+#[derive(Debug)]
+pub struct BfProgram {
+    commands: Vec<Command>,
+}
+
+/// Output buffer filled by [`bf_run`] and released with [`bf_free_output`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct BfOutput {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(BF_ERR_PANIC)
+}
+
+fn error_code(e: &Error) -> c_int {
+    match e {
+        Error::Parse(_) | Error::Dialect(_) | Error::Macro(_) => BF_ERR_PARSE,
+        Error::Runtime(_) | Error::Config(_) => BF_ERR_RUNTIME,
+        Error::Io(_) => BF_ERR_IO,
+        Error::Utf8(_) => BF_ERR_ENCODING,
+    }
+}
+
+/// Compiles the NUL-terminated `src` and stores the program in `*out`.
+///
+/// # Safety
+///
+/// `src` must be null or a valid NUL-terminated string, and `out` must be
+/// null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_compile(src: *const c_char, out: *mut *mut BfProgram) -> c_int {
+    guard(|| {
+        if src.is_null() || out.is_null() {
+            return BF_ERR_NULL;
+        }
+        let Ok(source) = unsafe { CStr::from_ptr(src) }.to_str() else {
+            return BF_ERR_ENCODING;
+        };
+        match compile(source) {
+            Ok(commands) => {
+                let program = Box::new(BfProgram { commands });
+                unsafe { *out = Box::into_raw(program) };
+                BF_OK
+            }
+            Err(e) => error_code(&e.into()),
+        }
+    })
+}
+
+/// Runs `program` with `len` bytes of `input` and stores its output in `*out`.
+///
+/// Reads past the end of the input store zero. On failure `*out` is left
+/// empty.
+///
+/// # Safety
+///
+/// `program` must come from [`bf_compile`], `input` must be valid for `len`
+/// reads (it may be null when `len` is zero), and `out` must be valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_run(
+    program: *const BfProgram,
+    input: *const u8,
+    len: usize,
+    out: *mut BfOutput,
+) -> c_int {
+    guard(|| {
+        if program.is_null() || out.is_null() || (input.is_null() && len > 0) {
+            return BF_ERR_NULL;
+        }
+        let input = if len == 0 {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(input, len) }
+        };
+        unsafe {
+            *out = BfOutput {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+        }
+
+        let mut output = Vec::new();
+        if let Err(e) = eval(unsafe { &(*program).commands }, input, &mut output) {
+            return error_code(&e);
+        }
+
+        let output = Box::into_raw(output.into_boxed_slice());
+        unsafe {
+            *out = BfOutput {
+                data: output.cast(),
+                len: output.len(),
+            };
+        }
+        BF_OK
+    })
+}
+
+/// Releases a program returned by [`bf_compile`]. Null is ignored.
+///
+/// # Safety
+///
+/// `program` must be null or come from [`bf_compile`], and must not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_free_program(program: *mut BfProgram) {
+    if !program.is_null() {
+        drop(unsafe { Box::from_raw(program) });
+    }
+}
+
+/// Releases the buffer of an output filled by [`bf_run`] and resets it to
+/// empty. Null and empty outputs are ignored.
+///
+/// # Safety
+///
+/// `output` must be null or point to an output filled by [`bf_run`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_free_output(output: *mut BfOutput) {
+    let Some(output) = (unsafe { output.as_mut() }) else {
+        return;
+    };
+    if !output.data.is_null() {
+        let buffer = ptr::slice_from_raw_parts_mut(output.data, output.len);
+        drop(unsafe { Box::from_raw(buffer) });
+    }
+    output.data = ptr::null_mut();
+    output.len = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test compiling, running, and freeing through the C functions.
+    #[test]
+    fn test_round_trip() {
+        let mut program = ptr::null_mut();
+        let mut output = BfOutput {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+
+        unsafe {
+            assert_eq!(bf_compile(c",[.,]".as_ptr(), &mut program), BF_OK);
+            assert_eq!(bf_run(program, b"ffi".as_ptr(), 3, &mut output), BF_OK);
+            assert_eq!(std::slice::from_raw_parts(output.data, output.len), b"ffi");
+            bf_free_output(&mut output);
+            bf_free_program(program);
+        }
+        assert!(output.data.is_null());
+    }
+
+    /// Test the status codes reported for bad arguments and failing programs.
+    #[test]
+    fn test_error_codes() {
+        let mut program = ptr::null_mut();
+        let mut output = BfOutput {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+
+        unsafe {
+            assert_eq!(bf_compile(ptr::null(), &mut program), BF_ERR_NULL);
+            assert_eq!(bf_compile(c"+]".as_ptr(), &mut program), BF_ERR_PARSE);
+            assert_eq!(bf_compile(c"\xff".as_ptr(), &mut program), BF_ERR_ENCODING);
+            assert!(program.is_null());
+
+            assert_eq!(bf_compile(c"+[<+]".as_ptr(), &mut program), BF_OK);
+            assert_eq!(bf_run(program, ptr::null(), 1, &mut output), BF_ERR_NULL);
+            assert_eq!(bf_run(program, ptr::null(), 0, &mut output), BF_ERR_RUNTIME);
+            assert!(output.data.is_null());
+            bf_free_program(program);
+        }
+    }
+
+    /// Test that a panic is turned into a status code.
+    #[test]
+    fn test_panic() {
+        assert_eq!(guard(|| panic!("boom")), BF_ERR_PANIC);
+    }
+}
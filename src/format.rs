@@ -0,0 +1,234 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{ParsingError, compile};
+
+/// The eight command characters.
+const COMMANDS: &str = "><+-.,[]";
+
+/// Removes every character that is not a command.
+pub fn strip_comments(source: &str) -> String {
+    source.chars().filter(|&ch| COMMANDS.contains(ch)).collect()
+}
+
+/// Removes every character that is not a command and, from `level` 2 on,
+/// code that cannot change what the program does.
+///
+/// Level 2 cancels adjacent `+-`, `-+`, `<>`, and `><`, and removes loops
+/// that are never entered: loops right after another `]`, and loops before
+/// the first `+`, `-`, or `,`, when every cell is still zero. This keeps the
+/// behavior of programs that run on a zeroed tape with wrapping cells and
+/// never move left of the first cell; with other settings a cancelled pair
+/// may have saturated a cell or failed. The output is plain Brainfuck.
+///
+/// Level 2 needs matching brackets, so it fails like [`compile`] if they do
+/// not match.
+pub fn minify(source: &str, level: u8) -> Result<String, ParsingError> {
+    let stripped = strip_comments(source);
+    if level < 2 {
+        return Ok(stripped);
+    }
+    compile(source)?;
+
+    let mut minified = Vec::with_capacity(stripped.len());
+    // `+`, `-`, and `,` left in `minified`; loops are dead while it is 0.
+    let mut changes = 0_usize;
+    let mut commands = stripped.bytes();
+    while let Some(command) = commands.next() {
+        let inverse = match command {
+            b'+' => b'-',
+            b'-' => b'+',
+            b'<' => b'>',
+            b'>' => b'<',
+            _ => 0,
+        };
+        if command == b'[' && (changes == 0 || minified.last() == Some(&b']')) {
+            let mut depth = 1;
+            while depth > 0 {
+                match commands.next() {
+                    Some(b'[') => depth += 1,
+                    Some(b']') => depth -= 1,
+                    _ => {}
+                }
+            }
+        } else if minified.last() == Some(&inverse) {
+            minified.pop();
+            changes -= usize::from(matches!(command, b'+' | b'-'));
+        } else {
+            minified.push(command);
+            changes += usize::from(matches!(command, b'+' | b'-' | b','));
+        }
+    }
+
+    // Only ASCII commands were pushed.
+    Ok(String::from_utf8(minified).unwrap())
+}
+
+/// Piece of source code that the formatter never splits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    /// One or more of the same command other than a bracket.
+    Run(&'a str),
+    Open,
+    Close,
+    /// Text between whitespace that contains no command.
+    Word(&'a str),
+}
+
+/// Re-flows Brainfuck source code by loop depth, so it can be read.
+///
+/// Every `[` ends its line and indents the lines of the loop body by
+/// `indent` more spaces, and every `]` starts a line at the depth of its
+/// `[`. Runs of the same command, like `+++`, stay together, and lines
+/// wrap before they get longer than `width` unless a single run does not
+/// fit. Comments are kept in place, with their whitespace collapsed to
+/// single spaces.
+///
+/// Only whitespace changes, so the program behaves exactly as before, and
+/// formatting the output again changes nothing.
+pub fn format_source(source: &str, width: usize, indent: usize) -> String {
+    let mut formatted = String::new();
+    let mut line = String::new();
+    let mut depth = 0_usize;
+    let mut last = None;
+
+    for token in tokenize(source) {
+        if token == Token::Close {
+            depth = depth.saturating_sub(1);
+            end_line(&mut formatted, &mut line);
+            last = None;
+        }
+        if line.is_empty() {
+            line.extend(core::iter::repeat_n(' ', depth * indent));
+        }
+
+        let text = match token {
+            Token::Run(text) | Token::Word(text) => text,
+            Token::Open => "[",
+            Token::Close => "]",
+        };
+        let spaced = matches!(token, Token::Word(_)) || matches!(last, Some(Token::Word(_)));
+        let separator = if spaced && last.is_some() { " " } else { "" };
+        if last.is_some() && line.len() + separator.len() + text.len() > width {
+            end_line(&mut formatted, &mut line);
+            line.extend(core::iter::repeat_n(' ', depth * indent));
+        } else {
+            line.push_str(separator);
+        }
+        line.push_str(text);
+        last = Some(token);
+
+        if token == Token::Open {
+            depth += 1;
+            end_line(&mut formatted, &mut line);
+            last = None;
+        }
+    }
+
+    end_line(&mut formatted, &mut line);
+    formatted
+}
+
+/// Moves `line` to the output, unless it is empty.
+fn end_line(formatted: &mut String, line: &mut String) {
+    if !line.trim_start().is_empty() {
+        formatted.push_str(line);
+        formatted.push('\n');
+    }
+    line.clear();
+}
+
+/// Splits source code into runs, brackets, and comment words, dropping
+/// whitespace.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(ch) = rest.chars().next() {
+        let len = if ch.is_whitespace() {
+            ch.len_utf8()
+        } else if ch == '[' || ch == ']' {
+            tokens.push(if ch == '[' { Token::Open } else { Token::Close });
+            1
+        } else if COMMANDS.contains(ch) {
+            let len = rest.find(|next| next != ch).unwrap_or(rest.len());
+            tokens.push(Token::Run(&rest[..len]));
+            len
+        } else {
+            let len = rest
+                .find(|next: char| next.is_whitespace() || COMMANDS.contains(next))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..len]));
+            len
+        };
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_to_string;
+
+    const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+        >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    /// Test that loops are indented and the program still runs the same.
+    #[test]
+    fn test_format_hello_world() {
+        let formatted = format_source(HELLO_WORLD, 40, 2);
+        assert_eq!(
+            formatted,
+            "++++++++[\n\
+            \x20 >++++[\n\
+            \x20   >++>+++>+++>+<<<<-\n\
+            \x20 ]>+>+>->>+[\n\
+            \x20   <\n\
+            \x20 ]<-\n\
+            ]>>.>---.+++++++..+++.>>.<-.<.+++.------\n\
+            .--------.>>+.>++.\n"
+        );
+        assert_eq!(compile(&formatted), compile(HELLO_WORLD));
+        assert_eq!(run_to_string(&formatted, &[]).unwrap(), "Hello World!\n");
+        assert_eq!(format_source(&formatted, 40, 2), formatted);
+    }
+
+    /// Test that comments stay between the commands around them, and that
+    /// a run longer than the width is not split.
+    #[test]
+    fn test_format_comments() {
+        let source = "set   two\n++ and loop[-]\tdone";
+        let formatted = format_source(source, 80, 4);
+        assert_eq!(formatted, "set two ++ and loop [\n    -\n] done\n");
+        assert_eq!(format_source(&formatted, 80, 4), formatted);
+        assert_eq!(strip_comments(source), "++[-]");
+
+        assert_eq!(format_source("+++++>", 3, 2), "+++++\n>\n");
+        assert_eq!(format_source("", 80, 2), "");
+    }
+
+    /// Test that level 2 cancels pairs and dead loops but keeps behavior.
+    #[test]
+    fn test_minify() {
+        let source =
+            String::from("[ prints . and , ] hello: +-\n") + HELLO_WORLD + "[-][never] ><<>";
+        assert_eq!(
+            minify(&source, 1).unwrap(),
+            String::from("[.,]+-") + HELLO_WORLD + "[-][]><<>"
+        );
+        let minified = minify(&source, 2).unwrap();
+        assert_eq!(minified, String::from(HELLO_WORLD) + "[-]");
+        assert_eq!(run_to_string(&minified, &[]).unwrap(), "Hello World!\n");
+
+        assert_eq!(minify("+>+-<-", 2).unwrap(), "");
+        assert_eq!(minify(",[-][-]>+[+-[-]]", 2).unwrap(), ",[-]>+[[-]]");
+        assert_eq!(minify("++--[.]>[.]+", 2).unwrap(), ">+");
+        assert!(matches!(
+            minify("x[", 2),
+            Err(ParsingError::UnmatchedBracket { offset: 1, .. })
+        ));
+        assert_eq!(minify("x[", 1).unwrap(), "[");
+    }
+}
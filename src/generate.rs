@@ -0,0 +1,82 @@
+use alloc::string::String;
+
+/// Longest multiplication loop factor tried by [`generate_printer`].
+const MAX_FACTOR: usize = 16;
+
+/// Generates a Brainfuck program that prints `bytes`.
+///
+/// The program keeps the last printed byte in the first cell and moves it
+/// to the next byte by the shortest wrapping delta, using the second cell
+/// as a loop counter to multiply large deltas. It relies on 8-bit cells
+/// that wrap around, and the counter is back at zero once the program ends.
+pub fn generate_printer(bytes: &[u8]) -> String {
+    let mut program = String::new();
+    let mut current = 0_u8;
+
+    for &byte in bytes {
+        let delta = byte.wrapping_sub(current) as i8;
+        let command = if delta < 0 { '-' } else { '+' };
+        push_delta(&mut program, usize::from(delta.unsigned_abs()), command);
+        program.push('.');
+        current = byte;
+    }
+
+    program
+}
+
+/// Appends the shortest code that applies `command` `count` times to the
+/// current cell.
+fn push_delta(program: &mut String, count: usize, command: char) {
+    // `>` + `a` times `[<` + `b` times `>-]<` + `r` times adds `a * b + r`.
+    let looped = (2..=MAX_FACTOR)
+        .map(|a| (a, count / a, count % a))
+        .filter(|&(_, b, _)| b > 0)
+        .min_by_key(|&(a, b, r)| a + b + r);
+    match looped {
+        Some((a, b, r)) if a + b + r + 6 < count => {
+            program.push('>');
+            push_repeated(program, '+', a);
+            program.push_str("[<");
+            push_repeated(program, command, b);
+            program.push_str(">-]<");
+            push_repeated(program, command, r);
+        }
+        _ => push_repeated(program, command, count),
+    }
+}
+
+fn push_repeated(program: &mut String, command: char, count: usize) {
+    program.extend(core::iter::repeat_n(command, count));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_to_bytes;
+
+    /// Test that the generated programs print exactly the requested bytes.
+    #[test]
+    fn test_round_trip() {
+        let all_bytes: alloc::vec::Vec<u8> = (0..=255).rev().collect();
+        for bytes in [
+            &b"Hello, World!\n"[..],
+            "caf\u{e9} \u{1f600}".as_bytes(),
+            &[0, 255, 128, 127, 0x80, 1],
+            &all_bytes,
+            &[],
+        ] {
+            let program = generate_printer(bytes);
+            assert_eq!(run_to_bytes(&program, &[]).unwrap(), bytes, "{program}");
+        }
+        assert_eq!(generate_printer(&[]), "");
+    }
+
+    /// Test that large deltas use a loop and small ones do not.
+    #[test]
+    fn test_short_output() {
+        assert_eq!(generate_printer(b"\x03\x01"), "+++.--.");
+        assert_eq!(generate_printer(b"H"), ">++++++++[<+++++++++>-]<.");
+        // Only `+`, `-`, and `.` would take 390 commands.
+        assert_eq!(generate_printer(b"Hello, World!\n").len(), 205);
+    }
+}
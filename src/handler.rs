@@ -0,0 +1,427 @@
+use crate::DebugDump;
+use crate::bytes::{ByteSink, ByteSource, IoError, invalid_data};
+
+/// Callbacks invoked by the interpreter for `,` and `.`.
+pub trait IoHandler {
+    /// Supplies the byte for `,`.
+    /// `None` signals end of input, which is handled like EOF on a stream.
+    fn input(&mut self) -> Result<Option<u8>, IoError>;
+
+    /// Consumes the byte written by `.`.
+    fn output(&mut self, byte: u8) -> Result<(), IoError>;
+
+    /// Supplies the value for `,`, which is reduced modulo the cell size.
+    /// Defaults to the byte from [`IoHandler::input`].
+    fn input_value(&mut self) -> Result<Option<u64>, IoError> {
+        Ok(self.input()?.map(u64::from))
+    }
+
+    /// Consumes the value of the cell written by `.`.
+    /// Defaults to passing its low byte to [`IoHandler::output`].
+    fn output_value(&mut self, value: i64) -> Result<(), IoError> {
+        self.output(value as u8)
+    }
+
+    /// Reports the tape when a `#` executes, see
+    /// [`compile_with_debug_dumps`](crate::compile_with_debug_dumps).
+    /// Defaults to writing `dump` as one line to stderr, or to doing
+    /// nothing without the `std` feature.
+    fn debug_dump(&mut self, dump: &DebugDump) -> Result<(), IoError> {
+        write_to_stderr(dump)
+    }
+}
+
+/// Writes `dump` as one line to stderr, where there is one.
+fn write_to_stderr(dump: &DebugDump) -> Result<(), IoError> {
+    #[cfg(feature = "std")]
+    {
+        use std::io::Write;
+        writeln!(std::io::stderr(), "{dump}")
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = dump;
+        Ok(())
+    }
+}
+
+impl<H: IoHandler + ?Sized> IoHandler for &mut H {
+    fn input(&mut self) -> Result<Option<u8>, IoError> {
+        (**self).input()
+    }
+
+    fn output(&mut self, byte: u8) -> Result<(), IoError> {
+        (**self).output(byte)
+    }
+
+    fn input_value(&mut self) -> Result<Option<u64>, IoError> {
+        (**self).input_value()
+    }
+
+    fn output_value(&mut self, value: i64) -> Result<(), IoError> {
+        (**self).output_value(value)
+    }
+
+    fn debug_dump(&mut self, dump: &DebugDump) -> Result<(), IoError> {
+        (**self).debug_dump(dump)
+    }
+}
+
+/// Adapter that serves `,` from a [`ByteSource`] and `.` into a [`ByteSink`],
+/// e.g. a `Read` and a `Write`.
+///
+/// The writer is flushed before every read that follows output, so a prompt
+/// shows up before the program waits on its answer, even through a
+/// `BufWriter`.
+#[derive(Debug)]
+pub struct Streams<R, W> {
+    reader: R,
+    writer: W,
+    /// Whether bytes were written since the last flush.
+    unflushed: bool,
+    echo: bool,
+}
+
+impl<R: ByteSource, W: ByteSink> Streams<R, W> {
+    /// Wraps a reader for `,` and a writer for `.`.
+    pub fn new(reader: R, writer: W) -> Self {
+        Streams {
+            reader,
+            writer,
+            unflushed: false,
+            echo: false,
+        }
+    }
+
+    /// Makes every byte read for `,` also go to the writer, which is flushed
+    /// right away, like a terminal echoing keystrokes. Echoed bytes do not
+    /// count as output of the program, e.g. for
+    /// [`ExecutionReport::bytes_written`](crate::ExecutionReport::bytes_written)
+    /// or the output limit.
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Returns the wrapped reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: ByteSource, W: ByteSink> IoHandler for Streams<R, W> {
+    fn input(&mut self) -> Result<Option<u8>, IoError> {
+        if self.unflushed {
+            self.writer.flush()?;
+            self.unflushed = false;
+        }
+        let byte = self.reader.read_byte()?;
+        if let Some(byte) = byte.filter(|_| self.echo) {
+            self.writer.write_byte(byte)?;
+            self.writer.flush()?;
+        }
+        Ok(byte)
+    }
+
+    fn output(&mut self, byte: u8) -> Result<(), IoError> {
+        self.unflushed = true;
+        self.writer.write_byte(byte)
+    }
+
+    /// Flushes the output so far first, so that both appear in order when
+    /// stdout and stderr share a terminal.
+    fn debug_dump(&mut self, dump: &DebugDump) -> Result<(), IoError> {
+        if self.unflushed {
+            self.writer.flush()?;
+            self.unflushed = false;
+        }
+        write_to_stderr(dump)
+    }
+}
+
+/// Handler built from a pair of closures, see [`io_handler_fn`].
+#[derive(Debug)]
+pub struct FnHandler<I, O> {
+    input: I,
+    output: O,
+}
+
+/// Creates an [`IoHandler`] that calls `input` for `,` and `output` for `.`.
+pub fn io_handler_fn<I, O>(input: I, output: O) -> FnHandler<I, O>
+where
+    I: FnMut() -> Result<Option<u8>, IoError>,
+    O: FnMut(u8) -> Result<(), IoError>,
+{
+    FnHandler { input, output }
+}
+
+impl<I, O> IoHandler for FnHandler<I, O>
+where
+    I: FnMut() -> Result<Option<u8>, IoError>,
+    O: FnMut(u8) -> Result<(), IoError>,
+{
+    fn input(&mut self) -> Result<Option<u8>, IoError> {
+        (self.input)()
+    }
+
+    fn output(&mut self, byte: u8) -> Result<(), IoError> {
+        (self.output)(byte)
+    }
+}
+
+/// Adapter that makes `,` and `.` exchange decimal numbers instead of bytes.
+///
+/// `.` writes the cell value, e.g. `-1` or `65`, followed by the separator.
+/// `,` skips ASCII whitespace and parses the next integer, optionally
+/// negative, up to the following whitespace or end of input. Anything else
+/// fails with an I/O error. End of input before a number is handled like
+/// EOF on a byte stream.
+#[derive(Debug)]
+pub struct DecimalIo<H> {
+    inner: H,
+    separator: u8,
+}
+
+impl<H: IoHandler> DecimalIo<H> {
+    /// Exchanges numbers through the bytes of `inner`, writing `separator`
+    /// after every number.
+    pub fn new(inner: H, separator: u8) -> Self {
+        DecimalIo { inner, separator }
+    }
+
+    /// Returns the wrapped handler.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H: IoHandler> IoHandler for DecimalIo<H> {
+    fn input(&mut self) -> Result<Option<u8>, IoError> {
+        self.inner.input()
+    }
+
+    fn output(&mut self, byte: u8) -> Result<(), IoError> {
+        self.inner.output(byte)
+    }
+
+    fn input_value(&mut self) -> Result<Option<u64>, IoError> {
+        let first = loop {
+            match self.inner.input()? {
+                Some(byte) if byte.is_ascii_whitespace() => continue,
+                Some(byte) => break byte,
+                None => return Ok(None),
+            }
+        };
+        let negative = first == b'-';
+        let mut next = if negative {
+            self.inner.input()?
+        } else {
+            Some(first)
+        };
+
+        let mut value = 0_u64;
+        let mut digits = 0;
+        while let Some(byte) = next.filter(|byte| !byte.is_ascii_whitespace()) {
+            if !byte.is_ascii_digit() {
+                return Err(malformed_number());
+            }
+            // Wrapping keeps the value correct modulo every cell size.
+            value = value.wrapping_mul(10).wrapping_add(u64::from(byte - b'0'));
+            digits += 1;
+            next = self.inner.input()?;
+        }
+        if digits == 0 {
+            return Err(malformed_number());
+        }
+        Ok(Some(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        }))
+    }
+
+    fn output_value(&mut self, value: i64) -> Result<(), IoError> {
+        let mut digits = [0; 20];
+        let mut len = 0;
+        let mut rest = value.unsigned_abs();
+        loop {
+            digits[len] = b'0' + (rest % 10) as u8;
+            len += 1;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+
+        if value < 0 {
+            self.inner.output(b'-')?;
+        }
+        for &digit in digits[..len].iter().rev() {
+            self.inner.output(digit)?;
+        }
+        self.inner.output(self.separator)
+    }
+
+    fn debug_dump(&mut self, dump: &DebugDump) -> Result<(), IoError> {
+        self.inner.debug_dump(dump)
+    }
+}
+
+fn malformed_number() -> IoError {
+    invalid_data("expected a decimal number")
+}
+
+/// Reads one UTF-8 encoded character from `handler`, or `None` at the end
+/// of input.
+pub(crate) fn read_utf8<H: IoHandler + ?Sized>(handler: &mut H) -> Result<Option<char>, IoError> {
+    let Some(first) = handler.input()? else {
+        return Ok(None);
+    };
+    let len = match first.leading_ones() {
+        0 => 1,
+        2..=4 => first.leading_ones() as usize,
+        _ => return Err(invalid_utf8()),
+    };
+    let mut bytes = [first, 0, 0, 0];
+    for byte in &mut bytes[1..len] {
+        *byte = handler.input()?.ok_or_else(invalid_utf8)?;
+    }
+    match core::str::from_utf8(&bytes[..len]) {
+        Ok(text) => Ok(text.chars().next()),
+        Err(_) => Err(invalid_utf8()),
+    }
+}
+
+/// Writes `ch` encoded as UTF-8 to `handler`.
+pub(crate) fn write_utf8<H: IoHandler + ?Sized>(handler: &mut H, ch: char) -> Result<(), IoError> {
+    for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+        handler.output(byte)?;
+    }
+    Ok(())
+}
+
+fn invalid_utf8() -> IoError {
+    invalid_data("input is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Interpreter, compile, eval_on_tape};
+
+    /// Test closures feeding input from an iterator and collecting output.
+    #[test]
+    fn test_closure_handler() {
+        let program = compile(",[.,]").unwrap();
+        let mut input = "closure".bytes();
+        let mut output = Vec::new();
+
+        let handler = io_handler_fn(
+            || Ok(input.next()),
+            |byte| {
+                output.push(byte);
+                Ok(())
+            },
+        );
+        Interpreter::default()
+            .run_with_handler(&program, handler)
+            .unwrap();
+
+        assert_eq!(output, b"closure");
+    }
+
+    /// Test that handler errors abort execution.
+    #[test]
+    fn test_handler_error() {
+        let program = compile("+[.]").unwrap();
+        let mut count = 0;
+
+        let handler = io_handler_fn(
+            || Ok(None),
+            |_| {
+                count += 1;
+                if count == 5 {
+                    Err(io::Error::other("enough"))
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        let result = Interpreter::default().run_with_handler(&program, handler);
+
+        assert_eq!(result.unwrap_err().to_string(), "I/O error: enough");
+        assert_eq!(count, 5);
+    }
+
+    /// Test that end of input stores zero in the current cell.
+    #[test]
+    fn test_end_of_input() {
+        let program = compile("+,.").unwrap();
+        let mut output = Vec::new();
+
+        let handler = Streams::new(io::empty(), &mut output);
+        Interpreter::default()
+            .run_with_handler(&program, handler)
+            .unwrap();
+
+        assert_eq!(output, [0]);
+    }
+
+    /// I/O event seen by [`EventLog`].
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Write(u8),
+        Flush,
+        Read,
+    }
+
+    /// Reader and writer that record every call in the shared log.
+    struct EventLog(Rc<RefCell<Vec<Event>>>);
+
+    impl io::Read for EventLog {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.borrow_mut().push(Event::Read);
+            buf[0] = b'y';
+            Ok(1)
+        }
+    }
+
+    impl io::Write for EventLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut log = self.0.borrow_mut();
+            log.extend(buf.iter().map(|&byte| Event::Write(byte)));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().push(Event::Flush);
+            Ok(())
+        }
+    }
+
+    /// Test that a prompt is flushed before the read, and only once.
+    #[test]
+    fn test_flush_before_read() {
+        let program = compile("++++++++[>++++++++<-]>-.,,.").unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let reader = EventLog(Rc::clone(&log));
+        let writer = EventLog(Rc::clone(&log));
+        eval_on_tape(&program, vec![0_u8; 2], 0, reader, writer).unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                Event::Write(b'?'),
+                Event::Flush,
+                Event::Read,
+                Event::Read,
+                Event::Write(b'y')
+            ]
+        );
+    }
+}
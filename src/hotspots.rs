@@ -0,0 +1,134 @@
+//! Reports of the loops a run spent its steps in.
+
+use std::fmt;
+
+use brainfuck_vm::{LoopProfile, LoopProfiler};
+
+/// The loops of `hotspots` that took the most steps, each with the source
+/// offset of its `[` if the program has a source.
+#[derive(Debug, PartialEq)]
+pub struct Hotspots {
+    /// Commands the run executed, in loops or not.
+    pub steps: u64,
+    pub loops: Vec<(LoopProfile, Option<usize>)>,
+}
+
+impl Hotspots {
+    /// The `top` loops of `profiler` that ran at all, with the source offset
+    /// of every command of the program in `offsets`.
+    pub fn new(profiler: &LoopProfiler, offsets: Option<&[usize]>, top: usize) -> Hotspots {
+        let loops = profiler
+            .loops()
+            .into_iter()
+            .filter(|profile| profile.steps > 0)
+            .take(top)
+            .map(|profile| (profile, offsets.map(|offsets| offsets[profile.start])))
+            .collect();
+        Hotspots {
+            steps: profiler.steps(),
+            loops,
+        }
+    }
+
+    /// Fraction of all steps taken by `profile`.
+    fn share(&self, profile: &LoopProfile) -> f64 {
+        profile.steps as f64 / self.steps as f64
+    }
+
+    /// The report as one JSON object, with `null` for unknown offsets.
+    pub fn to_json(&self) -> String {
+        let loops: Vec<_> = self
+            .loops
+            .iter()
+            .map(|(profile, offset)| {
+                let offset = offset.map_or("null".to_string(), |offset| offset.to_string());
+                format!(
+                    "{{\"start\":{},\"end\":{},\"offset\":{offset},\"entries\":{},\
+                     \"steps\":{},\"share\":{:.4}}}",
+                    profile.start,
+                    profile.end,
+                    profile.entries,
+                    profile.steps,
+                    self.share(profile)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"steps\":{},\"loops\":[{}]}}",
+            self.steps,
+            loops.join(",")
+        )
+    }
+}
+
+impl fmt::Display for Hotspots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.loops.is_empty() {
+            return write!(f, "no loop ran\n{} steps in total", self.steps);
+        }
+        writeln!(
+            f,
+            "{:>4}  {:<13}  {:>8}  {:>12}  {:>6}  {:>10}",
+            "rank", "loop", "offset", "steps", "share", "entries"
+        )?;
+        for (rank, (profile, offset)) in self.loops.iter().enumerate() {
+            let offset = offset.map_or("-".to_string(), |offset| offset.to_string());
+            writeln!(
+                f,
+                "{:>4}  {:<13}  {offset:>8}  {:>12}  {:>5.1}%  {:>10}",
+                rank + 1,
+                format!("{}..{}", profile.start, profile.end),
+                profile.steps,
+                self.share(profile) * 100.0,
+                profile.entries
+            )?;
+        }
+        write!(f, "{} steps in total", self.steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brainfuck_vm::{Streams, Vm, compile};
+
+    /// The report of running `source_code` with the given offsets.
+    fn hotspots(source_code: &str, offsets: Option<&[usize]>, top: usize) -> Hotspots {
+        let program = compile(source_code).unwrap();
+        let mut profiler = LoopProfiler::new(&program);
+        let mut vm = Vm::with_tape(&program, vec![0u8; 8], 0);
+        vm.run_observed(Streams::new(&[][..], Vec::new()), &mut profiler)
+            .unwrap();
+        Hotspots::new(&profiler, offsets, top)
+    }
+
+    /// Test the ranked table, which leaves out loops that never ran.
+    #[test]
+    fn test_table() {
+        let offsets: Vec<_> = (0..13).map(|address| address * 2).collect();
+        let report = hotspots("++[->+<][[-]]", Some(&offsets), 10);
+        assert_eq!(
+            report.to_string(),
+            "rank  loop             offset         steps   share     entries\n   \
+             1  2..7                  4            11   78.6%           1\n   \
+             2  8..12                16             1    7.1%           0\n\
+             14 steps in total"
+        );
+        assert_eq!(
+            hotspots("+", None, 10).to_string(),
+            "no loop ran\n1 steps in total"
+        );
+    }
+
+    /// Test that the JSON lists the top loops, with `null` for unknown
+    /// offsets.
+    #[test]
+    fn test_json() {
+        let report = hotspots("++++[->++++[->+<]<]", None, 1);
+        assert_eq!(
+            report.to_json(),
+            "{\"steps\":121,\"loops\":[{\"start\":4,\"end\":18,\"offset\":null,\
+             \"entries\":1,\"steps\":117,\"share\":0.9669}]}"
+        );
+    }
+}
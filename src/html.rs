@@ -0,0 +1,194 @@
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::{Command, ParsingError, compile};
+
+/// Number of colors that nested brackets cycle through.
+const DEPTH_COLORS: usize = 6;
+
+const HEAD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>"#;
+
+const STYLE: &str = r#"</title>
+<style>
+body { margin: 2em; background: #1e1e1e; color: #d4d4d4; }
+pre { font: 14px/1.5 monospace; }
+.comment { color: #6a6a6a; }
+.move { color: #4fc1ff; }
+.add { color: #b5cea8; }
+.io { color: #ce9178; }
+.d0 { color: #ffd700; }
+.d1 { color: #da70d6; }
+.d2 { color: #179fff; }
+.d3 { color: #4ec9b0; }
+.d4 { color: #f44747; }
+.d5 { color: #c586c0; }
+.pair { background: #264f78; outline: 1px solid #d4d4d4; }
+</style>
+</head>
+<body>
+<pre>"#;
+
+const TAIL: &str = r#"</pre>
+<script>
+for (const bracket of document.querySelectorAll("[data-pair]")) {
+  const partner = document.getElementById(bracket.dataset.pair);
+  const highlight = (on) => {
+    bracket.classList.toggle("pair", on);
+    partner.classList.toggle("pair", on);
+  };
+  bracket.addEventListener("mouseenter", () => highlight(true));
+  bracket.addEventListener("mouseleave", () => highlight(false));
+}
+</script>
+</body>
+</html>
+"#;
+
+/// Renders Brainfuck source code as a standalone HTML page titled `title`.
+///
+/// Commands are colored by kind and comments are dimmed. Every bracket
+/// gets a class for its nesting depth, `id="bN"` where `N` is its index in
+/// the [`compile`]d program, and `data-pair` with the id of its partner,
+/// which is highlighted together with it on hover. Fails like [`compile`]
+/// if the brackets do not match.
+pub fn export_html(source: &str, title: &str) -> Result<String, ParsingError> {
+    let commands = compile(source)?;
+
+    let mut html = String::with_capacity(HEAD.len() + STYLE.len() + TAIL.len() + source.len() * 2);
+    html.push_str(HEAD);
+    push_escaped(&mut html, title);
+    html.push_str(STYLE);
+
+    // Class of the span that is open; runs of one class share a span, while
+    // every bracket gets its own.
+    let mut open_span = None;
+    let mut index = 0;
+    let mut depth = 0_usize;
+    for ch in source.chars() {
+        let class = match ch {
+            '<' | '>' => Some("move"),
+            '+' | '-' => Some("add"),
+            '.' | ',' => Some("io"),
+            '[' | ']' => None,
+            _ => Some("comment"),
+        };
+        if open_span != class {
+            if open_span.is_some() {
+                html.push_str("</span>");
+            }
+            if let Some(class) = class {
+                let _ = write!(html, r#"<span class="{class}">"#);
+            }
+            open_span = class;
+        }
+
+        if class.is_none() {
+            let partner = match commands[index] {
+                Command::JumpForwardIfZero(partner) => partner,
+                Command::JumpBackwardIfNonZero(partner) => {
+                    depth -= 1;
+                    partner
+                }
+                _ => unreachable!("every bracket compiles to a jump"),
+            };
+            let kind = if ch == '[' { "open" } else { "close" };
+            let _ = write!(
+                html,
+                r#"<span class="{kind} d{}" id="b{index}" data-pair="b{partner}">{ch}</span>"#,
+                depth % DEPTH_COLORS
+            );
+            if ch == '[' {
+                depth += 1;
+            }
+        } else {
+            push_escaped(&mut html, ch.encode_utf8(&mut [0; 4]));
+        }
+        if class != Some("comment") {
+            index += 1;
+        }
+    }
+
+    if open_span.is_some() {
+        html.push_str("</span>");
+    }
+    html.push_str(TAIL);
+    Ok(html)
+}
+
+/// Appends `text` with HTML special characters escaped, and control
+/// characters other than whitespace shown as their Unicode pictures.
+fn push_escaped(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            '\t' | '\n' => html.push(ch),
+            '\u{0}'..='\u{1f}' => {
+                html.push(char::from_u32(0x2400 + u32::from(ch)).unwrap_or('\u{fffd}'))
+            }
+            '\u{7f}' => html.push('\u{2421}'),
+            _ => html.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+
+    /// Test that every bracket links to the partner found by the compiler.
+    #[test]
+    fn test_bracket_pairs() {
+        let source = "+[ loop [->+<] then [[]>] ]<.";
+        let html = export_html(source, "pairs").unwrap();
+
+        let mut brackets = BTreeMap::new();
+        for (start, _) in html.match_indices(r#" id="b"#) {
+            let rest = &html[start + 6..];
+            let id: usize = rest[..rest.find('"').unwrap()].parse().unwrap();
+            let rest = &rest[rest.find(r#"data-pair="b"#).unwrap() + 12..];
+            let partner: usize = rest[..rest.find('"').unwrap()].parse().unwrap();
+            let bracket = rest[rest.find('>').unwrap() + 1..].chars().next().unwrap();
+            brackets.insert(id, (partner, bracket));
+        }
+
+        let commands = compile(source).unwrap();
+        let mut expected = BTreeMap::new();
+        for (index, command) in commands.iter().enumerate() {
+            match *command {
+                Command::JumpForwardIfZero(partner) => expected.insert(index, (partner, '[')),
+                Command::JumpBackwardIfNonZero(partner) => expected.insert(index, (partner, ']')),
+                _ => None,
+            };
+        }
+        assert_eq!(brackets.len(), 8);
+        assert_eq!(brackets, expected);
+        assert!(html.contains(r#"<span class="open d1" id="b2" data-pair="b7">[</span>"#));
+        assert!(html.contains(r#"<span class="close d0" id="b13" data-pair="b1">]</span>"#));
+    }
+
+    /// Test that comments and commands cannot inject markup.
+    #[test]
+    fn test_escaping() {
+        let html = export_html("<script>alert('&')</script>\u{0}.", "a < b").unwrap();
+        assert!(html.contains("<title>a &lt; b</title>"));
+        assert!(html.contains(r#"<span class="move">&lt;</span>"#));
+        assert!(html.contains("script</span>"));
+        assert!(html.contains("alert(&#39;&amp;&#39;)"));
+        assert!(html.contains("\u{2400}"));
+        assert!(!html.contains('\u{0}'));
+        assert_eq!(html.matches("<script>").count(), 1);
+        assert!(matches!(
+            export_html("]", ""),
+            Err(ParsingError::UnmatchedBracket { offset: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,977 @@
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+use crate::vm::Limits;
+use crate::{
+    ByteSink, ByteSource, Bytecode, Cell, Command, DEFAULT_MAX_CALL_DEPTH, Error, ExecutionReport,
+    IoHandler, Streams, Tape, Vm,
+};
+
+/// Number of cells on the tape when no length is configured, as in the
+/// original Brainfuck implementation.
+pub const DEFAULT_TAPE_LEN: usize = 30_000;
+
+/// Enum for invalid interpreter configurations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The tape must contain at least one cell.
+    EmptyTape,
+    /// The initial data pointer does not point into the tape.
+    DataPointerOutOfRange {
+        data_pointer: usize,
+        tape_len: usize,
+    },
+    /// The initial tape contents need the first `end` cells, but the tape
+    /// is shorter.
+    TapeInitTooLong { end: usize, tape_len: usize },
+}
+
+/// What `+` and `-` do when a cell would leave its range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 255 + 1 is 0 and 0 - 1 is 255, which most programs rely on.
+    #[default]
+    Wrap,
+    /// The cell stays at its minimum or maximum.
+    Saturate,
+    /// Execution stops with [`RuntimeError::CellOverflow`](crate::RuntimeError::CellOverflow).
+    Error,
+}
+
+impl OverflowPolicy {
+    /// Value of `cell` after `+`, or `None` if the policy rejects it.
+    pub(crate) fn increment<C: Cell>(self, cell: C) -> Option<C> {
+        match self {
+            OverflowPolicy::Wrap => Some(cell.wrapping_inc()),
+            OverflowPolicy::Saturate => Some(cell.saturating_inc()),
+            OverflowPolicy::Error => cell.checked_inc(),
+        }
+    }
+
+    /// Value of `cell` after `-`, or `None` if the policy rejects it.
+    pub(crate) fn decrement<C: Cell>(self, cell: C) -> Option<C> {
+        match self {
+            OverflowPolicy::Wrap => Some(cell.wrapping_dec()),
+            OverflowPolicy::Saturate => Some(cell.saturating_dec()),
+            OverflowPolicy::Error => cell.checked_dec(),
+        }
+    }
+
+    /// Value of `cell` after `delta` times `+`, or `-` for a negative
+    /// `delta`, or `None` if the policy rejects one of them.
+    pub(crate) fn add<C: Cell>(self, cell: C, delta: i16) -> Option<C> {
+        if self == OverflowPolicy::Wrap {
+            return Some(cell.wrapping_add_signed(i64::from(delta)));
+        }
+        let step = if delta < 0 {
+            Self::decrement
+        } else {
+            Self::increment
+        };
+        (0..delta.unsigned_abs()).try_fold(cell, |cell, _| step(self, cell))
+    }
+}
+
+/// What `,` does once the input is exhausted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EofBehavior {
+    /// The current cell is set to 0.
+    #[default]
+    SetZero,
+    /// The current cell is set to -1, which is the maximum for unsigned cells.
+    SetMinusOne,
+    /// The current cell keeps its value, so `,` does nothing.
+    Unchanged,
+}
+
+/// How `,` and `.` translate between cells and the input and output bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// `,` reads one byte and `.` writes the low byte of the cell.
+    #[default]
+    Bytes,
+    /// Numbers in decimal, with `separator` after every written number, see
+    /// [`DecimalIo`](crate::DecimalIo). The output limit then counts numbers
+    /// instead of bytes.
+    Decimal { separator: u8 },
+    /// Unicode characters encoded as UTF-8: `.` writes the character whose
+    /// code point is in the cell, and `,` stores the code point of the next
+    /// character. Meant for 32-bit cells, since narrower ones cannot hold
+    /// every code point.
+    ///
+    /// Writing a value that is not a Unicode scalar value fails with
+    /// [`RuntimeError::InvalidScalarValue`](crate::RuntimeError::InvalidScalarValue),
+    /// and input that is not valid UTF-8 with an I/O error. The output limit
+    /// counts characters instead of bytes.
+    Utf8,
+}
+
+/// How a [`Vm`] gets from a command to the code that executes it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Every step matches on the command, so all commands share one
+    /// indirect branch.
+    #[default]
+    Match,
+    /// The program is first translated into a function pointer per command,
+    /// and every step calls through the pointer of its command, so each
+    /// kind of command gets a branch of its own. Both engines run the same
+    /// code for each command.
+    Threaded,
+    /// The program is first compiled to machine code, which runs the
+    /// commands that only change cells and move the pointer, and the loops
+    /// around them, natively, and hands every other command to the VM,
+    /// which runs it like [`Engine::Match`]. Native code is only run on
+    /// x86-64 Unix, with byte-sized cells that wrap, on a tape that is one
+    /// slice, and without stats; everywhere else, and without the `jit`
+    /// feature, this is [`Engine::Match`].
+    Jit,
+}
+
+/// Validated settings for running compiled programs.
+///
+/// Every run gets a fresh zeroed tape, so one `Interpreter` can be reused
+/// for any number of programs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpreter {
+    tape_len: usize,
+    data_pointer: usize,
+    overflow_policy: OverflowPolicy,
+    eof_behavior: EofBehavior,
+    io_mode: IoMode,
+    engine: Engine,
+    echo_input: bool,
+    collect_stats: bool,
+    max_steps: Option<u64>,
+    max_output: Option<u64>,
+    #[cfg(feature = "std")]
+    timeout: Option<Duration>,
+    tape_init: TapeInit,
+    max_call_depth: usize,
+    seed: u64,
+}
+
+/// Bytes copied into the tape before a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TapeInit {
+    offset: usize,
+    data: Vec<u8>,
+}
+
+impl Interpreter {
+    /// Starts configuring an interpreter with the default settings.
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::default()
+    }
+
+    /// Number of cells on the tape.
+    pub fn tape_len(&self) -> usize {
+        self.tape_len
+    }
+
+    /// Cell the data pointer starts at.
+    pub fn data_pointer(&self) -> usize {
+        self.data_pointer
+    }
+
+    /// What `+` and `-` do at the ends of the cell range.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// What `,` does once the input is exhausted.
+    pub fn eof_behavior(&self) -> EofBehavior {
+        self.eof_behavior
+    }
+
+    /// How `,` and `.` exchange data with the streams.
+    pub fn io_mode(&self) -> IoMode {
+        self.io_mode
+    }
+
+    /// How commands are dispatched.
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Whether input read from a reader is echoed to the writer.
+    pub fn echo_input(&self) -> bool {
+        self.echo_input
+    }
+
+    /// Whether runs count how often each kind of command runs.
+    pub fn collect_stats(&self) -> bool {
+        self.collect_stats
+    }
+
+    /// Number of commands a run may execute before it fails, if limited.
+    pub fn max_steps(&self) -> Option<u64> {
+        self.max_steps
+    }
+
+    /// Number of bytes a run may write before it fails, if limited.
+    pub fn max_output(&self) -> Option<u64> {
+        self.max_output
+    }
+
+    /// How long a run may take before it fails, if limited.
+    #[cfg(feature = "std")]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Number of pbrain procedure calls that may be active at the same time.
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// Seed of the generator behind `?`.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes copied into every fresh tape, and the cell the first one goes to.
+    pub fn tape_init(&self) -> (usize, &[u8]) {
+        (self.tape_init.offset, &self.tape_init.data)
+    }
+
+    /// Creates a [`Vm`] for the program on a fresh tape.
+    /// The VM itself does not enforce the step, output, or time limits.
+    pub fn vm<'a>(&self, commands: &'a [Command]) -> Vm<'a> {
+        self.vm_with_cells(commands)
+    }
+
+    /// Same as [`Interpreter::vm`], but with cells of type `C`.
+    pub fn vm_with_cells<'a, C: Cell>(&self, commands: &'a [Command]) -> Vm<'a, Vec<C>> {
+        self.vm_on_tape(commands, vec![C::ZERO; self.tape_len])
+    }
+
+    /// Same as [`Interpreter::vm`], but on the given tape, e.g. a
+    /// [`PagedTape`](crate::PagedTape) of [`Interpreter::tape_len`] cells.
+    /// The data pointer and the initial tape contents must fit into `tape`.
+    pub fn vm_on_tape<'a, T: Tape>(&self, commands: &'a [Command], mut tape: T) -> Vm<'a, T> {
+        self.fill(&mut tape);
+        self.configure(Vm::with_tape(commands, tape, self.data_pointer))
+    }
+
+    /// Same as [`Interpreter::vm_on_tape`], but on `tape` as it is, with the
+    /// data pointer at `data_pointer`, e.g. to go on where an earlier
+    /// program left off. The data pointer must be on the tape.
+    pub fn resume_vm<'a, T: Tape>(
+        &self,
+        commands: &'a [Command],
+        tape: T,
+        data_pointer: usize,
+    ) -> Vm<'a, T> {
+        self.configure(Vm::with_tape(commands, tape, data_pointer))
+    }
+
+    /// Same as [`Interpreter::vm`], but for a [`pack`](crate::pack)ed program.
+    pub fn bytecode_vm<'a>(&self, bytecode: &'a Bytecode) -> Vm<'a> {
+        let mut tape = vec![0; self.tape_len];
+        self.fill(&mut tape);
+        self.configure(Vm::with_bytecode(bytecode, tape, self.data_pointer))
+    }
+
+    /// Copies the initial tape contents into `tape`.
+    fn fill<T: Tape>(&self, tape: &mut T) {
+        let TapeInit { offset, data } = &self.tape_init;
+        for (index, &byte) in data.iter().enumerate() {
+            tape.set(offset + index, T::Cell::from_byte(byte));
+        }
+    }
+
+    fn configure<'a, T: Tape>(&self, vm: Vm<'a, T>) -> Vm<'a, T> {
+        vm.with_overflow_policy(self.overflow_policy)
+            .with_eof_behavior(self.eof_behavior)
+            .with_io_mode(self.io_mode)
+            .with_engine(self.engine)
+            .with_max_call_depth(self.max_call_depth)
+            .with_seed(self.seed)
+            .with_stats(self.collect_stats)
+    }
+
+    /// Executes a compiled program on a fresh tape.
+    pub fn run<R: ByteSource, W: ByteSink>(
+        &self,
+        commands: &[Command],
+        reader: R,
+        writer: W,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_with_handler(commands, self.streams(reader, writer))
+    }
+
+    /// Same as [`Interpreter::run`], but with cells of type `C`.
+    pub fn run_with_cells<C: Cell, R: ByteSource, W: ByteSink>(
+        &self,
+        commands: &[Command],
+        reader: R,
+        writer: W,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_vm(
+            self.vm_with_cells::<C>(commands),
+            self.streams(reader, writer),
+        )
+    }
+
+    /// Same as [`Interpreter::run`], but on the given tape.
+    pub fn run_on_tape<T: Tape, R: ByteSource, W: ByteSink>(
+        &self,
+        commands: &[Command],
+        tape: T,
+        reader: R,
+        writer: W,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_vm(
+            self.vm_on_tape(commands, tape),
+            self.streams(reader, writer),
+        )
+    }
+
+    /// Executes a compiled program on a fresh tape, serving I/O through `handler`.
+    pub fn run_with_handler<H: IoHandler>(
+        &self,
+        commands: &[Command],
+        handler: H,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_vm(self.vm(commands), handler)
+    }
+
+    /// Same as [`Interpreter::run`], but for a [`pack`](crate::pack)ed program.
+    pub fn run_bytecode<R: ByteSource, W: ByteSink>(
+        &self,
+        bytecode: &Bytecode,
+        reader: R,
+        writer: W,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_vm(self.bytecode_vm(bytecode), self.streams(reader, writer))
+    }
+
+    fn streams<R: ByteSource, W: ByteSink>(&self, reader: R, writer: W) -> Streams<R, W> {
+        Streams::new(reader, writer).with_echo(self.echo_input)
+    }
+
+    /// Runs `vm` to completion within the limits of the interpreter,
+    /// serving I/O through `handler`. The VM is kept, so its tape can be
+    /// looked at afterwards, also when the program failed.
+    pub fn run_on_vm<T: Tape, H: IoHandler>(
+        &self,
+        vm: &mut Vm<'_, T>,
+        handler: H,
+    ) -> Result<ExecutionReport, Error> {
+        let limits = Limits {
+            max_steps: self.max_steps.unwrap_or(u64::MAX),
+            max_output: self.max_output.unwrap_or(u64::MAX),
+        };
+        #[cfg(feature = "std")]
+        if let Some(timeout) = self.timeout {
+            return vm.run_metered(handler, limits, crate::vm::timeout_check(timeout));
+        }
+        match (self.max_steps, self.max_output) {
+            (None, None) => vm.run_with(handler),
+            _ => vm.run_metered(handler, limits, |_| Ok(())),
+        }
+    }
+
+    fn run_vm<T: Tape, H: IoHandler>(
+        &self,
+        mut vm: Vm<'_, T>,
+        handler: H,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_on_vm(&mut vm, handler)
+    }
+}
+
+impl Default for Interpreter {
+    /// A 30,000-cell tape with the data pointer on the first cell, wrapping
+    /// cells, zero on end of input, and no step limit.
+    fn default() -> Self {
+        Interpreter {
+            tape_len: DEFAULT_TAPE_LEN,
+            data_pointer: 0,
+            overflow_policy: OverflowPolicy::Wrap,
+            eof_behavior: EofBehavior::SetZero,
+            io_mode: IoMode::Bytes,
+            engine: Engine::Match,
+            echo_input: false,
+            collect_stats: false,
+            max_steps: None,
+            max_output: None,
+            #[cfg(feature = "std")]
+            timeout: None,
+            tape_init: TapeInit::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            seed: 0,
+        }
+    }
+}
+
+/// Builder for [`Interpreter`].
+/// Settings that are not specified keep their default values.
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterBuilder {
+    tape_len: Option<usize>,
+    data_pointer: usize,
+    /// Whether `data_pointer` counts from the start of `tape_init`.
+    pointer_in_init: bool,
+    tape_init: TapeInit,
+    overflow_policy: OverflowPolicy,
+    eof_behavior: EofBehavior,
+    io_mode: IoMode,
+    engine: Engine,
+    echo_input: bool,
+    collect_stats: bool,
+    max_steps: Option<u64>,
+    max_output: Option<u64>,
+    #[cfg(feature = "std")]
+    timeout: Option<Duration>,
+    max_call_depth: Option<usize>,
+    seed: u64,
+}
+
+impl InterpreterBuilder {
+    /// Sets the number of cells on the tape.
+    pub fn tape_len(mut self, tape_len: usize) -> Self {
+        self.tape_len = Some(tape_len);
+        self
+    }
+
+    /// Sets the cell the data pointer starts at.
+    /// Defaults to the first cell.
+    pub fn data_pointer(mut self, data_pointer: usize) -> Self {
+        self.data_pointer = data_pointer;
+        self.pointer_in_init = false;
+        self
+    }
+
+    /// Sets the data pointer to start `delta` cells after the first cell of
+    /// the data from [`InterpreterBuilder::tape_init`].
+    pub fn data_pointer_in_init(mut self, delta: usize) -> Self {
+        self.data_pointer = delta;
+        self.pointer_in_init = true;
+        self
+    }
+
+    /// Copies `data` into the tape, starting at cell `offset`, before every
+    /// run. Defaults to a tape of zeros.
+    pub fn tape_init(mut self, offset: usize, data: impl Into<Vec<u8>>) -> Self {
+        self.tape_init = TapeInit {
+            offset,
+            data: data.into(),
+        };
+        self
+    }
+
+    /// Sets what `+` and `-` do at the ends of the cell range.
+    /// Defaults to [`OverflowPolicy::Wrap`].
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets what `,` does once the input is exhausted.
+    /// Defaults to [`EofBehavior::SetZero`].
+    pub fn eof_behavior(mut self, eof_behavior: EofBehavior) -> Self {
+        self.eof_behavior = eof_behavior;
+        self
+    }
+
+    /// Sets how `,` and `.` exchange data with the streams.
+    /// Defaults to [`IoMode::Bytes`].
+    pub fn io_mode(mut self, io_mode: IoMode) -> Self {
+        self.io_mode = io_mode;
+        self
+    }
+
+    /// Sets how commands are dispatched, see [`Vm::with_engine`].
+    /// Defaults to [`Engine::Match`].
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Sets whether runs with a reader and a writer echo every input byte,
+    /// see [`Streams::with_echo`]. Defaults to `false`.
+    pub fn echo_input(mut self, echo_input: bool) -> Self {
+        self.echo_input = echo_input;
+        self
+    }
+
+    /// Sets whether the reports of runs hold
+    /// [`ExecutionReport::stats`](crate::ExecutionReport::stats), see
+    /// [`Vm::with_stats`]. Defaults to `false`.
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.collect_stats = collect_stats;
+        self
+    }
+
+    /// Makes runs fail with
+    /// [`RuntimeError::StepLimitExceeded`](crate::RuntimeError::StepLimitExceeded)
+    /// after `max_steps` commands, jumps included. Defaults to no limit.
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Makes runs fail with
+    /// [`RuntimeError::OutputLimitExceeded`](crate::RuntimeError::OutputLimitExceeded)
+    /// instead of writing more than `max_output` bytes. Defaults to no limit.
+    pub fn max_output(mut self, max_output: u64) -> Self {
+        self.max_output = Some(max_output);
+        self
+    }
+
+    /// Makes runs fail with
+    /// [`RuntimeError::Timeout`](crate::RuntimeError::Timeout) once
+    /// `timeout` has passed. Defaults to no limit.
+    #[cfg(feature = "std")]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes a pbrain `:` fail with
+    /// [`RuntimeError::CallStackOverflow`](crate::RuntimeError::CallStackOverflow)
+    /// instead of nesting more than `max_call_depth` calls.
+    /// Defaults to [`DEFAULT_MAX_CALL_DEPTH`].
+    pub fn max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = Some(max_call_depth);
+        self
+    }
+
+    /// Seeds the generator that `?` draws from; see [`Vm::with_seed`].
+    /// Defaults to 0.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Validates the settings and creates the interpreter.
+    pub fn build(self) -> Result<Interpreter, ConfigError> {
+        let tape_len = self.tape_len.unwrap_or(DEFAULT_TAPE_LEN);
+        if tape_len == 0 {
+            return Err(ConfigError::EmptyTape);
+        }
+
+        let end = self
+            .tape_init
+            .offset
+            .saturating_add(self.tape_init.data.len());
+        if end > tape_len {
+            return Err(ConfigError::TapeInitTooLong { end, tape_len });
+        }
+
+        let data_pointer = if self.pointer_in_init {
+            self.tape_init.offset.saturating_add(self.data_pointer)
+        } else {
+            self.data_pointer
+        };
+        if data_pointer >= tape_len {
+            return Err(ConfigError::DataPointerOutOfRange {
+                data_pointer,
+                tape_len,
+            });
+        }
+
+        Ok(Interpreter {
+            tape_len,
+            data_pointer,
+            overflow_policy: self.overflow_policy,
+            eof_behavior: self.eof_behavior,
+            io_mode: self.io_mode,
+            engine: self.engine,
+            echo_input: self.echo_input,
+            collect_stats: self.collect_stats,
+            max_steps: self.max_steps,
+            max_output: self.max_output,
+            #[cfg(feature = "std")]
+            timeout: self.timeout,
+            tape_init: self.tape_init,
+            max_call_depth: self.max_call_depth.unwrap_or(DEFAULT_MAX_CALL_DEPTH),
+            seed: self.seed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutputIter, RuntimeError, Status, Streams, compile};
+
+    /// Test that the builder defaults match the classic `eval` settings.
+    #[test]
+    fn test_default_settings() {
+        let interpreter = Interpreter::builder().build().unwrap();
+
+        assert_eq!(interpreter, Interpreter::default());
+        assert_eq!(interpreter.tape_len(), 30_000);
+        assert_eq!(interpreter.data_pointer(), 0);
+    }
+
+    /// Test that the data pointer defaults to the first cell of a custom tape.
+    #[test]
+    fn test_custom_tape_len() {
+        let interpreter = Interpreter::builder().tape_len(10).build().unwrap();
+
+        assert_eq!(interpreter.tape_len(), 10);
+        assert_eq!(interpreter.data_pointer(), 0);
+    }
+
+    /// Test moving left from a pointer that starts on the last cell.
+    #[test]
+    fn test_custom_data_pointer() {
+        let interpreter = Interpreter::builder()
+            .tape_len(10)
+            .data_pointer(9)
+            .build()
+            .unwrap();
+        let program = compile("<<<+").unwrap();
+        let mut vm = interpreter.vm(&program);
+
+        assert_eq!(vm.run(), Ok(Status::Halted));
+        assert_eq!(vm.data_pointer(), 6);
+        assert_eq!(vm.tape()[6], 1);
+    }
+
+    /// Test that invalid combinations are rejected before running anything.
+    #[test]
+    fn test_invalid_settings() {
+        assert_eq!(
+            Interpreter::builder().tape_len(0).build(),
+            Err(ConfigError::EmptyTape)
+        );
+        assert_eq!(
+            Interpreter::builder().tape_len(10).data_pointer(10).build(),
+            Err(ConfigError::DataPointerOutOfRange {
+                data_pointer: 10,
+                tape_len: 10
+            })
+        );
+    }
+
+    /// Test running a program that touches both ends of a small tape.
+    #[test]
+    fn test_run_on_small_tape() {
+        let interpreter = Interpreter::builder()
+            .tape_len(3)
+            .data_pointer(0)
+            .build()
+            .unwrap();
+        let program = compile("+++>>++++++[<+>-]<[<+>-]<.").unwrap();
+        let mut writer = Vec::new();
+
+        interpreter
+            .run(&program, std::io::empty(), &mut writer)
+            .unwrap();
+
+        assert_eq!(writer, [9]);
+    }
+
+    /// Test `-` on a zero cell under every overflow policy.
+    #[test]
+    fn test_overflow_policies() {
+        let program = compile("-").unwrap();
+        let run = |overflow_policy| {
+            let interpreter = Interpreter::builder()
+                .tape_len(1)
+                .data_pointer(0)
+                .overflow_policy(overflow_policy)
+                .build()
+                .unwrap();
+            let mut vm = interpreter.vm(&program);
+            vm.run().map(|status| (status, vm.tape()[0]))
+        };
+
+        assert_eq!(run(OverflowPolicy::Wrap), Ok((Status::Halted, 255)));
+        assert_eq!(run(OverflowPolicy::Saturate), Ok((Status::Halted, 0)));
+        assert_eq!(
+            run(OverflowPolicy::Error),
+            Err(RuntimeError::CellOverflow {
+                instruction_index: 0
+            })
+        );
+    }
+
+    /// Test the cat program on truncated input under every EOF behavior.
+    #[test]
+    fn test_eof_behaviors() {
+        let program = compile(",[.,]").unwrap();
+        let run = |eof_behavior| {
+            let interpreter = Interpreter::builder()
+                .eof_behavior(eof_behavior)
+                .build()
+                .unwrap();
+            OutputIter::from_vm(interpreter.vm(&program), &b"ab"[..])
+                .take(5)
+                .collect::<Result<Vec<u8>, _>>()
+                .unwrap()
+        };
+
+        assert_eq!(run(EofBehavior::SetZero), b"ab");
+        assert_eq!(run(EofBehavior::SetMinusOne), [b'a', b'b', 255, 255, 255]);
+        assert_eq!(run(EofBehavior::Unchanged), b"abbbb");
+    }
+
+    /// Test that an endless loop stops at exactly the step limit.
+    #[test]
+    fn test_max_steps() {
+        let program = compile("+[]").unwrap();
+        let interpreter = Interpreter::builder().max_steps(1000).build().unwrap();
+
+        let error = interpreter
+            .run(&program, std::io::empty(), std::io::sink())
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            Error::Runtime(RuntimeError::StepLimitExceeded {
+                instruction_index: 2,
+                steps: 1000
+            })
+        ));
+    }
+
+    /// Test that a resumed VM goes on from the tape and data pointer an
+    /// earlier one left, also after it ran out of steps.
+    #[test]
+    fn test_resume_vm() {
+        let interpreter = Interpreter::builder().max_steps(3).build().unwrap();
+        let first = compile("+>++").unwrap();
+        let mut vm = interpreter.vm(&first);
+        assert!(
+            interpreter
+                .run_on_vm(&mut vm, Streams::new(&[][..], Vec::new()))
+                .is_err()
+        );
+        let (data_pointer, tape) = (vm.data_pointer(), vm.into_tape());
+        assert_eq!((data_pointer, &tape[..2]), (1, &[1, 1][..]));
+
+        let second = compile("+<.>.").unwrap();
+        let mut vm = interpreter.resume_vm(&second, tape, data_pointer);
+        let mut output = Vec::new();
+        interpreter
+            .run_on_vm(&mut vm, Streams::new(&[][..], &mut output))
+            .unwrap_err();
+        assert_eq!(output, [1]);
+        assert_eq!(&vm.tape()[..2], [1, 2]);
+    }
+
+    /// Test that a spin loop stops soon after the timeout.
+    #[test]
+    fn test_timeout() {
+        let program = compile("+.[]").unwrap();
+        let interpreter = Interpreter::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+
+        let start = std::time::Instant::now();
+        let error = interpreter
+            .run(&program, std::io::empty(), &mut output)
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(output, [1]);
+        let Error::Runtime(RuntimeError::Timeout {
+            instruction_index,
+            elapsed,
+            steps,
+        }) = error
+        else {
+            panic!("unexpected error {error:?}");
+        };
+        assert_eq!(instruction_index, 3);
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(steps > 0);
+    }
+
+    /// Test that an endless printer stops exactly at the output limit.
+    #[test]
+    fn test_max_output() {
+        let program = compile("+[.]").unwrap();
+        let interpreter = Interpreter::builder().max_output(1024).build().unwrap();
+        let mut output = Vec::new();
+
+        let error = interpreter.run(&program, std::io::empty(), &mut output);
+
+        assert_eq!(output.len(), 1024);
+        assert!(matches!(
+            error,
+            Err(Error::Runtime(RuntimeError::OutputLimitExceeded {
+                instruction_index: 2,
+                limit: 1024
+            }))
+        ));
+
+        // A full slice rejects the byte before the limit is reached.
+        let mut buffer = [0; 10];
+        let error = interpreter.run(&program, std::io::empty(), &mut buffer[..]);
+        assert!(matches!(error, Err(Error::Io(_))));
+        assert_eq!(buffer, [1; 10]);
+    }
+
+    /// Test printing preloaded data without reading any input.
+    #[test]
+    fn test_tape_init() {
+        let program = compile(".>.").unwrap();
+        let interpreter = Interpreter::builder().tape_init(0, "HI").build().unwrap();
+        let mut output = Vec::new();
+
+        interpreter
+            .run(&program, std::io::empty(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"HI");
+
+        let interpreter = Interpreter::builder()
+            .tape_len(10)
+            .tape_init(5, "abc")
+            .data_pointer_in_init(1)
+            .build()
+            .unwrap();
+        assert_eq!(interpreter.data_pointer(), 6);
+        let mut vm = interpreter.vm_with_cells::<u16>(&program);
+        vm.run_with(Streams::new(std::io::empty(), &mut output))
+            .unwrap();
+        assert_eq!(output, b"HIbc");
+        assert_eq!(vm.tape()[5], u16::from(b'a'));
+
+        assert_eq!(
+            Interpreter::builder()
+                .tape_len(10)
+                .tape_init(8, "abc")
+                .build(),
+            Err(ConfigError::TapeInitTooLong {
+                end: 11,
+                tape_len: 10
+            })
+        );
+    }
+
+    /// Test that decimal mode prints numbers where byte mode prints bytes.
+    #[test]
+    fn test_decimal_io() {
+        let program = compile("++++++++++.").unwrap();
+        let decimal = Interpreter::builder()
+            .io_mode(IoMode::Decimal { separator: b'\n' })
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+
+        decimal.run(&program, &[][..], &mut output).unwrap();
+        assert_eq!(output, b"10\n");
+
+        output.clear();
+        Interpreter::default()
+            .run(&program, &[][..], &mut output)
+            .unwrap();
+        assert_eq!(output, b"\n");
+
+        // 300 wraps to 44 in a byte and stays 300 in a 16-bit cell;
+        // -1 is the maximum of an unsigned cell.
+        let program = compile(",.,.,.").unwrap();
+        let decimal = Interpreter::builder()
+            .io_mode(IoMode::Decimal { separator: b' ' })
+            .build()
+            .unwrap();
+        output.clear();
+        decimal
+            .run(&program, &b"  300\n-1 7"[..], &mut output)
+            .unwrap();
+        assert_eq!(output, b"44 255 7 ");
+
+        output.clear();
+        decimal
+            .run_with_cells::<u16, _, _>(&program, &b"300 -1"[..], &mut output)
+            .unwrap();
+        assert_eq!(output, b"300 65535 0 ");
+
+        output.clear();
+        decimal
+            .run_with_cells::<i8, _, _>(&program, &b"-1 200 128"[..], &mut output)
+            .unwrap();
+        assert_eq!(output, b"-1 -56 -128 ");
+    }
+
+    /// Test that input that is not a number is an error.
+    #[test]
+    fn test_decimal_malformed() {
+        let program = compile(",.").unwrap();
+        let decimal = Interpreter::builder()
+            .io_mode(IoMode::Decimal { separator: b'\n' })
+            .build()
+            .unwrap();
+
+        for input in ["12a", "-", "x", "--1"] {
+            let error = decimal
+                .run(&program, input.as_bytes(), Vec::new())
+                .unwrap_err();
+            assert_eq!(error.to_string(), "I/O error: expected a decimal number");
+        }
+    }
+
+    /// Test printing and copying characters as UTF-8 with 32-bit cells.
+    #[test]
+    fn test_utf8_io() {
+        let interpreter = Interpreter::builder()
+            .io_mode(IoMode::Utf8)
+            .build()
+            .unwrap();
+        // 0x4E2D is 78 * 256 + 45.
+        let source = format!(
+            "{}[>{}<-]>{}.",
+            "+".repeat(78),
+            "+".repeat(256),
+            "+".repeat(45)
+        );
+        let program = compile(&source).unwrap();
+        let mut output = Vec::new();
+
+        interpreter
+            .run_with_cells::<u32, _, _>(&program, &[][..], &mut output)
+            .unwrap();
+        assert_eq!(output, "中".as_bytes());
+        assert_eq!(output, [0xE4, 0xB8, 0xAD]);
+
+        let cat = compile(",[.,]").unwrap();
+        let text = "naïve 中文 🦀";
+        output.clear();
+        let report = interpreter
+            .run_with_cells::<u32, _, _>(&cat, text.as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(output, text.as_bytes());
+        assert_eq!(report.bytes_read, text.chars().count() as u64);
+    }
+
+    /// Test that surrogates and broken input are rejected.
+    #[test]
+    fn test_utf8_invalid() {
+        let interpreter = Interpreter::builder()
+            .io_mode(IoMode::Utf8)
+            .build()
+            .unwrap();
+        let program = compile(",.").unwrap();
+        let mut output = Vec::new();
+
+        // 0xD800, the first surrogate, is 216 * 256.
+        let source = format!("{}[>{}<-]>.", "+".repeat(216), "+".repeat(256));
+        let error = interpreter
+            .run_with_cells::<u32, _, _>(&compile(&source).unwrap(), &[][..], &mut output)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Runtime(RuntimeError::InvalidScalarValue {
+                instruction_index: 478,
+                value: 0xD800
+            })
+        ));
+        assert!(output.is_empty());
+
+        let error = interpreter
+            .run_with_cells::<u32, _, _>(&program, &[0xE4, 0xB8][..], &mut output)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "I/O error: input is not valid UTF-8");
+    }
+}
@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+
+use crate::{ByteSource, Command, Error, Status, Tape, Vm};
+
+/// Iterator over the bytes a program writes with `.`.
+///
+/// The program only runs while the iterator is advanced, so endless
+/// printers can be consumed with adapters like `take`. Input for `,` is read
+/// from `reader` on demand. After an error the iterator is exhausted.
+#[derive(Debug)]
+pub struct OutputIter<'a, R, T = Vec<u8>> {
+    vm: Vm<'a, T>,
+    reader: R,
+    done: bool,
+}
+
+impl<'a, R: ByteSource> OutputIter<'a, R> {
+    /// Runs the program with the default interpreter settings.
+    pub fn new(commands: &'a [Command], reader: R) -> Self {
+        OutputIter::from_vm(Vm::new(commands), reader)
+    }
+}
+
+impl<'a, R: ByteSource, T: Tape> OutputIter<'a, R, T> {
+    /// Continues running an existing VM.
+    pub fn from_vm(vm: Vm<'a, T>, reader: R) -> Self {
+        OutputIter {
+            vm,
+            reader,
+            done: false,
+        }
+    }
+
+    /// Returns the underlying VM, e.g. to inspect the tape.
+    pub fn into_vm(self) -> Vm<'a, T> {
+        self.vm
+    }
+}
+
+impl<R: ByteSource, T: Tape> Iterator for OutputIter<'_, R, T> {
+    type Item = Result<u8, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let status = match self.vm.run() {
+                Ok(status) => status,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            match status {
+                Status::Running | Status::OutOfFuel | Status::DebugDump => {}
+                Status::NeedsInput => match self.reader.read_byte() {
+                    Ok(Some(byte)) => self.vm.provide_input(byte),
+                    Ok(None) => self.vm.provide_eof(),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e.into()));
+                    }
+                },
+                Status::ProducedOutput(byte) => return Some(Ok(byte)),
+                Status::Halted => self.done = true,
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::compile;
+
+    /// Test iterating the output of the cat program.
+    #[test]
+    fn test_cat_output() {
+        let program = compile(">,[>,]<[<]>[.>]").unwrap();
+
+        let output: Vec<u8> = OutputIter::new(&program, &b"abc"[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(output, b"abc");
+    }
+
+    /// Test taking a prefix of an endless output.
+    #[test]
+    fn test_endless_output() {
+        let program = compile("+[.+]").unwrap();
+
+        let output: Vec<u8> = OutputIter::new(&program, io::empty())
+            .take(200)
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(output.len(), 200);
+        assert_eq!(output[..3], [1, 2, 3]);
+        assert_eq!(output[199], 200);
+    }
+
+    /// Test that the iterator ends once the program halts.
+    #[test]
+    fn test_halted_program() {
+        let program = compile("+.").unwrap();
+        let mut iter = OutputIter::new(&program, io::empty());
+
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,626 @@
+//! Native code for [`Engine::Jit`](crate::Engine::Jit): a program compiled
+//! to x86-64 machine code that runs on the cells of a [`Vm`](crate::Vm).
+//!
+//! The code keeps the pointer, the fuel, and the lowest and highest cells
+//! visited in registers, so `+`, `>`, and the commands the optimizer merges
+//! them into become a few instructions each, and loops real branches. It
+//! only runs what it can without the rest of the VM: any other command, be
+//! it `.`, `,`, a pbrain procedure, or a move off the tape, exits with the
+//! instruction pointer on it, before anything changed, and the VM runs it
+//! as one step. Every I/O and limit setting is thus applied by the VM, and
+//! every error comes from the interpreter, at the same instruction.
+//!
+//! A side table holds where every command starts in the code, so that the
+//! VM can enter it again wherever it stopped.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr;
+
+use crate::{Cell, Command};
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+const RBP: u8 = 5;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+const R12: u8 = 12;
+const R13: u8 = 13;
+const R14: u8 = 14;
+const R15: u8 = 15;
+
+/// Registers the code works with. `RDI` holds the [`Frame`] throughout,
+/// and `RAX`, `RCX`, and `RDX` are scratch.
+const CELLS: u8 = RBX;
+const POINTER: u8 = R12;
+const FUEL: u8 = R13;
+const LEN: u8 = R14;
+const HIGH: u8 = R15;
+const LOW: u8 = RBP;
+
+/// State the code reads on entry and writes back on exit.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Registers {
+    pub(crate) pointer: usize,
+    pub(crate) instruction_pointer: usize,
+    /// Commands the code may still run; it stops on the one it has no fuel
+    /// for.
+    pub(crate) fuel: u64,
+    /// Lowest and highest cells visited, as in the
+    /// [`ExecutionReport`](crate::ExecutionReport).
+    pub(crate) low: usize,
+    pub(crate) high: usize,
+}
+
+/// What the code gets a pointer to, with the fields at fixed offsets.
+#[repr(C)]
+struct Frame {
+    cells: *mut u8,
+    len: usize,
+    registers: Registers,
+}
+
+/// Offsets of the fields of [`Frame`].
+const FRAME_CELLS: i32 = 0;
+const FRAME_LEN: i32 = 8;
+const FRAME_POINTER: i32 = 16;
+const FRAME_IP: i32 = 24;
+const FRAME_FUEL: i32 = 32;
+const FRAME_LOW: i32 = 40;
+const FRAME_HIGH: i32 = 48;
+
+/// A program as machine code.
+#[derive(Debug)]
+pub(crate) struct Native {
+    memory: Memory,
+    /// Offset in the code of every command, and of the exit after the last.
+    offsets: Vec<u32>,
+}
+
+impl Native {
+    /// Compiles `commands`, or nothing if the machine code cannot be mapped
+    /// as executable.
+    pub(crate) fn compile(commands: &[Command]) -> Option<Native> {
+        let (code, offsets) = generate(commands)?;
+        Some(Native {
+            memory: Memory::new(&code)?,
+            offsets,
+        })
+    }
+
+    /// Number of commands of the program.
+    pub(crate) fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Runs the code on `cells` from the instruction pointer on, until it
+    /// halts, runs out of fuel, or reaches a command it leaves to the VM.
+    ///
+    /// # Panics
+    ///
+    /// Panics if cells are more than a byte, or the pointer is past the end
+    /// of the tape, or the instruction pointer past the end of the program.
+    pub(crate) fn run<C: Cell>(&self, cells: &mut [C], registers: &mut Registers) {
+        assert_eq!(size_of::<C>(), 1, "native code runs on cells of one byte");
+        assert!(registers.pointer < cells.len(), "pointer past the tape");
+        let entry = self.offsets[registers.instruction_pointer] as usize;
+        let mut frame = Frame {
+            cells: cells.as_mut_ptr().cast(),
+            len: cells.len(),
+            registers: *registers,
+        };
+        // SAFETY: the code starts with the prologue of a function of this
+        // type, and jumps to `entry`, which is the start of a command. It
+        // only touches the cells after checking their index against `len`,
+        // and one-byte cells are plain bytes.
+        unsafe {
+            let enter: unsafe extern "sysv64" fn(*mut Frame, *const u8) =
+                core::mem::transmute(self.memory.pointer);
+            enter(&mut frame, self.memory.pointer.add(entry));
+        }
+        *registers = frame.registers;
+    }
+}
+
+/// Where code jumps to, by its index in [`Assembler::labels`].
+#[derive(Debug, Clone, Copy)]
+struct Label(usize);
+
+/// Condition codes of `jcc`.
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Zero = 0x84,
+    NotZero = 0x85,
+    AboveOrEqual = 0x83,
+}
+
+/// Encoder of the few x86-64 instructions the code is made of.
+#[derive(Debug, Default)]
+struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    /// Offset of every 32-bit displacement of a jump, and its target.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn rex(&mut self, wide: bool, reg: u8, index: u8, base: u8) {
+        let rex = 0x40 | u8::from(wide) << 3 | (reg >> 3) << 2 | (index >> 3) << 1 | base >> 3;
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    /// `opcode` with the 64-bit registers `reg` and `rm`, or an opcode
+    /// extension in place of `reg`.
+    fn reg_reg(&mut self, opcode: &[u8], reg: u8, rm: u8) {
+        self.rex(true, reg, 0, rm);
+        self.bytes(opcode);
+        self.code.push(0xC0 | (reg & 7) << 3 | rm & 7);
+    }
+
+    /// `opcode` with `reg` and the memory at `base + index + disp`.
+    fn reg_mem(
+        &mut self,
+        wide: bool,
+        opcode: &[u8],
+        reg: u8,
+        base: u8,
+        index: Option<u8>,
+        disp: i32,
+    ) {
+        self.rex(wide, reg, index.unwrap_or(0), base);
+        self.bytes(opcode);
+        // Always with a displacement, which `RBP` and `R13` need as a base.
+        let short = i8::try_from(disp).is_ok();
+        let mode = if short { 0x40 } else { 0x80 };
+        match index {
+            Some(index) => {
+                self.code.push(mode | (reg & 7) << 3 | 4);
+                self.code.push((index & 7) << 3 | base & 7);
+            }
+            None if base & 7 == 4 => {
+                self.code.push(mode | (reg & 7) << 3 | 4);
+                self.code.push(0x24);
+            }
+            None => self.code.push(mode | (reg & 7) << 3 | base & 7),
+        }
+        match short {
+            true => self.code.push(disp as u8),
+            false => self.imm32(disp as u32),
+        }
+    }
+
+    /// The byte-sized `opcode` with the cell at `index`, and `imm` after it.
+    fn cell(&mut self, opcode: u8, extension: u8, index: u8, imm: Option<u8>) {
+        self.reg_mem(false, &[opcode], extension, CELLS, Some(index), 0);
+        self.code.extend(imm);
+    }
+
+    fn jump(&mut self, condition: Option<Condition>, target: Label) {
+        match condition {
+            Some(condition) => self.bytes(&[0x0F, condition as u8]),
+            None => self.code.push(0xE9),
+        }
+        self.fixups.push((self.code.len(), target));
+        self.imm32(0);
+    }
+
+    fn push(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x50 | reg & 7);
+    }
+
+    fn pop(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x58 | reg & 7);
+    }
+
+    /// `RAX` set to the pointer plus `offset`, if that is a cell of the tape,
+    /// and the cells visited updated to it; otherwise a jump to `bail`.
+    fn target(&mut self, offset: i32, bail: Label) {
+        self.reg_mem(true, &[0x8D], RAX, POINTER, None, offset);
+        // Below zero wraps around to above the length.
+        self.reg_reg(&[0x39], LEN, RAX);
+        self.jump(Some(Condition::AboveOrEqual), bail);
+        self.visit(RAX, offset.into());
+    }
+
+    /// The lowest or highest cell visited updated to `reg`, which is `offset`
+    /// away from the pointer.
+    fn visit(&mut self, reg: u8, offset: i64) {
+        if offset > 0 {
+            self.reg_reg(&[0x39], HIGH, reg);
+            // cmova
+            self.reg_reg(&[0x0F, 0x47], HIGH, reg);
+        } else if offset < 0 {
+            self.reg_reg(&[0x39], LOW, reg);
+            // cmovb
+            self.reg_reg(&[0x0F, 0x42], LOW, reg);
+        }
+    }
+
+    /// The code, with every jump pointing at its label.
+    fn finish(mut self) -> Vec<u8> {
+        for &(at, target) in &self.fixups {
+            let target = self.labels[target.0].expect("every label is bound");
+            let rel = target as i64 - (at + 4) as i64;
+            self.code[at..at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+        self.code
+    }
+}
+
+/// Machine code for `commands`, and the offset of every command in it.
+fn generate(commands: &[Command]) -> Option<(Vec<u8>, Vec<u32>)> {
+    use self::Command as C;
+
+    let len = u32::try_from(commands.len()).ok()?;
+    let mut asm = Assembler::default();
+
+    // A function of the `Frame` and the entry point, with the registers
+    // loaded from the frame, and an exit that stores them back.
+    for reg in [RBX, RBP, R12, R13, R14, R15] {
+        asm.push(reg);
+    }
+    for (reg, field) in [
+        (CELLS, FRAME_CELLS),
+        (LEN, FRAME_LEN),
+        (POINTER, FRAME_POINTER),
+        (FUEL, FRAME_FUEL),
+        (LOW, FRAME_LOW),
+        (HIGH, FRAME_HIGH),
+    ] {
+        asm.reg_mem(true, &[0x8B], reg, RDI, None, field);
+    }
+    // jmp rsi
+    asm.reg_reg(&[0xFF], 4, RSI);
+    // Reached with the instruction pointer in `RAX`.
+    let exit = asm.label();
+    asm.bind(exit);
+    for (reg, field) in [
+        (POINTER, FRAME_POINTER),
+        (RAX, FRAME_IP),
+        (FUEL, FRAME_FUEL),
+        (LOW, FRAME_LOW),
+        (HIGH, FRAME_HIGH),
+    ] {
+        asm.reg_mem(true, &[0x89], reg, RDI, None, field);
+    }
+    for reg in [R15, R14, R13, R12, RBP, RBX] {
+        asm.pop(reg);
+    }
+    // ret
+    asm.code.push(0xC3);
+
+    let starts: Vec<_> = (0..=commands.len()).map(|_| asm.label()).collect();
+    // Exits on every command, emitted after all of them.
+    let mut stubs = vec![None; commands.len()];
+    let mut offsets = Vec::with_capacity(commands.len() + 1);
+    for (address, command) in commands.iter().enumerate() {
+        asm.bind(starts[address]);
+        offsets.push(u32::try_from(asm.code.len()).ok()?);
+        let bail = *stubs[address].get_or_insert_with(|| asm.label());
+
+        // test r13, r13
+        asm.reg_reg(&[0x85], FUEL, FUEL);
+        asm.jump(Some(Condition::Zero), bail);
+        let address_after = |target: usize| starts.get(target.checked_add(1)?).copied();
+        match *command {
+            C::Increment => asm.cell(0x80, 0, POINTER, Some(1)),
+            C::Decrement => asm.cell(0x80, 0, POINTER, Some(0xFF)),
+            C::Add(delta) => asm.cell(0x80, 0, POINTER, Some(delta as u8)),
+            C::Set(value) => asm.cell(0xC6, 0, POINTER, Some(value)),
+            C::IncrementDataPointer | C::DecrementDataPointer | C::MovePointer(_) => {
+                let offset = match *command {
+                    C::IncrementDataPointer => 1,
+                    C::DecrementDataPointer => -1,
+                    C::MovePointer(offset) => offset,
+                    _ => unreachable!(),
+                };
+                asm.target(offset, bail);
+                // mov r12, rax
+                asm.reg_reg(&[0x89], RAX, POINTER);
+            }
+            C::AddAt { offset, value } => {
+                asm.target(offset, bail);
+                asm.cell(0x80, 0, RAX, Some(value as u8));
+            }
+            C::SetAt { offset, value } => {
+                asm.target(offset, bail);
+                asm.cell(0xC6, 0, RAX, Some(value));
+            }
+            C::MulAdd { offset, factor } => {
+                // The cell `offset` away is only looked at if this one is
+                // not zero, as in the interpreter.
+                let skip = asm.label();
+                // movzx ecx, byte [cell]; test ecx, ecx
+                asm.reg_mem(false, &[0x0F, 0xB6], RCX, CELLS, Some(POINTER), 0);
+                asm.bytes(&[0x85, 0xC9]);
+                asm.jump(Some(Condition::Zero), skip);
+                asm.target(offset, bail);
+                // imul ecx, ecx, factor; add [target], cl
+                asm.bytes(&[0x69, 0xC9]);
+                asm.imm32(i32::from(factor) as u32);
+                asm.reg_mem(false, &[0x00], RCX, CELLS, Some(RAX), 0);
+                asm.bind(skip);
+            }
+            C::ScanRight(stride) | C::ScanLeft(stride)
+                if (1..=i32::MAX as usize).contains(&stride) =>
+            {
+                let stride = match *command {
+                    C::ScanRight(_) => stride as i32,
+                    _ => -(stride as i32),
+                };
+                // Without a zero before the end of the tape, the interpreter
+                // moves one stride at a time until it fails.
+                let done = asm.label();
+                let next = asm.label();
+                asm.cell(0x80, 7, POINTER, Some(0));
+                asm.jump(Some(Condition::Zero), done);
+                // mov rax, r12
+                asm.reg_reg(&[0x89], POINTER, RAX);
+                asm.bind(next);
+                asm.reg_mem(true, &[0x8D], RAX, RAX, None, stride);
+                asm.reg_reg(&[0x39], LEN, RAX);
+                asm.jump(Some(Condition::AboveOrEqual), bail);
+                asm.cell(0x80, 7, RAX, Some(0));
+                asm.jump(Some(Condition::NotZero), next);
+                asm.reg_reg(&[0x89], RAX, POINTER);
+                asm.visit(POINTER, stride.into());
+                asm.bind(done);
+            }
+            C::ClearRange { len } => {
+                let last = len - len.signum();
+                let count = len.unsigned_abs().max(1);
+                let next = asm.label();
+                asm.target(last, bail);
+                // mov rcx, <first>; mov edx, count
+                let first = if len < 0 { RAX } else { POINTER };
+                asm.reg_reg(&[0x89], first, RCX);
+                asm.code.push(0xBA);
+                asm.imm32(count);
+                asm.bind(next);
+                asm.cell(0xC6, 0, RCX, Some(0));
+                // inc rcx; dec rdx
+                asm.reg_reg(&[0xFF], 0, RCX);
+                asm.reg_reg(&[0xFF], 1, RDX);
+                asm.jump(Some(Condition::NotZero), next);
+                asm.reg_reg(&[0x89], RAX, POINTER);
+            }
+            C::JumpForwardIfZero(target) | C::JumpBackwardIfNonZero(target)
+                if address_after(target).is_some() =>
+            {
+                // The fuel goes first, since `dec` sets the flags too.
+                asm.reg_reg(&[0xFF], 1, FUEL);
+                asm.cell(0x80, 7, POINTER, Some(0));
+                let condition = match *command {
+                    C::JumpForwardIfZero(_) => Condition::Zero,
+                    _ => Condition::NotZero,
+                };
+                asm.jump(Some(condition), address_after(target).unwrap());
+                continue;
+            }
+            _ => {
+                asm.jump(None, bail);
+                continue;
+            }
+        }
+        // dec r13
+        asm.reg_reg(&[0xFF], 1, FUEL);
+    }
+    asm.bind(starts[commands.len()]);
+    offsets.push(u32::try_from(asm.code.len()).ok()?);
+    let exit_at = |asm: &mut Assembler, address: u32| {
+        // mov eax, address
+        asm.code.push(0xB8);
+        asm.imm32(address);
+        asm.jump(None, exit);
+    };
+    exit_at(&mut asm, len);
+    for (address, stub) in stubs.into_iter().enumerate() {
+        if let Some(stub) = stub {
+            asm.bind(stub);
+            exit_at(&mut asm, address as u32);
+        }
+    }
+    Some((asm.finish(), offsets))
+}
+
+/// Pages mapped as executable, holding the code.
+#[derive(Debug)]
+struct Memory {
+    pointer: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the pages are never written after they are mapped.
+unsafe impl Send for Memory {}
+unsafe impl Sync for Memory {}
+
+impl Memory {
+    fn new(code: &[u8]) -> Option<Memory> {
+        let len = code.len();
+        // SAFETY: a fresh private mapping, written before it is made
+        // executable and unmapped on failure.
+        unsafe {
+            let pointer = libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if pointer == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), pointer.cast(), len);
+            if libc::mprotect(pointer, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                libc::munmap(pointer, len);
+                return None;
+            }
+            Some(Memory {
+                pointer: pointer.cast(),
+                len,
+            })
+        }
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        // SAFETY: mapped in `Memory::new`, and no `Native` is left to run it.
+        unsafe {
+            libc::munmap(self.pointer.cast(), self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{Engine, OverflowPolicy, RuntimeError, Status, Vm, compile, optimize};
+
+    /// Every status of `vm` run to the end `fuel` commands at a time, with
+    /// the input served from `input`, and its tape and report after that.
+    fn trace(mut vm: Vm<'_>, fuel: u64, input: &[u8]) -> impl PartialEq + core::fmt::Debug {
+        let mut input = input.iter();
+        let mut statuses = Vec::new();
+        let result = loop {
+            match vm.run_for(fuel) {
+                Ok(Status::NeedsInput) => match input.next() {
+                    Some(&byte) => vm.provide_input(byte),
+                    None => vm.provide_eof(),
+                },
+                Ok(Status::Halted) => break Ok(()),
+                Ok(status) => statuses.push(status),
+                Err(e) => break Err(e),
+            }
+        };
+        (statuses, result, *vm.report(), vm.into_tape())
+    }
+
+    /// Test that the native code runs programs as the interpreter does,
+    /// with every optimized command, at every stop for fuel, and where it
+    /// fails.
+    #[test]
+    fn test_native_agrees() {
+        let programs: [(&str, &[u8]); 9] = [
+            ("++++++[>++++++++<-]>[>+>+<<-]>[->[.+]<]", b""),
+            (">,[>,]<[<]>[.>]", b"round trip"),
+            ("+++[>+++++<-]>[>+>++>+++<<<-]>>>[<]>[-]>[-]<<<.", b""),
+            ("+>>>+>>>+<<<<<<[>>>]>.[-]<<<[<<<]>+[>>>>]", b""),
+            ("-[->+>+<<]>>[-<+>]<[-]-[>]", b""),
+            ("+[>+]", b""),
+            ("+[<+]", b""),
+            (">>>>+>+>+<<[-]>[-]>[-]<<<<<<+[<<]", b""),
+            ("++[>>>-<<<-]>>>.<<<<", b""),
+        ];
+        for (source_code, input) in programs {
+            let commands = compile(source_code).unwrap();
+            for commands in [optimize(&commands, OverflowPolicy::Wrap), commands] {
+                for (tape_len, pointer) in [(16, 0), (16, 3), (300, 0)] {
+                    for fuel in [u64::MAX, 1, 2, 5] {
+                        let [matched, native] = [Engine::Match, Engine::Jit].map(|engine| {
+                            let vm = Vm::with_tape(&commands, vec![0; tape_len], pointer)
+                                .with_engine(engine);
+                            trace(vm, fuel, input)
+                        });
+                        assert_eq!(matched, native, "{source_code} with fuel {fuel}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Test that errors are reported at the command that failed, from both
+    /// ends of the tape, and that cells that cannot run natively still run.
+    #[test]
+    fn test_native_errors() {
+        let commands = optimize(&compile("+[>+]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&commands, vec![0_u8; 4], 0).with_engine(Engine::Jit);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 2,
+                pointer: 4
+            })
+        );
+        assert_eq!(vm.report().steps, 2 + 3 * 3);
+
+        let commands = compile("<").unwrap();
+        let mut vm = Vm::with_tape(&commands, vec![0_u16; 4], 0).with_engine(Engine::Jit);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 0,
+                pointer: -1
+            })
+        );
+    }
+
+    /// Test that a pointer that starts past the tape is left to the
+    /// interpreter, which moves it back or panics, rather than run natively.
+    #[test]
+    fn test_native_pointer_past_tape() {
+        let commands = compile("<<+.").unwrap();
+        let [matched, native] = [Engine::Match, Engine::Jit].map(|engine| {
+            let vm = Vm::with_tape(&commands, vec![0_u8; 4], 5).with_engine(engine);
+            trace(vm, u64::MAX, b"")
+        });
+        assert_eq!(matched, native);
+
+        let commands = compile("+").unwrap();
+        let result = std::panic::catch_unwind(|| {
+            Vm::with_tape(&commands, vec![0_u8; 4], 1 << 40)
+                .with_engine(Engine::Jit)
+                .run()
+        });
+        assert!(result.is_err());
+    }
+
+    /// Test that a long arithmetic program runs faster natively, and with
+    /// the same output and report.
+    #[test]
+    fn test_native_speed() {
+        // Nested loops that count down 65,536 times, printing every 256th.
+        let source_code = "++++[>++++<-]>[>++++++++++++++++[>-[>+>++<<-]>[<+>-]<<-]>.<<-]";
+        let commands = optimize(&compile(source_code).unwrap(), OverflowPolicy::Wrap);
+        let [(matched, interpreted), (native, compiled)] =
+            [Engine::Match, Engine::Jit].map(|engine| {
+                let vm = Vm::with_tape(&commands, vec![0; 16], 0).with_engine(engine);
+                let started = Instant::now();
+                let trace = trace(vm, u64::MAX, b"");
+                (trace, started.elapsed())
+            });
+        assert_eq!(matched, native);
+        assert!(
+            compiled < interpreted,
+            "{compiled:?} natively, {interpreted:?} interpreted"
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! A small Brainfuck interpreter.
+//!
+//! Source code is turned into a flat list of [`Command`]s by [`compile`] and
+//! executed with [`eval`] or [`eval_on_tape`] against any `Read`/`Write` pair.
+//! Use [`Interpreter::builder`] to configure the tape before running, or
+//! [`Vm`] to drive execution step by step from the outside.
+//!
+//! The `std` feature is enabled by default. Without it the crate only needs
+//! `alloc`, and I/O goes through the [`ByteSource`] and [`ByteSink`] traits.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use compiler::Compiler;
+
+mod ast;
+#[cfg(feature = "tokio")]
+mod async_eval;
+#[cfg(feature = "std")]
+mod batch;
+mod bfc;
+mod blocks;
+mod bounds;
+mod bytes;
+mod cell;
+mod compiler;
+mod decompile;
+mod dialect;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod format;
+mod generate;
+mod handler;
+mod html;
+mod interpreter;
+mod iter;
+#[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+mod jit;
+mod mapped;
+#[cfg(feature = "std")]
+mod newline;
+mod observe;
+mod optimize;
+mod packed;
+mod partial_eval;
+#[cfg(feature = "std")]
+mod pipe;
+mod preprocess;
+mod profile;
+mod program;
+mod report;
+mod snapshot;
+mod tape;
+mod transpile;
+mod validate;
+mod visit;
+mod vm;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod wasm_module;
+
+pub use ast::{Ast, lower, parse_ast};
+#[cfg(feature = "tokio")]
+pub use async_eval::{YIELD_INTERVAL, eval_async};
+#[cfg(feature = "std")]
+pub use batch::run_batch;
+pub use bfc::{BFC_MAGIC, BFC_VERSION, BfcError, BfcHeader};
+pub use bytes::{ByteSink, ByteSource, IoError};
+pub use cell::Cell;
+#[cfg(feature = "std")]
+pub use compiler::compile_from_reader;
+pub use compiler::{
+    DEFAULT_MAX_DEPTH, IncrementalCompiler, PushResult, compile_pbrain, compile_strict,
+    compile_with_debug_dumps, compile_with_max_depth, compile_with_random, find_errors,
+};
+pub use decompile::{to_ir, to_listing, to_source};
+pub use dialect::{DialectError, TokenMap, from_ook};
+pub use error::{Error, RuntimeError};
+pub use format::{format_source, minify, strip_comments};
+pub use generate::generate_printer;
+pub use handler::{DecimalIo, FnHandler, IoHandler, Streams, io_handler_fn};
+pub use html::export_html;
+pub use interpreter::{
+    ConfigError, DEFAULT_TAPE_LEN, Engine, EofBehavior, Interpreter, InterpreterBuilder, IoMode,
+    OverflowPolicy,
+};
+pub use iter::OutputIter;
+pub use mapped::{MappedCell, MappedTape};
+#[cfg(feature = "std")]
+pub use newline::{Newline, NewlineReader, NewlineWriter};
+pub use observe::Observer;
+pub use optimize::{OptLevel, Pass, Pipeline, eliminate_dead_code, optimize};
+pub use packed::{Bytecode, pack};
+pub use partial_eval::{DEFAULT_PARTIAL_EVAL_STEPS, partially_evaluate};
+#[cfg(feature = "std")]
+pub use pipe::{VmReader, VmWriter};
+pub use preprocess::{
+    CommentStyle, MacroError, SourceMap, blank_comments, expand_macros, expand_macros_with_map,
+};
+pub use profile::{LoopProfile, LoopProfiler};
+pub use program::Program;
+pub use report::{ExecutionReport, InstructionStats};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use tape::{GrowableTape, PAGE_LEN, PagedTape, SparseTape, Tape, TapeError};
+pub use transpile::{TranspileError, to_c, to_rust};
+pub use validate::{JumpError, validate};
+pub use visit::{Fold, MergeRuns, Visitor, walk, walk_fold, walk_nodes};
+pub use vm::{CLOCK_INTERVAL, DEBUG_WINDOW, DEFAULT_MAX_CALL_DEPTH, DebugDump, Status, Vm};
+#[cfg(feature = "wasm")]
+pub use wasm::bf_run;
+pub use wasm_module::{WASM_CELL_OVERFLOW, WASM_LEFT_OF_TAPE, WASM_RIGHT_OF_TAPE, to_wasm};
+
+/// Enum representing Brainfuck commands.
+/// Jump instructions use command addresses for loop execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Command {
+    IncrementDataPointer,
+    DecrementDataPointer,
+    Increment,
+    Decrement,
+    WriteByte,
+    ReadByte,
+    /// `[`: if the current cell is zero, continue after the command at the given address.
+    JumpForwardIfZero(CommandAddress),
+    /// `]`: if the current cell is non-zero, continue after the command at the given address.
+    JumpBackwardIfNonZero(CommandAddress),
+    /// `#`: hand a [`DebugDump`] of the tape to the host. Only emitted by
+    /// [`compile_with_debug_dumps`]; [`compile`] skips `#` like any comment.
+    DebugDump,
+    /// pbrain `(`: define the procedure numbered by the current cell as the
+    /// commands up to the `)` at the given address, and continue after it.
+    /// Only emitted by [`compile_pbrain`].
+    BeginProc(CommandAddress),
+    /// pbrain `)`: return from the procedure that begins at the given address.
+    EndProc(CommandAddress),
+    /// pbrain `:`: call the procedure numbered by the current cell.
+    Call,
+    /// `?`: set the current cell to a pseudo-random byte from the seeded
+    /// generator of the [`Vm`]. Only emitted by [`compile_with_random`].
+    Random,
+    /// A run of `+` (positive) or `-` (negative) executed as one command.
+    /// Only emitted by [`optimize`].
+    Add(i16),
+    /// A run of `>` (positive) or `<` (negative) executed as one command.
+    /// Only emitted by [`optimize`].
+    MovePointer(i32),
+    /// A loop like `[-]` that sets the current cell to the given byte in
+    /// one step. Only emitted by [`optimize`].
+    Set(u8),
+    /// Unless the current cell is zero, adds `factor` times its value to the
+    /// cell `offset` away, wrapping. A few of these followed by `Set(0)` do
+    /// what a loop like `[->+>+++<<]` does, in constant time. Only emitted
+    /// by [`optimize`].
+    MulAdd {
+        offset: i32,
+        factor: i16,
+    },
+    /// A loop like `[>]` or `[>>>]` that moves the pointer right by the
+    /// given stride until it reaches a zero cell, searching the tape
+    /// directly. Only emitted by [`optimize`].
+    ScanRight(usize),
+    /// A loop like `[<]` that moves the pointer left by the given stride
+    /// until it reaches a zero cell. Only emitted by [`optimize`].
+    ScanLeft(usize),
+    /// Adds `value` to the cell `offset` away from the current one, like
+    /// `>>+<<` without moving the pointer. Only emitted by [`optimize`].
+    AddAt {
+        offset: i32,
+        value: i16,
+    },
+    /// Sets the cell `offset` away to `value`. Only emitted by [`optimize`].
+    SetAt {
+        offset: i32,
+        value: u8,
+    },
+    /// `.` on the cell the given offset away. Only emitted by [`optimize`].
+    OutputAt(i32),
+    /// `,` into the cell the given offset away. Only emitted by [`optimize`].
+    InputAt(i32),
+    /// Sets `len` cells to zero, starting with the current one and going
+    /// right, or left for a negative `len`, and leaves the pointer on the
+    /// last of them, like `[-]>[-]>[-]` for a `len` of 3, in one step. Only
+    /// emitted by [`optimize`].
+    ClearRange {
+        len: i32,
+    },
+}
+
+/// Index of a command inside a compiled program.
+///
+/// Jump commands store the address of their matching bracket: a `[` at
+/// index `a` paired with a `]` at index `b` compiles to
+/// `JumpForwardIfZero(b)` at `a` and `JumpBackwardIfNonZero(a)` at `b`.
+/// Execution resumes at the command right after the target.
+pub type CommandAddress = usize;
+
+/// Enum for possible parsing errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsingError {
+    /// The `bracket` at byte `offset` of the source has no partner.
+    UnmatchedBracket { offset: usize, bracket: char },
+    /// The character `ch` at byte `offset` is neither a command nor a
+    /// comment; only reported by [`compile_strict`].
+    UnexpectedCharacter { offset: usize, ch: char },
+    /// The `[` at byte `offset` opens a loop at nesting `depth`, which is
+    /// one more than the limit, see [`DEFAULT_MAX_DEPTH`].
+    NestingTooDeep { offset: usize, depth: usize },
+}
+
+/// Parses Brainfuck source code into a vector of `Command` instructions.
+/// Ensures that brackets are correctly matched and links each jump to its partner.
+/// Loops may be nested up to [`DEFAULT_MAX_DEPTH`] deep.
+pub fn compile(text: &str) -> Result<Vec<Command>, ParsingError> {
+    let mut compiler = Compiler::default();
+    compiler.push(text.as_bytes())?;
+    compiler.finish()
+}
+
+/// Splits source code at its first `!` into the program and its input,
+/// following the convention of dbfi and other interpreters that read both
+/// from one text.
+///
+/// Without a `!` the whole text is the program and the input is empty.
+pub fn split_bang(source: &str) -> (&str, &[u8]) {
+    match source.split_once('!') {
+        Some((program, input)) => (program, input.as_bytes()),
+        None => (source, &[]),
+    }
+}
+
+/// Executes compiled Brainfuck commands on a memory tape, see [`Tape`].
+/// Handles input/output operations via the provided streams, e.g. a `Read`
+/// and a `Write`.
+/// Returns the counters collected during the run.
+pub fn eval_on_tape<T: Tape, R: ByteSource, W: ByteSink>(
+    commands: &[Command],
+    tape: T,
+    data_pointer: usize,
+    reader: R,
+    writer: W,
+) -> Result<ExecutionReport, Error> {
+    Vm::with_tape(commands, tape, data_pointer).run_with(Streams::new(reader, writer))
+}
+
+/// Wrapper function to initialize memory and execute a Brainfuck program.
+///
+/// Uses the default [`Interpreter`] settings: 30,000 cells with the data
+/// pointer on the first one. Pass a [`GrowableTape`] to [`eval_on_tape`] for
+/// a tape without ends.
+pub fn eval<R: ByteSource, W: ByteSink>(
+    commands: &[Command],
+    reader: R,
+    writer: W,
+) -> Result<ExecutionReport, Error> {
+    Interpreter::default().run(commands, reader, writer)
+}
+
+/// Same as [`eval`], but calls `observer` before every command.
+/// Returns early, without an error, when the observer breaks.
+pub fn eval_observed<R: ByteSource, W: ByteSink, O: Observer>(
+    commands: &[Command],
+    reader: R,
+    writer: W,
+    observer: O,
+) -> Result<ExecutionReport, Error> {
+    Interpreter::default()
+        .vm(commands)
+        .run_observed(Streams::new(reader, writer), observer)
+}
+
+/// Compiles and runs a program in one call, collecting its output.
+pub fn run_to_bytes(source: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let program = compile(source)?;
+    let mut output = Vec::new();
+    eval(&program, input, &mut output)?;
+    Ok(output)
+}
+
+/// Same as [`run_to_bytes`], but also decodes the output as UTF-8.
+/// Invalid output is reported as [`Error::Utf8`].
+pub fn run_to_string(source: &str, input: &[u8]) -> Result<String, Error> {
+    Ok(String::from_utf8(run_to_bytes(source, input)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test Brainfuck loop [->+<] which transfers a value from one cell to another.
+    #[test]
+    fn test_eval_add() {
+        let mut tape = [1_u8, 2];
+        let data_pointer = 0;
+
+        // [->+<]
+        let commands = [
+            Command::JumpForwardIfZero(5),
+            Command::Decrement,
+            Command::IncrementDataPointer,
+            Command::Increment,
+            Command::DecrementDataPointer,
+            Command::JumpBackwardIfNonZero(0),
+        ];
+
+        let reader = &[0_u8][..];
+        let writer = &mut [0_u8][..];
+
+        eval_on_tape(&commands, &mut tape, data_pointer, reader, writer).unwrap();
+
+        assert_eq!(tape[0], 0);
+        assert_eq!(tape[1], 1 + 2);
+    }
+
+    /// Test full "Hello World!" Brainfuck program.
+    #[test]
+    fn test_hello_world() {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let reader = &[0_u8][..];
+        let mut writer: Vec<u8> = Vec::new();
+
+        let program = compile(source_code).unwrap();
+        eval(&program, reader, &mut writer).unwrap();
+
+        assert_eq!(writer, "Hello World!\n".as_bytes());
+
+        let report = eval(&compile(source_code).unwrap(), &[][..], std::io::sink()).unwrap();
+        assert_eq!(report.bytes_written, 13);
+        assert_eq!(report.bytes_read, 0);
+    }
+
+    /// Test simple echo program that copies input to output.
+    #[test]
+    fn test_cat() {
+        let source_code = ">,[>,]<[<]>[.>]";
+        let reader = "Hello, World!\0".as_bytes();
+        let mut writer: Vec<u8> = Vec::new();
+
+        let program = compile(source_code).unwrap();
+        eval(&program, reader, &mut writer).unwrap();
+
+        assert_eq!(writer, reader[..reader.len() - 1]);
+    }
+
+    /// Test that input after `!` reaches the program through `,` instead of
+    /// being skipped like other text.
+    #[test]
+    fn test_split_bang() {
+        let source_code = "read a line: ,[.,] ! done!\nrest";
+        let (program, input) = split_bang(source_code);
+        assert_eq!(input, b" done!\nrest");
+
+        let mut output = Vec::new();
+        let report = eval(&compile(program).unwrap(), input, &mut output).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(report.bytes_read, 11);
+
+        assert_eq!(split_bang("+."), ("+.", &[][..]));
+        assert!(run_to_bytes(source_code, &[]).unwrap().is_empty());
+    }
+
+    /// Test that the one-shot helpers return what the program wrote.
+    #[test]
+    fn test_run_to_string() {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        assert_eq!(run_to_string(source_code, &[]).unwrap(), "Hello World!\n");
+        assert_eq!(run_to_bytes(",[.,]", b"echo").unwrap(), b"echo");
+    }
+
+    /// Test that each failure of the one-shot helpers is reported distinctly.
+    #[test]
+    fn test_run_errors() {
+        assert!(matches!(
+            run_to_bytes("[", &[]),
+            Err(Error::Parse(ParsingError::UnmatchedBracket {
+                offset: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            run_to_bytes("<", &[]),
+            Err(Error::Runtime(RuntimeError::PointerOutOfBounds { .. }))
+        ));
+
+        // Prints the single byte 0x80, which is not valid UTF-8.
+        let source_code = "++++++++[>++++++++++++++++<-]>.";
+        assert_eq!(run_to_bytes(source_code, &[]).unwrap(), [0x80]);
+        match run_to_string(source_code, &[]) {
+            Err(Error::Utf8(e)) => assert_eq!(e.into_bytes(), [0x80]),
+            other => panic!("expected a UTF-8 error, got {other:?}"),
+        }
+    }
+}
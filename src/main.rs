@@ -1,204 +1,1299 @@
-use std::io::{self, ErrorKind, Read, Write};
-
-/// Enum representing Brainfuck commands.
-/// Jump instructions use command addresses for loop execution.
-#[derive(Debug)]
-enum Command {
-    IncrementDataPointer,
-    DecrementDataPointer,
-    Increment,
-    Decrement,
-    WriteByte,
-    ReadByte,
-    JumpForwardIfZero(CommandAddress),
-    JumpBackwardIfNonZero(CommandAddress),
-}
-
-type CommandAddress = usize;
-
-/// Enum for possible parsing errors.
-/// Currently, it only detects unmatched brackets.
-#[derive(Debug)]
-enum ParsingError {
-    UnmatchedBracket(CommandAddress),
-}
-
-/// Parses Brainfuck source code into a vector of `Command` instructions.
-/// Ensures that brackets are correctly matched and swaps jump commands accordingly.
-fn compile(text: &str) -> Result<Vec<Command>, ParsingError> {
-    use self::Command as C;
-
-    let charset = "><+-.,[]";
-
-    let mut brackets_stack = Vec::new();
-    let mut brackets_swaps = Vec::new();
-
-    let tokens: Vec<char> = text.chars().filter(|c| charset.contains(*c)).collect();
-    let mut commands = Vec::with_capacity(tokens.len());
-
-    for (i, t) in tokens.into_iter().enumerate() {
-        let command = match t {
-            '>' => C::IncrementDataPointer,
-            '<' => C::DecrementDataPointer,
-            '+' => C::Increment,
-            '-' => C::Decrement,
-            '.' => C::WriteByte,
-            ',' => C::ReadByte,
-            '[' => {
-                brackets_stack.push(i);
-                C::JumpBackwardIfNonZero(i)
-            }
-            ']' => {
-                if let Some(matching_index) = brackets_stack.pop() {
-                    brackets_swaps.push((matching_index, i));
-                    C::JumpForwardIfZero(i)
-                } else {
-                    return Err(ParsingError::UnmatchedBracket(i));
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Read, Write};
+use std::ops::ControlFlow;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod bench;
+mod build;
+mod cache;
+mod check;
+mod hotspots;
+mod options;
+mod repl;
+mod source;
+mod stats;
+mod terminal;
+mod verify;
+
+use bench::Summary;
+use cache::{OutputCache, ProgramCache, Recorder};
+use check::Diagnostic;
+use hotspots::Hotspots;
+use options::{
+    ArgError, BENCH_USAGE, BUILD_USAGE, CHECK_USAGE, COMPILE_USAGE, CellSize, DISASM_USAGE,
+    Dialect, EXPORT_USAGE, FMT_USAGE, GEN_USAGE, HOTSPOTS_USAGE, MINIFY_USAGE, Options, REPL_USAGE,
+    StatsFormat, Target, USAGE, VERIFY_USAGE, parse_args, parse_bench_args, parse_build_args,
+    parse_check_args, parse_compile_args, parse_disasm_args, parse_export_args, parse_fmt_args,
+    parse_gen_args, parse_hotspots_args, parse_minify_args, parse_repl_args, parse_verify_args,
+};
+use repl::{Meta, Session};
+use source::Source;
+
+use brainfuck_vm::{
+    Cell, Command, DEFAULT_PARTIAL_EVAL_STEPS, Error, ExecutionReport, IncrementalCompiler,
+    Interpreter, InterpreterBuilder, LoopProfiler, NewlineReader, NewlineWriter, Observer,
+    OptLevel, PagedTape, Pass, Pipeline, Program, PushResult, RuntimeError, Streams,
+    TranspileError, blank_comments, compile, compile_pbrain, compile_strict,
+    compile_with_debug_dumps, compile_with_random, eliminate_dead_code, expand_macros_with_map,
+    export_html, find_errors, format_source, from_ook, generate_printer, minify,
+    partially_evaluate, split_bang, strip_comments, to_c, to_ir, to_listing, to_rust, to_wasm,
+};
+
+/// Exit code for a program that ran out of steps, so scripts can tell it
+/// apart from programs that failed.
+const EXIT_STEP_LIMIT: u8 = 3;
+
+/// Exit code for a program that was still running when the timeout expired.
+const EXIT_TIMEOUT: u8 = 4;
+
+/// Exit code for a program that tried to write more than `--max-output`.
+const EXIT_OUTPUT_LIMIT: u8 = 5;
+
+/// Exit code for a program whose tape needs more than `--max-memory`.
+const EXIT_MEMORY_LIMIT: u8 = 6;
+
+/// Exit code for a command line that could not be parsed, so scripts can
+/// tell it apart from programs that failed.
+const EXIT_USAGE: u8 = 2;
+
+/// Arguments after the name of a subcommand.
+type Args = std::vec::IntoIter<String>;
+
+/// Function that runs a subcommand on its arguments.
+type Subcommand = fn(Args) -> ExitCode;
+
+/// Every subcommand with the function that runs it.
+const SUBCOMMANDS: [(&str, Subcommand); 13] = [
+    ("run", run_program),
+    ("check", check),
+    ("repl", interact),
+    ("gen", generate),
+    ("fmt", format),
+    ("minify", shrink),
+    ("export-html", export),
+    ("compile", translate),
+    ("build", build_native),
+    ("verify", verify_program),
+    ("disasm", disassemble),
+    ("bench", benchmark),
+    ("hotspots", find_hotspots),
+];
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--help" | "-h") => {
+            let _ = writeln!(io::stdout().lock(), "{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Some("--version" | "-V") => {
+            let _ = writeln!(
+                io::stdout().lock(),
+                "brainfuck_vm {}",
+                env!("CARGO_PKG_VERSION")
+            );
+            return ExitCode::SUCCESS;
+        }
+        _ => {}
+    }
+    // Without a subcommand the arguments are those of `run`, as they were
+    // before there were any.
+    let subcommand = SUBCOMMANDS
+        .iter()
+        .find(|(name, ..)| args.first().is_some_and(|arg| arg == name));
+    let subcommand = match subcommand {
+        Some(&(_, subcommand)) => {
+            args.remove(0);
+            subcommand
+        }
+        None => run_program as Subcommand,
+    };
+    subcommand(args.into_iter())
+}
+
+/// Reports a command line that could not be parsed, with the usage of the
+/// subcommand it was meant for if it is malformed, or prints the usage on
+/// its own if that was asked for.
+fn arg_error(error: ArgError, usage: &str) -> ExitCode {
+    match error {
+        ArgError::Help => {
+            let _ = writeln!(io::stdout().lock(), "{usage}");
+            ExitCode::SUCCESS
+        }
+        ArgError::Usage(message) => {
+            eprintln!("{message}\n{usage}");
+            ExitCode::from(EXIT_USAGE)
+        }
+        ArgError::Io(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `run`, which runs the program with its input and output on stdin
+/// and stdout.
+fn run_program(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, USAGE),
+    };
+
+    let started = Instant::now();
+    let result = run(&options);
+    if options.time {
+        eprintln!("total: {:.2?}", started.elapsed());
+    }
+    if let (Ok(report), Some(format)) = (&result, options.stats)
+        && let Some(counted) = &report.stats
+    {
+        match format {
+            StatsFormat::Table => eprintln!("{}", stats::table(counted)),
+            StatsFormat::Json => eprintln!("{}", stats::to_json(counted)),
+        }
+    }
+    match result {
+        // Exit codes are bytes, so the cell is taken modulo 256.
+        Ok(report) if options.exit_cell => ExitCode::from(report.final_cell),
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            print_error(&options, &e);
+            match e {
+                Error::Runtime(RuntimeError::StepLimitExceeded { .. }) => {
+                    ExitCode::from(EXIT_STEP_LIMIT)
+                }
+                Error::Runtime(RuntimeError::Timeout { .. }) => ExitCode::from(EXIT_TIMEOUT),
+                Error::Runtime(RuntimeError::OutputLimitExceeded { .. }) => {
+                    ExitCode::from(EXIT_OUTPUT_LIMIT)
+                }
+                Error::Runtime(RuntimeError::MemoryLimitExceeded { .. }) => {
+                    ExitCode::from(EXIT_MEMORY_LIMIT)
                 }
+                _ => ExitCode::FAILURE,
             }
-            _ => unreachable!(),
-        };
-        commands.push(command);
+        }
     }
+}
 
-    if !brackets_stack.is_empty() {
-        return Err(ParsingError::UnmatchedBracket(brackets_stack[0]));
+/// Prints `e` to stderr, pointing parse errors at the source file.
+fn print_error(options: &Options, e: &Error) {
+    match e {
+        Error::Parse(error) => {
+            eprintln!(
+                "parse error: {}",
+                options.source.describe_error(error.clone())
+            )
+        }
+        e => eprintln!("{e}"),
     }
+}
 
-    for (a, b) in brackets_swaps {
-        commands.swap(a, b);
+/// Runs `check`, which compiles every program as `run` would, without
+/// running it, and reports all errors found in each.
+fn check(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_check_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, CHECK_USAGE),
+    };
+
+    let run = &mut options.run;
+    let mut diagnostics = Vec::new();
+    let mut failed = false;
+    for next in std::iter::once(None).chain(options.files.iter().map(Some)) {
+        if let Some(path) = next {
+            // A `.bfc` file only stands in for the first program.
+            run.compiled = None;
+            match Source::read(path) {
+                Ok(source) => run.source = source,
+                Err(message) => {
+                    failed = true;
+                    match options.json {
+                        true => diagnostics.push(Diagnostic::other(Some(path), message)),
+                        false => eprintln!("{message}"),
+                    }
+                    continue;
+                }
+            }
+        }
+        for error in find_all_errors(run) {
+            failed = true;
+            match (options.json, error) {
+                (true, Error::Parse(error)) => {
+                    diagnostics.push(Diagnostic::parse(run.source.locate(error)));
+                }
+                (true, e) => diagnostics.push(Diagnostic::other(run.source.path(), e.to_string())),
+                (false, e) => print_error(run, &e),
+            }
+        }
+    }
+
+    if options.json {
+        println!("{}", check::to_json(&diagnostics));
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
     }
+}
 
-    Ok(commands)
+/// Compiles the program like [`load_program`], and returns every error of
+/// its source code if it fails, or the one error that stopped it where the
+/// source is changed before compiling.
+fn find_all_errors(options: &Options) -> Vec<Error> {
+    let source_code = match options.bang_input {
+        true => split_bang(&options.source.text).0,
+        false => &options.source.text,
+    };
+    let first = match load_program(options, source_code) {
+        Ok(_) => return Vec::new(),
+        Err(e @ Error::Parse(_)) => e,
+        Err(e) => return vec![e],
+    };
+    let errors = match options.compiled.is_none() && written_as_is(options) {
+        true => find_errors(source_code, options.strict),
+        false => Vec::new(),
+    };
+    match errors.is_empty() {
+        true => vec![first],
+        false => errors.into_iter().map(Error::Parse).collect(),
+    }
 }
 
-/// Executes compiled Brainfuck commands on a memory tape.
-/// Handles input/output operations via provided `Read` and `Write` streams.
-fn eval_on_tape<R: Read, W: Write>(
-    commands: &[Command],
-    tape: &mut [u8],
-    mut data_pointer: usize,
-    mut reader: R,
-    mut writer: W,
-) -> io::Result<()> {
-    use self::Command as C;
+/// Whether the source is compiled as it is written, so that where its
+/// brackets are can be told from the text alone.
+fn written_as_is(options: &Options) -> bool {
+    options.dialect == Dialect::Brainfuck
+        && options.token_map.is_none()
+        && options.comments.is_none()
+        && !options.macros
+}
 
-    let mut instruction_pointer = 0;
+/// Runs `repl`, which runs every entry read from stdin as a program on the
+/// tape the entry before left.
+fn interact(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_repl_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, REPL_USAGE),
+    };
 
-    while instruction_pointer < commands.len() {
-        let command = &commands[instruction_pointer];
+    // Removing the loops at the start of a program assumes a blank tape,
+    // which only the first entry starts on.
+    options.disabled_passes.push(Pass::DeadCode);
+    let interpreter = match configure(&options).build() {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match options.cell_size {
+        CellSize::Eight => interact_with_cells::<u8>(&mut options, &interpreter),
+        CellSize::Sixteen => interact_with_cells::<u16>(&mut options, &interpreter),
+        CellSize::ThirtyTwo => interact_with_cells::<u32>(&mut options, &interpreter),
+        CellSize::SignedEight => interact_with_cells::<i8>(&mut options, &interpreter),
+    }
+}
 
-        match command {
-            C::IncrementDataPointer => data_pointer += 1,
-            C::DecrementDataPointer => data_pointer -= 1,
-            C::Increment => tape[data_pointer] += 1,
-            C::Decrement => tape[data_pointer] -= 1,
-            C::WriteByte => {
-                writer.write(&tape[data_pointer..data_pointer + 1])?;
-            }
-            C::ReadByte => {
-                let mut buf = [0];
-                let read = match reader.read_exact(&mut buf) {
-                    Ok(()) => buf[0],
-                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => 0,
-                    e => return e,
-                };
-                tape[data_pointer] = read;
+/// Runs the session of `repl` with cells of type `C`, starting with the
+/// program given on the command line. `,` reads the lines after the entry.
+fn interact_with_cells<C: Cell>(options: &mut Options, interpreter: &Interpreter) -> ExitCode {
+    let mut session = Session::<C>::new(interpreter);
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    let mut input = stdin.lock();
+    // Brackets can only be counted across lines in the program as written.
+    let multi_line = written_as_is(options);
+    let program = options.source.text.clone();
+    run_entry(
+        &mut session,
+        options,
+        interpreter,
+        &mut input,
+        &program,
+        true,
+    );
+    // A `.bfc` file only stands in for the program on the command line.
+    options.compiled = None;
+    loop {
+        let entry = match read_entry(&mut input, prompt, multi_line) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
             }
-            C::JumpForwardIfZero(address) => {
-                if tape[data_pointer] == 0 {
-                    instruction_pointer = *address;
+        };
+        if !entry.trim_start().starts_with(':') {
+            run_entry(
+                &mut session,
+                options,
+                interpreter,
+                &mut input,
+                &entry,
+                false,
+            );
+            continue;
+        }
+        match Meta::parse(entry.trim()) {
+            Ok(Meta::Dump) => println!("{}", session.dump()),
+            Ok(Meta::Reset) => session = Session::new(interpreter),
+            Ok(Meta::Pointer(index)) => {
+                if let Err(message) = session.set_pointer(index) {
+                    eprintln!("{message}");
                 }
             }
-            C::JumpBackwardIfNonZero(address) => {
-                if tape[data_pointer] != 0 {
-                    instruction_pointer = *address;
+            Ok(Meta::Load(path)) => match Source::read(&path) {
+                Ok(source) => {
+                    options.source = source;
+                    let program = options.source.text.clone();
+                    run_entry(
+                        &mut session,
+                        options,
+                        interpreter,
+                        &mut input,
+                        &program,
+                        true,
+                    );
                 }
+                Err(message) => eprintln!("{message}"),
+            },
+            Ok(Meta::Quit) => return ExitCode::SUCCESS,
+            Err(message) => eprintln!("{message}"),
+        }
+    }
+}
+
+/// Runs one entry of `repl`, and reports what went wrong, with the place in
+/// the file if the program is `options.source`.
+fn run_entry<C: Cell>(
+    session: &mut Session<C>,
+    options: &Options,
+    interpreter: &Interpreter,
+    input: &mut impl BufRead,
+    entry: &str,
+    from_source: bool,
+) {
+    let result = load_program(options, entry).and_then(|program| {
+        let handler = Streams::new(input, io::stdout().lock());
+        session.run(interpreter, &program, handler)
+    });
+    match result {
+        Err(e) if from_source => print_error(options, &e),
+        Err(e) => eprintln!("{e}"),
+        Ok(_) => {}
+    }
+}
+
+/// Reads the next entry of `repl`: a line, or with `multi_line` every line
+/// up to the one that closes the brackets the first one left open. Nothing
+/// at the end of stdin.
+fn read_entry(
+    input: &mut impl BufRead,
+    prompt: bool,
+    multi_line: bool,
+) -> io::Result<Option<String>> {
+    let mut entry = String::new();
+    let mut brackets = IncrementalCompiler::new();
+    loop {
+        if prompt {
+            print!("{}", if entry.is_empty() { "bf> " } else { "... " });
+        }
+        let _ = io::stdout().flush();
+        let start = entry.len();
+        if input.read_line(&mut entry)? == 0 {
+            // An entry cut off by the end still runs, to report its brackets.
+            return Ok((!entry.is_empty()).then_some(entry));
+        }
+        let line = &entry[start..];
+        let meta = start == 0 && line.trim_start().starts_with(':');
+        let open =
+            !meta && multi_line && matches!(brackets.push(line), Ok(PushResult::NeedsMore { .. }));
+        if !open {
+            return Ok(Some(entry));
+        }
+    }
+}
+
+/// Runs `gen`, which writes a program instead of running one.
+fn generate(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_gen_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, GEN_USAGE),
+    };
+
+    let program = generate_printer(&options.payload) + "\n";
+    match write_output(options.output.as_deref(), &program) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `fmt`, which writes the program re-flowed by loop depth, or with
+/// `--check` fails if the file is not formatted that way already.
+fn format(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_fmt_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, FMT_USAGE),
+    };
+
+    let path = options.path.display();
+    let source = match std::fs::read_to_string(&options.path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("cannot read '{path}': {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let flowed = match options.strip_comments {
+        true => format_source(&strip_comments(&source), options.width, options.indent),
+        false => format_source(&source, options.width, options.indent),
+    };
+
+    if options.check {
+        if flowed == source {
+            return ExitCode::SUCCESS;
+        }
+        eprintln!("'{path}' is not formatted");
+        return ExitCode::FAILURE;
+    }
+    match write_output(options.output.as_deref(), &flowed) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `minify`, which writes the program with only its commands and
+/// reports the bytes saved on stderr.
+fn shrink(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_minify_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, MINIFY_USAGE),
+    };
+
+    let path = options.path.display();
+    let source = match std::fs::read_to_string(&options.path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("cannot read '{path}': {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let minified = match minify(&source, options.level) {
+        Ok(minified) => minified,
+        Err(e) => {
+            eprintln!("parse error: {e} in {path}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(message) = write_output(options.output.as_deref(), &(minified.clone() + "\n")) {
+        eprintln!("{message}");
+        return ExitCode::FAILURE;
+    }
+    eprintln!(
+        "{} -> {} bytes, saved {}",
+        source.len(),
+        minified.len(),
+        source.len() - minified.len()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Runs `export-html`, which writes the program as a standalone web page.
+fn export(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_export_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, EXPORT_USAGE),
+    };
+
+    let path = options.path.display();
+    // Comments may be in any encoding; invalid bytes show up as U+FFFD.
+    let source = match std::fs::read(&options.path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) => {
+            eprintln!("cannot read '{path}': {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let title = options
+        .path
+        .file_name()
+        .map_or(path.to_string(), |name| name.to_string_lossy().into_owned());
+    let html = match export_html(&source, &title) {
+        Ok(html) => html,
+        Err(e) => {
+            eprintln!("parse error: {e} in {path}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match write_output(options.output.as_deref(), &html) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `compile`, which writes the program translated into another
+/// language, behaving as it would when run with the same flags.
+fn translate(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_compile_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, COMPILE_USAGE),
+    };
+
+    let code = match translation(&options.run, options.target) {
+        Ok(code) => code,
+        Err(code) => return code,
+    };
+    match write_output(options.output.as_deref(), &code) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `build`, which turns the program into an executable through C.
+fn build_native(args: impl Iterator<Item = String>) -> ExitCode {
+    let options = match parse_build_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, BUILD_USAGE),
+    };
+
+    let code = match translation(&options.run, Target::C) {
+        Ok(code) => String::from_utf8(code).expect("C translations are ASCII"),
+        Err(code) => return code,
+    };
+    // Without -o, the executable is named after the program file.
+    let output = options.output.unwrap_or_else(|| {
+        let path = options
+            .run
+            .source
+            .path()
+            .expect("checked by parse_build_args");
+        let name = path.file_stem().unwrap_or(path.as_os_str());
+        std::path::PathBuf::from(name).with_extension(std::env::consts::EXE_EXTENSION)
+    });
+    match build::build(&code, &output, options.optimize_c) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the program given after `verify` unoptimized and optimized on the
+/// same inputs, and fails on the first difference between the two.
+fn verify_program(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_verify_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, VERIFY_USAGE),
+    };
+
+    let run = &mut options.run;
+    let mut inputs = Vec::new();
+    if let Some(input) = &run.input {
+        match input.read_all() {
+            Ok(data) => inputs.push(data),
+            Err(message) => {
+                eprintln!("{message}");
+                return ExitCode::FAILURE;
             }
+        }
+    }
+    // Both programs draw the same bytes at `?`.
+    let seed = *run.seed.get_or_insert(0);
+    inputs.extend(verify::random_inputs(options.random_inputs, seed));
+    if inputs.is_empty() {
+        inputs.push(Vec::new());
+    }
+
+    let level = run.opt_level;
+    run.opt_level = OptLevel::O0;
+    let plain = load_program(run, &run.source.text);
+    run.opt_level = level;
+    let compiled = plain.and_then(|plain| {
+        let optimized = load_program(run, &run.source.text)?;
+        Ok((plain, optimized, configure(run).build()?))
+    });
+    let (plain, optimized, interpreter) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let compare = match run.cell_size {
+        CellSize::Eight => verify_with_cells::<u8>,
+        CellSize::Sixteen => verify_with_cells::<u16>,
+        CellSize::ThirtyTwo => verify_with_cells::<u32>,
+        CellSize::SignedEight => verify_with_cells::<i8>,
+    };
+    for (index, input) in inputs.iter().enumerate() {
+        match compare(&plain, &optimized, &interpreter, run, input) {
+            Ok(None) => {}
+            Ok(Some(divergence)) => {
+                eprintln!(
+                    "divergence on input {index} ({} bytes):\n{divergence}",
+                    input.len()
+                );
+                return ExitCode::FAILURE;
+            }
+            Err(message) => {
+                eprintln!("cannot verify input {index}: {message}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    match inputs.len() {
+        1 => println!("verified 1 input"),
+        n => println!("verified {n} inputs"),
+    }
+    ExitCode::SUCCESS
+}
+
+/// Compares `plain` and `optimized` on `input` with cells of type `C`,
+/// after running the start of `optimized` ahead if the options ask for it.
+fn verify_with_cells<C: Cell>(
+    plain: &[Command],
+    optimized: &[Command],
+    interpreter: &Interpreter,
+    options: &Options,
+    input: &[u8],
+) -> Result<Option<String>, String> {
+    let optimized = evaluate_ahead::<C>(optimized, interpreter, options);
+    verify::compare::<C>(plain, &optimized, interpreter, input)
+}
+
+/// Runs `disasm`, which lists the compiled program instead of running it.
+fn disassemble(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_disasm_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, DISASM_USAGE),
+    };
+
+    let run = &mut options.run;
+    let listed = if options.source_map {
+        compile_with_offsets(run).map(|(program, offsets)| (program, Some(offsets)))
+    } else {
+        let source_code = match run.bang_input {
+            true => split_bang(&run.source.text).0,
+            false => &run.source.text,
         };
+        load_program(run, source_code).map(|program| (program, None))
+    };
+    let (program, offsets) = match listed {
+        Ok(listed) => listed,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let listing = to_listing(&program, options.range, offsets.as_deref());
+    match io::stdout().lock().write_all(listing.as_bytes()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `bench`, which times repeated runs of the program.
+fn benchmark(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_bench_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, BENCH_USAGE),
+    };
+
+    let run = &mut options.run;
+    let (source_code, bang_data) = match run.bang_input {
+        true => split_bang(&run.source.text),
+        false => (run.source.text.as_str(), &[][..]),
+    };
+    let input = match &run.input {
+        Some(input) => match input.read_all() {
+            Ok(data) => data,
+            Err(message) => {
+                eprintln!("{message}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => bang_data.to_vec(),
+    };
+    let compiled =
+        load_program(run, source_code).and_then(|program| Ok((program, configure(run).build()?)));
+    let (program, interpreter) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let time = match run.cell_size {
+        CellSize::Eight => bench_with_cells::<u8>,
+        CellSize::Sixteen => bench_with_cells::<u16>,
+        CellSize::ThirtyTwo => bench_with_cells::<u32>,
+        CellSize::SignedEight => bench_with_cells::<i8>,
+    };
+    let runs = options.warmup + options.runs;
+    let times = match time(&interpreter, &program, run, &input, runs) {
+        Ok(times) => times,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-        instruction_pointer += 1;
+    let summary = Summary::new(&times[options.warmup..], options.warmup);
+    if options.json {
+        println!("{}", summary.to_json());
+    } else {
+        println!("{summary}");
     }
+    ExitCode::SUCCESS
+}
 
-    Ok(())
+/// Runs `program` `runs` times on `input` with cells of type `C`, each on a
+/// fresh tape with its output discarded, and returns how long each run
+/// took and how many commands it executed.
+fn bench_with_cells<C: Cell>(
+    interpreter: &Interpreter,
+    program: &[Command],
+    options: &mut Options,
+    input: &[u8],
+    runs: usize,
+) -> Result<Vec<(Duration, u64)>, Error> {
+    // The start is evaluated once, like the rest is compiled once.
+    let program = evaluate_ahead::<C>(program, interpreter, options).into_owned();
+    options.partial_eval = false;
+    (0..runs)
+        .map(|_| {
+            let started = Instant::now();
+            let report = run_with_cells::<C>(interpreter, &program, options, (input, io::sink()))?;
+            Ok((started.elapsed(), report.steps))
+        })
+        .collect()
 }
 
-/// Wrapper function to initialize memory and execute a Brainfuck program.
-fn eval<R: Read, W: Write>(commands: &[Command], reader: R, writer: W) -> io::Result<()> {
-    let mut tape = vec![0; 10_000];
-    let data_pointer = tape.len() / 2;
-    eval_on_tape(commands, &mut tape, data_pointer, reader, writer)
+/// Runs `hotspots`, which profiles one run of the program and lists the
+/// loops it spent the most steps in.
+fn find_hotspots(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut options = match parse_hotspots_args(args) {
+        Ok(options) => options,
+        Err(error) => return arg_error(error, HOTSPOTS_USAGE),
+    };
+
+    let run = &mut options.run;
+    let bang_data = match run.bang_input {
+        true => split_bang(&run.source.text).1,
+        false => &[][..],
+    };
+    let input = match &run.input {
+        Some(input) => match input.read_all() {
+            Ok(data) => data,
+            Err(message) => {
+                eprintln!("{message}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => bang_data.to_vec(),
+    };
+    // A compiled program has no source to point into.
+    let compiled = match &run.compiled {
+        Some(program) => Ok((program.to_vec(), None)),
+        None => compile_with_offsets(run).map(|(program, offsets)| (program, Some(offsets))),
+    };
+    let compiled = compiled.and_then(|compiled| Ok((compiled, configure(run).build()?)));
+    let ((program, offsets), interpreter) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let profile = match run.cell_size {
+        CellSize::Eight => profile_with_cells::<u8>,
+        CellSize::Sixteen => profile_with_cells::<u16>,
+        CellSize::ThirtyTwo => profile_with_cells::<u32>,
+        CellSize::SignedEight => profile_with_cells::<i8>,
+    };
+    let profiler = match profile(&interpreter, &program, run, &input) {
+        Ok(profiler) => profiler,
+        Err(e) => {
+            print_error(run, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let hotspots = Hotspots::new(&profiler, offsets.as_deref(), options.top);
+    if options.json {
+        println!("{}", hotspots.to_json());
+    } else {
+        println!("{hotspots}");
+    }
+    ExitCode::SUCCESS
 }
 
-fn main() -> io::Result<()> {
-    let Some(source_code) = std::env::args().nth(1) else {
-        return Err(io::Error::other(
-            "No second argument. Please provide an argument with Brainfuck program as a string.",
-        ));
+/// Runs `program` once on `input` with cells of type `C` and its output
+/// discarded, counting the steps of every loop.
+fn profile_with_cells<C: Cell>(
+    interpreter: &Interpreter,
+    program: &[Command],
+    options: &Options,
+    input: &[u8],
+) -> Result<LoopProfiler, Error> {
+    let mut limit = StepLimit {
+        observer: LoopProfiler::new(program),
+        steps: 0,
+        max_steps: interpreter.max_steps().unwrap_or(u64::MAX),
     };
+    let handler = Streams::new(input, io::sink());
+    let instruction_pointer = if options.sparse_tape {
+        let mut tape = PagedTape::<C>::new(interpreter.tape_len());
+        if let Some(max_memory) = options.max_memory {
+            tape = tape.with_max_tape_bytes(max_memory);
+        }
+        let mut vm = interpreter.vm_on_tape(program, tape);
+        vm.run_observed(handler, &mut limit)?;
+        vm.instruction_pointer()
+    } else {
+        let mut vm = interpreter.vm_with_cells::<C>(program);
+        vm.run_observed(handler, &mut limit)?;
+        vm.instruction_pointer()
+    };
+    if instruction_pointer < program.len() {
+        return Err(RuntimeError::StepLimitExceeded {
+            instruction_index: instruction_pointer,
+            steps: limit.max_steps,
+        }
+        .into());
+    }
+    Ok(limit.observer)
+}
 
-    match compile(&source_code) {
-        Ok(program) => eval(&program, std::io::stdin(), std::io::stdout()),
-        Err(ParsingError::UnmatchedBracket(index)) => writeln!(
-            std::io::stderr(),
-            "The program is incorrect. Unmatched bracket at index {index}"
-        ),
+/// Observer that hands every step to `observer` and stops the run after
+/// `max_steps`, which [`Vm::run_observed`](brainfuck_vm::Vm::run_observed)
+/// does not do on its own.
+struct StepLimit<O> {
+    observer: O,
+    steps: u64,
+    max_steps: u64,
+}
+
+impl<C, O: Observer<C>> Observer<C> for StepLimit<O> {
+    fn on_step(
+        &mut self,
+        instruction_pointer: usize,
+        command: &Command,
+        data_pointer: usize,
+        cell: C,
+    ) -> ControlFlow<()> {
+        if self.steps == self.max_steps {
+            return ControlFlow::Break(());
+        }
+        self.steps += 1;
+        self.observer
+            .on_step(instruction_pointer, command, data_pointer, cell)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Compiles the program like [`load_program`], and finds the source offset
+/// of every command through the passes that made it.
+fn compile_with_offsets(options: &mut Options) -> Result<(Vec<Command>, Vec<usize>), Error> {
+    let blanked;
+    let mut source_code = options.source.text.as_str();
+    if options.bang_input {
+        source_code = split_bang(source_code).0;
+    }
+    if let Some(style) = options.comments {
+        blanked = blank_comments(source_code, style)?;
+        source_code = blanked.as_str();
+    }
+    let expanded = options
+        .macros
+        .then(|| expand_macros_with_map(source_code))
+        .transpose()?;
+    let source_code = expanded.as_ref().map_or(source_code, |(text, _)| text);
 
-    /// Test Brainfuck loop [->+<] which transfers a value from one cell to another.
-    #[test]
-    fn test_eval_add() {
-        let mut tape = [1, 2];
-        let data_pointer = 0;
+    let level = options.opt_level;
+    options.opt_level = OptLevel::O0;
+    let plain = compile_source(options, source_code);
+    options.opt_level = level;
+    let plain = plain.map_err(|e| match (e, &expanded) {
+        (Error::Parse(e), Some((_, source_map))) => Error::Parse(source_map.map_error(e)),
+        (e, _) => e,
+    })?;
 
-        // [->+<]
-        let commands = [
-            Command::JumpForwardIfZero(5),
-            Command::Decrement,
-            Command::IncrementDataPointer,
-            Command::Increment,
-            Command::DecrementDataPointer,
-            Command::JumpBackwardIfNonZero(0),
-        ];
+    let mut offsets = command_offsets(options, source_code);
+    if let Some((_, source_map)) = &expanded {
+        for offset in &mut offsets {
+            *offset = source_map.origin(*offset);
+        }
+    }
+    debug_assert_eq!(offsets.len(), plain.len(), "every command has an offset");
+    let (program, origins) = pipeline(options).run_with_origins(&plain);
+    let offsets = origins.iter().map(|&origin| offsets[origin]).collect();
+    Ok((program, offsets))
+}
 
-        let reader = &[0_u8][..];
-        let writer = &mut [0_u8][..];
+/// Offset of every byte of `source_code` that compiles to a command with
+/// the dialect and extensions of the options.
+fn command_offsets(options: &Options, source_code: &str) -> Vec<usize> {
+    let mut commands = b"><+-.,[]".to_vec();
+    if options.dialect == Dialect::Pbrain {
+        commands.extend_from_slice(b"():");
+    }
+    if options.debug_ext {
+        commands.push(b'#');
+    }
+    if options.random_ext {
+        commands.push(b'?');
+    }
+    // `--strict` reads `#` to the end of the line as a comment.
+    let mut comment = false;
+    let mut offsets = Vec::new();
+    for (offset, byte) in source_code.bytes().enumerate() {
+        match byte {
+            b'\n' => comment = false,
+            b'#' if options.strict => comment = true,
+            _ if !comment && commands.contains(&byte) => offsets.push(offset),
+            _ => {}
+        }
+    }
+    offsets
+}
 
-        eval_on_tape(&commands, &mut tape, data_pointer, reader, writer).unwrap();
+/// Translates the program selected by `run` to `target`, or reports why
+/// it cannot be.
+fn translation(run: &Options, target: Target) -> Result<Vec<u8>, ExitCode> {
+    let compiled = load_program(run, &run.source.text)
+        .and_then(|program| Ok((program, configure(run).build()?)));
+    let (program, interpreter) = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            print_error(run, &e);
+            return Err(ExitCode::FAILURE);
+        }
+    };
+    let translated = match run.cell_size {
+        CellSize::Eight => translate_with_cells::<u8>(&program, &interpreter, run, target),
+        CellSize::Sixteen => translate_with_cells::<u16>(&program, &interpreter, run, target),
+        CellSize::ThirtyTwo => translate_with_cells::<u32>(&program, &interpreter, run, target),
+        CellSize::SignedEight => translate_with_cells::<i8>(&program, &interpreter, run, target),
+    };
+    translated.map_err(|e| {
+        eprintln!("cannot compile: {e}");
+        ExitCode::FAILURE
+    })
+}
 
-        assert_eq!(tape[0], 0);
-        assert_eq!(tape[1], 1 + 2);
+/// Translates `program` to `target` with cells of type `C`.
+fn translate_with_cells<C: Cell>(
+    program: &[Command],
+    interpreter: &Interpreter,
+    options: &Options,
+    target: Target,
+) -> Result<Vec<u8>, TranspileError> {
+    let program = evaluate_ahead::<C>(program, interpreter, options);
+    match target {
+        Target::C => to_c::<C>(&program, interpreter).map(String::into_bytes),
+        Target::Rust => to_rust::<C>(&program, interpreter).map(String::into_bytes),
+        Target::Wasm => to_wasm::<C>(&program, interpreter),
+        Target::Bfc => {
+            let program = Program::new(program.into_owned()).expect("compiled programs are valid");
+            let mut bytes = Vec::new();
+            let header = options.cell_size.header(options.opt_level);
+            program
+                .save(&header, &mut bytes)
+                .expect("writing to a Vec cannot fail");
+            Ok(bytes)
+        }
     }
+}
 
-    /// Test full "Hello World!" Brainfuck program.
-    #[test]
-    fn test_hello_world() {
-        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
-            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
-        let reader = &[0_u8][..];
-        let mut writer: Vec<u8> = Vec::new();
+/// Runs the input-free start of `program` ahead of time if the options
+/// ask for it.
+fn evaluate_ahead<'a, C: Cell>(
+    program: &'a [Command],
+    interpreter: &Interpreter,
+    options: &Options,
+) -> Cow<'a, [Command]> {
+    if !options.partial_eval {
+        return Cow::Borrowed(program);
+    }
+    let evaluated = partially_evaluate::<C>(program, interpreter, DEFAULT_PARTIAL_EVAL_STEPS);
+    if options.verbose {
+        eprintln!(
+            "partial evaluation turned {} instructions into {}",
+            program.len(),
+            evaluated.len()
+        );
+    }
+    Cow::Owned(evaluated)
+}
 
-        let program = compile(source_code).unwrap();
-        eval(&program, reader, &mut writer).unwrap();
+/// Writes the output of a subcommand to `path`, or to stdout without one.
+fn write_output(path: Option<&std::path::Path>, output: impl AsRef<[u8]>) -> Result<(), String> {
+    match path {
+        Some(path) => std::fs::write(path, output)
+            .map_err(|e| format!("cannot write '{}': {e}", path.display())),
+        None => io::stdout()
+            .write_all(output.as_ref())
+            .map_err(|e| e.to_string()),
+    }
+}
 
-        assert_eq!(writer, "Hello World!\n".as_bytes());
+/// Compiles `source_code` in the dialect chosen by the options.
+fn compile_source(options: &Options, source_code: &str) -> Result<Vec<Command>, Error> {
+    let program = if let Some(map) = &options.token_map {
+        let source_code = if options.strict {
+            map.translate_strict(source_code)?
+        } else {
+            map.translate(source_code)?
+        };
+        compile(&source_code)?
+    } else if options.dialect == Dialect::Pbrain {
+        compile_pbrain(source_code)?
+    } else if options.dialect == Dialect::Ook {
+        compile(&from_ook(source_code)?)?
+    } else if options.strict {
+        compile_strict(source_code)?
+    } else if options.debug_ext {
+        compile_with_debug_dumps(source_code)?
+    } else if options.random_ext {
+        compile_with_random(source_code)?
+    } else {
+        compile(source_code)?
+    };
+    let pipeline = pipeline(options);
+    // Dead code elimination is the last pass; it runs on its own to report
+    // what it did.
+    let optimized = pipeline.clone().without(Pass::DeadCode).run(&program);
+    if !pipeline.runs(Pass::DeadCode) {
+        return Ok(optimized);
     }
+    let live = eliminate_dead_code(&optimized, options.tape_init.is_none());
+    if options.verbose {
+        eprintln!(
+            "dead code elimination removed {} of {} instructions",
+            optimized.len() - live.len(),
+            optimized.len()
+        );
+    }
+    Ok(live)
+}
 
-    /// Test simple echo program that copies input to output.
-    #[test]
-    fn test_cat() {
-        let source_code = ">,[>,]<[<]>[.>]";
-        let reader = "Hello, World!\0".as_bytes();
-        let mut writer: Vec<u8> = Vec::new();
+/// Passes the options ask for.
+fn pipeline(options: &Options) -> Pipeline {
+    let mut pipeline = Pipeline::new(options.opt_level)
+        .with_overflow_policy(options.overflow_policy)
+        .with_zeroed_start(options.tape_init.is_none());
+    for &pass in &options.disabled_passes {
+        pipeline = pipeline.without(pass);
+    }
+    pipeline
+}
 
-        let program = compile(source_code).unwrap();
-        eval(&program, reader, &mut writer).unwrap();
+/// Compiles `source_code` after blanking comments and expanding macros,
+/// as the options ask, or takes the program loaded from a `.bfc` file.
+fn load_program(options: &Options, source_code: &str) -> Result<Vec<Command>, Error> {
+    if let Some(program) = &options.compiled {
+        return Ok(program.to_vec());
+    }
+    let blanked;
+    let source_code = match options.comments {
+        Some(style) => {
+            blanked = blank_comments(source_code, style)?;
+            blanked.as_str()
+        }
+        None => source_code,
+    };
+    if options.macros {
+        let (source_code, source_map) = expand_macros_with_map(source_code)?;
+        // Point parse errors at the source as written, not at the expansion.
+        compile_source(options, &source_code).map_err(|e| match e {
+            Error::Parse(e) => Error::Parse(source_map.map_error(e)),
+            e => e,
+        })
+    } else {
+        compile_source(options, source_code)
+    }
+}
 
-        assert_eq!(writer, reader[..reader.len() - 1]);
+/// Starts an interpreter with the settings of the options.
+fn configure(options: &Options) -> InterpreterBuilder {
+    let mut builder = Interpreter::builder()
+        .eof_behavior(options.eof_behavior)
+        .overflow_policy(options.overflow_policy)
+        .io_mode(options.io_mode)
+        .engine(options.engine)
+        .collect_stats(options.stats.is_some());
+    if let Some(tape_size) = options.tape_size {
+        builder = builder.tape_len(tape_size);
+    }
+    if let Some(pointer_start) = options.pointer_start {
+        builder = builder.data_pointer(pointer_start);
+    }
+    if let Some(data) = &options.tape_init {
+        builder = builder.tape_init(options.tape_init_offset, data.clone());
+    }
+    if let Some(delta) = options.init_pointer {
+        builder = builder.data_pointer_in_init(delta);
+    }
+    if let Some(max_steps) = options.max_steps {
+        builder = builder.max_steps(max_steps);
+    }
+    if let Some(max_output) = options.max_output {
+        builder = builder.max_output(max_output);
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(max_call_depth) = options.max_call_depth {
+        builder = builder.max_call_depth(max_call_depth);
+    }
+    if options.random_ext {
+        let seed = options.seed.unwrap_or_else(|| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH);
+            now.map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        builder = builder.seed(seed);
+    }
+    builder
+}
+
+fn run(options: &Options) -> Result<ExecutionReport, Error> {
+    let (source_code, bang_data) = if options.bang_input {
+        let (source_code, data) = split_bang(&options.source.text);
+        (source_code, Some(data))
+    } else {
+        (options.source.text.as_str(), None)
+    };
+    let started = Instant::now();
+    let cache = ProgramCache::for_source(options, source_code);
+    let program = match cache.as_ref().and_then(ProgramCache::load) {
+        Some(program) => {
+            if options.verbose {
+                eprintln!("program taken from the cache");
+            }
+            if options.time {
+                eprintln!("compile: skipped, loaded in {:.2?}", started.elapsed());
+            }
+            program
+        }
+        None => {
+            let program = load_program(options, source_code)?;
+            if options.time {
+                eprintln!("compile: {:.2?}", started.elapsed());
+            }
+            if let Some(cache) = cache {
+                // Failing to store the program does not fail the run.
+                let _ = cache.store(&program);
+            }
+            program
+        }
+    };
+    if options.emit_ir {
+        io::stdout().lock().write_all(to_ir(&program).as_bytes())?;
+        return Ok(ExecutionReport::new(0));
+    }
+    let reads_stdin = bang_data.is_none() && options.input.is_none() && !options.stdin_program;
+    let interpreter = configure(options)
+        .echo_input(options.echo && reads_stdin && io::stdin().is_terminal())
+        .build()?;
+    // Restores the terminal when dropped, also if the run fails or panics.
+    let _raw_mode = if options.raw {
+        terminal::RawMode::enable()?
+    } else {
+        None
+    };
+    let input: Box<dyn Read> = match (bang_data, &options.input) {
+        (Some(data), _) => Box::new(data),
+        (None, Some(input)) => input.reader(),
+        // The program itself was read from stdin, up to its end.
+        (None, None) if options.stdin_program => Box::new(io::empty()),
+        (None, None) => Box::new(io::stdin()),
+    };
+    let output: Box<dyn Write> = match &options.output {
+        Some(file) => Box::new(BufWriter::new(file)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let (mut stdin, mut stdout): (Box<dyn Read>, Box<dyn Write>) = match options.newline {
+        Some(newline) => (
+            Box::new(NewlineReader::new(input, newline)),
+            Box::new(NewlineWriter::new(output, newline)),
+        ),
+        None => (input, output),
+    };
+
+    let cache = OutputCache::for_run(options, &program, &interpreter);
+    if let Some(output) = cache.as_ref().and_then(OutputCache::load) {
+        if options.verbose {
+            eprintln!("output taken from the cache");
+        }
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+        return Ok(ExecutionReport {
+            bytes_written: output.len() as u64,
+            ..ExecutionReport::new(interpreter.data_pointer())
+        });
+    }
+    let mut stdout = Recorder::new(stdout, cache.is_some());
+    let io = (&mut stdin, &mut stdout);
+    let result = match options.cell_size {
+        CellSize::Eight => run_with_cells::<u8>(&interpreter, &program, options, io),
+        CellSize::Sixteen => run_with_cells::<u16>(&interpreter, &program, options, io),
+        CellSize::ThirtyTwo => run_with_cells::<u32>(&interpreter, &program, options, io),
+        CellSize::SignedEight => run_with_cells::<i8>(&interpreter, &program, options, io),
+    };
+    // Output written before a failure is still delivered.
+    stdout.flush()?;
+    if let (Ok(_), Some(cache), Some(output)) = (&result, cache, stdout.recorded()) {
+        // Failing to store the output does not fail the run.
+        let _ = cache.store(output);
+    }
+    result
+}
+
+/// Runs the program on the tape selected by `options`, with cells of type `C`.
+fn run_with_cells<C: Cell>(
+    interpreter: &Interpreter,
+    program: &[brainfuck_vm::Command],
+    options: &Options,
+    (stdin, stdout): (impl Read, impl Write),
+) -> Result<ExecutionReport, Error> {
+    let tape_len = interpreter.tape_len();
+    if options.sparse_tape {
+        let mut tape = PagedTape::<C>::new(tape_len);
+        if let Some(max_memory) = options.max_memory {
+            tape = tape.with_max_tape_bytes(max_memory);
+        }
+        interpreter.run_on_tape(program, tape, stdin, stdout)
+    } else {
+        // The dense tape is allocated in full before the program starts.
+        if let Some(max_memory) = options.max_memory
+            && tape_len.saturating_mul(size_of::<C>()) > max_memory
+        {
+            return Err(RuntimeError::MemoryLimitExceeded {
+                instruction_index: 0,
+                requested_cells: tape_len,
+            }
+            .into());
+        }
+        let program = evaluate_ahead::<C>(program, interpreter, options);
+        interpreter.run_with_cells::<C, _, _>(&program, stdin, stdout)
     }
 }
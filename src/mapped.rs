@@ -0,0 +1,198 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::Range;
+
+use crate::{Cell, Tape, TapeError};
+
+/// Handler for `+`, `-`, `.`, and loop tests reading a mapped cell.
+type ReadHandler<X, C> = Box<dyn Fn(&mut X, usize) -> C>;
+
+/// Handler for `+`, `-`, and `,` writing a mapped cell.
+type WriteHandler<X, C> = Box<dyn Fn(&mut X, usize, C)>;
+
+/// Range of cells that act like hardware registers of a [`MappedTape`]:
+/// reading or writing them calls the host instead of touching the tape.
+///
+/// Handlers get the tape context as `&mut X` and the index of the cell.
+/// Without a read handler the cells read from the tape underneath, and
+/// without a write handler they write to it, so e.g. a key register only
+/// needs [`MappedCell::on_read`].
+pub struct MappedCell<X, C> {
+    range: Range<usize>,
+    on_read: Option<ReadHandler<X, C>>,
+    on_write: Option<WriteHandler<X, C>>,
+}
+
+impl<X, C> MappedCell<X, C> {
+    /// Maps the cells in `range`, which behave like normal cells until
+    /// handlers are added.
+    pub fn new(range: Range<usize>) -> Self {
+        MappedCell {
+            range,
+            on_read: None,
+            on_write: None,
+        }
+    }
+
+    /// Calls `on_read` whenever a cell in the range is read.
+    pub fn on_read(mut self, on_read: impl Fn(&mut X, usize) -> C + 'static) -> Self {
+        self.on_read = Some(Box::new(on_read));
+        self
+    }
+
+    /// Calls `on_write` with the new value whenever a cell in the range is
+    /// written. The tape underneath keeps its value.
+    pub fn on_write(mut self, on_write: impl Fn(&mut X, usize, C) + 'static) -> Self {
+        self.on_write = Some(Box::new(on_write));
+        self
+    }
+}
+
+/// Tape with [`MappedCell`]s on top of another tape, for embedding the VM
+/// in a host that exposes its state through cells.
+///
+/// Cells outside every mapped range go straight to the tape underneath
+/// after one range check. Where ranges overlap, the first one added wins.
+/// Pass `&mut` a mapped tape to [`Interpreter::run_on_tape`](crate::Interpreter::run_on_tape)
+/// to look at the context after the run.
+pub struct MappedTape<T: Tape, X> {
+    tape: T,
+    /// Borrowed mutably by the handlers, even from [`Tape::get`].
+    context: RefCell<X>,
+    cells: Vec<MappedCell<X, T::Cell>>,
+    /// Smallest range containing every mapped cell.
+    bounds: Range<usize>,
+}
+
+impl<T: Tape, X> MappedTape<T, X> {
+    /// Wraps `tape` without mapping any cells yet.
+    pub fn new(tape: T, context: X) -> Self {
+        MappedTape {
+            tape,
+            context: RefCell::new(context),
+            cells: Vec::new(),
+            bounds: 0..0,
+        }
+    }
+
+    /// Adds a range of mapped cells.
+    pub fn with_cell(mut self, cell: MappedCell<X, T::Cell>) -> Self {
+        if !cell.range.is_empty() {
+            self.bounds = if self.bounds.is_empty() {
+                cell.range.clone()
+            } else {
+                self.bounds.start.min(cell.range.start)..self.bounds.end.max(cell.range.end)
+            };
+        }
+        self.cells.push(cell);
+        self
+    }
+
+    /// Tape underneath the mapped cells.
+    pub fn tape(&self) -> &T {
+        &self.tape
+    }
+
+    /// State shared by the handlers.
+    pub fn context(&mut self) -> &mut X {
+        self.context.get_mut()
+    }
+
+    /// Consumes the mapped tape and returns the tape and the context.
+    pub fn into_parts(self) -> (T, X) {
+        (self.tape, self.context.into_inner())
+    }
+
+    fn mapped(&self, index: usize) -> Option<&MappedCell<X, T::Cell>> {
+        if !self.bounds.contains(&index) {
+            return None;
+        }
+        self.cells.iter().find(|cell| cell.range.contains(&index))
+    }
+}
+
+impl<T: Tape, X> Tape for MappedTape<T, X> {
+    type Cell = T::Cell;
+
+    fn get(&self, index: usize) -> T::Cell {
+        match self.mapped(index).and_then(|cell| cell.on_read.as_ref()) {
+            Some(on_read) => on_read(&mut self.context.borrow_mut(), index),
+            None => self.tape.get(index),
+        }
+    }
+
+    fn set(&mut self, index: usize, value: T::Cell) {
+        let on_write = self.mapped(index).and_then(|cell| cell.on_write.as_ref());
+        match on_write {
+            Some(on_write) => on_write(&mut self.context.borrow_mut(), index, value),
+            None => self.tape.set(index, value),
+        }
+    }
+
+    fn inc(&mut self, index: usize) {
+        match self.mapped(index) {
+            Some(_) => self.set(index, self.get(index).wrapping_inc()),
+            None => self.tape.inc(index),
+        }
+    }
+
+    fn dec(&mut self, index: usize) {
+        match self.mapped(index) {
+            Some(_) => self.set(index, self.get(index).wrapping_dec()),
+            None => self.tape.dec(index),
+        }
+    }
+
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+        self.tape.move_right(index)
+    }
+
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        self.tape.move_left(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Vm, compile};
+
+    /// Test that every read of a mapped cell asks its handler again.
+    #[test]
+    fn test_read_counter() {
+        let program = compile("...").unwrap();
+        let mut tape = MappedTape::new(vec![0_u8; 4], 0_u8).with_cell(
+            MappedCell::new(0..1).on_read(|count: &mut u8, _| {
+                *count += 1;
+                *count
+            }),
+        );
+        let mut output = Vec::new();
+        Interpreter::default()
+            .run_on_tape(&program, &mut tape, &[][..], &mut output)
+            .unwrap();
+
+        assert_eq!(output, [1, 2, 3]);
+        // The report reads the final cell once more.
+        assert_eq!(*tape.context(), 4);
+    }
+
+    /// Test that writes reach the handler and leave the tape alone, while
+    /// other cells behave as usual.
+    #[test]
+    fn test_write_register() {
+        let program = compile("+>++>-<<+").unwrap();
+        let tape = MappedTape::new(vec![0_u8; 4], Vec::new()).with_cell(
+            MappedCell::new(1..3).on_write(|log: &mut Vec<(usize, u8)>, index, value| {
+                log.push((index, value));
+            }),
+        );
+        let mut vm = Vm::with_tape(&program, tape, 0);
+        vm.run().unwrap();
+
+        let (tape, log) = vm.into_tape().into_parts();
+        assert_eq!(log, [(1, 1), (1, 1), (2, u8::MAX)]);
+        assert_eq!(tape, [2, 0, 0, 0]);
+    }
+}
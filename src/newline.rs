@@ -0,0 +1,204 @@
+use std::io::{self, Read, Write};
+
+use crate::ByteSource;
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+
+/// Line ending that [`NewlineReader`] and [`NewlineWriter`] translate to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Newline {
+    /// Unix line endings: every CR LF pair becomes a single LF.
+    Lf,
+    /// DOS line endings: every LF without a CR before it becomes CR LF.
+    Crlf,
+}
+
+impl Newline {
+    /// Line ending of the platform this was compiled for.
+    pub const fn native() -> Self {
+        if cfg!(windows) {
+            Newline::Crlf
+        } else {
+            Newline::Lf
+        }
+    }
+}
+
+/// Reader that translates the line endings of `inner` to a [`Newline`].
+///
+/// A CR that is not part of a line ending is passed through unchanged, and
+/// so is every other byte. Each `read` returns at most one byte, so that a
+/// program waiting on `,` never waits for more input than it asked for.
+#[derive(Debug)]
+pub struct NewlineReader<R> {
+    inner: R,
+    newline: Newline,
+    /// Byte that was read ahead or still has to be returned.
+    queued: Option<u8>,
+    /// Whether the last returned byte was a CR.
+    after_cr: bool,
+}
+
+impl<R: Read> NewlineReader<R> {
+    /// Translates the line endings read from `inner` to `newline`.
+    pub fn new(inner: R, newline: Newline) -> Self {
+        NewlineReader {
+            inner,
+            newline,
+            queued: None,
+            after_cr: false,
+        }
+    }
+
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = match self.queued.take() {
+            Some(byte) => byte,
+            None => match self.inner.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            },
+        };
+        match (self.newline, byte) {
+            (Newline::Lf, CR) => match self.inner.read_byte()? {
+                Some(LF) => Ok(Some(LF)),
+                next => {
+                    self.queued = next;
+                    Ok(Some(CR))
+                }
+            },
+            (Newline::Crlf, LF) if !self.after_cr => {
+                self.queued = Some(LF);
+                Ok(Some(CR))
+            }
+            _ => Ok(Some(byte)),
+        }
+    }
+}
+
+impl<R: Read> Read for NewlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(first) = buf.first_mut() else {
+            return Ok(0);
+        };
+        match self.next_byte()? {
+            Some(byte) => {
+                self.after_cr = byte == CR;
+                *first = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Writer that translates line endings to a [`Newline`] before passing
+/// bytes on to `inner`.
+///
+/// Like [`NewlineReader`], it leaves a CR that is not part of a line ending
+/// and every other byte alone. Under [`Newline::Lf`] a trailing CR is held
+/// back until the next byte shows whether it starts a line ending, so call
+/// [`Write::flush`] when done.
+#[derive(Debug)]
+pub struct NewlineWriter<W> {
+    inner: W,
+    newline: Newline,
+    /// Whether the last byte written was a CR; held back under [`Newline::Lf`].
+    after_cr: bool,
+}
+
+impl<W: Write> NewlineWriter<W> {
+    /// Translates the line endings written to `inner` to `newline`.
+    pub fn new(inner: W, newline: Newline) -> Self {
+        NewlineWriter {
+            inner,
+            newline,
+            after_cr: false,
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        let after_cr = self.after_cr;
+        self.after_cr = byte == CR;
+        match (self.newline, byte) {
+            (Newline::Lf, LF) => self.inner.write_all(&[LF]),
+            (Newline::Lf, CR) if after_cr => self.inner.write_all(&[CR]),
+            (Newline::Lf, CR) => Ok(()),
+            (Newline::Lf, _) if after_cr => self.inner.write_all(&[CR, byte]),
+            (Newline::Crlf, LF) if !after_cr => self.inner.write_all(&[CR, LF]),
+            _ => self.inner.write_all(&[byte]),
+        }
+    }
+}
+
+impl<W: Write> Write for NewlineWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.write_byte(byte)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Also writes a CR that was held back.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.newline == Newline::Lf && self.after_cr {
+            self.inner.write_all(&[CR])?;
+            self.after_cr = false;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, eval};
+
+    /// Runs the cat program with translation on both of its streams.
+    fn cat(input: &[u8], newline: Newline) -> Vec<u8> {
+        let program = compile(",[.,]").unwrap();
+        let mut output = NewlineWriter::new(Vec::new(), newline);
+
+        eval(&program, NewlineReader::new(input, newline), &mut output).unwrap();
+        output.flush().unwrap();
+        output.inner
+    }
+
+    /// Test piping a DOS line through cat in every mode.
+    #[test]
+    fn test_cat_newlines() {
+        assert_eq!(cat(b"a\r\nb", Newline::Lf), b"a\nb");
+        assert_eq!(cat(b"a\r\nb", Newline::Crlf), b"a\r\nb");
+        let native: &[u8] = if cfg!(windows) { b"a\r\nb" } else { b"a\nb" };
+        assert_eq!(cat(b"a\r\nb", Newline::native()), native);
+
+        assert_eq!(cat(b"a\nb\n", Newline::Crlf), b"a\r\nb\r\n");
+        assert_eq!(cat(b"a\nb\n", Newline::Lf), b"a\nb\n");
+    }
+
+    /// Test that a CR outside of a line ending is left alone.
+    #[test]
+    fn test_lone_cr() {
+        for newline in [Newline::Lf, Newline::Crlf] {
+            assert_eq!(cat(b"a\rb", newline), b"a\rb");
+            assert_eq!(cat(b"a\r", newline), b"a\r");
+        }
+        assert_eq!(cat(b"\r\r\n", Newline::Lf), b"\r\n");
+        assert_eq!(cat(b"\r\r\n", Newline::Crlf), b"\r\r\n");
+    }
+
+    /// Test translating what the program sees, without the writer.
+    #[test]
+    fn test_reader() {
+        let mut text = String::new();
+        NewlineReader::new(&b"x\r\ny\nz"[..], Newline::Crlf)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "x\r\ny\r\nz");
+    }
+}
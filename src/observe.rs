@@ -0,0 +1,101 @@
+use core::ops::ControlFlow;
+
+use crate::Command;
+
+/// Hook that sees every command right before it executes.
+///
+/// Used through [`eval_observed`](crate::eval_observed) or
+/// [`Vm::run_observed`](crate::Vm::run_observed); the other entry points do
+/// not call an observer at all. Returning [`ControlFlow::Break`] stops the
+/// program before `command` executes. `C` is the [`Cell`](crate::Cell) type
+/// of the tape.
+pub trait Observer<C = u8> {
+    fn on_step(
+        &mut self,
+        instruction_pointer: usize,
+        command: &Command,
+        data_pointer: usize,
+        cell: C,
+    ) -> ControlFlow<()>;
+}
+
+impl<C, O: Observer<C> + ?Sized> Observer<C> for &mut O {
+    fn on_step(
+        &mut self,
+        instruction_pointer: usize,
+        command: &Command,
+        data_pointer: usize,
+        cell: C,
+    ) -> ControlFlow<()> {
+        (**self).on_step(instruction_pointer, command, data_pointer, cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{Streams, Vm, compile, eval_observed};
+
+    /// Observer recording the data pointer before every command.
+    #[derive(Default)]
+    struct PointerTrace(Vec<usize>);
+
+    impl Observer for PointerTrace {
+        fn on_step(
+            &mut self,
+            _: usize,
+            _: &Command,
+            data_pointer: usize,
+            _: u8,
+        ) -> ControlFlow<()> {
+            self.0.push(data_pointer);
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Test the exact pointer trace of moving a value one cell to the right.
+    #[test]
+    fn test_pointer_trace() {
+        let program = compile("[->+<]").unwrap();
+        let mut trace = PointerTrace::default();
+
+        let mut vm = Vm::with_tape(&program, vec![2, 0], 0);
+        vm.run_observed(Streams::new(&[][..], Vec::new()), &mut trace)
+            .unwrap();
+
+        assert_eq!(trace.0, [0, 0, 0, 1, 1, 0, 0, 0, 1, 1, 0]);
+        assert_eq!(vm.into_tape(), [0, 2]);
+    }
+
+    /// Test stopping an endless program from the observer.
+    #[test]
+    fn test_break() {
+        struct StopAtOutput;
+
+        impl Observer for StopAtOutput {
+            fn on_step(
+                &mut self,
+                _: usize,
+                command: &Command,
+                _: usize,
+                cell: u8,
+            ) -> ControlFlow<()> {
+                if *command == Command::WriteByte && cell == 3 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            }
+        }
+
+        let program = compile("+[.+]").unwrap();
+        let mut output = Vec::new();
+
+        let report = eval_observed(&program, &[][..], &mut output, StopAtOutput).unwrap();
+
+        assert_eq!(output, [1, 2]);
+        assert_eq!(report.bytes_written, 2);
+    }
+}
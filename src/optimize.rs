@@ -0,0 +1,1511 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Command, OverflowPolicy};
+
+/// How much a [`Pipeline`] rewrites a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OptLevel {
+    /// Run the program as compiled.
+    O0,
+    /// Fold runs, clear loops like `[-]`, and merge neighbouring commands.
+    O1,
+    /// Run every pass.
+    #[default]
+    O2,
+}
+
+/// One rewrite of a [`Pipeline`], which can be turned off on its own to
+/// find the one that changes what a program does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pass {
+    /// Runs of `+`, `-`, `>`, or `<` become one [`Command::Add`] or
+    /// [`Command::MovePointer`].
+    FoldRuns,
+    /// Loops like `[-]` become [`Command::Set`] to 0.
+    ClearLoops,
+    /// Loops like `[>]` become [`Command::ScanRight`] or
+    /// [`Command::ScanLeft`].
+    ScanLoops,
+    /// Loops like `[->+<]` become [`Command::MulAdd`]s.
+    MulLoops,
+    /// Neighbouring commands are merged until nothing changes.
+    Peephole,
+    /// Runs like `[-]>[-]>[-]` become one [`Command::ClearRange`].
+    ClearRanges,
+    /// Straight-line code is addressed relative to the pointer.
+    Offsets,
+    /// Loops that are never entered are dropped.
+    DeadCode,
+}
+
+impl Pass {
+    /// Every pass, in the order a [`Pipeline`] runs them.
+    pub const ALL: [Pass; 8] = [
+        Pass::FoldRuns,
+        Pass::ClearLoops,
+        Pass::ScanLoops,
+        Pass::MulLoops,
+        Pass::Peephole,
+        Pass::ClearRanges,
+        Pass::Offsets,
+        Pass::DeadCode,
+    ];
+
+    /// Name of the pass, like `mul-loops`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::FoldRuns => "fold-runs",
+            Pass::ClearLoops => "clear-loops",
+            Pass::ScanLoops => "scan-loops",
+            Pass::MulLoops => "mul-loops",
+            Pass::Peephole => "peephole",
+            Pass::ClearRanges => "clear-ranges",
+            Pass::Offsets => "offsets",
+            Pass::DeadCode => "dead-code",
+        }
+    }
+
+    /// Pass with the given [`name`](Pass::name).
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    /// Lowest level that runs the pass.
+    pub fn level(self) -> OptLevel {
+        match self {
+            Pass::FoldRuns | Pass::ClearLoops | Pass::Peephole => OptLevel::O1,
+            Pass::ScanLoops
+            | Pass::MulLoops
+            | Pass::ClearRanges
+            | Pass::Offsets
+            | Pass::DeadCode => OptLevel::O2,
+        }
+    }
+}
+
+/// Passes to run over a compiled program, picked by an [`OptLevel`].
+///
+/// The passes run in the order of [`Pass::ALL`], but runs are folded and
+/// loops rewritten in the same walk over the program, so the loop passes
+/// see the commands as compiled. Every pass keeps what the
+/// program does, so only the step counts, the instruction indices in
+/// errors, and where the pointer is between brackets differ from the
+/// original program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    level: OptLevel,
+    disabled: Vec<Pass>,
+    overflow_policy: OverflowPolicy,
+    zeroed_start: bool,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new(OptLevel::default())
+    }
+}
+
+impl Pipeline {
+    /// Pipeline of every pass up to `level`, for programs that wrap and
+    /// start on a zeroed tape.
+    pub fn new(level: OptLevel) -> Self {
+        Pipeline {
+            level,
+            disabled: Vec::new(),
+            overflow_policy: OverflowPolicy::Wrap,
+            zeroed_start: true,
+        }
+    }
+
+    /// Sets the overflow policy the program runs under. Loops are only
+    /// rewritten into constant-time commands with [`OverflowPolicy::Wrap`].
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets whether the tape is all zeros when the program starts, as it is
+    /// unless the tape is initialized; see [`eliminate_dead_code`].
+    pub fn with_zeroed_start(mut self, zeroed_start: bool) -> Self {
+        self.zeroed_start = zeroed_start;
+        self
+    }
+
+    /// Leaves out `pass`, even if the level would run it.
+    pub fn without(mut self, pass: Pass) -> Self {
+        if !self.disabled.contains(&pass) {
+            self.disabled.push(pass);
+        }
+        self
+    }
+
+    /// Whether the pipeline runs `pass`.
+    pub fn runs(&self, pass: Pass) -> bool {
+        pass.level() <= self.level && !self.disabled.contains(&pass)
+    }
+
+    /// Passes the pipeline runs, in order.
+    pub fn passes(&self) -> impl Iterator<Item = Pass> + '_ {
+        Pass::ALL.into_iter().filter(|&pass| self.runs(pass))
+    }
+
+    /// Rewrites `commands` with every pass of the pipeline.
+    ///
+    /// The jumps are expected to be consistent, as
+    /// [`validate`](crate::validate) checks; one that is not still gets an
+    /// address, but not a meaningful one.
+    pub fn run(&self, commands: &[Command]) -> Vec<Command> {
+        self.run_with_origins(commands).0
+    }
+
+    /// Same as [`Pipeline::run`], but also returns the origin of every
+    /// rewritten command: the address of the first command of `commands` it
+    /// was made from.
+    ///
+    /// Commands that only exist in the rewritten program, like the moves
+    /// after a segment addressed relative to the pointer, share the origin
+    /// of the command before them.
+    pub fn run_with_origins(&self, commands: &[Command]) -> (Vec<Command>, Vec<usize>) {
+        let folds = [
+            Pass::FoldRuns,
+            Pass::ClearLoops,
+            Pass::ScanLoops,
+            Pass::MulLoops,
+        ];
+        // New address of every original command.
+        let mut addresses: Vec<usize> = (0..commands.len()).collect();
+        let mut commands = commands.to_vec();
+        let mut apply = |(rewritten, moved): (Vec<Command>, Vec<usize>)| {
+            for address in &mut addresses {
+                *address = moved.get(*address).copied().unwrap_or(rewritten.len());
+            }
+            rewritten
+        };
+        if folds.iter().any(|&pass| self.runs(pass)) {
+            commands = apply(fold(&commands, self));
+        }
+        if self.runs(Pass::Peephole) {
+            commands = apply(peephole(commands, self.overflow_policy));
+        }
+        if self.runs(Pass::ClearRanges) {
+            commands = apply(clear_ranges(&commands));
+        }
+        if self.runs(Pass::Offsets) {
+            commands = apply(address_cells(&commands));
+        }
+        if self.runs(Pass::DeadCode) {
+            commands = apply(remove_dead_code(&commands, self.zeroed_start));
+        }
+
+        let mut origins = vec![None; commands.len()];
+        for (original, &address) in addresses.iter().enumerate() {
+            if let Some(origin @ None) = origins.get_mut(address) {
+                *origin = Some(original);
+            }
+        }
+        let mut previous = 0;
+        let origins = origins
+            .into_iter()
+            .map(|origin| {
+                previous = origin.unwrap_or(previous);
+                previous
+            })
+            .collect();
+        (commands, origins)
+    }
+}
+
+/// Rewrites a compiled program into one with the same behavior that takes
+/// fewer steps.
+///
+/// Runs of `+`, `-`, `>`, or `<` become a single [`Command::Add`] or
+/// [`Command::MovePointer`], which the [`Vm`](crate::Vm) applies in one
+/// step under the same overflow policy and tape, and jump addresses are
+/// moved to the new positions of their brackets.
+///
+/// Loops that only move the pointer one way, like `[>]` or `[<<]`, become
+/// [`Command::ScanRight`] or [`Command::ScanLeft`], which search the tape
+/// for the next zero cell in one step where the [`Tape`](crate::Tape) can,
+/// and otherwise stop at its edge or grow it like the loop.
+///
+/// Under [`OverflowPolicy::Wrap`], loops whose body is a single `-` or `+`
+/// become [`Command::Set`] to 0, since they count the cell down or up to
+/// zero. Loops like `[->+>+++<<]`, which subtract one from their cell and
+/// add constants to cells at fixed offsets before returning to it, become
+/// one [`Command::MulAdd`] per changed cell followed by `Set(0)`, so they
+/// take constant time. Loops with I/O or nested loops in them are kept.
+/// With other policies these loops may stop at the edge of a cell or fail
+/// instead, so they are kept.
+///
+/// Neighbouring commands are then merged until nothing changes: adds and
+/// moves in the same direction are summed, and with wrapping, opposite adds
+/// like `+-` cancel out. A move that comes back part of the way, like `><`,
+/// still has to reach the farthest cell, so it only becomes one command.
+/// Adds right after a `Set` are folded into it, and a `Set` makes adds
+/// before it pointless unless they could fail.
+///
+/// Runs of `Set(0)` one cell apart, like `[-]>[-]>[-]` or `[-]<[-]`,
+/// become a [`Command::ClearRange`], which clears them all in one step and
+/// leaves the pointer on the last, as the run does.
+///
+/// Then straight-line code between loops is addressed relative to the
+/// pointer: `>>+<.` becomes [`Command::AddAt`] 2 and [`Command::OutputAt`]
+/// 1 followed by a single move by 1, so the pointer is where it was at
+/// every bracket. A cell reached this way for the first time is still
+/// reached by moving, so the tape fails or grows at the same command.
+///
+/// Only the step counts, the instruction indices in errors, and where the
+/// pointer is between brackets, e.g. as an [`Observer`](crate::Observer)
+/// sees it, differ from the original program.
+///
+/// This is a [`Pipeline`] at [`OptLevel::O2`] without [`Pass::DeadCode`].
+/// The jumps are expected to be consistent, as [`validate`](crate::validate)
+/// checks; one that is not still gets an address, but not a meaningful one.
+pub fn optimize(commands: &[Command], overflow_policy: OverflowPolicy) -> Vec<Command> {
+    Pipeline::new(OptLevel::O2)
+        .with_overflow_policy(overflow_policy)
+        .without(Pass::DeadCode)
+        .run(commands)
+}
+
+/// Drops loops that can never run because the current cell is zero when
+/// they are reached.
+///
+/// That is the case for a loop right after another loop, a scan, or a
+/// `Set(0)`, with only commands in between that leave the current cell
+/// alone, like `.`. With `zeroed_start`, which holds unless the tape is
+/// initialized with something else, it is also the case for loops at the
+/// very start of the program, like the comment loops some programs begin
+/// with. Jump addresses are moved to the new positions of their brackets.
+///
+/// A dropped loop is never entered, so only the step counts and the
+/// instruction indices in errors differ from the original program. The
+/// jumps are expected to be consistent, as [`validate`](crate::validate)
+/// checks.
+pub fn eliminate_dead_code(commands: &[Command], zeroed_start: bool) -> Vec<Command> {
+    remove_dead_code(commands, zeroed_start).0
+}
+
+/// Drops the loops [`eliminate_dead_code`] drops, and returns the new
+/// address of every command as well.
+fn remove_dead_code(commands: &[Command], zeroed_start: bool) -> (Vec<Command>, Vec<usize>) {
+    use self::Command as C;
+
+    let mut kept = Vec::with_capacity(commands.len());
+    // New address of every original command, for moving the jumps.
+    let mut addresses = vec![0; commands.len()];
+    // Whether the current cell is zero whenever the next command is reached.
+    let mut zero = zeroed_start;
+
+    let mut index = 0;
+    while index < commands.len() {
+        let command = &commands[index];
+        if let C::JumpForwardIfZero(end) = *command
+            && zero
+            && (index + 1..commands.len()).contains(&end)
+        {
+            addresses[index..=end].fill(kept.len());
+            index = end + 1;
+            continue;
+        }
+
+        zero = match *command {
+            C::JumpBackwardIfNonZero(_)
+            | C::ScanRight(_)
+            | C::ScanLeft(_)
+            | C::Set(0)
+            | C::ClearRange { .. } => true,
+            C::WriteByte | C::DebugDump | C::OutputAt(_) => zero,
+            C::AddAt { offset, .. } | C::SetAt { offset, .. } | C::InputAt(offset) => {
+                zero && offset != 0
+            }
+            _ => false,
+        };
+        addresses[index] = kept.len();
+        kept.push(command.clone());
+        index += 1;
+    }
+
+    relocate(&mut kept, &addresses);
+    (kept, addresses)
+}
+
+/// Folds runs and rewrites loops, as far as `pipeline` runs those passes,
+/// and returns the new address of every command as well.
+fn fold(commands: &[Command], pipeline: &Pipeline) -> (Vec<Command>, Vec<usize>) {
+    use self::Command as C;
+
+    let mut optimized = Vec::with_capacity(commands.len());
+    // New address of every original command, for moving the jumps.
+    let mut addresses = vec![0; commands.len()];
+
+    let mut index = 0;
+    while index < commands.len() {
+        if let Some((len, rewritten)) = rewrite_loop(&commands[index..], index, pipeline) {
+            addresses[index..index + len].fill(optimized.len());
+            optimized.extend(rewritten);
+            index += len;
+            continue;
+        }
+
+        let command = &commands[index];
+        let longest = match command {
+            _ if !pipeline.runs(Pass::FoldRuns) => 1,
+            C::Increment | C::Decrement => i16::MAX as usize,
+            C::IncrementDataPointer | C::DecrementDataPointer => i32::MAX as usize,
+            _ => 1,
+        };
+        let run = commands[index..]
+            .iter()
+            .take(longest)
+            .take_while(|&next| next == command)
+            .count();
+
+        addresses[index..index + run].fill(optimized.len());
+        optimized.push(match command {
+            _ if run == 1 => command.clone(),
+            C::Increment => C::Add(run as i16),
+            C::Decrement => C::Add(-(run as i16)),
+            C::IncrementDataPointer => C::MovePointer(run as i32),
+            C::DecrementDataPointer => C::MovePointer(-(run as i32)),
+            _ => unreachable!("only runs of +, -, >, and < are folded"),
+        });
+        index += run;
+    }
+
+    relocate(&mut optimized, &addresses);
+    (optimized, addresses)
+}
+
+/// Moves the jumps of a rewritten program, where `addresses` holds the new
+/// address of every command of the program it was rewritten from.
+fn relocate(commands: &mut [Command], addresses: &[usize]) {
+    use self::Command as C;
+
+    let end = commands.len();
+    for command in commands {
+        if let C::JumpForwardIfZero(address)
+        | C::JumpBackwardIfNonZero(address)
+        | C::BeginProc(address)
+        | C::EndProc(address) = command
+        {
+            *address = addresses.get(*address).copied().unwrap_or(end);
+        }
+    }
+}
+
+/// Merges neighbouring commands until no more can be merged, and returns
+/// the new address of every command as well.
+fn peephole(
+    mut commands: Vec<Command>,
+    overflow_policy: OverflowPolicy,
+) -> (Vec<Command>, Vec<usize>) {
+    let mut moved: Vec<usize> = (0..commands.len()).collect();
+    loop {
+        let mut merged: Vec<Command> = Vec::with_capacity(commands.len());
+        let mut addresses = vec![0; commands.len()];
+        for (address, command) in commands.iter().enumerate() {
+            match merged
+                .last()
+                .and_then(|last| merge(last, command, overflow_policy))
+            {
+                Some(replacement) => {
+                    merged.pop();
+                    merged.extend(replacement);
+                }
+                None => merged.push(command.clone()),
+            }
+            // Jumps are never merged, so this is exact where it matters.
+            addresses[address] = merged.len().saturating_sub(1);
+        }
+        relocate(&mut merged, &addresses);
+        for address in &mut moved {
+            *address = addresses.get(*address).copied().unwrap_or(merged.len());
+        }
+
+        if merged == commands {
+            return (merged, moved);
+        }
+        commands = merged;
+    }
+}
+
+/// Commands that do what `first` followed by `second` does, if they are
+/// fewer or simpler.
+fn merge(
+    first: &Command,
+    second: &Command,
+    overflow_policy: OverflowPolicy,
+) -> Option<Vec<Command>> {
+    use self::Command as C;
+
+    let wrap = overflow_policy == OverflowPolicy::Wrap;
+    if let (Some(a), Some(b)) = (added(first), added(second)) {
+        let sum = i32::from(a) + i32::from(b);
+        if !wrap && (a > 0) != (b > 0) {
+            return None;
+        }
+        return Some(match sum {
+            0 => vec![],
+            1 => vec![C::Increment],
+            -1 => vec![C::Decrement],
+            _ => vec![C::Add(i16::try_from(sum).ok()?)],
+        });
+    }
+    if let (Some(a), Some(b)) = (moved(first), moved(second)) {
+        let sum = i64::from(a) + i64::from(b);
+        let mut merged = Vec::with_capacity(2);
+        if (a > 0) != (b > 0) {
+            // The cell `a` away is still reached, which fails or grows the
+            // tape like the first move would.
+            merged.push(C::AddAt {
+                offset: a,
+                value: 0,
+            });
+        }
+        match sum {
+            0 => {}
+            1 => merged.push(C::IncrementDataPointer),
+            -1 => merged.push(C::DecrementDataPointer),
+            _ => merged.push(C::MovePointer(i32::try_from(sum).ok()?)),
+        }
+        return Some(merged);
+    }
+
+    match (first, second) {
+        (C::Set(_), C::Set(value)) => Some(vec![C::Set(*value)]),
+        // Only failing adds are observable before a `Set`.
+        (first, C::Set(value)) if added(first).is_some() => {
+            (overflow_policy != OverflowPolicy::Error).then(|| vec![C::Set(*value)])
+        }
+        (C::Set(value), second) => {
+            let value = i32::from(*value);
+            let sum = value + i32::from(added(second)?);
+            // Without wrapping, all cell types only agree up to 127, where
+            // the add cannot fail or saturate.
+            let max = if wrap { 255 } else { 127 };
+            (value <= max && (0..=max).contains(&sum)).then(|| vec![C::Set(sum as u8)])
+        }
+        _ => None,
+    }
+}
+
+/// Replaces every run of at least two `Set(0)` with a move by one cell
+/// between each, in the same direction, by a [`Command::ClearRange`], and
+/// returns the new address of every command as well.
+fn clear_ranges(commands: &[Command]) -> (Vec<Command>, Vec<usize>) {
+    use self::Command as C;
+
+    let mut rewritten = Vec::with_capacity(commands.len());
+    let mut addresses = vec![0; commands.len()];
+
+    let mut index = 0;
+    while index < commands.len() {
+        // The run is `commands[index..=end]`, clearing `len` cells.
+        let (mut end, mut len, mut step) = (index, 1, 0);
+        if commands[index] == C::Set(0) {
+            while let [next, C::Set(0), ..] = &commands[end + 1..]
+                && len < i32::MAX
+            {
+                match moved(next) {
+                    Some(moved @ (1 | -1)) if step == 0 || moved == step => step = moved,
+                    _ => break,
+                }
+                end += 2;
+                len += 1;
+            }
+        }
+        addresses[index..=end].fill(rewritten.len());
+        rewritten.push(match len {
+            1 => commands[index].clone(),
+            len => C::ClearRange { len: len * step },
+        });
+        index = end + 1;
+    }
+
+    relocate(&mut rewritten, &addresses);
+    (rewritten, addresses)
+}
+
+/// Amount a `+`, `-`, or [`Command::Add`] adds to the cell.
+fn added(command: &Command) -> Option<i16> {
+    match *command {
+        Command::Increment => Some(1),
+        Command::Decrement => Some(-1),
+        Command::Add(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Amount a `>`, `<`, or [`Command::MovePointer`] moves the pointer.
+fn moved(command: &Command) -> Option<i32> {
+    match *command {
+        Command::IncrementDataPointer => Some(1),
+        Command::DecrementDataPointer => Some(-1),
+        Command::MovePointer(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Commands of a straight-line segment, by their offset from the cell the
+/// segment starts on, with their address before the rewrite.
+#[derive(Default)]
+struct Segment {
+    commands: Vec<(i32, Command, usize)>,
+    /// Addresses of the moves in the segment before the rewrite, with the
+    /// number of commands before each. A move becomes the command after it,
+    /// or the one move at the end.
+    moves: Vec<(usize, usize)>,
+    /// Offset the pointer has moved to.
+    offset: i32,
+    /// Offsets the rewritten segment has reached so far: the cells between
+    /// the start and every command.
+    reached: (i32, i32),
+}
+
+impl Segment {
+    /// Appends the rewritten segment to `rewritten`, ending with the pointer
+    /// where the original leaves it, and starts a new one. The new address
+    /// of every command goes into `addresses`.
+    fn flush(&mut self, rewritten: &mut Vec<Command>, addresses: &mut [usize]) {
+        use self::Command as C;
+
+        let start = rewritten.len();
+        let len = self.commands.len();
+        for (address, before) in self.moves.drain(..) {
+            addresses[address] = match before {
+                _ if before < len => start + before,
+                // Moves that cancel out become the last command.
+                _ if self.offset == 0 && len > 0 => start + len - 1,
+                _ => start + len,
+            };
+        }
+        for (offset, command, address) in self.commands.drain(..) {
+            addresses[address] = rewritten.len();
+            rewritten.push(match (offset, command) {
+                (0, command) => command,
+                (offset, C::Increment) => C::AddAt { offset, value: 1 },
+                (offset, C::Decrement) => C::AddAt { offset, value: -1 },
+                (offset, C::Add(value)) => C::AddAt { offset, value },
+                (offset, C::Set(value)) => C::SetAt { offset, value },
+                (offset, C::WriteByte) => C::OutputAt(offset),
+                (offset, C::ReadByte) => C::InputAt(offset),
+                _ => unreachable!("only cell commands are addressed"),
+            });
+        }
+        match self.offset {
+            0 => {}
+            1 => rewritten.push(C::IncrementDataPointer),
+            -1 => rewritten.push(C::DecrementDataPointer),
+            offset => rewritten.push(C::MovePointer(offset)),
+        }
+        *self = Segment::default();
+    }
+}
+
+/// Rewrites the straight-line code between jumps relative to the pointer,
+/// and returns the new address of every command as well.
+fn address_cells(commands: &[Command]) -> (Vec<Command>, Vec<usize>) {
+    use self::Command as C;
+
+    let mut rewritten = Vec::with_capacity(commands.len());
+    let mut addresses = vec![0; commands.len()];
+    let mut segment = Segment::default();
+
+    for (address, command) in commands.iter().enumerate() {
+        addresses[address] = rewritten.len();
+        let (offset, command) = match *command {
+            C::IncrementDataPointer => (1, None),
+            C::DecrementDataPointer => (-1, None),
+            C::MovePointer(moved) => (moved, None),
+            C::AddAt { offset, value } => (offset, Some(C::Add(value))),
+            C::Increment | C::Decrement | C::Add(_) | C::Set(_) | C::WriteByte | C::ReadByte => {
+                (0, Some(command.clone()))
+            }
+            _ => {
+                segment.flush(&mut rewritten, &mut addresses);
+                addresses[address] = rewritten.len();
+                rewritten.push(command.clone());
+                continue;
+            }
+        };
+
+        let (low, high) = segment.reached;
+        let Some(command) = command else {
+            // Turning back beyond the cells reached so far would skip cells
+            // the original visits, so the pointer has to stop there.
+            let turns =
+                (segment.offset > high && offset < 0) || (segment.offset < low && offset > 0);
+            match segment.offset.checked_add(offset).filter(|_| !turns) {
+                Some(offset) => segment.offset = offset,
+                None => {
+                    segment.flush(&mut rewritten, &mut addresses);
+                    segment.offset = offset;
+                }
+            }
+            segment.moves.push((address, segment.commands.len()));
+            continue;
+        };
+        let offset = match segment.offset.checked_add(offset) {
+            Some(offset) => offset,
+            None => {
+                segment.flush(&mut rewritten, &mut addresses);
+                offset
+            }
+        };
+        // Adding 0 only reaches the cell, which is pointless once reached.
+        let (low, high) = segment.reached;
+        if command == C::Add(0) && (low..=high).contains(&offset) {
+            continue;
+        }
+        segment.reached = (low.min(offset), high.max(offset));
+        segment.commands.push((offset, command, address));
+    }
+    segment.flush(&mut rewritten, &mut addresses);
+
+    relocate(&mut rewritten, &addresses);
+    (rewritten, addresses)
+}
+
+/// Faster replacement for the loop at the start of `commands`, which start
+/// at `address`, with the number of commands it replaces.
+///
+/// For a multiplication, the farthest cells the loop visits on either side
+/// must be cells it changes: the replacement only moves to those, and it
+/// has to fail or grow the tape wherever the loop would. Only the loop
+/// passes `pipeline` runs are applied.
+fn rewrite_loop(
+    commands: &[Command],
+    address: usize,
+    pipeline: &Pipeline,
+) -> Option<(usize, Vec<Command>)> {
+    use self::Command as C;
+
+    let C::JumpForwardIfZero(end) = *commands.first()? else {
+        return None;
+    };
+    let len = end.checked_sub(address)? + 1;
+    if commands.get(len - 1) != Some(&C::JumpBackwardIfNonZero(address)) {
+        return None;
+    }
+    let body = &commands[1..len - 1];
+
+    // A scan only moves, so it does not depend on the overflow policy.
+    if let [first, ..] = body
+        && body.iter().all(|command| command == first)
+        && pipeline.runs(Pass::ScanLoops)
+    {
+        match first {
+            C::IncrementDataPointer => return Some((len, vec![C::ScanRight(body.len())])),
+            C::DecrementDataPointer => return Some((len, vec![C::ScanLeft(body.len())])),
+            _ => {}
+        }
+    }
+    if pipeline.overflow_policy != OverflowPolicy::Wrap {
+        return None;
+    }
+    if body == [C::Increment] && pipeline.runs(Pass::ClearLoops) {
+        return Some((len, vec![C::Set(0)]));
+    }
+
+    // Net change of every cell, by its offset from the loop cell.
+    let mut deltas = BTreeMap::new();
+    let mut offset = 0_i64;
+    let (mut lowest, mut highest) = (0, 0);
+    for command in body {
+        let (moved, added) = match *command {
+            C::IncrementDataPointer => (1, 0),
+            C::DecrementDataPointer => (-1, 0),
+            C::MovePointer(moved) => (i64::from(moved), 0),
+            C::Increment => (0, 1),
+            C::Decrement => (0, -1),
+            C::Add(added) => (0, i64::from(added)),
+            _ => return None,
+        };
+        offset += moved;
+        lowest = lowest.min(offset);
+        highest = highest.max(offset);
+        if added != 0 {
+            *deltas.entry(offset).or_insert(0) += added;
+        }
+    }
+    if offset != 0 || deltas.remove(&0) != Some(-1) {
+        return None;
+    }
+    let changed = |edge| edge == 0 || deltas.get(&edge).is_some_and(|&delta| delta != 0);
+    if !changed(lowest) || !changed(highest) {
+        return None;
+    }
+
+    let mut rewritten = Vec::with_capacity(deltas.len() + 1);
+    for (offset, factor) in deltas {
+        if factor != 0 {
+            rewritten.push(C::MulAdd {
+                offset: i32::try_from(offset).ok()?,
+                factor: i16::try_from(factor).ok()?,
+            });
+        }
+    }
+    rewritten.push(C::Set(0));
+    // Without a multiplication this is a clear loop like `[-]`.
+    let pass = if rewritten.len() == 1 {
+        Pass::ClearLoops
+    } else {
+        Pass::MulLoops
+    };
+    pipeline.runs(pass).then_some((len, rewritten))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        EofBehavior, GrowableTape, Interpreter, OverflowPolicy, RuntimeError, Status, Streams, Vm,
+        compile, eval,
+    };
+
+    /// Test that every rewritten command knows the command it came from.
+    #[test]
+    fn test_origins() {
+        let commands = compile("++>[-]<.>>+<<[->+<]").unwrap();
+        let pipeline = Pipeline::new(OptLevel::O2);
+        let (optimized, origins) = pipeline.run_with_origins(&commands);
+        assert_eq!(optimized, pipeline.run(&commands));
+        assert_eq!(origins.len(), optimized.len());
+        assert!(origins.is_sorted(), "{origins:?}");
+        assert_eq!(origins[0], 0);
+        let write = optimized.iter().position(|c| *c == Command::WriteByte);
+        // The `.` is now addressed, so it starts at the `<` before it.
+        assert_eq!(write.map(|address| origins[address]), Some(6));
+        let multiply = optimized
+            .iter()
+            .position(|c| matches!(c, Command::MulAdd { .. }));
+        assert_eq!(multiply.map(|address| origins[address]), Some(13));
+
+        let (plain, origins) = Pipeline::new(OptLevel::O0).run_with_origins(&commands);
+        assert_eq!(plain, commands);
+        assert_eq!(origins, (0..commands.len()).collect::<Vec<_>>());
+    }
+
+    /// Test which passes the levels run, and that a pass can be left out.
+    #[test]
+    fn test_pipeline() {
+        use self::Command as C;
+
+        assert_eq!(Pipeline::new(OptLevel::O0).passes().count(), 0);
+        assert_eq!(
+            Pipeline::new(OptLevel::O1).passes().collect::<Vec<_>>(),
+            [Pass::FoldRuns, Pass::ClearLoops, Pass::Peephole]
+        );
+        assert_eq!(Pipeline::default().passes().count(), Pass::ALL.len());
+        for pass in Pass::ALL {
+            assert_eq!(Pass::from_name(pass.name()), Some(pass));
+        }
+        assert_eq!(Pass::from_name("everything"), None);
+
+        let program = compile("+[-]>[->++<]>[>]").unwrap();
+        assert_eq!(Pipeline::new(OptLevel::O0).run(&program), program);
+        let full = Pipeline::default().run(&program);
+        assert_eq!(
+            full,
+            [
+                C::Set(0),
+                C::IncrementDataPointer,
+                C::MulAdd {
+                    offset: 1,
+                    factor: 2
+                },
+                C::Set(0),
+                C::IncrementDataPointer,
+                C::ScanRight(1),
+            ]
+        );
+        let without_mul = Pipeline::default().without(Pass::MulLoops).run(&program);
+        assert!(
+            without_mul.contains(&C::JumpForwardIfZero(5)),
+            "{without_mul:?}"
+        );
+        assert!(!without_mul.iter().any(|c| matches!(c, C::MulAdd { .. })));
+        let without_clear = Pipeline::new(OptLevel::O1)
+            .without(Pass::ClearLoops)
+            .run(&compile("+[-]").unwrap());
+        assert_eq!(
+            without_clear,
+            [
+                C::Increment,
+                C::JumpForwardIfZero(3),
+                C::Decrement,
+                C::JumpBackwardIfNonZero(1),
+            ]
+        );
+    }
+
+    /// Test which loops are dropped as dead code.
+    #[test]
+    fn test_dead_loops() {
+        use self::Command as C;
+
+        let commands = compile("[comment.]+[-][>+<-].[<]++[>]").unwrap();
+        assert_eq!(
+            eliminate_dead_code(&commands, true),
+            [
+                C::Increment,
+                C::JumpForwardIfZero(3),
+                C::Decrement,
+                C::JumpBackwardIfNonZero(1),
+                C::WriteByte,
+                C::Increment,
+                C::Increment,
+                C::JumpForwardIfZero(9),
+                C::IncrementDataPointer,
+                C::JumpBackwardIfNonZero(7),
+            ]
+        );
+        // The first cell may be set before the program starts.
+        assert_eq!(eliminate_dead_code(&commands, false).len(), 13);
+
+        let optimized = optimize(&compile("[-]>+<[>-<][.]>").unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(
+            eliminate_dead_code(&optimized, false),
+            [
+                C::Set(0),
+                C::AddAt {
+                    offset: 1,
+                    value: 1
+                },
+                C::IncrementDataPointer
+            ]
+        );
+        let kept = ",[.,]>[-]<[-]";
+        let commands = compile(kept).unwrap();
+        assert_eq!(eliminate_dead_code(&commands, true), commands);
+    }
+
+    /// Test the folded commands and their moved jumps.
+    #[test]
+    fn test_fold_runs() {
+        use self::Command as C;
+
+        let (optimized, addresses) = fold(&compile("+++>>--<[->]").unwrap(), &Pipeline::default());
+        assert_eq!(
+            optimized,
+            [
+                C::Add(3),
+                C::MovePointer(2),
+                C::Add(-2),
+                C::DecrementDataPointer,
+                C::JumpForwardIfZero(7),
+                C::Decrement,
+                C::IncrementDataPointer,
+                C::JumpBackwardIfNonZero(4),
+            ]
+        );
+        assert_eq!(addresses, [0, 0, 0, 1, 1, 2, 2, 3, 4, 5, 6, 7]);
+
+        let long = optimize(&compile(&"+".repeat(40_000)).unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(long, [C::Add(i16::MAX), C::Add(7_233)]);
+        let mut vm = Vm::with_tape(&long, vec![0_u16], 0);
+        vm.run().unwrap();
+        assert_eq!(vm.tape(), &[40_000]);
+    }
+
+    /// Test that hello world and cat print the same in fewer steps.
+    #[test]
+    fn test_same_output() {
+        for (source_code, input) in [
+            (
+                "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+                >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+                &b""[..],
+            ),
+            (",[.,]", b"cat\n"),
+            (">,[>,]<[<]>[.>]", b"round trip\0"),
+            (">++++++[<++++++++>-]>++++++++++[<<.+>>-]", b""),
+            (">,>,[<.>-]<<.>.", b"\x03\x05"),
+            (include_str!("../tests/cli/commented.b"), b""),
+        ] {
+            let program = compile(source_code).unwrap();
+            let optimized = optimize(&program, OverflowPolicy::Wrap);
+
+            let mut expected = Vec::new();
+            let before = eval(&program, input, &mut expected).unwrap();
+            let mut output = Vec::new();
+            let after = eval(&optimized, input, &mut output).unwrap();
+
+            assert_eq!(output, expected);
+            assert!(after.steps <= before.steps);
+            assert_eq!(after.max_pointer, before.max_pointer);
+        }
+    }
+
+    /// Test that cells are addressed relative to the pointer between loops.
+    #[test]
+    fn test_address_cells() {
+        use self::Command as C;
+
+        let optimize = |source| optimize(&compile(source).unwrap(), OverflowPolicy::Saturate);
+        assert_eq!(
+            optimize(">>+<<"),
+            [C::AddAt {
+                offset: 2,
+                value: 1
+            }]
+        );
+        assert_eq!(
+            optimize(">>+<.>,"),
+            [
+                C::AddAt {
+                    offset: 2,
+                    value: 1
+                },
+                C::OutputAt(1),
+                C::InputAt(2),
+                C::MovePointer(2),
+            ]
+        );
+        assert_eq!(
+            optimize("+>>[-<+>]<"),
+            [
+                C::Increment,
+                C::MovePointer(2),
+                C::JumpForwardIfZero(5),
+                C::Decrement,
+                C::AddAt {
+                    offset: -1,
+                    value: 1
+                },
+                C::JumpBackwardIfNonZero(2),
+                C::DecrementDataPointer,
+            ]
+        );
+        // The pointer still turns around at the farthest cell.
+        assert_eq!(
+            address_cells(&fold(&compile(">>>><<+").unwrap(), &Pipeline::default()).0).0,
+            [
+                C::MovePointer(4),
+                C::AddAt {
+                    offset: -2,
+                    value: 1
+                },
+                C::MovePointer(-2),
+            ]
+        );
+    }
+
+    /// Test that a generated program full of opposite pairs shrinks to
+    /// almost nothing and still does the same.
+    #[test]
+    fn test_cancel_pairs() {
+        use self::Command as C;
+
+        let source =
+            "+-".repeat(5_000) + ">" + &"><".repeat(5_000) + "-+".repeat(5_000).as_str() + "+.";
+        let program = compile(&source).unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+        assert_eq!(
+            optimized,
+            [
+                C::AddAt {
+                    offset: 2,
+                    value: 0
+                },
+                C::AddAt {
+                    offset: 1,
+                    value: 1
+                },
+                C::OutputAt(1),
+                C::IncrementDataPointer,
+            ]
+        );
+        let mut expected = Vec::new();
+        let before = eval(&program, &[][..], &mut expected).unwrap();
+        let mut output = Vec::new();
+        let after = eval(&optimized, &[][..], &mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(after.max_pointer, before.max_pointer);
+        assert_eq!(after.final_pointer, before.final_pointer);
+
+        // Only wrapping makes `+-` do nothing at the ends of the cell.
+        let saturated = optimize(&compile("+-").unwrap(), OverflowPolicy::Saturate);
+        assert_eq!(saturated, [C::Increment, C::Decrement]);
+        let mut vm = Vm::with_tape(&saturated, vec![u8::MAX], 0)
+            .with_overflow_policy(OverflowPolicy::Saturate);
+        vm.run().unwrap();
+        assert_eq!(vm.tape(), &[u8::MAX - 1]);
+
+        // `><` on the last cell still fails.
+        let program = optimize(&compile("+><").unwrap(), OverflowPolicy::Wrap);
+        assert!(matches!(
+            Vm::with_tape(&program, vec![0_u8], 0).run(),
+            Err(RuntimeError::PointerOutOfBounds { pointer: 1, .. })
+        ));
+    }
+
+    /// Test that sets absorb the adds around them, and jumps still match.
+    #[test]
+    fn test_merge_sets() {
+        use self::Command as C;
+
+        let optimize = |source| optimize(&compile(source).unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(optimize("+++[-]++"), [C::Set(2)]);
+        assert_eq!(optimize("[-]-"), [C::Set(0), C::Decrement]);
+        assert_eq!(
+            optimize(",+[+-[-]+-]"),
+            [
+                C::ReadByte,
+                C::Increment,
+                C::JumpForwardIfZero(4),
+                C::Set(0),
+                C::JumpBackwardIfNonZero(2),
+            ]
+        );
+        let mut output = Vec::new();
+        eval(&optimize("+++++[+-[-]+-]+."), &[][..], &mut output).unwrap();
+        assert_eq!(output, [1]);
+
+        // A failing add before a set is kept, and so is an add that would
+        // saturate a signed cell.
+        let strict = peephole(vec![C::Increment, C::Set(0)], OverflowPolicy::Error).0;
+        assert_eq!(strict, [C::Increment, C::Set(0)]);
+        let signed = peephole(vec![C::Set(100), C::Add(50)], OverflowPolicy::Saturate).0;
+        assert_eq!(signed, [C::Set(100), C::Add(50)]);
+    }
+
+    /// Test that addressed cells fail or grow the tape where moving did.
+    #[test]
+    fn test_address_edges() {
+        let program = compile("+>>>+").unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+        let mut before = Vm::with_tape(&program, vec![0_u8; 3], 0);
+        let mut after = Vm::with_tape(&optimized, vec![0_u8; 3], 0);
+        assert!(matches!(
+            before.run(),
+            Err(RuntimeError::PointerOutOfBounds { pointer: 3, .. })
+        ));
+        assert_eq!(
+            after.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 1,
+                pointer: 3
+            })
+        );
+        assert_eq!(after.data_pointer(), before.data_pointer());
+        assert_eq!(after.tape(), before.tape());
+
+        let program = compile("<<+>>>+<.<<<-.").unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+        let mut expected = Vec::new();
+        let mut before = Vm::with_tape(&program, GrowableTape::new(), 0);
+        before
+            .run_with(Streams::new(&[][..], &mut expected))
+            .unwrap();
+        let mut output = Vec::new();
+        let mut after = Vm::with_tape(&optimized, GrowableTape::new(), 0);
+        after.run_with(Streams::new(&[][..], &mut output)).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(after.tape().cell(-3), u8::MAX);
+        assert_eq!(after.tape().cell(-2), 1);
+        assert_eq!(after.tape().cell(1), 1);
+        assert_eq!(after.data_pointer(), before.data_pointer());
+        assert_eq!(after.report().min_pointer, before.report().min_pointer);
+    }
+
+    /// Test that input goes to the addressed cell, also at the end of input.
+    #[test]
+    fn test_input_at() {
+        let program = optimize(&compile(">,>,<<.>.>.").unwrap(), OverflowPolicy::Wrap);
+        assert!(program.contains(&Command::InputAt(2)));
+        let mut vm =
+            Vm::with_tape(&program, vec![0_u8, 7, 9], 0).with_eof_behavior(EofBehavior::Unchanged);
+        let mut output = Vec::new();
+        vm.run_with(Streams::new(&b"a"[..], &mut output)).unwrap();
+        assert_eq!(output, [0, b'a', 9]);
+
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 3], 0);
+        assert_eq!(vm.run(), Ok(Status::NeedsInput));
+        vm.provide_input(1);
+        assert_eq!(vm.run_for(1), Ok(Status::NeedsInput));
+        vm.provide_eof();
+        assert_eq!(vm.run(), Ok(Status::ProducedOutput(0)));
+        assert_eq!(vm.tape(), &[0, 1, 0]);
+    }
+
+    /// Test that the digits program moves the pointer far less often.
+    #[test]
+    fn test_fewer_moves() {
+        let program = compile(">++++++[<++++++++>-]>++++++++++[<<.+>>-]").unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Error);
+        let moves = |commands: &[Command]| {
+            commands
+                .iter()
+                .filter(|command| {
+                    matches!(
+                        command,
+                        Command::IncrementDataPointer
+                            | Command::DecrementDataPointer
+                            | Command::MovePointer(_)
+                    )
+                })
+                .count()
+        };
+        assert_eq!(moves(&optimized), 2);
+
+        let interpreter = Interpreter::builder()
+            .overflow_policy(OverflowPolicy::Error)
+            .build()
+            .unwrap();
+        let mut expected = Vec::new();
+        let before = interpreter
+            .vm(&program)
+            .run_with(Streams::new(&[][..], &mut expected))
+            .unwrap();
+        let mut output = Vec::new();
+        let after = interpreter
+            .vm(&optimized)
+            .run_with(Streams::new(&[][..], &mut output))
+            .unwrap();
+        assert_eq!(output, b"0123456789");
+        assert_eq!(output, expected);
+        assert_eq!((before.steps, after.steps), (172, 64));
+    }
+
+    /// Test that folded runs keep stopping at the edges of the cell.
+    #[test]
+    fn test_overflow_policies() {
+        let program = compile(&"+".repeat(300)).unwrap();
+
+        let saturate = Interpreter::builder()
+            .tape_len(1)
+            .overflow_policy(OverflowPolicy::Saturate)
+            .build()
+            .unwrap();
+        let optimized = optimize(&program, saturate.overflow_policy());
+        let mut vm = saturate.vm(&optimized);
+        vm.run().unwrap();
+        assert_eq!(vm.tape(), &[u8::MAX]);
+
+        let error = Interpreter::builder()
+            .tape_len(1)
+            .overflow_policy(OverflowPolicy::Error)
+            .build()
+            .unwrap();
+        let optimized = optimize(&program, error.overflow_policy());
+        assert_eq!(
+            error.vm(&optimized).run(),
+            Err(RuntimeError::CellOverflow {
+                instruction_index: 0
+            })
+        );
+    }
+
+    /// Test that `[-]` and `[+]` clear the cell in one step, only with
+    /// wrapping and only when the body is nothing else.
+    #[test]
+    fn test_clear_loops() {
+        use self::Command as C;
+
+        let program = compile("-[-]>-[+]+>[->][-.][--]").unwrap();
+        let optimized = fold(&program, &Pipeline::default()).0;
+        assert_eq!(
+            optimized[..6],
+            [
+                C::Decrement,
+                C::Set(0),
+                C::IncrementDataPointer,
+                C::Decrement,
+                C::Set(0),
+                C::Increment,
+            ]
+        );
+        assert_eq!(optimized.iter().filter(|c| **c == C::Set(0)).count(), 2);
+        assert_eq!(optimized.len(), 18);
+
+        let program = compile("-[-]>,[+]+.").unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+        let mut expected = Vec::new();
+        let before = eval(&program, &[7][..], &mut expected).unwrap();
+        let mut output = Vec::new();
+        let after = eval(&optimized, &[7][..], &mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(output, [1]);
+        // 255 and 249 iterations of two steps each become one step each,
+        // which the `-` before and the `+` after are merged into.
+        assert_eq!(before.steps, 2 + 255 * 2 + 3 + 249 * 2 + 2);
+        assert_eq!(after.steps, 5);
+
+        let saturated = optimize(&program, OverflowPolicy::Saturate);
+        assert!(!saturated.contains(&C::Set(0)));
+    }
+
+    /// Test which loops become multiplications.
+    #[test]
+    fn test_multiply_loops() {
+        use self::Command as C;
+
+        let optimize = |source| optimize(&compile(source).unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(
+            optimize("[->+>+++<<]"),
+            [
+                C::MulAdd {
+                    offset: 1,
+                    factor: 1
+                },
+                C::MulAdd {
+                    offset: 2,
+                    factor: 3
+                },
+                C::Set(0),
+            ]
+        );
+        assert_eq!(
+            optimize("[<<+>>>--<-]"),
+            [
+                C::MulAdd {
+                    offset: -2,
+                    factor: 1
+                },
+                C::MulAdd {
+                    offset: 1,
+                    factor: -2
+                },
+                C::Set(0),
+            ]
+        );
+        // Output, a nested loop, a step other than -1, a moving loop, and a
+        // loop reaching a cell it does not change.
+        for source in ["[->+<.]", "[->+<[-]]", "[-->+<]", "[->+]", "[->+>+-<<]"] {
+            let optimized = optimize(source);
+            assert!(
+                !optimized
+                    .iter()
+                    .any(|command| matches!(command, C::MulAdd { .. })),
+                "{source}"
+            );
+        }
+
+        let program = optimize("+[-<+>]");
+        assert_eq!(
+            Vm::new(&program).run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 1,
+                pointer: -1
+            })
+        );
+    }
+
+    /// Test which loops become scans, under every overflow policy.
+    #[test]
+    fn test_scan_loops() {
+        use self::Command as C;
+
+        let program = compile(">,[>,]<[<]>[.>]").unwrap();
+        for policy in [OverflowPolicy::Wrap, OverflowPolicy::Error] {
+            let optimized = optimize(&program, policy);
+            assert_eq!(
+                optimized[6..9],
+                [
+                    C::DecrementDataPointer,
+                    C::ScanLeft(1),
+                    C::IncrementDataPointer
+                ]
+            );
+        }
+        let optimize = |source| optimize(&compile(source).unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(optimize("[>>>]"), [C::ScanRight(3)]);
+        assert_eq!(optimize("[<<][>]"), [C::ScanLeft(2), C::ScanRight(1)]);
+        for source in ["[]", "[><]", "[>.]", "[>+]"] {
+            let optimized = optimize(source);
+            assert!(
+                !optimized
+                    .iter()
+                    .any(|command| matches!(command, C::ScanRight(_) | C::ScanLeft(_))),
+                "{source}"
+            );
+        }
+    }
+
+    /// Test that a scan over 100,000 cells takes one step instead of one
+    /// per cell, with byte and wider cells.
+    #[test]
+    fn test_long_scan() {
+        const LEN: usize = 100_000;
+        let program = compile("[>]+[<<<]").unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+
+        let mut tape = vec![1_u8; LEN];
+        tape[0] = 0;
+        tape[LEN - 1] = 0;
+        let mut vm = Vm::with_tape(&program, tape.clone(), 1);
+        let before = vm.run_with(Streams::new(&[][..], &mut Vec::new())).unwrap();
+        let expected = (vm.data_pointer(), vm.into_tape());
+        let mut vm = Vm::with_tape(&optimized, tape, 1);
+        let after = vm.run_with(Streams::new(&[][..], &mut Vec::new())).unwrap();
+        assert_eq!((vm.data_pointer(), vm.into_tape()), expected);
+        assert_eq!(expected.0, 0);
+        // Two steps per cell right, then four per stride of three back left.
+        assert_eq!(
+            before.steps,
+            2 * (LEN as u64 - 2) + 3 + 4 * (LEN as u64 - 1) / 3
+        );
+        assert_eq!(after.steps, 3);
+        assert_eq!(after.max_pointer, before.max_pointer);
+
+        let mut tape = vec![1_u16; LEN];
+        tape[0] = 0;
+        let program = optimize(&compile("[<]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, tape, LEN - 1);
+        let report = vm.run_with(Streams::new(&[][..], &mut Vec::new())).unwrap();
+        assert_eq!((vm.data_pointer(), report.steps), (0, 1));
+    }
+
+    /// Test that a scan that finds no zero fails at the edge of a fixed tape
+    /// and grows a growable one, like the loop.
+    #[test]
+    fn test_scan_edges() {
+        let program = optimize(&compile("+[>>]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, vec![1_u8; 6], 0);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 1,
+                pointer: 6
+            })
+        );
+        assert_eq!(vm.data_pointer(), 5);
+
+        let program = optimize(&compile("+>+>+<<[>]+").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, GrowableTape::new(), 0);
+        vm.run().unwrap();
+        assert_eq!(vm.tape().cells()[..4], [1, 1, 1, 1]);
+
+        let mut vm = Vm::with_tape(&program, GrowableTape::with_limit(3), 0);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::MemoryLimitExceeded {
+                instruction_index: 3,
+                requested_cells: 4
+            })
+        );
+    }
+
+    /// Test which runs of clear loops become one range, in either direction.
+    #[test]
+    fn test_clear_ranges() {
+        use self::Command as C;
+
+        let clear = |source_code| optimize(&compile(source_code).unwrap(), OverflowPolicy::Wrap);
+        assert_eq!(clear("[-]>[-]>[+]"), [C::ClearRange { len: 3 }]);
+        assert_eq!(
+            clear("[-]<[-]<[-]>"),
+            [C::ClearRange { len: -3 }, C::IncrementDataPointer]
+        );
+        assert_eq!(
+            clear(".[-]>[-]."),
+            [C::WriteByte, C::ClearRange { len: 2 }, C::WriteByte]
+        );
+        // A stride of two or a turn ends the run.
+        assert!(
+            !clear("[-]>>[-]>>[-]")
+                .iter()
+                .any(|c| matches!(c, C::ClearRange { .. }))
+        );
+        assert_eq!(clear("[-]>[-]<[-]")[0], C::ClearRange { len: 2 });
+        assert_eq!(clear("[-]>[-]").len(), 1);
+        let pipeline = Pipeline::default().without(Pass::ClearRanges);
+        assert_eq!(pipeline.run(&compile("[-]>[-]").unwrap())[0], C::Set(0));
+    }
+
+    /// Test that clearing many cells takes one step and ends like the loops.
+    #[test]
+    fn test_long_clear() {
+        const LEN: usize = 10_000;
+        let source_code = ["[-]"; LEN].join(">") + ".";
+        let program = compile(&source_code).unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+        assert_eq!(optimized.len(), 2);
+
+        let run = |commands: &[Command], tape| {
+            let mut vm = Vm::with_tape(commands, tape, 2);
+            let mut output = Vec::new();
+            let report = vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+            (report, output, vm.data_pointer(), vm.into_tape())
+        };
+        let tape = vec![7_u16; LEN + 4];
+        let (before, output, pointer, cells) = run(&program, tape.clone());
+        let after = run(&optimized, tape);
+        assert_eq!((&after.1, after.2, &after.3), (&output, pointer, &cells));
+        assert_eq!(pointer, LEN + 1);
+        assert_eq!(cells[1..LEN + 3], [&[7][..], &[0; LEN], &[7]].concat());
+        assert_eq!(after.0.steps, 2);
+        assert!(before.steps > 4 * LEN as u64);
+        assert_eq!(after.0.max_pointer, before.max_pointer);
+        assert_eq!(after.0.max_pointer, LEN + 1);
+
+        // Leftwards, the range starts on the last cell.
+        let program = optimize(&compile("[-]<[-]<[-]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, vec![5_u8; 6], 4);
+        vm.run().unwrap();
+        assert_eq!((vm.data_pointer(), vm.tape()), (2, &vec![5, 5, 0, 0, 0, 5]));
+        assert_eq!(vm.report().min_pointer, 2);
+    }
+
+    /// Test that a range past the edge of the tape clears up to the edge and
+    /// fails there, or grows the tape, like the loops.
+    #[test]
+    fn test_clear_edges() {
+        let program = optimize(&compile("[-]>[-]>[-]>[-]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, vec![1_u8; 4], 2);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 0,
+                pointer: 4
+            })
+        );
+        assert_eq!((vm.data_pointer(), vm.tape()), (3, &vec![1, 1, 0, 0]));
+
+        let program = optimize(&compile("+>+<[-]<[-]<[-]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, GrowableTape::new(), 0);
+        vm.run().unwrap();
+        assert_eq!(vm.tape().cell(1), 1);
+        assert_eq!(vm.tape().origin() - vm.data_pointer(), 2);
+        assert_eq!(vm.report().min_pointer, vm.data_pointer());
+
+        let program = optimize(&compile("[-]<[-]<[-]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::with_tape(&program, GrowableTape::with_limit(2), 0);
+        assert!(matches!(
+            vm.run(),
+            Err(RuntimeError::MemoryLimitExceeded {
+                instruction_index: 0,
+                ..
+            })
+        ));
+    }
+
+    /// Test that a factorial prints the same in far fewer steps.
+    #[test]
+    fn test_factorial() {
+        // Cells: n, product, copy of the product, counter, scratch.
+        let source_code = "++++++++>+<[\
+            >[->+<]\
+            <[->>>+>+<<<<]>>>>[-<<<<+>>>>]<\
+            [-<[-<+>>>+<<]>>[-<<+>>]<]\
+            <[-]<<-]>.";
+        let program = compile(source_code).unwrap();
+        let optimized = optimize(&program, OverflowPolicy::Wrap);
+
+        let mut vm = Vm::with_tape(&program, vec![0_u32; 5], 0);
+        let mut expected = Vec::new();
+        let before = vm.run_with(Streams::new(&[][..], &mut expected)).unwrap();
+        assert_eq!(vm.tape(), &[0, 40_320, 0, 0, 0]);
+
+        let mut vm = Vm::with_tape(&optimized, vec![0_u32; 5], 0);
+        let mut output = Vec::new();
+        let after = vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+        assert_eq!(vm.tape(), &[0, 40_320, 0, 0, 0]);
+        assert_eq!(output, expected);
+        assert!(after.steps * 1000 < before.steps, "{after:?} {before:?}");
+    }
+}
@@ -0,0 +1,1353 @@
+//! Command line flags and the settings they turn into.
+
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use brainfuck_vm::{
+    BfcHeader, CommentStyle, Engine, EofBehavior, IoMode, Newline, OptLevel, OverflowPolicy, Pass,
+    Program, TokenMap,
+};
+
+use crate::source::Source;
+
+/// Why a command line could not be turned into settings.
+#[derive(Debug, PartialEq)]
+pub enum ArgError {
+    /// The command line is malformed, e.g. a flag is unknown or lacks its
+    /// value.
+    Usage(String),
+    /// A file the command line names cannot be read, written or loaded.
+    Io(String),
+    /// `--help` or `-h` was given as a flag.
+    Help,
+}
+
+impl From<String> for ArgError {
+    fn from(message: String) -> Self {
+        ArgError::Usage(message)
+    }
+}
+
+impl From<&str> for ArgError {
+    fn from(message: &str) -> Self {
+        ArgError::Usage(message.into())
+    }
+}
+
+pub const USAGE: &str = "Usage: brainfuck_vm [run] [--profile dbfi|classic|strict|large]\n           \
+[--eof zero|minus-one|unchanged] [--overflow wrap|saturate|error]\n           \
+[--tape-size N] [--pointer-start N] [--cell-size 8|16|32|i8] [--tape dense|sparse]\n           \
+[--tape-init FILE | --tape-init-hex HEX] [--tape-init-offset N] [--init-pointer N]\n           \
+[--max-steps N] [--max-output N] [--timeout 500ms|5s|1m] [--max-memory 64MiB]\n           \
+[--dialect brainfuck|pbrain|ook | --dialect-map FILE] [--max-call-depth N] [--exit-cell]\n           \
+[--strict] [--debug-ext] [--random-ext] [--seed N] [--comments braces|semicolon] [--macros]\n           \
+[-O0|-O1|-O2] [--disable-pass NAME] [--emit-ir] [--partial-eval] [--no-cache]\n           \
+[--verbose] [--time] [--stats | --stats-json] [--engine match|threaded|jit]\n           \
+[--raw] [--numeric] [--separator newline|space] [--unicode] [--newline lf|crlf|native] [--echo]\n           \
+[--bang-input | --input FILE | --input-bytes TEXT] [--output FILE [--append]]\n           \
+<program> | --file FILE | FILE | -\n       \
+brainfuck_vm check [--json] [OPTIONS] <program> | --file FILE | FILE... | -\n       \
+brainfuck_vm repl [OPTIONS] [<program> | --file FILE | FILE]\n       \
+brainfuck_vm gen [--] <text> | --input-file FILE [-o FILE]\n       \
+brainfuck_vm fmt [--width N] [--indent N] [--strip-comments] [--check] [-o FILE] FILE\n       \
+brainfuck_vm minify [--level 1|2] [-o FILE] FILE\n       \
+brainfuck_vm export-html [-o FILE] FILE\n       \
+brainfuck_vm compile [--target c|rust|wasm|bfc] [OPTIONS] [-o FILE] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm build [--opt] [OPTIONS] [-o FILE] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm verify [--random-inputs N] [OPTIONS] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm disasm [--range START..END] [--source-map] [OPTIONS] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm bench [--runs N] [--warmup N] [--json] [OPTIONS] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm hotspots [--top N] [--json] [OPTIONS] <program> | --file FILE | FILE | -\n       \
+brainfuck_vm --help | --version";
+
+pub const CHECK_USAGE: &str = "Usage: brainfuck_vm check [--json] [OPTIONS] <program> | --file FILE | FILE... | -\n\
+OPTIONS are those of a run. Every program is compiled as it would be to run, and all errors found in each are reported; --json prints them as an array of objects with the file, offset, line, column, and message of each.";
+
+pub const REPL_USAGE: &str = "Usage: brainfuck_vm repl [OPTIONS] [<program> | --file FILE | FILE]\n\
+OPTIONS are those of a run. Every line read from stdin runs as a program of its own on a tape that persists between lines, after the program if one is given. A line that leaves a '[' open goes on until the line that closes it. A run that fails leaves the tape as it was before.\n\
+Lines starting with ':' are commands: ':dump' shows the cells around the pointer, ':reset' clears the tape, ':pointer N' moves the pointer to cell N, ':load FILE' runs the program in FILE, and ':quit' ends the session.";
+
+pub const GEN_USAGE: &str = "Usage: brainfuck_vm gen [--] <text> | --input-file FILE [-o FILE]\n\
+A text that starts with '-' goes after --.";
+
+pub const FMT_USAGE: &str =
+    "Usage: brainfuck_vm fmt [--width N] [--indent N] [--strip-comments] [--check] [-o FILE] FILE";
+
+pub const MINIFY_USAGE: &str = "Usage: brainfuck_vm minify [--level 1|2] [-o FILE] FILE";
+
+pub const EXPORT_USAGE: &str = "Usage: brainfuck_vm export-html [-o FILE] FILE";
+
+pub const COMPILE_USAGE: &str = "Usage: brainfuck_vm compile [--target c|rust|wasm|bfc] [OPTIONS] [-o FILE] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of a run that change what the program does, e.g. --eof, --overflow, --tape-size, and --cell-size.\n\
+The bfc target, also chosen by -o FILE.bfc, writes the compiled program, which runs with brainfuck_vm FILE.bfc.";
+
+pub const BUILD_USAGE: &str = "Usage: brainfuck_vm build [--opt] [OPTIONS] [-o FILE] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of compile; --opt builds with -O2.";
+
+pub const VERIFY_USAGE: &str = "Usage: brainfuck_vm verify [--random-inputs N] [OPTIONS] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of a run; -O and --disable-pass pick the passes to check, --input or --input-bytes is the input of one run on top of the random ones, and --seed also seeds the random inputs. With the jit feature the optimized program also runs as machine code.";
+
+pub const DISASM_USAGE: &str = "Usage: brainfuck_vm disasm [--range START..END] [--source-map] [OPTIONS] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of a run; the program is listed as it would run with them.";
+
+pub const BENCH_USAGE: &str = "Usage: brainfuck_vm bench [--runs N] [--warmup N] [--json] [OPTIONS] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of a run. The program is compiled once and run --warmup times, 1 by default, then --runs times, 10 by default, on the same input with its output discarded.";
+
+pub const HOTSPOTS_USAGE: &str = "Usage: brainfuck_vm hotspots [--top N] [--json] [OPTIONS] <program> | --file FILE | FILE | -\n\
+OPTIONS are those of a run. The program runs once with its output discarded, and the --top loops it spent the most steps in, 10 by default, are listed with their source offsets.";
+
+/// Settings taken from the command line.
+pub struct Options {
+    /// Program text with its includes resolved.
+    pub source: Source,
+    /// Program loaded from a `.bfc` file, which runs instead of `source`.
+    pub compiled: Option<Program>,
+    pub dialect: Dialect,
+    /// Spelling of the commands, from `--dialect-map`.
+    pub token_map: Option<TokenMap>,
+    pub eof_behavior: EofBehavior,
+    pub overflow_policy: OverflowPolicy,
+    pub io_mode: IoMode,
+    /// Line ending to translate input and output to, if any.
+    pub newline: Option<Newline>,
+    pub tape_size: Option<usize>,
+    pub pointer_start: Option<usize>,
+    pub cell_size: CellSize,
+    pub sparse_tape: bool,
+    pub tape_init: Option<Vec<u8>>,
+    pub tape_init_offset: usize,
+    pub init_pointer: Option<usize>,
+    pub max_steps: Option<u64>,
+    pub max_output: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub max_memory: Option<usize>,
+    pub max_call_depth: Option<usize>,
+    /// Exit with the final current cell instead of 0.
+    pub exit_cell: bool,
+    /// Reject characters that are neither commands nor `#` comments.
+    pub strict: bool,
+    /// Dump the tape to stderr at every `#`.
+    pub debug_ext: bool,
+    /// Set the current cell to a random byte at every `?`.
+    pub random_ext: bool,
+    /// Seed for `?`, or one taken from the clock.
+    pub seed: Option<u64>,
+    /// Comments to blank out before compiling.
+    pub comments: Option<CommentStyle>,
+    /// Expand `@def` macros before compiling.
+    pub macros: bool,
+    /// Passes to run before running, from `-O0`, `-O1`, or `-O2`.
+    pub opt_level: OptLevel,
+    /// Passes left out with `--disable-pass`.
+    pub disabled_passes: Vec<Pass>,
+    /// Print the optimized program instead of running it.
+    pub emit_ir: bool,
+    /// Run the start of the program that needs no input ahead of time.
+    pub partial_eval: bool,
+    /// Compile the program and run it even if the compiled program or its
+    /// output is cached.
+    pub no_cache: bool,
+    /// Report what the optimizer and the caches did on stderr.
+    pub verbose: bool,
+    /// Report how long compiling and running took on stderr.
+    pub time: bool,
+    /// Report how often each kind of command ran on stderr, and how.
+    pub stats: Option<StatsFormat>,
+    pub engine: Engine,
+    /// Pass keystrokes to `,` as they are typed, without echo.
+    pub raw: bool,
+    /// Copy input from a terminal to the output.
+    pub echo: bool,
+    /// Read input from the source code after its first `!`.
+    pub bang_input: bool,
+    /// What `,` reads instead of stdin, from `--input` or `--input-bytes`.
+    pub input: Option<Input>,
+    /// File `.` writes to instead of stdout, from `--output`, emptied first
+    /// unless `--append` is given.
+    pub output: Option<File>,
+    /// The program was read from stdin with `-`, so without any other
+    /// input every `,` finds the end of its input.
+    pub stdin_program: bool,
+}
+
+/// Input of a run given on the command line.
+pub enum Input {
+    /// File opened for `--input`, which is read as the program runs.
+    File { path: PathBuf, file: File },
+    /// Bytes of the argument of `--input-bytes`.
+    Bytes(Vec<u8>),
+}
+
+impl Input {
+    /// Reader over the input, which is used up as it reads.
+    pub fn reader(&self) -> Box<dyn Read + '_> {
+        match self {
+            Input::File { file, .. } => Box::new(file),
+            Input::Bytes(bytes) => Box::new(bytes.as_slice()),
+        }
+    }
+
+    /// All of the input, e.g. for runs that each read it from the start.
+    pub fn read_all(&self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        match self {
+            Input::File { path, file } => {
+                let mut file = file;
+                file.read_to_end(&mut data)
+                    .map_err(|e| format!("cannot read '{}': {e}", path.display()))?;
+            }
+            Input::Bytes(bytes) => data.extend_from_slice(bytes),
+        }
+        Ok(data)
+    }
+}
+
+/// Settings expected by a family of programs, chosen with `--profile`.
+/// Flags given on their own take precedence over the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub overflow_policy: OverflowPolicy,
+    pub eof_behavior: EofBehavior,
+    pub tape_size: usize,
+    /// Allocate the tape in pages as it is written, so a long tape is free.
+    pub sparse_tape: bool,
+    /// Cell the pointer starts on; the pointer may not leave the tape.
+    pub pointer_start: usize,
+}
+
+/// Every profile `--profile` accepts.
+pub const PROFILES: [Profile; 4] = [
+    // Self-interpreters like dbfi read the program and its input from the
+    // same stream and leave the cell alone at EOF, and nesting them needs
+    // far more than 30,000 cells.
+    Profile {
+        name: "dbfi",
+        overflow_policy: OverflowPolicy::Wrap,
+        eof_behavior: EofBehavior::Unchanged,
+        tape_size: 1 << 24,
+        sparse_tape: true,
+        pointer_start: 0,
+    },
+    // What most programs, mandelbrot.b included, were written against.
+    Profile {
+        name: "classic",
+        overflow_policy: OverflowPolicy::Wrap,
+        eof_behavior: EofBehavior::SetZero,
+        tape_size: 30_000,
+        sparse_tape: false,
+        pointer_start: 0,
+    },
+    // Turns wrapping cells into errors, for programs meant to be portable.
+    Profile {
+        name: "strict",
+        overflow_policy: OverflowPolicy::Error,
+        eof_behavior: EofBehavior::SetZero,
+        tape_size: 30_000,
+        sparse_tape: false,
+        pointer_start: 0,
+    },
+    // Room in both directions, for programs that move left of their start.
+    Profile {
+        name: "large",
+        overflow_policy: OverflowPolicy::Wrap,
+        eof_behavior: EofBehavior::SetZero,
+        tape_size: 1 << 30,
+        sparse_tape: true,
+        pointer_start: 1 << 29,
+    },
+];
+
+/// Looks up a profile by its name.
+pub fn profile(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+/// Language the program is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Brainfuck,
+    /// Brainfuck with `(`, `)`, and `:` for procedures.
+    Pbrain,
+    /// Brainfuck spelled with pairs of `Ook.`, `Ook?`, and `Ook!`.
+    Ook,
+}
+
+/// How the instruction mix of a run is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// One line per kind of command that ran, from `--stats`.
+    Table,
+    /// One JSON object, from `--stats-json`.
+    Json,
+}
+
+/// Width of the tape cells in bits, or signed bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellSize {
+    Eight,
+    Sixteen,
+    ThirtyTwo,
+    SignedEight,
+}
+
+impl CellSize {
+    /// Value of `--cell-size` that selects these cells.
+    pub fn name(self) -> &'static str {
+        match self {
+            CellSize::Eight => "8",
+            CellSize::Sixteen => "16",
+            CellSize::ThirtyTwo => "32",
+            CellSize::SignedEight => "i8",
+        }
+    }
+
+    /// `header` with these cells and the passes of `opt_level`.
+    pub fn header(self, opt_level: OptLevel) -> BfcHeader {
+        let (cell_bits, signed_cells) = match self {
+            CellSize::Eight => (8, false),
+            CellSize::Sixteen => (16, false),
+            CellSize::ThirtyTwo => (32, false),
+            CellSize::SignedEight => (8, true),
+        };
+        BfcHeader {
+            cell_bits,
+            signed_cells,
+            opt_level,
+        }
+    }
+
+    /// Cells a `.bfc` file was compiled for.
+    fn of_header(header: &BfcHeader) -> CellSize {
+        match (header.cell_bits, header.signed_cells) {
+            (16, _) => CellSize::Sixteen,
+            (32, _) => CellSize::ThirtyTwo,
+            (_, true) => CellSize::SignedEight,
+            _ => CellSize::Eight,
+        }
+    }
+}
+
+/// Number of `-O`, e.g. `2` for `-O2`.
+fn opt_level_name(opt_level: OptLevel) -> u8 {
+    match opt_level {
+        OptLevel::O0 => 0,
+        OptLevel::O1 => 1,
+        OptLevel::O2 => 2,
+    }
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, ArgError> {
+    single_program(parse_run_args(args, true)?)
+}
+
+/// The options of a run whose program file came with no more arguments.
+fn single_program((options, rest): (Options, Vec<String>)) -> Result<Options, ArgError> {
+    match rest.first() {
+        Some(arg) => Err(format!("unexpected argument '{arg}'").into()),
+        None => Ok(options),
+    }
+}
+
+/// Parses the flags of a run, which may leave out the program unless
+/// `program_required` is set, and returns them with the arguments that came
+/// after a program file other than flags.
+fn parse_run_args(
+    mut args: impl Iterator<Item = String>,
+    program_required: bool,
+) -> Result<(Options, Vec<String>), ArgError> {
+    let mut source_code = None;
+    let mut file = None;
+    let mut script = false;
+    let mut rest = Vec::new();
+    let mut dialect = Dialect::Brainfuck;
+    let mut token_map = None;
+    let mut profile_name = None;
+    let mut eof_behavior = None;
+    let mut overflow_policy = None;
+    let mut tape_size = None;
+    let mut pointer_start = None;
+    let mut cell_size = None;
+    let mut sparse_tape = None;
+    let mut tape_init = None;
+    let mut tape_init_offset = 0;
+    let mut init_pointer = None;
+    let mut max_steps = None;
+    let mut max_output = None;
+    let mut timeout = None;
+    let mut max_memory = None;
+    let mut max_call_depth = None;
+    let mut exit_cell = false;
+    let mut strict = false;
+    let mut debug_ext = false;
+    let mut random_ext = false;
+    let mut seed = None;
+    let mut comments = None;
+    let mut macros = false;
+    let mut opt_level = None;
+    let mut disabled_passes = Vec::new();
+    let mut emit_ir = false;
+    let mut partial_eval = false;
+    let mut no_cache = false;
+    let mut verbose = false;
+    let mut time = false;
+    let mut stats = None;
+    let mut engine = Engine::Match;
+    let mut raw = false;
+    let mut echo = false;
+    let mut bang_input = false;
+    let mut input = None;
+    let mut stdin_program = false;
+    let mut output = None;
+    let mut append = false;
+    let mut numeric = false;
+    let mut unicode = false;
+    let mut newline = None;
+    let mut separator = b'\n';
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--eof" => {
+                let value = args.next().ok_or("--eof needs a value")?;
+                eof_behavior = Some(match value.as_str() {
+                    "zero" => EofBehavior::SetZero,
+                    "minus-one" => EofBehavior::SetMinusOne,
+                    "unchanged" => EofBehavior::Unchanged,
+                    _ => return Err(format!("unknown EOF behavior '{value}'").into()),
+                });
+            }
+            "--overflow" => {
+                let value = args.next().ok_or("--overflow needs a value")?;
+                overflow_policy = Some(match value.as_str() {
+                    "wrap" => OverflowPolicy::Wrap,
+                    "saturate" => OverflowPolicy::Saturate,
+                    "error" => OverflowPolicy::Error,
+                    _ => return Err(format!("unknown overflow policy '{value}'").into()),
+                });
+            }
+            "--profile" => profile_name = Some(args.next().ok_or("--profile needs a value")?),
+            "--tape-size" => tape_size = Some(parse_number(&arg, args.next())?),
+            "--pointer-start" => pointer_start = Some(parse_number(&arg, args.next())?),
+            "--max-steps" => max_steps = Some(parse_number(&arg, args.next())?),
+            "--max-output" => max_output = Some(parse_number(&arg, args.next())?),
+            "--timeout" => {
+                let value = args.next().ok_or("--timeout needs a value")?;
+                timeout = Some(parse_duration(&value)?);
+            }
+            "--max-memory" => {
+                let value = args.next().ok_or("--max-memory needs a value")?;
+                max_memory = Some(parse_size(&value)?);
+            }
+            "--max-call-depth" => max_call_depth = Some(parse_number(&arg, args.next())?),
+            "--dialect" => {
+                let value = args.next().ok_or("--dialect needs a value")?;
+                dialect = match value.as_str() {
+                    "brainfuck" => Dialect::Brainfuck,
+                    "pbrain" => Dialect::Pbrain,
+                    "ook" => Dialect::Ook,
+                    _ => return Err(format!("unknown dialect '{value}'").into()),
+                };
+            }
+            "--dialect-map" => {
+                let path = args.next().ok_or("--dialect-map needs a file")?;
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|e| ArgError::Io(format!("cannot read '{path}': {e}")))?;
+                let map = TokenMap::from_toml(&text)
+                    .map_err(|e| ArgError::Io(format!("'{path}': {e}")))?;
+                token_map = Some(map);
+            }
+            "--tape" => {
+                let value = args.next().ok_or("--tape needs a value")?;
+                sparse_tape = Some(match value.as_str() {
+                    "dense" => false,
+                    "sparse" => true,
+                    _ => return Err(format!("unknown tape '{value}'").into()),
+                });
+            }
+            "--tape-init" => {
+                let path = args.next().ok_or("--tape-init needs a file")?;
+                let data = std::fs::read(&path)
+                    .map_err(|e| ArgError::Io(format!("cannot read '{path}': {e}")))?;
+                tape_init = Some(data);
+            }
+            "--tape-init-hex" => {
+                let value = args.next().ok_or("--tape-init-hex needs a value")?;
+                tape_init = Some(parse_hex(&value)?);
+            }
+            "--tape-init-offset" => tape_init_offset = parse_number(&arg, args.next())?,
+            "--init-pointer" => init_pointer = Some(parse_number(&arg, args.next())?),
+            "--file" => file = Some(PathBuf::from(args.next().ok_or("--file needs a file")?)),
+            "--exit-cell" => exit_cell = true,
+            "--strict" => strict = true,
+            "--debug-ext" => debug_ext = true,
+            "--random-ext" => random_ext = true,
+            "--seed" => seed = Some(parse_number(&arg, args.next())?),
+            "--comments" => {
+                let value = args.next().ok_or("--comments needs a value")?;
+                comments = Some(match value.as_str() {
+                    "braces" => CommentStyle::Braces,
+                    "semicolon" => CommentStyle::Semicolon,
+                    _ => return Err(format!("unknown comment style '{value}'").into()),
+                });
+            }
+            "--macros" => macros = true,
+            "-O0" => opt_level = Some(OptLevel::O0),
+            "-O1" => opt_level = Some(OptLevel::O1),
+            "-O2" => opt_level = Some(OptLevel::O2),
+            "--disable-pass" => {
+                let value = args.next().ok_or("--disable-pass needs a value")?;
+                let pass = Pass::from_name(&value).ok_or_else(|| {
+                    let names: Vec<_> = Pass::ALL.iter().map(|pass| pass.name()).collect();
+                    format!(
+                        "unknown pass '{value}', expected one of {}",
+                        names.join(", ")
+                    )
+                })?;
+                disabled_passes.push(pass);
+            }
+            "--emit-ir" => emit_ir = true,
+            "--partial-eval" => partial_eval = true,
+            "--no-cache" => no_cache = true,
+            "--verbose" => verbose = true,
+            "--time" => time = true,
+            "--stats" => stats = Some(StatsFormat::Table),
+            "--stats-json" => stats = Some(StatsFormat::Json),
+            "--engine" => {
+                let value = args.next().ok_or("--engine needs a value")?;
+                engine = match value.as_str() {
+                    "match" => Engine::Match,
+                    "threaded" => Engine::Threaded,
+                    #[cfg(feature = "jit")]
+                    "jit" => Engine::Jit,
+                    #[cfg(not(feature = "jit"))]
+                    "jit" => return Err("the jit engine needs the 'jit' feature".into()),
+                    _ => return Err(format!("unknown engine '{value}'").into()),
+                };
+            }
+            "--raw" => raw = true,
+            "--echo" => echo = true,
+            "--bang-input" => bang_input = true,
+            "--input" | "--input-bytes" if input.is_some() => {
+                return Err("only one of --input and --input-bytes can be given".into());
+            }
+            // The file is opened right away, so that a missing one is
+            // reported before anything is compiled or printed.
+            "--input" => {
+                let path = PathBuf::from(args.next().ok_or("--input needs a file")?);
+                let file = File::open(&path)
+                    .map_err(|e| ArgError::Io(format!("cannot read '{}': {e}", path.display())))?;
+                input = Some(Input::File { path, file });
+            }
+            "--output" => output = Some(PathBuf::from(args.next().ok_or("--output needs a file")?)),
+            "--append" => append = true,
+            "--input-bytes" => {
+                let text = args.next().ok_or("--input-bytes needs a value")?;
+                input = Some(Input::Bytes(text.into_bytes()));
+            }
+            "--numeric" => numeric = true,
+            "--unicode" => unicode = true,
+            "--newline" => {
+                let value = args.next().ok_or("--newline needs a value")?;
+                newline = Some(match value.as_str() {
+                    "lf" => Newline::Lf,
+                    "crlf" => Newline::Crlf,
+                    "native" => Newline::native(),
+                    _ => return Err(format!("unsupported newline '{value}'").into()),
+                });
+            }
+            "--separator" => {
+                let value = args.next().ok_or("--separator needs a value")?;
+                separator = match value.as_str() {
+                    "newline" => b'\n',
+                    "space" => b' ',
+                    _ => return Err(format!("unsupported separator '{value}'").into()),
+                };
+            }
+            "--cell-size" => {
+                let value = args.next().ok_or("--cell-size needs a value")?;
+                cell_size = Some(match value.as_str() {
+                    "8" => CellSize::Eight,
+                    "16" => CellSize::Sixteen,
+                    "32" => CellSize::ThirtyTwo,
+                    "i8" => CellSize::SignedEight,
+                    _ => return Err(format!("unsupported cell size '{value}'").into()),
+                });
+            }
+            "-" if !stdin_program => stdin_program = true,
+            "--help" | "-h" => return Err(ArgError::Help),
+            _ if is_flag(&arg) => return Err(format!("unknown flag '{arg}'").into()),
+            // `#!/usr/bin/env brainfuck_vm` runs a script as its first
+            // argument, and flags may still follow it.
+            _ if source_code.is_none() && file.is_none() && Path::new(&arg).is_file() => {
+                file = Some(PathBuf::from(arg));
+                script = true;
+            }
+            _ if script => rest.push(arg),
+            _ if source_code.is_none() => source_code = Some(arg),
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
+    let mut compiled = None;
+    let source = match (source_code, file) {
+        (Some(_), Some(_)) => {
+            return Err("--file cannot be combined with a program argument".into());
+        }
+        (Some(_), None) | (None, Some(_)) if stdin_program => {
+            return Err("'-' cannot be combined with --file or a program argument".into());
+        }
+        (None, None) if stdin_program => Source::stdin().map_err(ArgError::Io)?,
+        (Some(source_code), None) => Source::inline(&source_code).map_err(ArgError::Io)?,
+        (None, Some(path)) if path.extension().is_some_and(|ext| ext == "bfc") => {
+            let name = path.display();
+            let bytes = std::fs::read(&path)
+                .map_err(|e| ArgError::Io(format!("cannot read '{name}': {e}")))?;
+            let (header, program) = Program::load(&mut bytes.as_slice())
+                .map_err(|e| ArgError::Io(format!("'{name}': {e}")))?;
+            // The program only does what it was compiled to do with its own
+            // cells and passes.
+            let compiled_cells = CellSize::of_header(&header);
+            if let Some(cells) = cell_size.filter(|&cells| cells != compiled_cells) {
+                return Err(format!(
+                    "'{name}' was compiled with --cell-size {}, not {}",
+                    compiled_cells.name(),
+                    cells.name()
+                )
+                .into());
+            }
+            if let Some(level) = opt_level.filter(|&level| level != header.opt_level) {
+                return Err(format!(
+                    "'{name}' was compiled with -O{}, not -O{}",
+                    opt_level_name(header.opt_level),
+                    opt_level_name(level)
+                )
+                .into());
+            }
+            cell_size = Some(compiled_cells);
+            opt_level = Some(header.opt_level);
+            compiled = Some(program);
+            Source::inline("")?
+        }
+        (None, Some(path)) => Source::read(&path).map_err(ArgError::Io)?,
+        (None, None) if !program_required => Source::inline("")?,
+        (None, None) => {
+            return Err(
+                "No second argument. Please provide an argument with Brainfuck program as a string."
+                    .into(),
+            );
+        }
+    };
+    if let Some(name) = profile_name {
+        let profile = profile(&name).ok_or_else(|| format!("unknown profile '{name}'"))?;
+        eof_behavior = eof_behavior.or(Some(profile.eof_behavior));
+        overflow_policy = overflow_policy.or(Some(profile.overflow_policy));
+        tape_size = tape_size.or(Some(profile.tape_size));
+        sparse_tape = sparse_tape.or(Some(profile.sparse_tape));
+        pointer_start = pointer_start.or(Some(profile.pointer_start));
+    }
+    let io_mode = match (numeric, unicode) {
+        (false, false) => IoMode::Bytes,
+        (true, false) => IoMode::Decimal { separator },
+        (false, true) => IoMode::Utf8,
+        (true, true) => return Err("--numeric and --unicode cannot be combined".into()),
+    };
+    if strict && debug_ext {
+        return Err(
+            "--strict reads '#' as a comment, so it cannot be combined with --debug-ext".into(),
+        );
+    }
+    if random_ext && (strict || debug_ext) {
+        return Err("--random-ext cannot be combined with --strict or --debug-ext".into());
+    }
+    if dialect != Dialect::Brainfuck && (strict || debug_ext || random_ext) {
+        return Err(
+            "--strict, --debug-ext, and --random-ext only support --dialect brainfuck".into(),
+        );
+    }
+    if token_map.is_some() && (dialect != Dialect::Brainfuck || debug_ext || random_ext) {
+        return Err(
+            "--dialect-map cannot be combined with --dialect, --debug-ext, or --random-ext".into(),
+        );
+    }
+    if macros && (dialect == Dialect::Ook || token_map.is_some()) {
+        return Err("--macros cannot be combined with --dialect ook or --dialect-map".into());
+    }
+    if append && output.is_none() {
+        return Err("--append needs --output".into());
+    }
+    // Files hold bytes as they are written on every platform, so output
+    // only changes with --newline.
+    let output = match output {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(&path)
+                .map_err(|e| ArgError::Io(format!("cannot write '{}': {e}", path.display())))?,
+        ),
+        None => None,
+    };
+    if bang_input && input.is_some() {
+        return Err("--input and --input-bytes cannot be combined with --bang-input".into());
+    }
+    if dialect == Dialect::Ook && bang_input {
+        return Err("--bang-input cannot be combined with --dialect ook, which uses '!'".into());
+    }
+    let cell_size = cell_size.unwrap_or(CellSize::Eight);
+    if unicode && matches!(cell_size, CellSize::Eight | CellSize::SignedEight) {
+        return Err("--unicode needs --cell-size 16 or 32".into());
+    }
+    let options = Options {
+        source,
+        compiled,
+        dialect,
+        token_map,
+        eof_behavior: eof_behavior.unwrap_or_default(),
+        overflow_policy: overflow_policy.unwrap_or_default(),
+        io_mode,
+        newline,
+        tape_size,
+        pointer_start,
+        cell_size,
+        sparse_tape: sparse_tape.unwrap_or(false),
+        tape_init,
+        tape_init_offset,
+        init_pointer,
+        max_steps,
+        max_output,
+        timeout,
+        max_memory,
+        max_call_depth,
+        exit_cell,
+        strict,
+        debug_ext,
+        random_ext,
+        seed,
+        comments,
+        macros,
+        opt_level: opt_level.unwrap_or_default(),
+        disabled_passes,
+        emit_ir,
+        partial_eval,
+        no_cache,
+        verbose,
+        time,
+        stats,
+        engine,
+        raw,
+        echo,
+        bang_input,
+        input,
+        output,
+        stdin_program,
+    };
+    Ok((options, rest))
+}
+
+/// Settings for `check`, which reports the errors of programs without
+/// running them.
+pub struct CheckOptions {
+    /// How the programs are compiled, with the first of them.
+    pub run: Options,
+    /// Files checked after the first program.
+    pub files: Vec<PathBuf>,
+    /// Print the errors as JSON instead of text.
+    pub json: bool,
+}
+
+/// Parses the arguments after `check`: `--json` and the flags of a run,
+/// wherever they appear, the first program, and the files after it.
+pub fn parse_check_args(args: impl Iterator<Item = String>) -> Result<CheckOptions, ArgError> {
+    let mut json = false;
+    let mut rest = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ => rest.push(arg),
+        }
+    }
+
+    let (run, files) = parse_run_args(rest.into_iter(), true)?;
+    Ok(CheckOptions {
+        run,
+        files: files.into_iter().map(PathBuf::from).collect(),
+        json,
+    })
+}
+
+/// Parses the flags of `repl`, which starts from an empty program unless
+/// one is given.
+pub fn parse_repl_args(args: impl Iterator<Item = String>) -> Result<Options, ArgError> {
+    single_program(parse_run_args(args, false)?)
+}
+
+/// Settings for `gen`, which writes a program that prints the given bytes.
+pub struct GenOptions {
+    pub payload: Vec<u8>,
+    /// File to write the program to instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after `gen`.
+pub fn parse_gen_args(mut args: impl Iterator<Item = String>) -> Result<GenOptions, ArgError> {
+    let mut payload = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input-file" => {
+                let path = args.next().ok_or("--input-file needs a file")?;
+                let data = std::fs::read(&path)
+                    .map_err(|e| ArgError::Io(format!("cannot read '{path}': {e}")))?;
+                if payload.replace(data).is_some() {
+                    return Err("gen takes either a text or --input-file".into());
+                }
+            }
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            "--" if payload.is_none() => {
+                payload = Some(args.next().ok_or("-- needs a text")?.into_bytes());
+            }
+            "--help" | "-h" => return Err(ArgError::Help),
+            _ if is_flag(&arg) => return Err(format!("unknown flag '{arg}'").into()),
+            _ if payload.is_none() => payload = Some(arg.into_bytes()),
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
+    let payload = payload.ok_or("gen needs a text or --input-file")?;
+    Ok(GenOptions { payload, output })
+}
+
+/// Settings for `fmt`, which re-flows a program by loop depth.
+pub struct FmtOptions {
+    pub path: PathBuf,
+    pub width: usize,
+    pub indent: usize,
+    pub strip_comments: bool,
+    /// Only report whether the file is formatted, without writing anything.
+    pub check: bool,
+    /// File to write the formatted program to instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after `fmt`.
+pub fn parse_fmt_args(mut args: impl Iterator<Item = String>) -> Result<FmtOptions, ArgError> {
+    let mut path = None;
+    let mut width = 80;
+    let mut indent = 2;
+    let mut strip_comments = false;
+    let mut check = false;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => width = parse_number("--width", args.next())?,
+            "--indent" => indent = parse_number("--indent", args.next())?,
+            "--strip-comments" => strip_comments = true,
+            "--check" => check = true,
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            "--help" | "-h" => return Err(ArgError::Help),
+            _ if is_flag(&arg) => return Err(format!("unknown flag '{arg}'").into()),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
+    if check && output.is_some() {
+        return Err("--check does not write a file, so it cannot be used with -o".into());
+    }
+    let path = path.ok_or("fmt needs a file")?;
+    Ok(FmtOptions {
+        path,
+        width,
+        indent,
+        strip_comments,
+        check,
+        output,
+    })
+}
+
+/// Settings for `minify`, which writes a program with only its commands.
+pub struct MinifyOptions {
+    pub path: PathBuf,
+    /// 1 only strips comments, 2 also removes code that does nothing.
+    pub level: u8,
+    /// File to write the minified program to instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after `minify`.
+pub fn parse_minify_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<MinifyOptions, ArgError> {
+    let mut path = None;
+    let mut level = 1;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--level" => {
+                level = match args.next().as_deref() {
+                    Some("1") => 1,
+                    Some("2") => 2,
+                    Some(other) => return Err(format!("unknown minify level '{other}'").into()),
+                    None => return Err("--level needs a value".into()),
+                }
+            }
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            "--help" | "-h" => return Err(ArgError::Help),
+            _ if is_flag(&arg) => return Err(format!("unknown flag '{arg}'").into()),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
+    let path = path.ok_or("minify needs a file")?;
+    Ok(MinifyOptions {
+        path,
+        level,
+        output,
+    })
+}
+
+/// Settings for `export-html`, which renders a program as a web page.
+pub struct ExportOptions {
+    pub path: PathBuf,
+    /// File to write the page to instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after `export-html`.
+pub fn parse_export_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<ExportOptions, ArgError> {
+    let mut path = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            "--help" | "-h" => return Err(ArgError::Help),
+            _ if is_flag(&arg) => return Err(format!("unknown flag '{arg}'").into()),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
+    let path = path.ok_or("export-html needs a file")?;
+    Ok(ExportOptions { path, output })
+}
+
+/// Language that `compile` translates a program into.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    C,
+    Rust,
+    Wasm,
+    /// The compiled program itself, to be run later.
+    Bfc,
+}
+
+/// Settings for `compile`, which translates a program instead of running it.
+pub struct CompileOptions {
+    pub target: Target,
+    /// How the translated program behaves, as if it were run with them.
+    pub run: Options,
+    /// File to write the translation to instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after `compile`: `--target` and `-o`, wherever they
+/// appear, and otherwise the flags of a run.
+pub fn parse_compile_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<CompileOptions, ArgError> {
+    let mut target = None;
+    let mut output: Option<PathBuf> = None;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => {
+                let value = args.next().ok_or("--target needs a value")?;
+                target = Some(match value.as_str() {
+                    "c" => Target::C,
+                    "rust" => Target::Rust,
+                    "wasm" => Target::Wasm,
+                    "bfc" => Target::Bfc,
+                    _ => return Err(format!("unknown target '{value}'").into()),
+                });
+            }
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            _ => rest.push(arg),
+        }
+    }
+
+    // `-o program.bfc` is enough to ask for the compiled program.
+    let bfc_output = output
+        .as_ref()
+        .is_some_and(|path| path.extension().is_some_and(|ext| ext == "bfc"));
+    let target = target.unwrap_or(if bfc_output { Target::Bfc } else { Target::C });
+    Ok(CompileOptions {
+        target,
+        run: parse_translated_args(rest)?,
+        output,
+    })
+}
+
+/// Settings for `build`, which turns a program into an executable.
+pub struct BuildOptions {
+    /// How the executable behaves, as if the program were run with them.
+    pub run: Options,
+    /// Executable to write, named after the program file by default.
+    pub output: Option<PathBuf>,
+    /// Have the C compiler optimize, with `-O2`.
+    pub optimize_c: bool,
+}
+
+/// Parses the arguments after `build`: `--opt` and `-o`, wherever they
+/// appear, and otherwise the flags of a run.
+pub fn parse_build_args(mut args: impl Iterator<Item = String>) -> Result<BuildOptions, ArgError> {
+    let mut output = None;
+    let mut optimize_c = false;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--opt" => optimize_c = true,
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a file")?)),
+            _ => rest.push(arg),
+        }
+    }
+
+    let run = parse_translated_args(rest)?;
+    if output.is_none() && run.source.path().is_none() {
+        return Err("build needs -o for a program given inline".into());
+    }
+    Ok(BuildOptions {
+        run,
+        output,
+        optimize_c,
+    })
+}
+
+/// Settings for `verify`, which checks that optimizing a program keeps
+/// what it does.
+pub struct VerifyOptions {
+    /// How both programs are run, and which passes optimize one of them,
+    /// with the input of one run.
+    pub run: Options,
+    /// Number of runs on generated inputs.
+    pub random_inputs: usize,
+}
+
+/// Parses the arguments after `verify`: `--random-inputs`, wherever it
+/// appears, and otherwise the flags of a run.
+pub fn parse_verify_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<VerifyOptions, ArgError> {
+    let mut random_inputs = 0;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--random-inputs" => random_inputs = parse_number(&arg, args.next())?,
+            _ => rest.push(arg),
+        }
+    }
+
+    Ok(VerifyOptions {
+        run: parse_args(rest.into_iter())?,
+        random_inputs,
+    })
+}
+
+/// Settings for `disasm`, which lists the compiled program instead of
+/// running it.
+pub struct DisasmOptions {
+    /// How the program is compiled, as if it were run with them.
+    pub run: Options,
+    /// Addresses of the commands to list.
+    pub range: Range<usize>,
+    /// List the source offset of every command as well.
+    pub source_map: bool,
+}
+
+/// Parses the arguments after `disasm`: `--range` and `--source-map`,
+/// wherever they appear, and otherwise the flags of a run.
+pub fn parse_disasm_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<DisasmOptions, ArgError> {
+    let mut range = 0..usize::MAX;
+    let mut source_map = false;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--range" => range = parse_range(&args.next().ok_or("--range needs a value")?)?,
+            "--source-map" => source_map = true,
+            _ => rest.push(arg),
+        }
+    }
+
+    let run = parse_args(rest.into_iter())?;
+    // Offsets are only known for source the compiler reads as it is.
+    if source_map
+        && (run.compiled.is_some() || run.dialect == Dialect::Ook || run.token_map.is_some())
+    {
+        return Err(
+            "--source-map cannot be combined with a .bfc file, --dialect ook, or --dialect-map"
+                .into(),
+        );
+    }
+    Ok(DisasmOptions {
+        run,
+        range,
+        source_map,
+    })
+}
+
+/// Settings for `bench`, which times repeated runs of a program.
+pub struct BenchOptions {
+    /// How the program is compiled and run, with the input of every run.
+    pub run: Options,
+    /// Number of timed runs.
+    pub runs: usize,
+    /// Number of runs before the timed ones, which are not counted.
+    pub warmup: usize,
+    /// Print the results as JSON instead of a table.
+    pub json: bool,
+}
+
+/// Parses the arguments after `bench`: `--runs`, `--warmup`, and `--json`,
+/// wherever they appear, and otherwise the flags of a run.
+pub fn parse_bench_args(mut args: impl Iterator<Item = String>) -> Result<BenchOptions, ArgError> {
+    let mut runs = 10;
+    let mut warmup = 1;
+    let mut json = false;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => runs = parse_number(&arg, args.next())?,
+            "--warmup" => warmup = parse_number(&arg, args.next())?,
+            "--json" => json = true,
+            _ => rest.push(arg),
+        }
+    }
+    if runs == 0 {
+        return Err("--runs needs at least 1 run".into());
+    }
+
+    Ok(BenchOptions {
+        run: parse_args(rest.into_iter())?,
+        runs,
+        warmup,
+        json,
+    })
+}
+
+/// Settings for `hotspots`, which finds the loops a program spends its
+/// steps in.
+pub struct HotspotsOptions {
+    /// How the program is compiled and run, with its input.
+    pub run: Options,
+    /// Number of loops listed.
+    pub top: usize,
+    /// Print the results as JSON instead of a table.
+    pub json: bool,
+}
+
+/// Parses the arguments after `hotspots`: `--top` and `--json`, wherever
+/// they appear, and otherwise the flags of a run.
+pub fn parse_hotspots_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<HotspotsOptions, ArgError> {
+    let mut top = 10;
+    let mut json = false;
+    let mut rest = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--top" => top = parse_number(&arg, args.next())?,
+            "--json" => json = true,
+            _ => rest.push(arg),
+        }
+    }
+
+    Ok(HotspotsOptions {
+        run: parse_args(rest.into_iter())?,
+        top,
+        json,
+    })
+}
+
+/// Parses a range of addresses like `100..200`, where either end may be
+/// left out.
+fn parse_range(value: &str) -> Result<Range<usize>, String> {
+    let invalid = || format!("invalid range '{value}', expected e.g. 100..200");
+    let (start, end) = value.split_once("..").ok_or_else(invalid)?;
+    let start = match start {
+        "" => 0,
+        start => start.parse().map_err(|_| invalid())?,
+    };
+    let end = match end {
+        "" => usize::MAX,
+        end => end.parse().map_err(|_| invalid())?,
+    };
+    Ok(start..end)
+}
+
+/// Parses the flags of a run for a program that is translated instead,
+/// rejecting those that have no translation.
+fn parse_translated_args(args: Vec<String>) -> Result<Options, ArgError> {
+    let run = parse_args(args.into_iter())?;
+    // Limits, terminal settings, and the exit status only exist in a run.
+    for (given, flag) in [
+        (run.max_steps.is_some(), "--max-steps"),
+        (run.max_output.is_some(), "--max-output"),
+        (run.timeout.is_some(), "--timeout"),
+        (run.max_memory.is_some(), "--max-memory"),
+        (run.newline.is_some(), "--newline"),
+        (run.exit_cell, "--exit-cell"),
+        (run.raw, "--raw"),
+        (run.echo, "--echo"),
+        (run.bang_input, "--bang-input"),
+    ] {
+        if given {
+            return Err(format!("{flag} cannot be compiled").into());
+        }
+    }
+    Ok(run)
+}
+
+/// Parses bytes written as pairs of hex digits, e.g. `4849`.
+fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid hex string '{value}'");
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            let pair = value.get(i..i + 2).ok_or_else(invalid)?;
+            u8::from_str_radix(pair, 16).map_err(|_| invalid())
+        })
+        .collect()
+}
+
+/// Parses a duration like `500ms`, `5s`, or `1m`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid timeout '{value}', expected e.g. 500ms, 5s, or 1m");
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: u64 = value[..split].parse().map_err(|_| invalid())?;
+    match &value[split..] {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount.saturating_mul(60))),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a number of bytes like `4096`, `512KiB`, or `64MiB`.
+fn parse_size(value: &str) -> Result<usize, String> {
+    let invalid = || format!("invalid size '{value}', expected e.g. 4096, 512KiB, or 64MiB");
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let amount: usize = value[..split].parse().map_err(|_| invalid())?;
+    let unit: usize = match &value[split..] {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    amount.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// Whether `arg` is meant as a flag, such as `--tape-size` or `-O3`, even
+/// though no flag is spelled that way. Programs can start with `-` too, but
+/// not usually followed by a word without any other command.
+fn is_flag(arg: &str) -> bool {
+    let name = arg.trim_start_matches('-');
+    name.len() < arg.len()
+        && name.starts_with(|ch: char| ch.is_ascii_alphanumeric())
+        && !name.contains(['+', '<', '>', '[', ']', '.', ','])
+}
+
+/// Parses the value following the numeric flag `flag`.
+fn parse_number<N: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<N, String> {
+    let value = value.ok_or_else(|| format!("{flag} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        parse_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    /// Test that every profile can be chosen by its name.
+    #[test]
+    fn test_profiles() {
+        for expected in &PROFILES {
+            let options = parse(&["--profile", expected.name, "+"]);
+            let chosen = Profile {
+                name: expected.name,
+                overflow_policy: options.overflow_policy,
+                eof_behavior: options.eof_behavior,
+                tape_size: options.tape_size.unwrap(),
+                sparse_tape: options.sparse_tape,
+                pointer_start: options.pointer_start.unwrap(),
+            };
+            assert_eq!(&chosen, expected);
+        }
+        assert!(parse_args(["--profile", "turbo", "+"].map(String::from).into_iter()).is_err());
+    }
+
+    /// Test that flags win over the profile, wherever they appear.
+    #[test]
+    fn test_profile_overrides() {
+        let options = parse(&[
+            "--eof",
+            "minus-one",
+            "--profile",
+            "dbfi",
+            "--tape",
+            "dense",
+            "+",
+        ]);
+        assert_eq!(options.eof_behavior, EofBehavior::SetMinusOne);
+        assert!(!options.sparse_tape);
+        assert_eq!(options.tape_size, Some(1 << 24));
+
+        let options = parse(&["--profile", "strict", "--overflow", "wrap", "+"]);
+        assert_eq!(options.overflow_policy, OverflowPolicy::Wrap);
+        assert_eq!(options.tape_size, Some(30_000));
+
+        let options = parse(&["+"]);
+        assert_eq!(options.overflow_policy, OverflowPolicy::Wrap);
+        assert_eq!(options.tape_size, None);
+    }
+
+    /// Test the ranges `--range` accepts, open ends included.
+    #[test]
+    fn test_range() {
+        assert_eq!(parse_range("100..200"), Ok(100..200));
+        assert_eq!(parse_range("5.."), Ok(5..usize::MAX));
+        assert_eq!(parse_range("..7"), Ok(0..7));
+        assert_eq!(parse_range(".."), Ok(0..usize::MAX));
+        for invalid in ["100", "1...2", "a..b", "-1..2"] {
+            assert!(parse_range(invalid).is_err(), "{invalid}");
+        }
+    }
+}
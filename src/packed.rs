@@ -0,0 +1,289 @@
+use alloc::vec::Vec;
+
+use crate::Command;
+
+/// Bits of a word that hold the operand; the opcode is in the ones above.
+const OPERAND_BITS: u32 = 24;
+const OPERAND_MASK: u32 = (1 << OPERAND_BITS) - 1;
+/// Bits of each of the two operands of a command that has two.
+const HALF_BITS: u32 = OPERAND_BITS / 2;
+
+/// Opcode of a command whose operands do not fit into the word; the
+/// command itself is stored on the side.
+const WIDE: u32 = 0xff;
+
+/// Compiled program in a compact form, with every command packed into one
+/// `u32`: an opcode in the top 8 bits and an operand in the low 24.
+///
+/// A [`Command`] takes 16 bytes, so this is a quarter of the size, which
+/// keeps large programs in the cache. Commands with operands that do not
+/// fit, e.g. jumps past 16 Mi commands, are stored unpacked on the side.
+/// Create one with [`pack`] and run it with [`Vm::with_bytecode`](crate::Vm::with_bytecode)
+/// or [`Interpreter::run_bytecode`](crate::Interpreter::run_bytecode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bytecode {
+    words: Vec<u32>,
+    /// Commands that did not fit into their word, by address.
+    wide: Vec<(usize, Command)>,
+}
+
+/// Packs a program into [`Bytecode`].
+pub fn pack(commands: &[Command]) -> Bytecode {
+    let mut bytecode = Bytecode {
+        words: Vec::with_capacity(commands.len()),
+        wide: Vec::new(),
+    };
+    for (address, command) in commands.iter().enumerate() {
+        let word = encode(command).unwrap_or_else(|| {
+            bytecode.wide.push((address, command.clone()));
+            WIDE << OPERAND_BITS
+        });
+        bytecode.words.push(word);
+    }
+    debug_assert!(
+        (0..commands.len())
+            .all(|address| bytecode.get(address).as_ref() == Some(&commands[address])),
+        "packed commands do not unpack to themselves"
+    );
+    bytecode
+}
+
+impl Bytecode {
+    /// Number of commands.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether there are no commands.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The packed words, one per command.
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// Command at `address`, unpacked.
+    #[inline]
+    pub fn get(&self, address: usize) -> Option<Command> {
+        let word = *self.words.get(address)?;
+        Some(decode(word).unwrap_or_else(|| self.wide_at(address)))
+    }
+
+    /// Unpacks every command again.
+    pub fn unpack(&self) -> Vec<Command> {
+        (0..self.len())
+            .map(|address| self.get(address).unwrap())
+            .collect()
+    }
+
+    #[cold]
+    fn wide_at(&self, address: usize) -> Command {
+        let index = self
+            .wide
+            .binary_search_by_key(&address, |&(wide, _)| wide)
+            .expect("every wide word has its command stored");
+        self.wide[index].1.clone()
+    }
+}
+
+/// Packs `command` into a word, unless its operands do not fit.
+fn encode(command: &Command) -> Option<u32> {
+    use self::Command as C;
+
+    let (opcode, operand) = match *command {
+        C::IncrementDataPointer => (0, 0),
+        C::DecrementDataPointer => (1, 0),
+        C::Increment => (2, 0),
+        C::Decrement => (3, 0),
+        C::WriteByte => (4, 0),
+        C::ReadByte => (5, 0),
+        C::JumpForwardIfZero(address) => (6, unsigned(address)?),
+        C::JumpBackwardIfNonZero(address) => (7, unsigned(address)?),
+        C::DebugDump => (8, 0),
+        C::BeginProc(address) => (9, unsigned(address)?),
+        C::EndProc(address) => (10, unsigned(address)?),
+        C::Call => (11, 0),
+        C::Random => (12, 0),
+        C::Add(value) => (13, signed(value.into(), OPERAND_BITS)?),
+        C::MovePointer(offset) => (14, signed(offset.into(), OPERAND_BITS)?),
+        C::Set(value) => (15, value.into()),
+        C::MulAdd { offset, factor } => (16, pair(offset.into(), factor.into())?),
+        C::ScanRight(stride) => (17, unsigned(stride)?),
+        C::ScanLeft(stride) => (18, unsigned(stride)?),
+        C::AddAt { offset, value } => (19, pair(offset.into(), value.into())?),
+        C::SetAt { offset, value } => (20, pair(offset.into(), value.into())?),
+        C::OutputAt(offset) => (21, signed(offset.into(), OPERAND_BITS)?),
+        C::InputAt(offset) => (22, signed(offset.into(), OPERAND_BITS)?),
+        C::ClearRange { len } => (23, signed(len.into(), OPERAND_BITS)?),
+    };
+    Some(opcode << OPERAND_BITS | operand)
+}
+
+/// Unpacks a word, unless it is [`WIDE`].
+#[inline]
+fn decode(word: u32) -> Option<Command> {
+    use self::Command as C;
+
+    let operand = word & OPERAND_MASK;
+    let address = operand as usize;
+    // Sign-extends the whole operand, or each half of it.
+    let full = ((word << 8) as i32) >> 8;
+    let high = ((word << 8) as i32) >> (32 - HALF_BITS);
+    let low = ((word << (32 - HALF_BITS)) as i32) >> (32 - HALF_BITS);
+    Some(match word >> OPERAND_BITS {
+        0 => C::IncrementDataPointer,
+        1 => C::DecrementDataPointer,
+        2 => C::Increment,
+        3 => C::Decrement,
+        4 => C::WriteByte,
+        5 => C::ReadByte,
+        6 => C::JumpForwardIfZero(address),
+        7 => C::JumpBackwardIfNonZero(address),
+        8 => C::DebugDump,
+        9 => C::BeginProc(address),
+        10 => C::EndProc(address),
+        11 => C::Call,
+        12 => C::Random,
+        13 => C::Add(full as i16),
+        14 => C::MovePointer(full),
+        15 => C::Set(operand as u8),
+        16 => C::MulAdd {
+            offset: high,
+            factor: low as i16,
+        },
+        17 => C::ScanRight(address),
+        18 => C::ScanLeft(address),
+        19 => C::AddAt {
+            offset: high,
+            value: low as i16,
+        },
+        20 => C::SetAt {
+            offset: high,
+            value: low as u8,
+        },
+        21 => C::OutputAt(full),
+        22 => C::InputAt(full),
+        23 => C::ClearRange { len: full },
+        _ => return None,
+    })
+}
+
+/// `value` as an operand, if it fits.
+fn unsigned(value: usize) -> Option<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|&value| value <= OPERAND_MASK)
+}
+
+/// `value` as a two's-complement operand of `bits` bits, if it fits.
+fn signed(value: i64, bits: u32) -> Option<u32> {
+    let limit = 1 << (bits - 1);
+    (-limit..limit)
+        .contains(&value)
+        .then(|| value as u32 & ((1 << bits) - 1))
+}
+
+/// Two operands, each in half of the bits, if they fit.
+fn pair(high: i64, low: i64) -> Option<u32> {
+    Some(signed(high, HALF_BITS)? << HALF_BITS | signed(low, HALF_BITS)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, OverflowPolicy, Streams, Vm, compile, optimize};
+
+    /// Test that every kind of command, with small and large operands,
+    /// unpacks to itself.
+    #[test]
+    fn test_round_trip() {
+        use self::Command as C;
+
+        let commands = [
+            C::IncrementDataPointer,
+            C::DecrementDataPointer,
+            C::Increment,
+            C::Decrement,
+            C::WriteByte,
+            C::ReadByte,
+            C::JumpForwardIfZero(7),
+            C::JumpBackwardIfNonZero(OPERAND_MASK as usize + 1),
+            C::DebugDump,
+            C::BeginProc(0),
+            C::EndProc(usize::MAX),
+            C::Call,
+            C::Random,
+            C::Add(i16::MIN),
+            C::MovePointer(-(1 << 23)),
+            C::MovePointer(1 << 23),
+            C::MovePointer(i32::MIN),
+            C::Set(u8::MAX),
+            C::MulAdd {
+                offset: -2048,
+                factor: 2047,
+            },
+            C::MulAdd {
+                offset: 2048,
+                factor: -1,
+            },
+            C::ScanRight(3),
+            C::ScanLeft(1),
+            C::AddAt {
+                offset: 1,
+                value: -2049,
+            },
+            C::SetAt {
+                offset: -5,
+                value: 200,
+            },
+            C::OutputAt(-1),
+            C::InputAt(i32::MAX),
+            C::ClearRange { len: -4 },
+            C::ClearRange { len: 1 << 23 },
+        ];
+        let bytecode = pack(&commands);
+        assert_eq!(bytecode.unpack(), commands);
+        assert_eq!(bytecode.len(), commands.len());
+        assert_eq!(bytecode.wide.len(), 8);
+        assert_eq!(bytecode.words()[2], 2 << OPERAND_BITS);
+        assert_eq!(bytecode.get(commands.len()), None);
+        assert!(pack(&[]).is_empty());
+    }
+
+    /// Test that packed and unpacked programs print the same.
+    #[test]
+    fn test_same_output() {
+        for (source_code, input) in [
+            (
+                "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+                >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+                &b""[..],
+            ),
+            (">,[>,]<[<]>[.>]", b"round trip\0"),
+            (">,>,[<.>-]<<.>.", b"\x03\x05"),
+        ] {
+            let commands = compile(source_code).unwrap();
+            for commands in [optimize(&commands, OverflowPolicy::Wrap), commands] {
+                let interpreter = Interpreter::default();
+                let mut expected = Vec::new();
+                let before = interpreter.run(&commands, input, &mut expected).unwrap();
+                let mut output = Vec::new();
+                let after = interpreter
+                    .run_bytecode(&pack(&commands), input, &mut output)
+                    .unwrap();
+                assert_eq!(output, expected);
+                assert_eq!(after, before);
+            }
+        }
+
+        let bytecode = pack(&compile("+[>+]").unwrap());
+        let mut vm = Vm::with_bytecode(&bytecode, vec![0_u8; 3], 0);
+        let error = vm.run_with(Streams::new(&[][..], Vec::new())).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "runtime error: data pointer moved out of the tape to cell 3 at instruction 2"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Cell, Command, Interpreter, IoMode, OverflowPolicy, Status};
+
+/// Number of commands [`partially_evaluate`] runs ahead at most when it is
+/// not given a smaller budget.
+pub const DEFAULT_PARTIAL_EVAL_STEPS: u64 = 10_000_000;
+
+/// Runs the start of a program that needs no input ahead of time and
+/// replaces it with commands that recreate its output and tape.
+///
+/// The prefix ends before the first top-level command or loop that reads
+/// input, draws a random byte, takes part in a pbrain procedure, or dumps
+/// the tape, since those depend on more than the program. It runs on a
+/// dense tape of cells of type `C`, set up like `interpreter` sets up its
+/// tape, with its overflow policy, for at most `max_steps` commands and no
+/// more than the step limit of `interpreter`.
+///
+/// The prefix is replaced with a `Set` and `.` for every byte it wrote,
+/// followed by one [`Command::SetAt`] or `SetAt` and [`Command::AddAt`]
+/// for every cell that differs from the initial tape, and a move to where
+/// the pointer ended up. The rest of the program follows as is, with its
+/// jumps moved.
+///
+/// If the prefix fails, runs out of steps, or leaves a cell that cannot be
+/// recreated this way, e.g. a large 32-bit value, the program is returned
+/// unchanged, as it is with any I/O mode but [`IoMode::Bytes`]. Apart from
+/// the step counts and the instruction indices in errors, the result
+/// behaves exactly like the original.
+pub fn partially_evaluate<C: Cell>(
+    commands: &[Command],
+    interpreter: &Interpreter,
+    max_steps: u64,
+) -> Vec<Command> {
+    let split = input_free_prefix(commands);
+    if split == 0 || interpreter.io_mode() != IoMode::Bytes {
+        return commands.to_vec();
+    }
+    let max_steps = max_steps.min(interpreter.max_steps().unwrap_or(u64::MAX));
+    let Some(mut replacement) = evaluate::<C>(&commands[..split], interpreter, max_steps) else {
+        return commands.to_vec();
+    };
+
+    // The suffix never jumps into the prefix, which only holds whole loops.
+    let shift = replacement.len();
+    replacement.extend(commands[split..].iter().map(|command| {
+        use self::Command as C;
+
+        let mut command = command.clone();
+        if let C::JumpForwardIfZero(address)
+        | C::JumpBackwardIfNonZero(address)
+        | C::BeginProc(address)
+        | C::EndProc(address) = &mut command
+        {
+            *address = *address - split + shift;
+        }
+        command
+    }));
+    replacement
+}
+
+/// Number of commands at the start of `commands` that make up whole
+/// top-level commands and loops without input or other outside effects.
+fn input_free_prefix(commands: &[Command]) -> usize {
+    use self::Command as C;
+
+    let mut split = 0;
+    while split < commands.len() {
+        let end = match commands[split] {
+            C::JumpForwardIfZero(end) if end > split && end < commands.len() => end + 1,
+            _ => split + 1,
+        };
+        let outside = commands[split..end].iter().any(|command| {
+            matches!(
+                command,
+                C::ReadByte
+                    | C::InputAt(_)
+                    | C::Random
+                    | C::DebugDump
+                    | C::Call
+                    | C::BeginProc(_)
+                    | C::EndProc(_)
+            )
+        });
+        if outside {
+            break;
+        }
+        split = end;
+    }
+    split
+}
+
+/// Runs `prefix` and returns the commands that recreate what it did, or
+/// `None` if it cannot be replaced.
+fn evaluate<C: Cell>(
+    prefix: &[Command],
+    interpreter: &Interpreter,
+    max_steps: u64,
+) -> Option<Vec<Command>> {
+    use self::Command as Cmd;
+
+    let mut vm = interpreter.vm_with_cells::<C>(prefix);
+    let initial = vm.tape().clone();
+    let start = vm.data_pointer();
+
+    let mut output = Vec::new();
+    loop {
+        let fuel = max_steps.checked_sub(vm.report().steps)?;
+        match vm.run_for(fuel).ok()? {
+            Status::ProducedOutput(byte) => output.push(byte),
+            Status::Halted => break,
+            _ => return None,
+        }
+    }
+
+    let mut replacement = Vec::with_capacity(2 * output.len() + 2);
+    for byte in output.iter().copied() {
+        replacement.extend([Cmd::Set(byte), Cmd::WriteByte]);
+    }
+    let tape = vm.tape();
+    for (index, (&before, &after)) in initial.iter().zip(tape.iter()).enumerate() {
+        // The start cell also carried the output.
+        let carried_output = index == start && !output.is_empty();
+        if before == after && !carried_output {
+            continue;
+        }
+        let offset = i32::try_from(index as i64 - start as i64).ok()?;
+        replacement.extend(recreate(offset, after, interpreter.overflow_policy())?);
+    }
+    let moved = i32::try_from(vm.data_pointer() as i64 - start as i64).ok()?;
+    match moved {
+        0 => {}
+        1 => replacement.push(Cmd::IncrementDataPointer),
+        -1 => replacement.push(Cmd::DecrementDataPointer),
+        moved => replacement.push(Cmd::MovePointer(moved)),
+    }
+    Some(replacement)
+}
+
+/// Commands that store `value` in the cell `offset` away, without
+/// overflowing on the way unless `overflow_policy` wraps.
+fn recreate<C: Cell>(
+    offset: i32,
+    value: C,
+    overflow_policy: OverflowPolicy,
+) -> Option<Vec<Command>> {
+    let set = |value| match offset {
+        0 => Command::Set(value),
+        offset => Command::SetAt { offset, value },
+    };
+    let add = |value| match offset {
+        0 => Command::Add(value),
+        offset => Command::AddAt { offset, value },
+    };
+
+    if C::from_byte(value.low_byte()) == value {
+        return Some(vec![set(value.low_byte())]);
+    }
+    let value = value.to_i64();
+    let (base, delta) = if overflow_policy == OverflowPolicy::Wrap {
+        // The shorter way around the cell range, e.g. down from zero to -1.
+        let modulus = 1_i64 << (8 * size_of::<C>());
+        let up = value.rem_euclid(modulus);
+        (0, if up < modulus / 2 { up } else { up - modulus })
+    } else if value > 0 {
+        // Counting up from the largest byte or down from zero stays in range.
+        (255, value - 255)
+    } else {
+        (0, value)
+    };
+    Some(vec![set(base), add(i16::try_from(delta).ok()?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, compile_with_random};
+
+    /// Runs `commands` under `interpreter` with u8 cells on `input`.
+    fn output(commands: &[Command], interpreter: &Interpreter, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        interpreter
+            .run_with_cells::<u8, _, _>(commands, input, &mut output)
+            .unwrap();
+        output
+    }
+
+    /// Test that a program without input collapses to its output.
+    #[test]
+    fn test_hello_world() {
+        let hello = compile(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        )
+        .unwrap();
+        let interpreter = Interpreter::default();
+        let evaluated = partially_evaluate::<u8>(&hello, &interpreter, DEFAULT_PARTIAL_EVAL_STEPS);
+
+        assert!(evaluated.iter().all(|command| !matches!(
+            command,
+            Command::JumpForwardIfZero(_) | Command::JumpBackwardIfNonZero(_)
+        )));
+        assert_eq!(
+            &evaluated[..4],
+            [
+                Command::Set(b'H'),
+                Command::WriteByte,
+                Command::Set(b'e'),
+                Command::WriteByte,
+            ]
+        );
+        assert_eq!(output(&evaluated, &interpreter, b""), b"Hello World!\n");
+
+        let mut tape = [0_u8; 8];
+        crate::eval_on_tape(&evaluated, &mut tape, 0, &[][..], std::io::sink()).unwrap();
+        let mut expected = [0; 8];
+        crate::eval_on_tape(&hello, &mut expected, 0, &[][..], std::io::sink()).unwrap();
+        assert_eq!(tape, expected);
+    }
+
+    /// Test that a program that starts with input is left alone.
+    #[test]
+    fn test_cat() {
+        let cat = compile(",[.,]").unwrap();
+        assert_eq!(
+            partially_evaluate::<u8>(&cat, &Interpreter::default(), DEFAULT_PARTIAL_EVAL_STEPS),
+            cat
+        );
+    }
+
+    /// Test that only the commands from the first loop with input on stay.
+    #[test]
+    fn test_table_prologue() {
+        let program = compile("++++++[>++++++++<-]>>+++[-<+>]<.,[-.,]").unwrap();
+        let interpreter = Interpreter::default();
+        let evaluated = partially_evaluate::<u8>(&program, &interpreter, 1_000);
+
+        assert_eq!(
+            &evaluated[..5],
+            [
+                Command::Set(b'3'),
+                Command::WriteByte,
+                Command::Set(0),
+                Command::SetAt {
+                    offset: 1,
+                    value: b'3'
+                },
+                Command::IncrementDataPointer,
+            ]
+        );
+        assert_eq!(evaluated[5..6], [Command::ReadByte]);
+        assert_eq!(evaluated[6], Command::JumpForwardIfZero(10));
+        assert_eq!(crate::validate(&evaluated), Ok(()));
+        assert_eq!(
+            output(&evaluated, &interpreter, b"ab"),
+            output(&program, &interpreter, b"ab")
+        );
+    }
+
+    /// Test that a prefix that fails or runs too long is kept.
+    #[test]
+    fn test_limits() {
+        let interpreter = Interpreter::default();
+        for source in ["+[]", "+.<", "+[>+]"] {
+            let commands = compile(source).unwrap();
+            assert_eq!(
+                partially_evaluate::<u8>(&commands, &interpreter, 10_000),
+                commands,
+                "{source}"
+            );
+        }
+        let random = compile_with_random("+++[-]?.").unwrap();
+        assert_eq!(
+            partially_evaluate::<u8>(&random, &interpreter, 10_000),
+            [Command::Random, Command::WriteByte]
+        );
+
+        let limited = Interpreter::builder().max_steps(100).build().unwrap();
+        let slow = compile("++++++++[>++++++++<-]>.").unwrap();
+        assert_eq!(partially_evaluate::<u8>(&slow, &limited, u64::MAX), slow);
+        let numeric = Interpreter::builder()
+            .io_mode(IoMode::Decimal { separator: b' ' })
+            .build()
+            .unwrap();
+        assert_eq!(partially_evaluate::<u8>(&slow, &numeric, u64::MAX), slow);
+    }
+
+    /// Test that wide cells, signed cells, and overflow policies carry over.
+    #[test]
+    fn test_cell_types() {
+        let saturate = Interpreter::builder()
+            .overflow_policy(OverflowPolicy::Saturate)
+            .tape_len(4)
+            .tape_init(1, *b"\x07")
+            .build()
+            .unwrap();
+        let program = compile("-->++++++++++++++++[>++++++++++++++++++++<-]>+").unwrap();
+
+        let evaluated = partially_evaluate::<u8>(&program, &saturate, u64::MAX);
+        assert_eq!(
+            evaluated,
+            [
+                Command::SetAt {
+                    offset: 1,
+                    value: 0
+                },
+                Command::SetAt {
+                    offset: 2,
+                    value: 255
+                },
+                Command::MovePointer(2),
+            ]
+        );
+        let evaluated = partially_evaluate::<u16>(&program, &saturate, u64::MAX);
+        assert_eq!(
+            evaluated,
+            [
+                Command::SetAt {
+                    offset: 1,
+                    value: 0
+                },
+                Command::SetAt {
+                    offset: 2,
+                    value: 255
+                },
+                Command::AddAt {
+                    offset: 2,
+                    value: 23 * 20 + 1 - 255
+                },
+                Command::MovePointer(2),
+            ]
+        );
+        let evaluated = partially_evaluate::<i8>(&compile("-->+").unwrap(), &saturate, u64::MAX);
+        assert_eq!(
+            evaluated[1],
+            Command::SetAt {
+                offset: 1,
+                value: 8
+            }
+        );
+
+        let wide = compile(&"+".repeat(40_000)).unwrap();
+        assert_eq!(partially_evaluate::<u32>(&wide, &saturate, u64::MAX), wide);
+        let wrap = Interpreter::default();
+        assert_eq!(
+            partially_evaluate::<u32>(&compile("-").unwrap(), &wrap, u64::MAX),
+            [Command::Set(0), Command::Add(-1)]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::{ByteSource, Command, Status, Tape, Vm};
+
+/// Exposes the output of a program as a `Read` stream.
+///
+/// The program only runs while bytes are being read. Its own `,` input comes
+/// from `upstream`, so several programs can be chained like a shell pipe.
+/// Reading returns `Ok(0)` once the program has halted. Runtime errors are
+/// reported as [`io::Error`]s wrapping the [`RuntimeError`](crate::RuntimeError).
+#[derive(Debug)]
+pub struct VmReader<'a, R, T = Vec<u8>> {
+    vm: Vm<'a, T>,
+    upstream: R,
+}
+
+impl<'a, R: Read> VmReader<'a, R> {
+    /// Runs the program with the default interpreter settings.
+    pub fn new(commands: &'a [Command], upstream: R) -> Self {
+        VmReader::from_vm(Vm::new(commands), upstream)
+    }
+}
+
+impl<'a, R: Read, T: Tape> VmReader<'a, R, T> {
+    /// Continues running an existing VM.
+    pub fn from_vm(vm: Vm<'a, T>, upstream: R) -> Self {
+        VmReader { vm, upstream }
+    }
+
+    /// Returns the underlying VM.
+    pub fn into_vm(self) -> Vm<'a, T> {
+        self.vm
+    }
+}
+
+impl<R: Read, T: Tape> Read for VmReader<'_, R, T> {
+    /// Fills `buf` with output until it is full, the program halts, or the
+    /// program asks for input after at least one byte was produced.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match self.vm.run()? {
+                Status::Running | Status::OutOfFuel | Status::DebugDump => {}
+                Status::NeedsInput if filled > 0 => break,
+                Status::NeedsInput => match self.upstream.read_byte()? {
+                    Some(byte) => self.vm.provide_input(byte),
+                    None => self.vm.provide_eof(),
+                },
+                Status::ProducedOutput(byte) => {
+                    buf[filled] = byte;
+                    filled += 1;
+                }
+                Status::Halted => break,
+            }
+        }
+
+        Ok(filled)
+    }
+}
+
+/// Feeds everything written to it into a program's `,` input.
+///
+/// Output of the program is forwarded to `downstream`. Since a `Write` has no
+/// notion of end of input, call [`VmWriter::finish`] to signal EOF and run the
+/// program to completion.
+///
+/// `downstream` is flushed before each input byte that follows output, so a
+/// prompt is delivered before the program consumes its answer.
+#[derive(Debug)]
+pub struct VmWriter<'a, W, T = Vec<u8>> {
+    vm: Vm<'a, T>,
+    downstream: W,
+    /// Whether output was forwarded since the last flush.
+    unflushed: bool,
+}
+
+impl<'a, W: Write> VmWriter<'a, W> {
+    /// Runs the program with the default interpreter settings.
+    pub fn new(commands: &'a [Command], downstream: W) -> Self {
+        VmWriter::from_vm(Vm::new(commands), downstream)
+    }
+}
+
+impl<'a, W: Write, T: Tape> VmWriter<'a, W, T> {
+    /// Continues running an existing VM.
+    pub fn from_vm(vm: Vm<'a, T>, downstream: W) -> Self {
+        VmWriter {
+            vm,
+            downstream,
+            unflushed: false,
+        }
+    }
+
+    /// Runs the program until it waits on input, forwarding its output and
+    /// flushing it if there was any. Returns `false` if the program halted
+    /// instead.
+    fn run_until_input(&mut self) -> io::Result<bool> {
+        loop {
+            match self.vm.run()? {
+                Status::Running | Status::OutOfFuel | Status::DebugDump => {}
+                Status::NeedsInput => {
+                    if self.unflushed {
+                        self.downstream.flush()?;
+                        self.unflushed = false;
+                    }
+                    return Ok(true);
+                }
+                Status::ProducedOutput(byte) => {
+                    self.downstream.write_all(&[byte])?;
+                    self.unflushed = true;
+                }
+                Status::Halted => return Ok(false),
+            }
+        }
+    }
+
+    /// Signals end of input, runs the program to completion, and returns the
+    /// downstream writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        while self.run_until_input()? {
+            self.vm.provide_eof();
+        }
+        self.downstream.flush()?;
+        Ok(self.downstream)
+    }
+}
+
+impl<W: Write, T: Tape> Write for VmWriter<'_, W, T> {
+    /// Consumes bytes until the program halts.
+    /// Writing to a halted program fails with [`ErrorKind::BrokenPipe`].
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut consumed = 0;
+
+        for &byte in buf {
+            if !self.run_until_input()? {
+                break;
+            }
+            self.vm.provide_input(byte);
+            consumed += 1;
+        }
+
+        if consumed == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::BrokenPipe,
+                "the program has halted",
+            ));
+        }
+        Ok(consumed)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.downstream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, eval};
+
+    /// Test chaining a generator program into the cat program.
+    #[test]
+    fn test_generator_into_cat() {
+        let generator = compile("++++++++[>++++++++++++<-]>+.+.+.").unwrap();
+        let cat = compile(">,[>,]<[<]>[.>]").unwrap();
+        let mut output = Vec::new();
+
+        eval(&cat, VmReader::new(&generator, io::empty()), &mut output).unwrap();
+
+        assert_eq!(output, b"abc");
+    }
+
+    /// Test that an endless upstream only runs as far as it is read.
+    #[test]
+    fn test_lazy_upstream() {
+        let counter = compile("+[.+]").unwrap();
+        let take_three = compile(",.,.,.").unwrap();
+        let mut output = Vec::new();
+
+        eval(
+            &take_three,
+            VmReader::new(&counter, io::empty()),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(output, [1, 2, 3]);
+    }
+
+    /// Test that the upstream program's own input is passed through.
+    #[test]
+    fn test_upstream_input() {
+        let shift = compile(",[+.,]").unwrap();
+        let mut reader = VmReader::new(&shift, &b"HAL"[..]);
+        let mut output = String::new();
+
+        reader.read_to_string(&mut output).unwrap();
+
+        assert_eq!(output, "IBM");
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    /// Test pushing input into a program through `Write`.
+    #[test]
+    fn test_writer() {
+        let cat = compile(",[.,]").unwrap();
+        let mut writer = VmWriter::new(&cat, Vec::new());
+
+        io::copy(&mut &b"hello"[..], &mut writer).unwrap();
+
+        assert_eq!(writer.finish().unwrap(), b"hello");
+    }
+
+    /// Test that writing to a halted program reports a broken pipe.
+    #[test]
+    fn test_writer_halted() {
+        let take_two = compile(",.,.").unwrap();
+        let mut writer = VmWriter::new(&take_two, Vec::new());
+
+        assert_eq!(writer.write(b"abc").unwrap(), 2);
+        let error = writer.write(b"c").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+
+        assert_eq!(writer.finish().unwrap(), b"ab");
+    }
+
+    /// Test that a prompt reaches a buffered downstream before input is read.
+    #[test]
+    fn test_writer_flushes_prompt() {
+        let prompt = compile("++++++++[>++++++++<-]>-.,.").unwrap();
+        let mut writer = VmWriter::new(&prompt, io::BufWriter::new(Vec::new()));
+
+        writer.write_all(b"y").unwrap();
+
+        assert_eq!(writer.downstream.get_ref(), b"?");
+        assert_eq!(writer.finish().unwrap().into_inner().unwrap(), b"?y");
+    }
+}
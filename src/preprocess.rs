@@ -0,0 +1,481 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::ParsingError;
+
+/// Enum for macro definitions and uses that cannot be expanded.
+/// Offsets count bytes of the source passed to [`expand_macros`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroError {
+    /// The `@def` at `offset` is not followed by a name and a `{ ... }` body.
+    Malformed { offset: usize },
+    /// The `@def` at `offset` is inside the body of another definition.
+    NestedDefinition { offset: usize },
+    /// The `@def` at `offset` defines `name` a second time.
+    Duplicate { name: String, offset: usize },
+    /// The `@name` at `offset` uses a macro that is not defined.
+    Undefined { name: String, offset: usize },
+    /// Expanding the first macro in `cycle` leads back to itself through the
+    /// others.
+    Recursive { cycle: Vec<String> },
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacroError::Malformed { offset } => {
+                write!(
+                    f,
+                    "expected a name and a body after '@def' at offset {offset}"
+                )
+            }
+            MacroError::NestedDefinition { offset } => {
+                write!(f, "'@def' at offset {offset} is inside another definition")
+            }
+            MacroError::Duplicate { name, offset } => {
+                write!(f, "macro '{name}' is defined again at offset {offset}")
+            }
+            MacroError::Undefined { name, offset } => {
+                write!(f, "macro '{name}' used at offset {offset} is not defined")
+            }
+            MacroError::Recursive { cycle } => {
+                write!(f, "macro '{}' expands to itself: ", cycle[0])?;
+                for (i, name) in cycle.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" -> ")?;
+                    }
+                    f.write_str(name)?;
+                }
+                f.write_str(" -> ")?;
+                f.write_str(&cycle[0])
+            }
+        }
+    }
+}
+
+impl core::error::Error for MacroError {}
+
+/// Enum for comment conventions that [`blank_comments`] understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// `{ ... }` is a comment, and comments may nest.
+    Braces,
+    /// `;` starts a comment that runs to the end of the line.
+    Semicolon,
+}
+
+/// Replaces every byte of every comment with a space, so prose in the
+/// comments may contain command characters like `[` or `.`.
+///
+/// Byte offsets are the same in the output, so errors from
+/// [`compile`](crate::compile) point at the original text. The delimiters
+/// are blanked as well. An unclosed `{` or a stray `}` fails with
+/// [`ParsingError::UnmatchedBracket`].
+pub fn blank_comments(source: &str, style: CommentStyle) -> Result<String, ParsingError> {
+    let mut blanked = source.as_bytes().to_vec();
+    match style {
+        CommentStyle::Braces => {
+            let mut open = Vec::new();
+            for (offset, byte) in blanked.iter_mut().enumerate() {
+                match *byte {
+                    b'{' => open.push(offset),
+                    b'}' if open.pop().is_none() => {
+                        return Err(ParsingError::UnmatchedBracket {
+                            offset,
+                            bracket: '}',
+                        });
+                    }
+                    b'}' => {}
+                    _ if open.is_empty() => continue,
+                    _ => {}
+                }
+                *byte = b' ';
+            }
+            if let Some(&offset) = open.first() {
+                return Err(ParsingError::UnmatchedBracket {
+                    offset,
+                    bracket: '{',
+                });
+            }
+        }
+        CommentStyle::Semicolon => {
+            let mut in_comment = false;
+            for byte in &mut blanked {
+                in_comment = match *byte {
+                    b'\n' => false,
+                    b';' => true,
+                    _ => in_comment,
+                };
+                if in_comment {
+                    *byte = b' ';
+                }
+            }
+        }
+    }
+    // Whole characters are replaced, one space per byte.
+    Ok(String::from_utf8(blanked).expect("comments are blanked byte by byte"))
+}
+
+/// Maps byte offsets in generated source code back to the text it was
+/// copied from, e.g. to point a [`ParsingError`] at the user's file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Start of every copied run in the output, and where it came from.
+    segments: Vec<(usize, usize)>,
+}
+
+impl SourceMap {
+    /// Creates a map in which every offset maps to itself.
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    /// Records that the output from offset `output` on was copied from
+    /// offset `origin`, up to the next recorded run.
+    /// Runs must be pushed in the order of their output offsets.
+    pub fn push(&mut self, output: usize, origin: usize) {
+        match self.segments.last_mut() {
+            Some(last) if last.0 == output => last.1 = origin,
+            _ => self.segments.push((output, origin)),
+        }
+    }
+
+    /// Offset in the original text of the byte at `offset` in the output.
+    pub fn origin(&self, offset: usize) -> usize {
+        let i = self
+            .segments
+            .partition_point(|&(output, _)| output <= offset);
+        match i.checked_sub(1) {
+            Some(i) => {
+                let (output, origin) = self.segments[i];
+                origin + (offset - output)
+            }
+            None => offset,
+        }
+    }
+
+    /// Moves the offset in `error` from the output to the original text.
+    pub fn map_error(&self, error: ParsingError) -> ParsingError {
+        match error {
+            ParsingError::UnmatchedBracket { offset, bracket } => ParsingError::UnmatchedBracket {
+                offset: self.origin(offset),
+                bracket,
+            },
+            ParsingError::UnexpectedCharacter { offset, ch } => ParsingError::UnexpectedCharacter {
+                offset: self.origin(offset),
+                ch,
+            },
+            ParsingError::NestingTooDeep { offset, depth } => ParsingError::NestingTooDeep {
+                offset: self.origin(offset),
+                depth,
+            },
+        }
+    }
+}
+
+/// Expands macros in Brainfuck source code, to be passed on to
+/// [`compile`](crate::compile).
+///
+/// `@def name { ... }` defines a macro and is removed from the output, and
+/// every `@name` is replaced by the body of the macro, which may use other
+/// macros in turn. Definitions may come after their uses. Names consist of
+/// ASCII letters, digits, and `_`; any other `@` is left alone.
+pub fn expand_macros(source: &str) -> Result<String, MacroError> {
+    expand_macros_with_map(source).map(|(expanded, _)| expanded)
+}
+
+/// Same as [`expand_macros`], but also returns where every part of the
+/// output came from.
+pub fn expand_macros_with_map(source: &str) -> Result<(String, SourceMap), MacroError> {
+    let (definitions, top_level) = collect_definitions(source)?;
+    let mut expander = Expander {
+        source,
+        definitions,
+        stack: Vec::new(),
+        expanded: String::new(),
+        map: SourceMap::new(),
+    };
+    for (start, end) in top_level {
+        expander.expand(start, end)?;
+    }
+    Ok((expander.expanded, expander.map))
+}
+
+/// Macro definitions by name, and the ranges of source between them.
+type Definitions<'a> = (BTreeMap<&'a str, Definition>, Vec<(usize, usize)>);
+
+/// Body of a macro, as a range of the source.
+#[derive(Debug, Clone, Copy)]
+struct Definition {
+    start: usize,
+    end: usize,
+}
+
+/// Finds every `@def` and returns the definitions along with the ranges of
+/// source between them.
+fn collect_definitions(source: &str) -> Result<Definitions<'_>, MacroError> {
+    let mut definitions = BTreeMap::new();
+    let mut top_level = Vec::new();
+    let mut text_start = 0;
+    let mut search = 0;
+
+    while let Some(found) = source[search..].find("@def") {
+        let offset = search + found;
+        let after = offset + "@def".len();
+        search = after;
+        // `@define` is a use of another macro.
+        if source[after..].starts_with(is_name_char) {
+            continue;
+        }
+
+        let malformed = MacroError::Malformed { offset };
+        let rest = &source[after..];
+        let name_start = after + (rest.len() - rest.trim_start().len());
+        let name_len = source[name_start..]
+            .find(|ch: char| !is_name_char(ch))
+            .unwrap_or(source.len() - name_start);
+        if name_start == after || name_len == 0 {
+            return Err(malformed);
+        }
+        let name = &source[name_start..name_start + name_len];
+
+        let rest = &source[name_start + name_len..];
+        let Some(body) = rest.trim_start().strip_prefix('{') else {
+            return Err(malformed);
+        };
+        let start = source.len() - body.len();
+        let Some(len) = body_len(body) else {
+            return Err(malformed);
+        };
+        let end = start + len;
+        if let Some(nested) = source[start..end].find("@def") {
+            return Err(MacroError::NestedDefinition {
+                offset: start + nested,
+            });
+        }
+        if definitions
+            .insert(name, Definition { start, end })
+            .is_some()
+        {
+            return Err(MacroError::Duplicate {
+                name: name.to_string(),
+                offset,
+            });
+        }
+
+        top_level.push((text_start, offset));
+        text_start = end + 1;
+        search = text_start;
+    }
+
+    top_level.push((text_start, source.len()));
+    Ok((definitions, top_level))
+}
+
+/// Length of a macro body up to its closing `}`, counting nested braces.
+fn body_len(body: &str) -> Option<usize> {
+    let mut depth = 0_usize;
+    for (i, byte) in body.bytes().enumerate() {
+        match byte {
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// State of [`expand_macros_with_map`].
+struct Expander<'a> {
+    source: &'a str,
+    definitions: BTreeMap<&'a str, Definition>,
+    /// Macros that are being expanded, outermost first.
+    stack: Vec<&'a str>,
+    expanded: String,
+    map: SourceMap,
+}
+
+impl<'a> Expander<'a> {
+    /// Appends the source from `start` to `end` with its macros expanded.
+    fn expand(&mut self, start: usize, end: usize) -> Result<(), MacroError> {
+        let source = self.source;
+        let mut copied = start;
+        let mut search = start;
+
+        while let Some(found) = source[search..end].find('@') {
+            let at = search + found;
+            let name_len = source[at + 1..end]
+                .find(|ch: char| !is_name_char(ch))
+                .unwrap_or(end - at - 1);
+            search = at + 1;
+            if name_len == 0 {
+                continue;
+            }
+            let name = &source[at + 1..at + 1 + name_len];
+            let Some(&definition) = self.definitions.get(name) else {
+                return Err(MacroError::Undefined {
+                    name: name.to_string(),
+                    offset: at,
+                });
+            };
+            if let Some(first) = self.stack.iter().position(|&open| open == name) {
+                let cycle = self.stack[first..].iter().map(|name| name.to_string());
+                return Err(MacroError::Recursive {
+                    cycle: cycle.collect(),
+                });
+            }
+
+            self.copy(copied, at);
+            self.stack.push(name);
+            self.expand(definition.start, definition.end)?;
+            self.stack.pop();
+            copied = at + 1 + name_len;
+            search = copied;
+        }
+
+        self.copy(copied, end);
+        Ok(())
+    }
+
+    fn copy(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.map.push(self.expanded.len(), start);
+            self.expanded.push_str(&self.source[start..end]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, run_to_bytes};
+
+    /// Test a macro that is used twice, and one that uses another.
+    #[test]
+    fn test_expand() {
+        let source = "
+            @def move { [->+<] }
+            @def add_twice { @move > @move }
+            +++ @move > @move < +++++ @add_twice >.
+        ";
+
+        let expanded = expand_macros(source).unwrap();
+        assert_eq!(
+            expanded.split_whitespace().collect::<String>(),
+            "+++[->+<]>[->+<]<+++++[->+<]>[->+<]>."
+        );
+        assert_eq!(run_to_bytes(&expanded, &[]).unwrap(), [8]);
+    }
+
+    /// Test that a macro using itself reports the cycle.
+    #[test]
+    fn test_recursion() {
+        let source = "@def a { + @b } @def b { @c } @def c { @a } @b";
+        let error = expand_macros(source).unwrap_err();
+
+        assert_eq!(
+            error,
+            MacroError::Recursive {
+                cycle: ["b", "c", "a"].map(String::from).to_vec()
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "macro 'b' expands to itself: b -> c -> a -> b"
+        );
+        assert!(expand_macros("@def a { @a } unused").is_ok());
+    }
+
+    /// Test that a parse error inside a macro points into its definition.
+    #[test]
+    fn test_source_map() {
+        let source = "@def open { +[ } @open . @open";
+        let (expanded, map) = expand_macros_with_map(source).unwrap();
+        let error = compile(&expanded).unwrap_err();
+
+        assert_eq!(
+            map.map_error(error),
+            ParsingError::UnmatchedBracket {
+                offset: 13,
+                bracket: '['
+            }
+        );
+        assert_eq!(&source[13..14], "[");
+        assert_eq!(map.origin(expanded.find('.').unwrap()), 23);
+    }
+
+    /// Test that brackets in comments are blanked without moving the code.
+    #[test]
+    fn test_blank_comments() {
+        let source = "++{ see [RFC 1] {nested.} }>+<[->+<]>.";
+        let blanked = blank_comments(source, CommentStyle::Braces).unwrap();
+        assert_eq!(blanked.len(), source.len());
+        assert_eq!(
+            compile(&blanked).unwrap(),
+            compile("++>+<[->+<]>.").unwrap()
+        );
+
+        let source = "+++ ; see [RFC 1], caf\u{e9}.\n.";
+        let blanked = blank_comments(source, CommentStyle::Semicolon).unwrap();
+        assert_eq!(blanked, format!("+++ {}\n.", " ".repeat(21)));
+        assert_eq!(run_to_bytes(&blanked, &[]).unwrap(), [3]);
+    }
+
+    /// Test that unbalanced braces point at the brace.
+    #[test]
+    fn test_blank_comments_errors() {
+        assert_eq!(
+            blank_comments("+{ {} ", CommentStyle::Braces),
+            Err(ParsingError::UnmatchedBracket {
+                offset: 1,
+                bracket: '{'
+            })
+        );
+        assert_eq!(
+            blank_comments("+ } {", CommentStyle::Braces),
+            Err(ParsingError::UnmatchedBracket {
+                offset: 2,
+                bracket: '}'
+            })
+        );
+    }
+
+    /// Test the errors for broken definitions and unknown names.
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            expand_macros("+ @def { + }"),
+            Err(MacroError::Malformed { offset: 2 })
+        );
+        assert_eq!(
+            expand_macros("@def a { + "),
+            Err(MacroError::Malformed { offset: 0 })
+        );
+        assert_eq!(
+            expand_macros("@def a { @def b {} }"),
+            Err(MacroError::NestedDefinition { offset: 9 })
+        );
+        assert_eq!(
+            expand_macros("@def a {} @def a {}"),
+            Err(MacroError::Duplicate {
+                name: "a".into(),
+                offset: 10
+            })
+        );
+        assert_eq!(
+            expand_macros("+ @missing"),
+            Err(MacroError::Undefined {
+                name: "missing".into(),
+                offset: 2
+            })
+        );
+        assert_eq!(expand_macros("mail me @ home").unwrap(), "mail me @ home");
+    }
+}
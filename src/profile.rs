@@ -0,0 +1,151 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::{Cell, Command, Observer};
+
+/// What one loop of a program cost, as counted by a [`LoopProfiler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopProfile {
+    /// Address of the loop's `[`.
+    pub start: usize,
+    /// Address of the matching `]`.
+    pub end: usize,
+    /// Times the `[` found a non-zero cell and ran the body.
+    pub entries: u64,
+    /// Commands executed from `start` to `end`, the brackets and nested
+    /// loops included.
+    pub steps: u64,
+}
+
+/// [`Observer`] that counts how often every command runs, to find the loops
+/// a program spends its time in.
+///
+/// Like every observer it only costs anything while it observes, through
+/// [`Vm::run_observed`](crate::Vm::run_observed). Loops are attributed by
+/// address, so a pbrain procedure called from a loop counts toward the
+/// loops around its definition instead.
+#[derive(Debug, Clone)]
+pub struct LoopProfiler {
+    /// Every bracket pair, by the address of its `[`.
+    loops: Vec<(usize, usize)>,
+    /// Times each command ran.
+    counts: Vec<u64>,
+    /// Times each `[` ran its body.
+    entries: Vec<u64>,
+}
+
+impl LoopProfiler {
+    /// A profiler for `commands`, which have to be the ones it observes.
+    pub fn new(commands: &[Command]) -> Self {
+        let loops = commands
+            .iter()
+            .enumerate()
+            .filter_map(|(address, command)| match *command {
+                Command::JumpForwardIfZero(end) => Some((address, end)),
+                _ => None,
+            })
+            .collect();
+        LoopProfiler {
+            loops,
+            counts: vec![0; commands.len()],
+            entries: vec![0; commands.len()],
+        }
+    }
+
+    /// Commands executed so far, in loops or not.
+    pub fn steps(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Every loop of the program, the one with the most steps first, and
+    /// loops with as many in the order they appear.
+    pub fn loops(&self) -> Vec<LoopProfile> {
+        // `totals[a]` is the number of steps before address `a`.
+        let mut totals = Vec::with_capacity(self.counts.len() + 1);
+        totals.push(0);
+        for count in &self.counts {
+            totals.push(totals[totals.len() - 1] + count);
+        }
+        let mut loops: Vec<_> = self
+            .loops
+            .iter()
+            .map(|&(start, end)| LoopProfile {
+                start,
+                end,
+                entries: self.entries[start],
+                steps: totals[end + 1] - totals[start],
+            })
+            .collect();
+        loops.sort_by(|a, b| b.steps.cmp(&a.steps).then(a.start.cmp(&b.start)));
+        loops
+    }
+}
+
+impl<C: Cell> Observer<C> for LoopProfiler {
+    fn on_step(
+        &mut self,
+        instruction_pointer: usize,
+        command: &Command,
+        _: usize,
+        cell: C,
+    ) -> ControlFlow<()> {
+        self.counts[instruction_pointer] += 1;
+        if let Command::JumpForwardIfZero(_) = command
+            && cell != C::ZERO
+        {
+            self.entries[instruction_pointer] += 1;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Streams, Vm, compile};
+
+    /// Profiles `source_code` on a tape of eight bytes.
+    fn profile(source_code: &str) -> LoopProfiler {
+        let program = compile(source_code).unwrap();
+        let mut profiler = LoopProfiler::new(&program);
+        let mut vm = Vm::with_tape(&program, vec![0u8; 8], 0);
+        vm.run_observed(Streams::new(&[][..], Vec::new()), &mut profiler)
+            .unwrap();
+        profiler
+    }
+
+    /// Test that the inner of two nested loops dominates.
+    #[test]
+    fn test_nested_loops() {
+        let profiler = profile("++++[->++++[->+<]<]");
+        let inner = LoopProfile {
+            start: 11,
+            end: 16,
+            entries: 4,
+            steps: 84,
+        };
+        let outer = LoopProfile {
+            start: 4,
+            end: 18,
+            entries: 1,
+            steps: 117,
+        };
+        assert_eq!(profiler.loops(), [outer, inner]);
+        assert_eq!(profiler.steps(), 121);
+        // Most of the outer loop's steps are spent in the inner one.
+        assert!(inner.steps * 2 > outer.steps);
+    }
+
+    /// Test that skipped loops count their `[` but no entry.
+    #[test]
+    fn test_skipped_loop() {
+        let profiler = profile("[+]+[-][>]");
+        let entries: Vec<_> = profiler
+            .loops()
+            .iter()
+            .map(|profile| (profile.start, profile.entries, profile.steps))
+            .collect();
+        assert_eq!(entries, [(4, 1, 3), (0, 0, 1), (7, 0, 1)]);
+    }
+}
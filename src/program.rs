@@ -0,0 +1,107 @@
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::{Command, JumpError, validate};
+
+/// Command list whose jump addresses are known to be consistent.
+///
+/// This is the form to use when programs cross a trust boundary, e.g. when
+/// they are deserialized: constructing a `Program` always runs [`validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<Command>", into = "Vec<Command>")
+)]
+pub struct Program {
+    commands: Vec<Command>,
+}
+
+impl Program {
+    /// Validates the commands and wraps them.
+    pub fn new(commands: Vec<Command>) -> Result<Self, JumpError> {
+        validate(&commands)?;
+        Ok(Program { commands })
+    }
+
+    /// Returns the wrapped commands.
+    pub fn into_commands(self) -> Vec<Command> {
+        self.commands
+    }
+}
+
+impl Deref for Program {
+    type Target = [Command];
+
+    fn deref(&self) -> &[Command] {
+        &self.commands
+    }
+}
+
+impl TryFrom<Vec<Command>> for Program {
+    type Error = JumpError;
+
+    fn try_from(commands: Vec<Command>) -> Result<Self, JumpError> {
+        Program::new(commands)
+    }
+}
+
+impl From<Program> for Vec<Command> {
+    fn from(program: Program) -> Self {
+        program.commands
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::{compile, eval};
+
+    fn hello_world() -> Program {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        Program::new(compile(source_code).unwrap()).unwrap()
+    }
+
+    /// Test a round trip through JSON.
+    #[test]
+    fn test_json_round_trip() {
+        let program = hello_world();
+
+        let json = serde_json::to_string(&program).unwrap();
+        let loaded: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, program);
+
+        let mut writer = Vec::new();
+        eval(&loaded, &[][..], &mut writer).unwrap();
+        assert_eq!(writer, b"Hello World!\n");
+    }
+
+    /// Test a round trip through bincode.
+    #[test]
+    fn test_bincode_round_trip() {
+        let program = hello_world();
+
+        let bytes = bincode::serialize(&program).unwrap();
+        let loaded: Program = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(loaded, program);
+    }
+
+    /// Test that payloads with broken jumps are rejected.
+    #[test]
+    fn test_corrupted_payload() {
+        let past_end = r#"["Increment",{"JumpForwardIfZero":7},{"JumpBackwardIfNonZero":1}]"#;
+        let error = serde_json::from_str::<Program>(past_end).unwrap_err();
+        assert!(error.to_string().contains("points past the end"), "{error}");
+
+        let mismatched = r#"[{"JumpForwardIfZero":1},"Decrement"]"#;
+        let error = serde_json::from_str::<Program>(mismatched).unwrap_err();
+        assert!(error.to_string().contains("matching bracket"), "{error}");
+
+        let mut bytes = bincode::serialize(&hello_world()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x40;
+        assert!(bincode::deserialize::<Program>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Sessions of `repl`, which run one entry after another on the same tape.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use brainfuck_vm::{Cell, Command, Error, ExecutionReport, Interpreter, IoHandler};
+
+/// Number of cells `:dump` shows on either side of the pointer.
+const DUMP_RADIUS: usize = 8;
+
+/// A line of `repl` that starts with `:`, which is not a program.
+#[derive(Debug, PartialEq)]
+pub enum Meta {
+    /// `:dump`, which shows the cells around the pointer.
+    Dump,
+    /// `:reset`, which clears the tape and moves the pointer back.
+    Reset,
+    /// `:pointer N`, which moves the pointer to cell `N`.
+    Pointer(usize),
+    /// `:load FILE`, which runs the program in the file.
+    Load(PathBuf),
+    /// `:quit`, which ends the session.
+    Quit,
+}
+
+impl Meta {
+    /// Parses `line`, which starts with `:`.
+    pub fn parse(line: &str) -> Result<Meta, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        let meta = match (name, argument) {
+            (":dump", None) => Meta::Dump,
+            (":reset", None) => Meta::Reset,
+            (":quit", None) => Meta::Quit,
+            (":pointer", Some(index)) => match index.parse() {
+                Ok(index) => Meta::Pointer(index),
+                Err(_) => return Err(format!("invalid cell index '{index}'")),
+            },
+            (":pointer", None) => return Err("':pointer' takes the index of a cell".into()),
+            (":load", Some(_)) => Meta::Load(PathBuf::from(line[":load".len()..].trim())),
+            (":load", None) => return Err("':load' takes a file".into()),
+            (":dump" | ":reset" | ":quit", Some(_)) => {
+                return Err(format!("'{name}' takes no argument"));
+            }
+            _ => {
+                return Err(format!(
+                    "unknown command '{name}', expected :dump, :reset, :pointer N, :load FILE, or :quit"
+                ));
+            }
+        };
+        match (&meta, words.next()) {
+            (Meta::Pointer(_), Some(_)) => Err("':pointer' takes one cell index".into()),
+            _ => Ok(meta),
+        }
+    }
+}
+
+/// Tape and data pointer the entries of a session run on, each starting
+/// where the one before left off.
+pub struct Session<C> {
+    tape: Vec<C>,
+    data_pointer: usize,
+}
+
+impl<C: Cell> Session<C> {
+    /// A session on the tape `interpreter` starts programs on.
+    pub fn new(interpreter: &Interpreter) -> Self {
+        let vm = interpreter.vm_with_cells::<C>(&[]);
+        Session {
+            data_pointer: vm.data_pointer(),
+            tape: vm.into_tape(),
+        }
+    }
+
+    /// Runs `program` on the tape of the session, serving I/O through
+    /// `handler`. A program that fails leaves the tape and pointer as they
+    /// were before it ran, though what it wrote stays written.
+    pub fn run(
+        &mut self,
+        interpreter: &Interpreter,
+        program: &[Command],
+        handler: impl IoHandler,
+    ) -> Result<ExecutionReport, Error> {
+        let tape = self.tape.clone();
+        let mut vm = interpreter.resume_vm(program, tape, self.data_pointer);
+        let result = interpreter.run_on_vm(&mut vm, handler);
+        if result.is_ok() {
+            self.data_pointer = vm.data_pointer();
+            self.tape = vm.into_tape();
+        }
+        result
+    }
+
+    /// Moves the pointer to the cell at `index`.
+    pub fn set_pointer(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.tape.len() {
+            return Err(format!(
+                "cell {index} is past the end of the tape of {} cells",
+                self.tape.len()
+            ));
+        }
+        self.data_pointer = index;
+        Ok(())
+    }
+
+    /// The cells around the pointer as `index:value`, with the one under
+    /// the pointer in brackets.
+    pub fn dump(&self) -> String {
+        let start = self.data_pointer.saturating_sub(DUMP_RADIUS);
+        let end = (self.data_pointer + DUMP_RADIUS + 1).min(self.tape.len());
+        let mut dump = String::new();
+        for (index, cell) in self.tape.iter().enumerate().take(end).skip(start) {
+            if !dump.is_empty() {
+                dump.push(' ');
+            }
+            let _ = match index == self.data_pointer {
+                true => write!(dump, "[{index}:{cell}]"),
+                false => write!(dump, "{index}:{cell}"),
+            };
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brainfuck_vm::{Streams, compile};
+
+    /// Runs `source_code` in `session` and returns its output.
+    fn enter(session: &mut Session<u8>, interpreter: &Interpreter, source_code: &str) -> Vec<u8> {
+        let mut output = Vec::new();
+        let program = compile(source_code).unwrap();
+        let _ = session.run(interpreter, &program, Streams::new(&[][..], &mut output));
+        output
+    }
+
+    /// Test that entries go on with the cells and pointer left before.
+    #[test]
+    fn test_entries() {
+        let interpreter = Interpreter::builder().tape_len(4).build().unwrap();
+        let mut session = Session::new(&interpreter);
+        assert!(enter(&mut session, &interpreter, "+++>++").is_empty());
+        assert_eq!(enter(&mut session, &interpreter, ".<."), [2, 3]);
+        assert_eq!(session.data_pointer, 0);
+    }
+
+    /// Test that a failing entry leaves the tape as it was before it ran.
+    #[test]
+    fn test_failing_entry() {
+        let interpreter = Interpreter::builder().tape_len(4).build().unwrap();
+        let mut session = Session::new(&interpreter);
+        enter(&mut session, &interpreter, ">++");
+        assert_eq!(enter(&mut session, &interpreter, "+.>>>"), [3]);
+        assert_eq!(enter(&mut session, &interpreter, "."), [2]);
+        assert_eq!(session.data_pointer, 1);
+    }
+
+    /// Test that the dump stops at the ends of the tape and marks the
+    /// pointer, which cannot move past the end.
+    #[test]
+    fn test_dump() {
+        let interpreter = Interpreter::builder().tape_len(20).build().unwrap();
+        let mut session = Session::new(&interpreter);
+        enter(&mut session, &interpreter, "+>++");
+        assert_eq!(session.dump(), "0:1 [1:2] 2:0 3:0 4:0 5:0 6:0 7:0 8:0 9:0");
+        session.set_pointer(19).unwrap();
+        assert_eq!(
+            session.dump(),
+            "11:0 12:0 13:0 14:0 15:0 16:0 17:0 18:0 [19:0]"
+        );
+        assert_eq!(
+            session.set_pointer(20),
+            Err("cell 20 is past the end of the tape of 20 cells".into())
+        );
+    }
+
+    /// Test the meta-commands and the errors of ones that do not parse.
+    #[test]
+    fn test_parse_meta() {
+        assert_eq!(Meta::parse(":dump"), Ok(Meta::Dump));
+        assert_eq!(Meta::parse(":pointer  12"), Ok(Meta::Pointer(12)));
+        assert_eq!(
+            Meta::parse(":load dir/a b.b"),
+            Ok(Meta::Load("dir/a b.b".into()))
+        );
+        assert_eq!(
+            Meta::parse(":pointer -1"),
+            Err("invalid cell index '-1'".into())
+        );
+        assert_eq!(
+            Meta::parse(":quit now"),
+            Err("':quit' takes no argument".into())
+        );
+        assert!(
+            Meta::parse(":help")
+                .unwrap_err()
+                .starts_with("unknown command ':help'")
+        );
+    }
+}
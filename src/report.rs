@@ -0,0 +1,151 @@
+use crate::Command;
+
+/// Counters collected while a program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionReport {
+    /// Number of executed commands, jumps included.
+    pub steps: u64,
+    /// Lowest cell index the data pointer reached.
+    pub min_pointer: usize,
+    /// Highest cell index the data pointer reached.
+    pub max_pointer: usize,
+    /// Number of bytes consumed by `,`, not counting end of input.
+    pub bytes_read: u64,
+    /// Number of bytes produced by `.`.
+    pub bytes_written: u64,
+    /// Index of the current cell once the program halted.
+    pub final_pointer: usize,
+    /// Low byte of the current cell once the program halted.
+    pub final_cell: u8,
+    /// How often each kind of command ran, if the run was asked to count,
+    /// see [`Vm::with_stats`](crate::Vm::with_stats).
+    pub stats: Option<InstructionStats>,
+}
+
+impl ExecutionReport {
+    /// Creates an empty report for a run starting at `data_pointer`.
+    pub fn new(data_pointer: usize) -> Self {
+        ExecutionReport {
+            steps: 0,
+            min_pointer: data_pointer,
+            max_pointer: data_pointer,
+            bytes_read: 0,
+            bytes_written: 0,
+            final_pointer: data_pointer,
+            final_cell: 0,
+            stats: None,
+        }
+    }
+}
+
+/// Number of times each kind of command ran, with `[` and `]` split by
+/// whether they jumped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstructionStats {
+    pub increments: u64,
+    pub decrements: u64,
+    pub right_moves: u64,
+    pub left_moves: u64,
+    pub writes: u64,
+    pub reads: u64,
+    /// `[` on a zero cell, which skips the loop.
+    pub forward_jumps_taken: u64,
+    pub forward_jumps_not_taken: u64,
+    /// `]` on a non-zero cell, which repeats the loop.
+    pub backward_jumps_taken: u64,
+    pub backward_jumps_not_taken: u64,
+    pub debug_dumps: u64,
+    /// pbrain `(`, which skips the body it defines.
+    pub procedures_defined: u64,
+    /// pbrain `)` at the end of a call.
+    pub returns: u64,
+    pub calls: u64,
+    pub randoms: u64,
+    pub adds: u64,
+    pub pointer_moves: u64,
+    pub sets: u64,
+    pub mul_adds: u64,
+    pub right_scans: u64,
+    pub left_scans: u64,
+    pub adds_at: u64,
+    pub sets_at: u64,
+    pub writes_at: u64,
+    pub reads_at: u64,
+    pub clear_ranges: u64,
+}
+
+impl InstructionStats {
+    /// Counts `command`, which ran on a current cell that was zero if
+    /// `zero` is set.
+    pub(crate) fn record(&mut self, command: &Command, zero: bool) {
+        use self::Command as C;
+
+        let count = match command {
+            C::Increment => &mut self.increments,
+            C::Decrement => &mut self.decrements,
+            C::IncrementDataPointer => &mut self.right_moves,
+            C::DecrementDataPointer => &mut self.left_moves,
+            C::WriteByte => &mut self.writes,
+            C::ReadByte => &mut self.reads,
+            C::JumpForwardIfZero(_) if zero => &mut self.forward_jumps_taken,
+            C::JumpForwardIfZero(_) => &mut self.forward_jumps_not_taken,
+            C::JumpBackwardIfNonZero(_) if zero => &mut self.backward_jumps_not_taken,
+            C::JumpBackwardIfNonZero(_) => &mut self.backward_jumps_taken,
+            C::DebugDump => &mut self.debug_dumps,
+            C::BeginProc(_) => &mut self.procedures_defined,
+            C::EndProc(_) => &mut self.returns,
+            C::Call => &mut self.calls,
+            C::Random => &mut self.randoms,
+            C::Add(_) => &mut self.adds,
+            C::MovePointer(_) => &mut self.pointer_moves,
+            C::Set(_) => &mut self.sets,
+            C::MulAdd { .. } => &mut self.mul_adds,
+            C::ScanRight(_) => &mut self.right_scans,
+            C::ScanLeft(_) => &mut self.left_scans,
+            C::AddAt { .. } => &mut self.adds_at,
+            C::SetAt { .. } => &mut self.sets_at,
+            C::OutputAt(_) => &mut self.writes_at,
+            C::InputAt(_) => &mut self.reads_at,
+            C::ClearRange { .. } => &mut self.clear_ranges,
+        };
+        *count += 1;
+    }
+
+    /// Every count, named after the instruction [`to_ir`](crate::to_ir)
+    /// writes, with `_taken` and `_not_taken` for the jumps.
+    pub fn counts(&self) -> [(&'static str, u64); 26] {
+        [
+            ("inc", self.increments),
+            ("dec", self.decrements),
+            ("right", self.right_moves),
+            ("left", self.left_moves),
+            ("out", self.writes),
+            ("in", self.reads),
+            ("jz_taken", self.forward_jumps_taken),
+            ("jz_not_taken", self.forward_jumps_not_taken),
+            ("jnz_taken", self.backward_jumps_taken),
+            ("jnz_not_taken", self.backward_jumps_not_taken),
+            ("dump", self.debug_dumps),
+            ("proc", self.procedures_defined),
+            ("ret", self.returns),
+            ("call", self.calls),
+            ("random", self.randoms),
+            ("add", self.adds),
+            ("move", self.pointer_moves),
+            ("set", self.sets),
+            ("mul_add", self.mul_adds),
+            ("scan_right", self.right_scans),
+            ("scan_left", self.left_scans),
+            ("add_at", self.adds_at),
+            ("set_at", self.sets_at),
+            ("out_at", self.writes_at),
+            ("in_at", self.reads_at),
+            ("clear_range", self.clear_ranges),
+        ]
+    }
+
+    /// Number of commands counted, which is the number of steps they took.
+    pub fn total(&self) -> u64 {
+        self.counts().iter().map(|&(_, count)| count).sum()
+    }
+}
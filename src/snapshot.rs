@@ -0,0 +1,95 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Saved state of a [`Vm`](crate::Vm), taken with
+/// [`Vm::snapshot`](crate::Vm::snapshot).
+///
+/// Together with the program it was taken from, this is everything needed to
+/// continue execution later, e.g. in another process when the `serde`
+/// feature is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    /// Contents of the whole tape.
+    pub tape: Vec<u8>,
+    /// Index of the current cell.
+    pub data_pointer: usize,
+    /// Index of the next command to execute.
+    pub instruction_pointer: usize,
+    /// Number of commands in the program, used to detect restoring into
+    /// a different program.
+    pub program_len: usize,
+}
+
+/// Enum for snapshots that do not fit the VM they are restored into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The snapshot was taken from a program with a different length.
+    ProgramMismatch { expected: usize, found: usize },
+    /// The instruction pointer is past the end of the program.
+    InstructionPointerOutOfRange {
+        instruction_pointer: usize,
+        program_len: usize,
+    },
+    /// The snapshot tape differs in length from the VM tape.
+    TapeMismatch { expected: usize, found: usize },
+    /// The data pointer does not point into the tape.
+    DataPointerOutOfRange {
+        data_pointer: usize,
+        tape_len: usize,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::ProgramMismatch { expected, found } => write!(
+                f,
+                "snapshot was taken from a program with {found} commands, expected {expected}"
+            ),
+            SnapshotError::InstructionPointerOutOfRange {
+                instruction_pointer,
+                program_len,
+            } => write!(
+                f,
+                "instruction pointer {instruction_pointer} is past the end of the {program_len}-command program"
+            ),
+            SnapshotError::TapeMismatch { expected, found } => {
+                write!(f, "snapshot tape has {found} cells, expected {expected}")
+            }
+            SnapshotError::DataPointerOutOfRange {
+                data_pointer,
+                tape_len,
+            } => write!(
+                f,
+                "data pointer {data_pointer} is outside of the {tape_len}-cell tape"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for SnapshotError {}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::{Status, Vm, compile};
+
+    /// Test continuing a program from a snapshot that went through JSON.
+    #[test]
+    fn test_json_round_trip() {
+        let program = compile("+++[>++<-].>.").unwrap();
+        let mut vm = Vm::new(&program);
+        for _ in 0..10 {
+            vm.step().unwrap();
+        }
+
+        let json = serde_json::to_string(&vm.snapshot()).unwrap();
+        let mut restored = Vm::new(&program);
+        restored
+            .restore(&serde_json::from_str(&json).unwrap())
+            .unwrap();
+
+        assert_eq!(restored.run().unwrap(), Status::ProducedOutput(0));
+        assert_eq!(restored.run().unwrap(), Status::ProducedOutput(6));
+    }
+}
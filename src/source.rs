@@ -0,0 +1,227 @@
+//! Loading programs that are split over several files with `@include`.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use brainfuck_vm::{ParsingError, SourceMap};
+
+/// How deep `@include` may nest before loading gives up.
+pub const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Program text with every `@include "path"` replaced by the contents of
+/// the file. Paths are relative to the including file, or to the working
+/// directory for a program given as an argument.
+pub struct Source {
+    pub text: String,
+    /// Files in the order they were read; one file can appear many times.
+    files: Vec<Option<PathBuf>>,
+    /// Offset of every line in each of `files`, for the positions of errors.
+    line_starts: Vec<Vec<usize>>,
+    /// Start of every copied run in `text`, and which file it came from.
+    runs: Vec<(usize, usize)>,
+    /// Offsets in `text` mapped to offsets in their file.
+    map: SourceMap,
+}
+
+/// Where an error is in the file it came from.
+pub struct Location<'a> {
+    /// The file, or `None` for a program given inline or on stdin.
+    pub path: Option<&'a Path>,
+    /// The error, with its offset in that file.
+    pub error: ParsingError,
+    /// Line of the offset, counted from 1.
+    pub line: usize,
+    /// Column of the offset in bytes like the offset, counted from 1.
+    pub column: usize,
+}
+
+/// A file that is being included, for cycle detection and error messages.
+struct Frame {
+    name: String,
+    canonical: Option<PathBuf>,
+}
+
+impl Source {
+    /// Loads a program given as an argument.
+    pub fn inline(text: &str) -> Result<Source, String> {
+        Source::load(None, text)
+    }
+
+    /// Loads the program in the file at `path`.
+    pub fn read(path: &Path) -> Result<Source, String> {
+        let mut text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read '{}': {e}", path.display()))?;
+        blank_header(&mut text);
+        Source::load(Some(path), &text)
+    }
+
+    /// Loads the program piped to stdin, read to its end. Bytes that are not
+    /// UTF-8 become replacement characters, which are comments like any
+    /// other character that is not a command.
+    pub fn stdin() -> Result<Source, String> {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("cannot read the program from stdin: {e}"))?;
+        let mut text = String::from_utf8_lossy(&bytes).into_owned();
+        blank_header(&mut text);
+        Source::load(None, &text)
+    }
+
+    /// The file the program was read from, unless it was given inline.
+    pub fn path(&self) -> Option<&Path> {
+        self.files[0].as_deref()
+    }
+
+    fn load(path: Option<&Path>, text: &str) -> Result<Source, String> {
+        let mut source = Source {
+            text: String::new(),
+            files: Vec::new(),
+            line_starts: Vec::new(),
+            runs: Vec::new(),
+            map: SourceMap::new(),
+        };
+        let frame = Frame {
+            name: path.map_or("<program>".into(), |path| path.display().to_string()),
+            canonical: path.and_then(|path| path.canonicalize().ok()),
+        };
+        source.include(path, text, &mut vec![frame])?;
+        Ok(source)
+    }
+
+    /// Appends `text`, read from `path`, with its includes resolved.
+    /// `chain` ends with the frame for `path`.
+    fn include(
+        &mut self,
+        path: Option<&Path>,
+        text: &str,
+        chain: &mut Vec<Frame>,
+    ) -> Result<(), String> {
+        let file = self.files.len();
+        self.files.push(path.map(Path::to_path_buf));
+        let lines = text.match_indices('\n').map(|(offset, _)| offset + 1);
+        self.line_starts
+            .push(std::iter::once(0).chain(lines).collect());
+        let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+        let mut copied = 0;
+        let mut search = 0;
+
+        while let Some(found) = text[search..].find("@include") {
+            let offset = search + found;
+            let after = offset + "@include".len();
+            search = after;
+            // `@includes` is not a directive, e.g. a macro with `--macros`.
+            if text[after..].starts_with(|ch: char| ch.is_ascii_alphanumeric() || ch == '_') {
+                continue;
+            }
+            let Some((name, end)) = quoted_path(&text[after..]) else {
+                return Err(format!(
+                    "expected a quoted path after '@include' at offset {offset} in {}",
+                    describe(chain)
+                ));
+            };
+
+            let included = dir.join(name);
+            let canonical = included.canonicalize().ok();
+            let frame = Frame {
+                name: included.display().to_string(),
+                canonical,
+            };
+            let cycle = chain
+                .iter()
+                .any(|open| open.canonical.is_some() && open.canonical == frame.canonical);
+            chain.push(frame);
+            if cycle {
+                return Err(format!("include cycle: {}", describe(chain)));
+            }
+            if chain.len() > MAX_INCLUDE_DEPTH {
+                return Err(format!(
+                    "includes nested more than {MAX_INCLUDE_DEPTH} deep: {}",
+                    describe(chain)
+                ));
+            }
+            let mut contents = fs::read_to_string(&included)
+                .map_err(|e| format!("cannot read {}: {e}", describe(chain)))?;
+            blank_header(&mut contents);
+
+            self.copy(file, text, copied, offset);
+            self.include(Some(&included), &contents, chain)?;
+            chain.pop();
+            copied = after + end;
+            search = copied;
+        }
+
+        self.copy(file, text, copied, text.len());
+        Ok(())
+    }
+
+    fn copy(&mut self, file: usize, text: &str, start: usize, end: usize) {
+        if start < end {
+            self.runs.push((self.text.len(), file));
+            self.map.push(self.text.len(), start);
+            self.text.push_str(&text[start..end]);
+        }
+    }
+
+    /// Finds `error`, whose offset counts bytes of [`Source::text`], in
+    /// the file it came from.
+    pub fn locate(&self, error: ParsingError) -> Location<'_> {
+        let run = self
+            .runs
+            .partition_point(|&(start, _)| start <= error.offset());
+        let file = run.checked_sub(1).map_or(0, |run| self.runs[run].1);
+        let error = self.map.map_error(error);
+        let line_starts = &self.line_starts[file];
+        let line = line_starts.partition_point(|&start| start <= error.offset());
+        Location {
+            path: self.files[file].as_deref(),
+            column: error.offset() - line_starts[line - 1] + 1,
+            line,
+            error,
+        }
+    }
+
+    /// Describes `error`, whose offset counts bytes of [`Source::text`],
+    /// with the offset in its own file and the name of that file.
+    pub fn describe_error(&self, error: ParsingError) -> String {
+        let location = self.locate(error);
+        match location.path {
+            Some(path) => format!("{} in {}", location.error, path.display()),
+            None => location.error.to_string(),
+        }
+    }
+}
+
+/// Replaces a byte-order mark and a `#!` line after it at the very start of
+/// a file with spaces, so neither the mark some editors save nor the
+/// interpreter path of an executable script is run as code, while offsets
+/// in the file stay the same.
+fn blank_header(text: &mut String) {
+    let bom = if text.starts_with('\u{feff}') {
+        text.replace_range(..'\u{feff}'.len_utf8(), "   ");
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    };
+    if text[bom..].starts_with("#!") {
+        let len = text.find('\n').unwrap_or(text.len());
+        text.replace_range(bom..len, &" ".repeat(len - bom));
+    }
+}
+
+/// Reads `"path"` after optional whitespace, and returns the path with the
+/// length of the text up to and including the closing quote.
+fn quoted_path(text: &str) -> Option<(&str, usize)> {
+    let rest = text.trim_start();
+    let start = text.len() - rest.len() + 1;
+    let rest = rest.strip_prefix('"')?;
+    let len = rest.find(['"', '\n'])?;
+    (rest[len..].starts_with('"') && len > 0).then(|| (&rest[..len], start + len + 1))
+}
+
+/// Names the files in `chain`, outermost first.
+fn describe(chain: &[Frame]) -> String {
+    let names: Vec<&str> = chain.iter().map(|frame| frame.name.as_str()).collect();
+    names.join(" -> ")
+}
@@ -0,0 +1,67 @@
+//! Reports of the instruction mix of a run, from `--stats`.
+
+use std::fmt::Write;
+
+use brainfuck_vm::InstructionStats;
+
+/// One line for every kind of command that ran, with its share of all
+/// steps, and a last line with the total.
+pub fn table(stats: &InstructionStats) -> String {
+    let total = stats.total();
+    let mut table = String::new();
+    for (name, count) in stats.counts() {
+        if count > 0 {
+            let share = count as f64 / total as f64 * 100.0;
+            let _ = writeln!(table, "{name:<13} {count:>12} {share:>6.1}%");
+        }
+    }
+    let _ = write!(table, "{:<13} {total:>12}", "total");
+    table
+}
+
+/// Every count as one JSON object, the kinds that never ran included.
+pub fn to_json(stats: &InstructionStats) -> String {
+    let counts: Vec<_> = stats
+        .counts()
+        .iter()
+        .map(|(name, count)| format!("\"{name}\":{count}"))
+        .collect();
+    format!("{{{},\"total\":{}}}", counts.join(","), stats.total())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats() -> InstructionStats {
+        InstructionStats {
+            increments: 3,
+            forward_jumps_not_taken: 1,
+            ..InstructionStats::default()
+        }
+    }
+
+    /// Test that the table leaves out commands that never ran.
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            table(&stats()),
+            "inc                      3   75.0%\n\
+             jz_not_taken             1   25.0%\n\
+             total                    4"
+        );
+        assert_eq!(
+            table(&InstructionStats::default()),
+            "total                    0"
+        );
+    }
+
+    /// Test that the JSON holds every count.
+    #[test]
+    fn test_json() {
+        let json = to_json(&stats());
+        assert!(json.starts_with("{\"inc\":3,\"dec\":0,"), "{json}");
+        assert!(json.ends_with(",\"clear_range\":0,\"total\":4}"), "{json}");
+        assert_eq!(json.matches(':').count(), 27);
+    }
+}
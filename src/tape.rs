@@ -0,0 +1,698 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::Cell;
+
+/// Enum for reasons the data pointer cannot reach a neighbouring cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeError {
+    /// The tape ends here.
+    OutOfBounds,
+    /// The tape would need room for `requested_cells` cells, which is more
+    /// than its limit allows.
+    LimitReached { requested_cells: usize },
+}
+
+/// Memory a program runs on.
+///
+/// Cells are addressed by index and read as zero until written. The
+/// movement hooks are asked before the data pointer leaves a cell and return
+/// the index of the neighbouring cell, so a backend can grow on demand. A
+/// tape that grows to the left shifts existing cells to higher indices.
+///
+/// `Vec<C>`, `Box<[C]>`, `[C; N]`, and `[C]` are tapes of fixed length for
+/// every [`Cell`] type `C`, and so are mutable references to any tape.
+pub trait Tape {
+    /// Integer type of the cells.
+    type Cell: Cell;
+
+    /// Value of the cell at `index`.
+    fn get(&self, index: usize) -> Self::Cell;
+
+    /// Stores `value` in the cell at `index`.
+    fn set(&mut self, index: usize, value: Self::Cell);
+
+    /// Adds one to the cell at `index`, wrapping around at the maximum.
+    fn inc(&mut self, index: usize) {
+        self.set(index, self.get(index).wrapping_inc());
+    }
+
+    /// Subtracts one from the cell at `index`, wrapping around at 0.
+    fn dec(&mut self, index: usize) {
+        self.set(index, self.get(index).wrapping_dec());
+    }
+
+    /// Called when `>` moves the pointer away from `index`.
+    /// Returns the index of the cell to the right.
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError>;
+
+    /// Called when `<` moves the pointer away from `index`.
+    /// Returns the index of the cell to the left.
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        index.checked_sub(1).ok_or(TapeError::OutOfBounds)
+    }
+
+    /// Sets the cells from `first` to `last`, both included, to zero.
+    ///
+    /// Lets [`Command::ClearRange`](crate::Command::ClearRange) fill them
+    /// at once; by default, they are set one at a time.
+    fn clear_cells(&mut self, first: usize, last: usize) {
+        for index in first..=last {
+            self.set(index, Self::Cell::ZERO);
+        }
+    }
+
+    /// Index of the first zero cell among `index`, `index + stride`,
+    /// `index + 2 * stride`, and so on, going left for a negative `stride`.
+    ///
+    /// Lets `[>]` and `[<]` skip to the zero without moving cell by cell.
+    /// Returns `None` if no such cell is stored yet, or if the tape cannot
+    /// search, which is the default; the pointer then moves one stride at a
+    /// time, so it fails or grows the tape at the edge as usual.
+    fn find_zero(&self, index: usize, stride: isize) -> Option<usize> {
+        let _ = (index, stride);
+        None
+    }
+
+    /// All cells, if the tape is a fixed number of them side by side.
+    ///
+    /// Lets the VM run machine code, or a loop that provably stays near its
+    /// start, with one bounds check on entry instead of one per access.
+    /// Returns `None` by default, so every access goes through the methods
+    /// above.
+    fn as_mut_slice(&mut self) -> Option<&mut [Self::Cell]> {
+        None
+    }
+}
+
+/// [`Tape::find_zero`] in contiguous cells.
+fn find_zero_in<C: Cell>(cells: &[C], index: usize, stride: isize) -> Option<usize> {
+    let step = stride.unsigned_abs();
+    match stride {
+        0 => (cells[index] == C::ZERO).then_some(index),
+        1 => C::position_zero(&cells[index..]).map(|found| index + found),
+        -1 => C::rposition_zero(&cells[..=index]),
+        _ if stride > 0 => cells[index..]
+            .iter()
+            .step_by(step)
+            .position(|&cell| cell == C::ZERO)
+            .map(|found| index + found * step),
+        _ => cells[..=index]
+            .iter()
+            .rev()
+            .step_by(step)
+            .position(|&cell| cell == C::ZERO)
+            .map(|found| index - found * step),
+    }
+}
+
+/// Implements [`Tape`] for a slice-like type with the given cell parameter.
+macro_rules! impl_slice_tape {
+    ($(impl<$c:ident $(, const $n:ident: usize)?> for $ty:ty;)*) => {$(
+        impl<$c: Cell $(, const $n: usize)?> Tape for $ty {
+            type Cell = $c;
+
+            #[inline]
+            fn get(&self, index: usize) -> $c {
+                self[index]
+            }
+
+            #[inline]
+            fn set(&mut self, index: usize, value: $c) {
+                self[index] = value;
+            }
+
+            #[inline]
+            fn inc(&mut self, index: usize) {
+                let cell = &mut self[index];
+                *cell = cell.wrapping_inc();
+            }
+
+            #[inline]
+            fn dec(&mut self, index: usize) {
+                let cell = &mut self[index];
+                *cell = cell.wrapping_dec();
+            }
+
+            #[inline]
+            fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+                if index + 1 == self.len() {
+                    return Err(TapeError::OutOfBounds);
+                }
+                Ok(index + 1)
+            }
+
+            #[inline]
+            fn clear_cells(&mut self, first: usize, last: usize) {
+                self[first..=last].fill($c::ZERO);
+            }
+
+            #[inline]
+            fn find_zero(&self, index: usize, stride: isize) -> Option<usize> {
+                find_zero_in(&self[..], index, stride)
+            }
+
+            #[inline]
+            fn as_mut_slice(&mut self) -> Option<&mut [$c]> {
+                Some(&mut self[..])
+            }
+        }
+    )*};
+}
+
+impl_slice_tape! {
+    impl<C> for Vec<C>;
+    impl<C> for Box<[C]>;
+    impl<C> for [C];
+    impl<C, const N: usize> for [C; N];
+}
+
+impl<T: Tape + ?Sized> Tape for &mut T {
+    type Cell = T::Cell;
+
+    #[inline]
+    fn get(&self, index: usize) -> T::Cell {
+        (**self).get(index)
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: T::Cell) {
+        (**self).set(index, value);
+    }
+
+    #[inline]
+    fn inc(&mut self, index: usize) {
+        (**self).inc(index);
+    }
+
+    #[inline]
+    fn dec(&mut self, index: usize) {
+        (**self).dec(index);
+    }
+
+    #[inline]
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+        (**self).move_right(index)
+    }
+
+    #[inline]
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        (**self).move_left(index)
+    }
+
+    #[inline]
+    fn clear_cells(&mut self, first: usize, last: usize) {
+        (**self).clear_cells(first, last);
+    }
+
+    #[inline]
+    fn find_zero(&self, index: usize, stride: isize) -> Option<usize> {
+        (**self).find_zero(index, stride)
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> Option<&mut [T::Cell]> {
+        (**self).as_mut_slice()
+    }
+}
+
+/// Contiguous tape that is extended with zeroed cells whenever the pointer
+/// moves past either end.
+///
+/// The length doubles on every extension, so walking in one direction costs
+/// amortized constant time. An optional limit on the cells or on their
+/// bytes turns runaway growth into [`TapeError::LimitReached`] instead of
+/// exhausting memory.
+///
+/// Cells also have a logical index relative to the cell the tape started
+/// with, which stays the same when the tape grows to the left: cell `-1` is
+/// the one left of the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrowableTape {
+    cells: Vec<u8>,
+    /// Index in `cells` of logical cell 0.
+    origin: usize,
+    limit: Option<usize>,
+}
+
+impl GrowableTape {
+    /// Creates a tape with a single zeroed cell that grows without limit.
+    pub fn new() -> Self {
+        GrowableTape {
+            cells: vec![0],
+            origin: 0,
+            limit: None,
+        }
+    }
+
+    /// Creates a tape that never grows past `limit` cells.
+    pub fn with_limit(limit: usize) -> Self {
+        GrowableTape {
+            cells: vec![0],
+            origin: 0,
+            limit: Some(limit),
+        }
+    }
+
+    /// Makes the tape stop growing before its cells take up more than
+    /// `max_tape_bytes` bytes, in addition to any cell limit.
+    pub fn with_max_tape_bytes(mut self, max_tape_bytes: usize) -> Self {
+        let limit = max_tape_bytes / size_of::<u8>();
+        self.limit = Some(self.limit.map_or(limit, |cells| cells.min(limit)));
+        self
+    }
+
+    /// Cells allocated so far, including zeroed cells the pointer has not
+    /// reached yet.
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Index in [`GrowableTape::cells`] of the cell the tape started with.
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    /// Value of the cell at a logical index; cells never reached read as zero.
+    pub fn cell(&self, logical: isize) -> u8 {
+        self.origin
+            .checked_add_signed(logical)
+            .and_then(|index| self.cells.as_slice().get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Number of cells to add to a tape of `len` cells, or an error if it is
+    /// already at its limit.
+    fn growth(&self, len: usize) -> Result<usize, TapeError> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        if len >= limit {
+            return Err(TapeError::LimitReached {
+                requested_cells: len + 1,
+            });
+        }
+        Ok(len.min(limit - len))
+    }
+}
+
+impl Default for GrowableTape {
+    fn default() -> Self {
+        GrowableTape::new()
+    }
+}
+
+impl Tape for GrowableTape {
+    type Cell = u8;
+
+    fn get(&self, index: usize) -> u8 {
+        self.cells[index]
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        self.cells[index] = value;
+    }
+
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+        let len = self.cells.len();
+        if index + 1 == len {
+            let growth = self.growth(len)?;
+            self.cells.resize(len + growth, 0);
+        }
+        Ok(index + 1)
+    }
+
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        if index > 0 {
+            return Ok(index - 1);
+        }
+
+        let growth = self.growth(self.cells.len())?;
+        self.cells.splice(0..0, core::iter::repeat_n(0, growth));
+        self.origin += growth;
+        Ok(growth - 1)
+    }
+
+    fn clear_cells(&mut self, first: usize, last: usize) {
+        self.cells[first..=last].fill(0);
+    }
+
+    fn find_zero(&self, index: usize, stride: isize) -> Option<usize> {
+        find_zero_in(&self.cells, index, stride)
+    }
+}
+
+/// Approximate bytes taken by one stored cell of a [`SparseTape`],
+/// including its share of the map nodes.
+const SPARSE_ENTRY_BYTES: usize = 32;
+
+/// Approximate bytes taken by the bookkeeping of one [`PagedTape`] page.
+const PAGE_OVERHEAD_BYTES: usize = 64;
+
+/// Tape that only stores the cells that are not zero, for programs that
+/// touch a few cells spread over a huge range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseTape {
+    cells: BTreeMap<usize, u8>,
+    /// Number of cells that may be stored.
+    max_cells: usize,
+}
+
+impl SparseTape {
+    /// Creates a tape where every cell is zero.
+    pub fn new() -> Self {
+        SparseTape {
+            cells: BTreeMap::new(),
+            max_cells: usize::MAX,
+        }
+    }
+
+    /// Limits the stored cells to roughly `max_tape_bytes` bytes.
+    ///
+    /// Since cells are only stored when written, the pointer is kept from
+    /// moving onto a cell that is not stored yet once the tape is full.
+    pub fn with_max_tape_bytes(mut self, max_tape_bytes: usize) -> Self {
+        self.max_cells = max_tape_bytes / SPARSE_ENTRY_BYTES;
+        self
+    }
+
+    /// Number of cells that are not zero.
+    pub fn stored_cells(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl Default for SparseTape {
+    fn default() -> Self {
+        SparseTape::new()
+    }
+}
+
+impl SparseTape {
+    /// Checks that the pointer may move onto the cell at `index`.
+    fn reserve(&self, index: usize) -> Result<usize, TapeError> {
+        if self.cells.len() >= self.max_cells && !self.cells.contains_key(&index) {
+            return Err(TapeError::LimitReached {
+                requested_cells: self.cells.len() + 1,
+            });
+        }
+        Ok(index)
+    }
+}
+
+impl Tape for SparseTape {
+    type Cell = u8;
+
+    fn get(&self, index: usize) -> u8 {
+        self.cells.get(&index).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, index: usize, value: u8) {
+        if value == 0 {
+            self.cells.remove(&index);
+        } else {
+            self.cells.insert(index, value);
+        }
+    }
+
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+        self.reserve(index.checked_add(1).ok_or(TapeError::OutOfBounds)?)
+    }
+
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        self.reserve(index.checked_sub(1).ok_or(TapeError::OutOfBounds)?)
+    }
+}
+
+/// Number of cells in one page of a [`PagedTape`].
+pub const PAGE_LEN: usize = 4096;
+
+/// Tape of a fixed length that only allocates the pages of [`PAGE_LEN`]
+/// cells that hold something other than zero.
+///
+/// Unlike a `Vec`, a huge tape costs nothing until it is written to, and a
+/// program jumping millions of cells apart only pays for the pages it
+/// touches. Cells within one page sit next to each other in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PagedTape<C = u8> {
+    pages: BTreeMap<usize, Box<[C; PAGE_LEN]>>,
+    len: usize,
+    /// Number of pages that may be allocated.
+    max_pages: usize,
+}
+
+impl<C: Cell> PagedTape<C> {
+    /// Creates a tape of `len` zeroed cells without allocating any of them.
+    pub fn new(len: usize) -> Self {
+        PagedTape {
+            pages: BTreeMap::new(),
+            len,
+            max_pages: usize::MAX,
+        }
+    }
+
+    /// Limits the allocated pages to roughly `max_tape_bytes` bytes, counting
+    /// the width of `C`.
+    ///
+    /// Since pages are only allocated when written, the pointer is kept from
+    /// moving onto a page that is not allocated yet once the tape is full.
+    pub fn with_max_tape_bytes(mut self, max_tape_bytes: usize) -> Self {
+        self.max_pages = max_tape_bytes / (PAGE_LEN * size_of::<C>() + PAGE_OVERHEAD_BYTES);
+        self
+    }
+
+    /// Number of cells on the tape.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tape has no cells.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of pages allocated so far.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl<C: Cell> Tape for PagedTape<C> {
+    type Cell = C;
+
+    #[inline]
+    fn get(&self, index: usize) -> C {
+        match self.pages.get(&(index / PAGE_LEN)) {
+            Some(page) => page[index % PAGE_LEN],
+            None => C::ZERO,
+        }
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: C) {
+        let page = match self.pages.get_mut(&(index / PAGE_LEN)) {
+            Some(page) => page,
+            // Zero is already what an unallocated page reads as.
+            None if value == C::ZERO => return,
+            None => self
+                .pages
+                .entry(index / PAGE_LEN)
+                .or_insert_with(|| Box::new([C::ZERO; PAGE_LEN])),
+        };
+        page[index % PAGE_LEN] = value;
+    }
+
+    #[inline]
+    fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+        if index + 1 >= self.len {
+            return Err(TapeError::OutOfBounds);
+        }
+        if (index + 1).is_multiple_of(PAGE_LEN) {
+            self.reserve((index + 1) / PAGE_LEN)?;
+        }
+        Ok(index + 1)
+    }
+
+    #[inline]
+    fn move_left(&mut self, index: usize) -> Result<usize, TapeError> {
+        let left = index.checked_sub(1).ok_or(TapeError::OutOfBounds)?;
+        if index.is_multiple_of(PAGE_LEN) {
+            self.reserve(left / PAGE_LEN)?;
+        }
+        Ok(left)
+    }
+}
+
+impl<C> PagedTape<C> {
+    /// Checks that the pointer may move onto page number `page`.
+    fn reserve(&self, page: usize) -> Result<(), TapeError> {
+        if self.pages.len() >= self.max_pages && !self.pages.contains_key(&page) {
+            return Err(TapeError::LimitReached {
+                requested_cells: (self.pages.len() + 1) * PAGE_LEN,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, RuntimeError, Streams, Vm, compile};
+
+    /// Test that a growable tape makes room for a program that walks right.
+    #[test]
+    fn test_growable_tape() {
+        let program = compile("+>++>>+++.").unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, GrowableTape::new(), 0);
+        vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+
+        assert_eq!(output, [3]);
+        assert_eq!(vm.tape().cells(), [1, 2, 0, 3]);
+    }
+
+    /// Test walking a million cells to the right of a one-cell tape.
+    #[test]
+    fn test_growable_long_walk() {
+        let source_code = ">".repeat(1_000_000) + "+.";
+        let program = compile(&source_code).unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, GrowableTape::new(), 0);
+        vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+
+        assert_eq!(output, [1]);
+        assert!(vm.tape().cells().len() > 1_000_000);
+    }
+
+    /// Test that runaway growth stops at the limit with an error.
+    #[test]
+    fn test_growable_limit() {
+        let program = compile("+[>+]").unwrap();
+
+        let mut vm = Vm::with_tape(&program, GrowableTape::with_limit(1000), 0);
+        let result = vm.run_with(Streams::new(&[][..], Vec::new()));
+
+        assert!(matches!(
+            result,
+            Err(Error::Runtime(RuntimeError::MemoryLimitExceeded {
+                instruction_index: 2,
+                requested_cells: 1001,
+            }))
+        ));
+        assert_eq!(vm.data_pointer(), 999);
+        assert_eq!(vm.tape().cells().len(), 1000);
+    }
+
+    /// Test cells far to the left of the start, which used to need the data
+    /// pointer to start in the middle of the tape.
+    #[test]
+    fn test_growable_left() {
+        let left = "<".repeat(10_000);
+        let right = ">".repeat(10_000);
+        let source_code = format!("{left}+++{right}+{left}.{right}.");
+        let program = compile(&source_code).unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, GrowableTape::new(), 0);
+        vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+
+        assert_eq!(output, [3, 1]);
+        let tape = vm.into_tape();
+        assert_eq!(tape.cell(-10_000), 3);
+        assert_eq!(tape.cell(0), 1);
+        assert_eq!(tape.cell(-1), 0);
+        assert_eq!(tape.cells()[tape.origin()], 1);
+    }
+
+    /// Test that a sparse tape only keeps the cells that were written.
+    #[test]
+    fn test_sparse_tape() {
+        let mut tape = SparseTape::new();
+        tape.set(10_000_000, 7);
+        tape.inc(0);
+        tape.inc(5);
+        tape.dec(5);
+
+        assert_eq!(tape.get(10_000_000), 7);
+        assert_eq!(tape.get(0), 1);
+        assert_eq!(tape.get(5), 0);
+        assert_eq!(tape.stored_cells(), 2);
+        assert_eq!(tape.move_right(10_000_000), Ok(10_000_001));
+        assert_eq!(tape.move_left(0), Err(TapeError::OutOfBounds));
+    }
+
+    /// Test that a paged tape only allocates the pages that were written.
+    #[test]
+    fn test_paged_tape() {
+        let mut tape = PagedTape::<u8>::new(20_000_000);
+        tape.set(0, 1);
+        tape.set(10_000_000, 2);
+        tape.set(10_000_001, 3);
+        tape.set(5_000_000, 0);
+
+        assert_eq!(tape.get(0), 1);
+        assert_eq!(tape.get(10_000_000), 2);
+        assert_eq!(tape.get(10_000_001), 3);
+        assert_eq!(tape.get(5_000_000), 0);
+        assert_eq!(tape.pages(), 2);
+        assert_eq!(tape.move_right(19_999_999), Err(TapeError::OutOfBounds));
+    }
+
+    /// Test running a program on a paged tape across a page boundary.
+    #[test]
+    fn test_paged_program() {
+        let source_code = ">".repeat(PAGE_LEN - 1) + "+++[>+<-]>.<<";
+        let program = compile(&source_code).unwrap();
+        let mut output = Vec::new();
+
+        let mut vm = Vm::with_tape(&program, PagedTape::<u16>::new(2 * PAGE_LEN), 0);
+        vm.run_with(Streams::new(&[][..], &mut output)).unwrap();
+
+        assert_eq!(output, [3]);
+        assert_eq!(vm.tape().get(PAGE_LEN), 3);
+        assert_eq!(vm.tape().pages(), 2);
+    }
+
+    /// Test that a 1 KiB memory limit stops a right-walking loop on every
+    /// growing backend, counting 4 bytes per `u32` cell.
+    #[test]
+    fn test_max_tape_bytes() {
+        let program = compile("+[>+]").unwrap();
+        let run = |tape: &mut dyn Tape<Cell = u8>| {
+            let mut vm = Vm::with_tape(&program, tape, 0);
+            (vm.run(), vm.data_pointer())
+        };
+        let limit = |requested_cells| {
+            Err(RuntimeError::MemoryLimitExceeded {
+                instruction_index: 2,
+                requested_cells,
+            })
+        };
+
+        let mut growable = GrowableTape::new().with_max_tape_bytes(1024);
+        assert_eq!(run(&mut growable), (limit(1025), 1023));
+        assert_eq!(growable.cells().len(), 1024);
+
+        let mut sparse = SparseTape::new().with_max_tape_bytes(1024);
+        assert_eq!(run(&mut sparse), (limit(33), 31));
+        assert_eq!(sparse.stored_cells(), 32);
+
+        // A page of u32 cells takes 16 KiB, so 40 KiB fit two pages.
+        let mut vm = Vm::with_tape(
+            &program,
+            PagedTape::<u32>::new(1 << 20).with_max_tape_bytes(40 * 1024),
+            0,
+        );
+        assert_eq!(vm.run(), limit(3 * PAGE_LEN));
+        assert_eq!(vm.data_pointer(), 2 * PAGE_LEN - 1);
+        assert_eq!(vm.tape().pages(), 2);
+
+        let mut paged = PagedTape::<u8>::new(1 << 20).with_max_tape_bytes(40 * 1024);
+        assert_eq!(run(&mut paged), (limit(10 * PAGE_LEN), 9 * PAGE_LEN - 1));
+    }
+}
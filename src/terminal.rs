@@ -0,0 +1,151 @@
+//! Raw terminal mode for `--raw`, so `,` sees every key as it is pressed.
+
+use std::io::{self, IsTerminal};
+
+/// Keeps the terminal in raw mode and restores the previous settings when
+/// dropped, even while unwinding from a panic. Ctrl-C restores them too
+/// before the process is killed.
+pub struct RawMode(());
+
+impl RawMode {
+    /// Turns off line buffering and echo for stdin.
+    /// Returns `None` and changes nothing if stdin is not a terminal.
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        if !io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        platform::enable()?;
+        Ok(Some(RawMode(())))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        platform::restore();
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::sync::OnceLock;
+
+    /// Settings before raw mode, also read by the signal handler.
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+    const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+    pub fn enable() -> io::Result<()> {
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: `tcgetattr` fills the struct when it succeeds.
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+        let original = *ORIGINAL.get_or_init(|| original);
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        let handler = restore_and_reraise as extern "C" fn(libc::c_int);
+        // SAFETY: the handler only calls async-signal-safe functions.
+        unsafe {
+            for signal in SIGNALS {
+                libc::signal(signal, handler as libc::sighandler_t);
+            }
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn restore() {
+        // SAFETY: plain libc calls with valid arguments.
+        unsafe {
+            if let Some(original) = ORIGINAL.get() {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+            for signal in SIGNALS {
+                libc::signal(signal, libc::SIG_DFL);
+            }
+        }
+    }
+
+    /// Restores the terminal, then lets the signal kill the process as usual.
+    extern "C" fn restore_and_reraise(signal: libc::c_int) {
+        restore();
+        // SAFETY: `raise` is async-signal-safe.
+        unsafe {
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+    use windows_sys::Win32::System::Console::{
+        ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, GetConsoleMode, GetStdHandle, STD_INPUT_HANDLE,
+        SetConsoleCtrlHandler, SetConsoleMode,
+    };
+
+    /// Console mode before raw mode, also read by the Ctrl-C handler.
+    static ORIGINAL: AtomicU32 = AtomicU32::new(0);
+
+    pub fn enable() -> io::Result<()> {
+        let mut mode = 0;
+        // SAFETY: plain console calls on the stdin handle.
+        unsafe {
+            let stdin = GetStdHandle(STD_INPUT_HANDLE);
+            if GetConsoleMode(stdin, &mut mode) == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+            ORIGINAL.store(mode, Ordering::SeqCst);
+            SetConsoleCtrlHandler(Some(on_ctrl), TRUE);
+            if SetConsoleMode(stdin, mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT)) == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn restore() {
+        // SAFETY: plain console calls on the stdin handle.
+        unsafe {
+            SetConsoleMode(
+                GetStdHandle(STD_INPUT_HANDLE),
+                ORIGINAL.load(Ordering::SeqCst),
+            );
+            SetConsoleCtrlHandler(Some(on_ctrl), FALSE);
+        }
+    }
+
+    /// Restores the console, then lets the default handler end the process.
+    unsafe extern "system" fn on_ctrl(_: u32) -> BOOL {
+        restore();
+        FALSE
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::io;
+
+    pub fn enable() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "raw mode is not supported on this platform",
+        ))
+    }
+
+    pub fn restore() {}
+}
@@ -0,0 +1,645 @@
+use alloc::string::{String, ToString};
+use core::fmt::{self, Write};
+use core::mem::size_of;
+
+use crate::{Cell, Command, EofBehavior, Interpreter, IoMode, JumpError, OverflowPolicy, validate};
+
+/// Enum for programs or settings that [`to_c`], [`to_rust`], and
+/// [`to_wasm`](crate::to_wasm) cannot translate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranspileError {
+    /// The jump addresses are inconsistent, see [`validate`].
+    Jump(JumpError),
+    /// The command at `address` is a `#` or a pbrain procedure command,
+    /// which have no translation.
+    UnsupportedCommand { address: usize },
+    /// The interpreter does not use [`IoMode::Bytes`].
+    UnsupportedIoMode,
+    /// The tape does not fit into the 4 GiB of a WebAssembly memory.
+    TapeTooLong,
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranspileError::Jump(e) => e.fmt(f),
+            TranspileError::UnsupportedCommand { address } => {
+                write!(f, "command {address} cannot be translated")
+            }
+            TranspileError::UnsupportedIoMode => f.write_str("only byte I/O can be translated"),
+            TranspileError::TapeTooLong => f.write_str("the tape does not fit into wasm memory"),
+        }
+    }
+}
+
+impl core::error::Error for TranspileError {}
+
+impl From<JumpError> for TranspileError {
+    fn from(error: JumpError) -> Self {
+        TranspileError::Jump(error)
+    }
+}
+
+/// Helpers shared by every generated program. `TAPE_LEN`, `cell`, `ucell`,
+/// `CELL_MIN`, and `CELL_MAX` are defined before them.
+const HELPERS: &str = r#"static cell tape[TAPE_LEN]INIT;
+
+#ifdef __GNUC__
+#define NORETURN __attribute__((noreturn))
+#else
+#define NORETURN
+#endif
+
+/* Output is flushed before the error goes to stderr, like the interpreter. */
+NORETURN static inline void out_of_tape(long long pointer, unsigned long long instruction) {
+    fflush(stdout);
+    fprintf(stderr, "runtime error: data pointer moved out of the tape to cell %lld at instruction %llu\n", pointer, instruction);
+    exit(1);
+}
+
+NORETURN static inline void overflowed(unsigned long long instruction) {
+    fflush(stdout);
+    fprintf(stderr, "runtime error: cell overflowed at instruction %llu\n", instruction);
+    exit(1);
+}
+
+/* Index of the cell `offset` away from `p`; leaving the tape fails at the
+   first cell past its end. */
+static inline size_t at(size_t p, long long offset, unsigned long long instruction) {
+    long long next = (long long)p + offset;
+    if (next < 0) {
+        out_of_tape(-1, instruction);
+    }
+    if ((unsigned long long)next >= TAPE_LEN) {
+        out_of_tape(TAPE_LEN, instruction);
+    }
+    return (size_t)next;
+}
+
+/* Clears `len` cells from `p` on, going left for a negative `len`, and
+   returns the last of them. */
+static inline size_t clear(size_t p, long long len, unsigned long long instruction) {
+    size_t last = at(p, len > 0 ? len - 1 : len + 1, instruction);
+    size_t first = last < p ? last : p;
+    memset(&tape[first], 0, ((last < p ? p - last : last - p) + 1) * sizeof tape[0]);
+    return last;
+}
+
+static inline cell add(cell value, long long delta, unsigned long long instruction) {
+    long long sum = (long long)value + delta;
+    (void)instruction;
+ADD}
+
+static inline void output(cell value) {
+    putchar((unsigned char)value);
+}
+
+static inline void input(cell *value) {
+    fflush(stdout);
+    int byte = getchar();
+    if (byte != EOF) {
+        *value = (cell)(ucell)(unsigned char)byte;
+    } else {
+EOF    }
+}
+"#;
+
+const RANDOM: &str = r#"
+/* SplitMix64, as behind the interpreter's `?`. */
+static cell next_random(void) {
+    static uint64_t state = SEEDull;
+    state += 0x9e3779b97f4a7c15ull;
+    uint64_t z = state;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9ull;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebull;
+    return (cell)(ucell)(unsigned char)((z ^ (z >> 31)) >> 56);
+}
+"#;
+
+/// Everything a generated `main.rs` has before `main`. `TAPE_LEN`, `Cell`,
+/// `ADD`, and `EOF` are replaced with the settings of the interpreter.
+const RUST_HELPERS: &str = r#"//! Translated from Brainfuck by brainfuck_vm.
+
+// Not every program uses every helper.
+#![allow(dead_code)]
+
+use std::io::{self, BufWriter, Read, StdinLock, StdoutLock, Write};
+use std::process;
+
+const TAPE_LEN: usize = 0;
+
+type Cell = u8;
+
+/// The tape, the data pointer, and the streams of the program.
+struct Machine {
+    tape: Vec<Cell>,
+    p: usize,
+    random_state: u64,
+    input: StdinLock<'static>,
+    output: BufWriter<StdoutLock<'static>>,
+}
+
+impl Machine {
+    /// Index of the cell `offset` cells from the pointer.
+    fn at(&mut self, offset: isize, instruction: usize) -> usize {
+        match self.p.checked_add_signed(offset) {
+            Some(index) if index < TAPE_LEN => index,
+            // The pointer moves one cell at a time, so it stops at the first
+            // cell off the tape.
+            _ => {
+                let cell = if offset < 0 { -1 } else { TAPE_LEN as isize };
+                self.fail(&format!(
+                    "data pointer moved out of the tape to cell {cell} at instruction {instruction}"
+                ))
+            }
+        }
+    }
+
+    fn move_by(&mut self, offset: isize, instruction: usize) {
+        self.p = self.at(offset, instruction);
+    }
+
+    fn add(&mut self, offset: isize, delta: i64, instruction: usize) {
+        let index = self.at(offset, instruction);
+        let sum = i64::from(self.tape[index]) + delta;
+        let value = ADD;
+        self.tape[index] = value;
+    }
+
+    fn set(&mut self, offset: isize, value: Cell, instruction: usize) {
+        let index = self.at(offset, instruction);
+        self.tape[index] = value;
+    }
+
+    /// Adds the current cell times `factor` to the cell `offset` cells
+    /// away. The product wraps under every policy, as in the interpreter.
+    fn mul_add(&mut self, offset: isize, factor: i64, instruction: usize) {
+        let value = i64::from(self.tape[self.p]);
+        if value != 0 {
+            let index = self.at(offset, instruction);
+            let sum = i64::from(self.tape[index]).wrapping_add(value.wrapping_mul(factor));
+            self.tape[index] = sum as Cell;
+        }
+    }
+
+    /// Clears `len` cells from the pointer on, going left for a negative
+    /// `len`, and moves the pointer to the last of them.
+    fn clear(&mut self, len: isize, instruction: usize) {
+        let last = self.at(len - len.signum(), instruction);
+        let cells = if last < self.p { last..=self.p } else { self.p..=last };
+        self.tape[cells].fill(0);
+        self.p = last;
+    }
+
+    fn scan(&mut self, stride: isize, instruction: usize) {
+        while self.tape[self.p] != 0 {
+            self.move_by(stride, instruction);
+        }
+    }
+
+    fn write(&mut self, offset: isize, instruction: usize) -> io::Result<()> {
+        let index = self.at(offset, instruction);
+        self.output.write_all(&[self.tape[index] as u8])
+    }
+
+    /// Reads a byte into the cell `offset` cells away, flushing the output
+    /// first so prompts show up.
+    fn read(&mut self, offset: isize, instruction: usize) -> io::Result<()> {
+        let index = self.at(offset, instruction);
+        self.output.flush()?;
+        let mut byte = [0];
+        match self.input.read_exact(&mut byte) {
+            Ok(()) => self.tape[index] = byte[0] as Cell,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => EOF,
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Sets the current cell to the next byte of SplitMix64.
+    fn random(&mut self) {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        self.tape[self.p] = ((z ^ (z >> 31)) >> 56) as u8 as Cell;
+    }
+
+    /// Reports a runtime error like the interpreter and exits, after the
+    /// output written so far.
+    fn fail(&mut self, message: &str) -> ! {
+        let _ = self.output.flush();
+        eprintln!("runtime error: {message}");
+        process::exit(1);
+    }
+}
+"#;
+
+/// Translates a program into a self-contained C file that behaves like
+/// running it with `interpreter` on cells of type `C`, one of `u8`, `u16`,
+/// `u32`, and `i8`.
+///
+/// The tape is a static array of [`Interpreter::tape_len`] cells with the
+/// initial contents and data pointer baked in, loops become `while` loops,
+/// and `,` and `.` use `getchar` and `putchar` with the [`EofBehavior`] and
+/// [`OverflowPolicy`] of the interpreter. Runtime errors print the same
+/// message and exit with status 1. The step, output, and time limits are
+/// not translated.
+///
+/// Fails for programs with `#` or pbrain procedures, and unless the
+/// interpreter uses [`IoMode::Bytes`].
+pub fn to_c<C: Cell>(
+    commands: &[Command],
+    interpreter: &Interpreter,
+) -> Result<String, TranspileError> {
+    check(commands, interpreter)?;
+
+    let bits = size_of::<C>() * 8;
+    let signed = C::MINUS_ONE.to_i64() < 0;
+    let (min, max) = if signed {
+        (-(1_i64 << (bits - 1)), (1_i64 << (bits - 1)) - 1)
+    } else {
+        (0, (1_i64 << bits) - 1)
+    };
+    let uses_random = commands.contains(&Command::Random);
+
+    let mut code = String::new();
+    let _ = write!(
+        code,
+        "/* Translated from Brainfuck by brainfuck_vm. */\n\
+        #include <stdint.h>\n\
+        #include <stdio.h>\n\
+        #include <stdlib.h>\n\
+        #include <string.h>\n\n\
+        #define TAPE_LEN {}ull\n\
+        #define CELL_MIN ({min}ll)\n\
+        #define CELL_MAX {max}ll\n\
+        typedef {}int{bits}_t cell;\n\
+        typedef uint{bits}_t ucell;\n\n",
+        interpreter.tape_len(),
+        if signed { "" } else { "u" },
+    );
+
+    let (offset, data) = interpreter.tape_init();
+    let mut init = String::new();
+    if !data.is_empty() {
+        let _ = write!(init, " = {{[{offset}] =");
+        for (index, &byte) in data.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let _ = write!(init, "{separator} {}", C::from_byte(byte).to_i64());
+        }
+        init.push_str(" }");
+    }
+    let add = match interpreter.overflow_policy() {
+        OverflowPolicy::Wrap => "    return (cell)(ucell)sum;\n",
+        OverflowPolicy::Saturate => {
+            "    return sum < CELL_MIN ? CELL_MIN : sum > CELL_MAX ? CELL_MAX : sum;\n"
+        }
+        OverflowPolicy::Error => {
+            "    if (sum < CELL_MIN || sum > CELL_MAX) {\n\
+            \x20       overflowed(instruction);\n\
+            \x20   }\n\
+            \x20   return sum;\n"
+        }
+    };
+    let eof = match interpreter.eof_behavior() {
+        EofBehavior::SetZero => String::from("        *value = 0;\n"),
+        EofBehavior::SetMinusOne => {
+            alloc::format!("        *value = {};\n", C::MINUS_ONE.to_i64())
+        }
+        EofBehavior::Unchanged => String::new(),
+    };
+    code.push_str(
+        &HELPERS
+            .replace("INIT", &init)
+            .replace("ADD", add)
+            .replace("EOF    }", &(eof + "    }")),
+    );
+    if uses_random {
+        let seed = interpreter.seed().to_string();
+        code.push_str(&RANDOM.replace("SEED", &seed));
+    }
+
+    let _ = writeln!(
+        code,
+        "\nint main(void) {{\n    size_t p = {};",
+        interpreter.data_pointer()
+    );
+    let from_byte = |byte| C::from_byte(byte).to_i64();
+    push_lines(&mut code, commands, |code, address, command| {
+        push_c_statement(code, address, command, from_byte)
+    })?;
+    code.push_str("    fflush(stdout);\n    return 0;\n}\n");
+    Ok(code)
+}
+
+/// Translates a program into a dependency-free `main.rs` that behaves like
+/// running it with `interpreter` on cells of type `C`, one of `u8`, `u16`,
+/// `u32`, and `i8`.
+///
+/// The program is best translated after [`optimize`](crate::optimize):
+/// every command becomes one line, so a folded `Add`, `Set`, or `MulAdd`
+/// reads far better than the runs and loops it replaces. The tape is a
+/// `Vec` of [`Interpreter::tape_len`] cells, I/O goes through `std::io`,
+/// and, as in [`to_c`], the [`EofBehavior`], [`OverflowPolicy`], and
+/// runtime errors carry over while the limits do not.
+///
+/// Fails for the same programs and settings as [`to_c`].
+pub fn to_rust<C: Cell>(
+    commands: &[Command],
+    interpreter: &Interpreter,
+) -> Result<String, TranspileError> {
+    check(commands, interpreter)?;
+
+    let name = match (size_of::<C>(), C::MINUS_ONE.to_i64() < 0) {
+        (size, true) => alloc::format!("i{}", size * 8),
+        (size, false) => alloc::format!("u{}", size * 8),
+    };
+    let add = match interpreter.overflow_policy() {
+        OverflowPolicy::Wrap => "sum as Cell",
+        OverflowPolicy::Saturate => "sum.clamp(Cell::MIN.into(), Cell::MAX.into()) as Cell",
+        OverflowPolicy::Error => {
+            "match Cell::try_from(sum) {\n\
+            \x20           Ok(value) => value,\n\
+            \x20           Err(_) => self.fail(&format!(\"cell overflowed at instruction {instruction}\")),\n\
+            \x20       }"
+        }
+    };
+    let eof = match interpreter.eof_behavior() {
+        EofBehavior::SetZero => String::from("self.tape[index] = 0"),
+        EofBehavior::SetMinusOne => alloc::format!("self.tape[index] = {}", C::MINUS_ONE.to_i64()),
+        EofBehavior::Unchanged => String::from("{}"),
+    };
+    let mut code = RUST_HELPERS
+        .replace(
+            "TAPE_LEN: usize = 0",
+            &alloc::format!("TAPE_LEN: usize = {}", interpreter.tape_len()),
+        )
+        .replace("Cell = u8", &alloc::format!("Cell = {name}"))
+        .replace("ADD", add)
+        .replace("EOF", &eof);
+
+    let _ = write!(
+        code,
+        "\nfn main() -> io::Result<()> {{\n\
+        \x20   let mut m = Machine {{\n\
+        \x20       tape: vec![0; TAPE_LEN],\n\
+        \x20       p: {},\n\
+        \x20       random_state: {},\n\
+        \x20       input: io::stdin().lock(),\n\
+        \x20       output: BufWriter::new(io::stdout().lock()),\n\
+        \x20   }};\n",
+        interpreter.data_pointer(),
+        interpreter.seed(),
+    );
+    let (offset, data) = interpreter.tape_init();
+    if !data.is_empty() {
+        let _ = write!(
+            code,
+            "    m.tape[{offset}..{}].copy_from_slice(&[",
+            offset + data.len()
+        );
+        for (index, &byte) in data.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let _ = write!(code, "{separator}{}", C::from_byte(byte).to_i64());
+        }
+        code.push_str("]);\n");
+    }
+    let from_byte = |byte| C::from_byte(byte).to_i64();
+    push_lines(&mut code, commands, |code, address, command| {
+        push_rust_statement(code, address, command, from_byte)
+    })?;
+    code.push_str("    m.output.flush()\n}\n");
+    Ok(code)
+}
+
+/// Checks that the program and the settings can be translated.
+pub(crate) fn check(commands: &[Command], interpreter: &Interpreter) -> Result<(), TranspileError> {
+    validate(commands)?;
+    if interpreter.io_mode() != IoMode::Bytes {
+        return Err(TranspileError::UnsupportedIoMode);
+    }
+    Ok(())
+}
+
+/// Appends a line for every command, indented by its loop depth, with
+/// `push` appending the statement itself.
+fn push_lines(
+    code: &mut String,
+    commands: &[Command],
+    mut push: impl FnMut(&mut String, usize, &Command) -> Result<(), TranspileError>,
+) -> Result<(), TranspileError> {
+    let mut depth = 1;
+    for (address, command) in commands.iter().enumerate() {
+        if let Command::JumpBackwardIfNonZero(_) = command {
+            depth -= 1;
+        }
+        code.extend(core::iter::repeat_n("    ", depth));
+        push(code, address, command)?;
+        if let Command::JumpForwardIfZero(_) = command {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Appends the C statement for the command at `address`, where
+/// `from_byte` gives the cell value that stores a byte.
+fn push_c_statement(
+    code: &mut String,
+    address: usize,
+    command: &Command,
+    from_byte: impl Fn(u8) -> i64,
+) -> Result<(), TranspileError> {
+    use self::Command as C;
+
+    let _ = match *command {
+        C::IncrementDataPointer => writeln!(code, "p = at(p, 1, {address});"),
+        C::DecrementDataPointer => writeln!(code, "p = at(p, -1, {address});"),
+        C::MovePointer(offset) => writeln!(code, "p = at(p, {offset}, {address});"),
+        C::Increment => writeln!(code, "tape[p] = add(tape[p], 1, {address});"),
+        C::Decrement => writeln!(code, "tape[p] = add(tape[p], -1, {address});"),
+        C::Add(delta) => writeln!(code, "tape[p] = add(tape[p], {delta}, {address});"),
+        C::Set(value) => writeln!(code, "tape[p] = {};", from_byte(value)),
+        C::WriteByte => writeln!(code, "output(tape[p]);"),
+        C::ReadByte => writeln!(code, "input(&tape[p]);"),
+        C::Random => writeln!(code, "tape[p] = next_random();"),
+        C::JumpForwardIfZero(_) => writeln!(code, "while (tape[p]) {{"),
+        C::JumpBackwardIfNonZero(_) => writeln!(code, "}}"),
+        C::ScanRight(stride) => writeln!(code, "while (tape[p]) p = at(p, {stride}, {address});"),
+        C::ScanLeft(stride) => writeln!(code, "while (tape[p]) p = at(p, -{stride}, {address});"),
+        // The product wraps under every policy, as in the interpreter.
+        C::MulAdd { offset, factor } => writeln!(
+            code,
+            "if (tape[p]) {{ size_t t = at(p, {offset}, {address}); \
+            tape[t] = (cell)(ucell)(tape[t] + (long long)tape[p] * {factor}); }}"
+        ),
+        C::AddAt { offset, value } => writeln!(
+            code,
+            "{{ size_t t = at(p, {offset}, {address}); \
+            tape[t] = add(tape[t], {value}, {address}); }}"
+        ),
+        C::SetAt { offset, value } => writeln!(
+            code,
+            "tape[at(p, {offset}, {address})] = {};",
+            from_byte(value)
+        ),
+        C::OutputAt(offset) => writeln!(code, "output(tape[at(p, {offset}, {address})]);"),
+        C::InputAt(offset) => writeln!(code, "input(&tape[at(p, {offset}, {address})]);"),
+        C::ClearRange { len } => writeln!(code, "p = clear(p, {len}, {address});"),
+        C::DebugDump | C::BeginProc(_) | C::EndProc(_) | C::Call => {
+            return Err(TranspileError::UnsupportedCommand { address });
+        }
+    };
+    Ok(())
+}
+
+/// Appends the Rust statement for the command at `address`, where
+/// `from_byte` gives the cell value that stores a byte.
+fn push_rust_statement(
+    code: &mut String,
+    address: usize,
+    command: &Command,
+    from_byte: impl Fn(u8) -> i64,
+) -> Result<(), TranspileError> {
+    use self::Command as C;
+
+    let _ = match *command {
+        C::IncrementDataPointer => writeln!(code, "m.move_by(1, {address});"),
+        C::DecrementDataPointer => writeln!(code, "m.move_by(-1, {address});"),
+        C::MovePointer(offset) => writeln!(code, "m.move_by({offset}, {address});"),
+        C::Increment => writeln!(code, "m.add(0, 1, {address});"),
+        C::Decrement => writeln!(code, "m.add(0, -1, {address});"),
+        C::Add(delta) => writeln!(code, "m.add(0, {delta}, {address});"),
+        C::Set(value) => writeln!(code, "m.tape[m.p] = {};", from_byte(value)),
+        C::WriteByte => writeln!(code, "m.write(0, {address})?;"),
+        C::ReadByte => writeln!(code, "m.read(0, {address})?;"),
+        C::Random => writeln!(code, "m.random();"),
+        C::JumpForwardIfZero(_) => writeln!(code, "while m.tape[m.p] != 0 {{"),
+        C::JumpBackwardIfNonZero(_) => writeln!(code, "}}"),
+        C::ScanRight(stride) => writeln!(code, "m.scan({stride}, {address});"),
+        C::ScanLeft(stride) => writeln!(code, "m.scan(-{stride}, {address});"),
+        C::MulAdd { offset, factor } => writeln!(code, "m.mul_add({offset}, {factor}, {address});"),
+        C::AddAt { offset, value } => writeln!(code, "m.add({offset}, {value}, {address});"),
+        C::SetAt { offset, value } => {
+            writeln!(code, "m.set({offset}, {}, {address});", from_byte(value))
+        }
+        C::OutputAt(offset) => writeln!(code, "m.write({offset}, {address})?;"),
+        C::InputAt(offset) => writeln!(code, "m.read({offset}, {address})?;"),
+        C::ClearRange { len } => writeln!(code, "m.clear({len}, {address});"),
+        C::DebugDump | C::BeginProc(_) | C::EndProc(_) | C::Call => {
+            return Err(TranspileError::UnsupportedCommand { address });
+        }
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, compile_pbrain, compile_with_debug_dumps, optimize};
+
+    /// Test that the settings end up in the C code.
+    #[test]
+    fn test_settings() {
+        let interpreter = Interpreter::builder()
+            .tape_len(16)
+            .data_pointer(2)
+            .tape_init(1, [200, 1])
+            .overflow_policy(OverflowPolicy::Saturate)
+            .eof_behavior(EofBehavior::SetMinusOne)
+            .build()
+            .unwrap();
+        let c = to_c::<i8>(&compile(",[.-]").unwrap(), &interpreter).unwrap();
+        assert!(c.contains("#define TAPE_LEN 16ull\n"));
+        assert!(c.contains("typedef int8_t cell;\ntypedef uint8_t ucell;\n"));
+        assert!(c.contains("static cell tape[TAPE_LEN] = {[1] = -56, 1 };"));
+        assert!(c.contains("sum > CELL_MAX ? CELL_MAX"));
+        assert!(c.contains("        *value = -1;\n"));
+        assert!(c.contains("    size_t p = 2;\n    input(&tape[p]);\n    while (tape[p]) {\n"));
+        assert!(c.contains("        tape[p] = add(tape[p], -1, 3);\n    }\n"));
+        assert!(!c.contains("next_random"));
+
+        let c = to_c::<u32>(&compile("").unwrap(), &Interpreter::default()).unwrap();
+        assert!(c.contains("#define CELL_MAX 4294967295ll\n"));
+        assert!(c.contains("static cell tape[TAPE_LEN];"));
+
+        let interpreter = Interpreter::builder()
+            .overflow_policy(OverflowPolicy::Error)
+            .build()
+            .unwrap();
+        let code = to_rust::<u32>(&[], &interpreter).unwrap();
+        assert!(code.contains("type Cell = u32;\n"));
+        assert!(code.contains("        let value = match Cell::try_from(sum) {\n"));
+    }
+
+    /// Test the Rust translation of a folded program line by line.
+    #[test]
+    fn test_rust_snapshot() {
+        let interpreter = Interpreter::builder()
+            .tape_len(100)
+            .tape_init(1, [200])
+            .eof_behavior(EofBehavior::SetMinusOne)
+            .build()
+            .unwrap();
+        let commands = optimize(&compile(",[->+++<]>.[-]<<").unwrap(), OverflowPolicy::Wrap);
+        let code = to_rust::<i8>(&commands, &interpreter).unwrap();
+        assert!(code.contains("const TAPE_LEN: usize = 100;\n\ntype Cell = i8;\n"));
+        assert!(code.contains(
+            "            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => \
+            self.tape[index] = -1,\n"
+        ));
+        assert!(code.contains("        let value = sum as Cell;\n"));
+        let main = &code[code.find("fn main").unwrap()..];
+        assert_eq!(
+            main,
+            "fn main() -> io::Result<()> {
+    let mut m = Machine {
+        tape: vec![0; TAPE_LEN],
+        p: 0,
+        random_state: 0,
+        input: io::stdin().lock(),
+        output: BufWriter::new(io::stdout().lock()),
+    };
+    m.tape[1..2].copy_from_slice(&[-56]);
+    m.read(0, 0)?;
+    m.mul_add(1, 3, 1);
+    m.tape[m.p] = 0;
+    m.write(1, 3)?;
+    m.set(1, 0, 4);
+    m.move_by(-1, 5);
+    m.output.flush()
+}
+"
+        );
+    }
+
+    /// Test that what C cannot do is rejected.
+    #[test]
+    fn test_unsupported() {
+        let interpreter = Interpreter::default();
+        assert_eq!(
+            to_c::<u8>(&compile_with_debug_dumps("+#").unwrap(), &interpreter),
+            Err(TranspileError::UnsupportedCommand { address: 1 })
+        );
+        assert_eq!(
+            to_c::<u8>(&compile_pbrain("(+):").unwrap(), &interpreter),
+            Err(TranspileError::UnsupportedCommand { address: 0 })
+        );
+        assert_eq!(
+            to_c::<u8>(&[Command::JumpForwardIfZero(3)], &interpreter),
+            Err(TranspileError::Jump(JumpError::OutOfRange {
+                address: 0,
+                target: 3
+            }))
+        );
+        let decimal = Interpreter::builder()
+            .io_mode(IoMode::Decimal { separator: b'\n' })
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_c::<u8>(&[], &decimal),
+            Err(TranspileError::UnsupportedIoMode)
+        );
+    }
+}
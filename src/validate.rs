@@ -0,0 +1,148 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Command, CommandAddress};
+
+/// Enum for inconsistent jump addresses in a command list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JumpError {
+    /// The jump at `address` points past the end of the program.
+    OutOfRange {
+        address: CommandAddress,
+        target: CommandAddress,
+    },
+    /// The jump at `address` does not point at its matching bracket.
+    Mismatched {
+        address: CommandAddress,
+        target: CommandAddress,
+    },
+}
+
+impl fmt::Display for JumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JumpError::OutOfRange { address, target } => write!(
+                f,
+                "jump at command {address} points past the end of the program (target {target})"
+            ),
+            JumpError::Mismatched { address, target } => write!(
+                f,
+                "jump at command {address} does not point at its matching bracket (target {target})"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for JumpError {}
+
+/// Checks that every jump points at its partner and that the brackets nest.
+/// The same goes for the pbrain procedure commands, which nest with loops.
+///
+/// Programs returned by [`compile`](crate::compile) always pass; this is meant
+/// for command lists that were built by hand or loaded from elsewhere.
+pub fn validate(commands: &[Command]) -> Result<(), JumpError> {
+    use self::Command as C;
+
+    let mut brackets_stack = Vec::new();
+
+    for (address, command) in commands.iter().enumerate() {
+        match *command {
+            C::JumpForwardIfZero(target) => {
+                if target >= commands.len() {
+                    return Err(JumpError::OutOfRange { address, target });
+                }
+                if target <= address || commands[target] != C::JumpBackwardIfNonZero(address) {
+                    return Err(JumpError::Mismatched { address, target });
+                }
+                brackets_stack.push(address);
+            }
+            C::JumpBackwardIfNonZero(target) => {
+                if target >= commands.len() {
+                    return Err(JumpError::OutOfRange { address, target });
+                }
+                if brackets_stack.pop() != Some(target) {
+                    return Err(JumpError::Mismatched { address, target });
+                }
+            }
+            C::BeginProc(target) => {
+                if target >= commands.len() {
+                    return Err(JumpError::OutOfRange { address, target });
+                }
+                if target <= address || commands[target] != C::EndProc(address) {
+                    return Err(JumpError::Mismatched { address, target });
+                }
+                brackets_stack.push(address);
+            }
+            C::EndProc(target) => {
+                if target >= commands.len() {
+                    return Err(JumpError::OutOfRange { address, target });
+                }
+                if brackets_stack.pop() != Some(target) {
+                    return Err(JumpError::Mismatched { address, target });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, compile_pbrain};
+
+    /// Test that compiled programs are always valid.
+    #[test]
+    fn test_compiled_programs() {
+        for source in ["", "[]", "[[]][]", "+[->[<+>-]<]", ",[.,]"] {
+            assert_eq!(validate(&compile(source).unwrap()), Ok(()));
+        }
+        for source in ["+(-)", "+([-]):", "([()]):"] {
+            assert_eq!(validate(&compile_pbrain(source).unwrap()), Ok(()));
+        }
+    }
+
+    /// Test the errors for hand-built command lists.
+    #[test]
+    fn test_invalid_jumps() {
+        use self::Command as C;
+
+        assert_eq!(
+            validate(&[C::JumpForwardIfZero(5), C::JumpBackwardIfNonZero(0)]),
+            Err(JumpError::OutOfRange {
+                address: 0,
+                target: 5
+            })
+        );
+        assert_eq!(
+            validate(&[C::JumpForwardIfZero(1), C::Increment]),
+            Err(JumpError::Mismatched {
+                address: 0,
+                target: 1
+            })
+        );
+        assert_eq!(
+            validate(&[C::Increment, C::JumpBackwardIfNonZero(0)]),
+            Err(JumpError::Mismatched {
+                address: 1,
+                target: 0
+            })
+        );
+
+        // Both pairs agree with each other, but they cross instead of nesting.
+        assert_eq!(
+            validate(&[
+                C::JumpForwardIfZero(2),
+                C::JumpForwardIfZero(3),
+                C::JumpBackwardIfNonZero(0),
+                C::JumpBackwardIfNonZero(1),
+            ]),
+            Err(JumpError::Mismatched {
+                address: 2,
+                target: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Differential runs of a program before and after optimization.
+
+use std::fmt::Write;
+use std::mem::discriminant;
+
+use brainfuck_vm::{Cell, Command, Engine, Interpreter, RuntimeError, Status};
+
+/// Number of commands the unoptimized program may run for each input when
+/// `--max-steps` is not given.
+pub const DEFAULT_VERIFY_STEPS: u64 = 100_000_000;
+
+/// Longest input `--random-inputs` generates.
+const MAX_RANDOM_INPUT: usize = 64;
+
+/// Number of differing cells listed in a report.
+const LISTED_CELLS: usize = 8;
+
+/// Everything a run did that the optimizer has to keep.
+struct Outcome<C> {
+    /// Bytes written, with the address of the `.` that wrote each.
+    output: Vec<(u8, usize)>,
+    result: Result<(), RuntimeError>,
+    tape: Vec<C>,
+    data_pointer: usize,
+    /// Address the run stopped at.
+    instruction_pointer: usize,
+}
+
+/// Runs `plain`, the program as compiled, and `optimized` on `input`, the
+/// latter again as machine code with the `jit` feature, and describes the
+/// first way they differ, if any.
+///
+/// Returns `Err` if `plain` does not finish within the step limit of
+/// `interpreter`, or [`DEFAULT_VERIFY_STEPS`], since there is nothing to
+/// compare then.
+pub fn compare<C: Cell>(
+    plain: &[Command],
+    optimized: &[Command],
+    interpreter: &Interpreter,
+    input: &[u8],
+) -> Result<Option<String>, String> {
+    let max_steps = interpreter.max_steps().unwrap_or(DEFAULT_VERIFY_STEPS);
+    let engine = interpreter.engine();
+    let expected = execute::<C>(plain, interpreter, engine, input, max_steps).ok_or_else(|| {
+        format!("the unoptimized program did not finish within {max_steps} steps")
+    })?;
+    let runs = [
+        ("optimized", engine),
+        #[cfg(feature = "jit")]
+        ("native", Engine::Jit),
+    ];
+    for (name, engine) in runs {
+        // No optimization may take more steps than the program it came from.
+        let Some(actual) = execute::<C>(optimized, interpreter, engine, input, max_steps) else {
+            return Ok(Some(format!(
+                "the {name} program did not finish within {max_steps} steps"
+            )));
+        };
+        if let Some(report) = divergence(&expected, &actual, name) {
+            return Ok(Some(report));
+        }
+    }
+    Ok(None)
+}
+
+/// Runs `program` with `engine` on `input` for at most `max_steps`
+/// commands, or returns `None` if it takes longer.
+fn execute<C: Cell>(
+    program: &[Command],
+    interpreter: &Interpreter,
+    engine: Engine,
+    input: &[u8],
+    max_steps: u64,
+) -> Option<Outcome<C>> {
+    let mut vm = interpreter.vm_with_cells::<C>(program).with_engine(engine);
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let result = loop {
+        let fuel = max_steps.checked_sub(vm.report().steps)?;
+        match vm.run_for(fuel) {
+            Ok(Status::ProducedOutput(byte)) => {
+                output.push((byte, vm.instruction_pointer() - 1));
+            }
+            Ok(Status::NeedsInput) => match input.next() {
+                Some(&byte) => vm.provide_input(byte),
+                None => vm.provide_eof(),
+            },
+            Ok(Status::Halted) => break Ok(()),
+            Ok(Status::OutOfFuel) => return None,
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    Some(Outcome {
+        output,
+        result,
+        data_pointer: vm.data_pointer(),
+        instruction_pointer: vm.instruction_pointer(),
+        tape: vm.into_tape(),
+    })
+}
+
+/// First difference between what the unoptimized program did, `expected`,
+/// and what the optimized one, described as `name`, did, `actual`.
+fn divergence<C: Cell>(expected: &Outcome<C>, actual: &Outcome<C>, name: &str) -> Option<String> {
+    let pairs = expected.output.iter().zip(&actual.output);
+    if let Some((index, (&(want, at), &(got, optimized_at)))) = pairs
+        .enumerate()
+        .find(|(_, ((want, _), (got, _)))| want != got)
+    {
+        return Some(format!(
+            "output byte {index} differs: {want:#04x} from instruction {at} unoptimized, \
+             {got:#04x} from instruction {optimized_at} {name}"
+        ));
+    }
+    if expected.output.len() != actual.output.len() {
+        return Some(format!(
+            "the unoptimized program wrote {} bytes, the {name} one {}",
+            expected.output.len(),
+            actual.output.len()
+        ));
+    }
+
+    let same_result = match (&expected.result, &actual.result) {
+        (Ok(()), Ok(())) => true,
+        (Err(want), Err(got)) => discriminant(want) == discriminant(got),
+        _ => false,
+    };
+    if !same_result {
+        let describe = |result: &Result<(), RuntimeError>| match result {
+            Ok(()) => "halted".to_string(),
+            Err(e) => format!("failed with: {e}"),
+        };
+        return Some(format!(
+            "the unoptimized program {} at instruction {}, the {name} one {} at instruction {}",
+            describe(&expected.result),
+            expected.instruction_pointer,
+            describe(&actual.result),
+            actual.instruction_pointer
+        ));
+    }
+
+    let mut report = String::new();
+    if expected.data_pointer != actual.data_pointer {
+        let _ = writeln!(
+            report,
+            "the pointer ended on cell {} unoptimized, {} {name}",
+            expected.data_pointer, actual.data_pointer
+        );
+    }
+    let cells = expected.tape.iter().zip(&actual.tape).enumerate();
+    let differing: Vec<_> = cells.filter(|(_, (want, got))| want != got).collect();
+    if !differing.is_empty() {
+        let _ = writeln!(report, "{} cells differ at the end:", differing.len());
+    }
+    for (index, (want, got)) in differing.iter().take(LISTED_CELLS) {
+        let _ = writeln!(
+            report,
+            "  cell {index}: {} unoptimized, {} {name}",
+            want.to_i64(),
+            got.to_i64()
+        );
+    }
+    if differing.len() > LISTED_CELLS {
+        let _ = writeln!(report, "  and {} more", differing.len() - LISTED_CELLS);
+    }
+    (!report.is_empty()).then(|| report.trim_end().to_string())
+}
+
+/// `count` inputs of up to [`MAX_RANDOM_INPUT`] bytes, the same for the
+/// same `seed`.
+pub fn random_inputs(count: usize, seed: u64) -> Vec<Vec<u8>> {
+    // SplitMix64, like `?` uses.
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    (0..count)
+        .map(|_| {
+            let len = next() as usize % (MAX_RANDOM_INPUT + 1);
+            (0..len).map(|_| (next() >> 56) as u8).collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brainfuck_vm::compile;
+
+    /// Compares `plain` and `optimized` on `input` with the default settings.
+    fn check(plain: &str, optimized: &str, input: &[u8]) -> Option<String> {
+        let interpreter = Interpreter::builder().tape_len(16).build().unwrap();
+        let (plain, optimized) = (compile(plain).unwrap(), compile(optimized).unwrap());
+        compare::<u8>(&plain, &optimized, &interpreter, input).unwrap()
+    }
+
+    /// Test that the report names the first difference.
+    #[test]
+    fn test_divergence() {
+        assert_eq!(check(",[.,]", ",[.,]", b"same"), None);
+        assert_eq!(
+            check("+.++.", "+.+.", b"").as_deref(),
+            Some(
+                "output byte 1 differs: 0x03 from instruction 4 unoptimized, \
+                 0x02 from instruction 3 optimized"
+            )
+        );
+        assert_eq!(
+            check("+..", "+.", b"").as_deref(),
+            Some("the unoptimized program wrote 2 bytes, the optimized one 1")
+        );
+        assert_eq!(
+            check("+>++<[-]", "+>+++<[-]>", b"").as_deref(),
+            Some(
+                "the pointer ended on cell 0 unoptimized, 1 optimized\n\
+                 1 cells differ at the end:\n  cell 1: 2 unoptimized, 3 optimized"
+            )
+        );
+        let failed = check("<", "+", b"").unwrap();
+        assert!(
+            failed.starts_with("the unoptimized program failed with: "),
+            "{failed}"
+        );
+        assert!(failed.ends_with("the optimized one halted at instruction 1"));
+    }
+
+    /// Test that the step limit applies to the unoptimized program first.
+    #[test]
+    fn test_step_limit() {
+        let interpreter = Interpreter::builder().max_steps(10).build().unwrap();
+        let (slow, fast) = (compile("+[]").unwrap(), compile("+").unwrap());
+        assert!(compare::<u8>(&slow, &fast, &interpreter, b"").is_err());
+        assert_eq!(
+            compare::<u8>(&fast, &slow, &interpreter, b"")
+                .unwrap()
+                .as_deref(),
+            Some("the optimized program did not finish within 10 steps")
+        );
+    }
+
+    /// Test that generated inputs depend on the seed alone.
+    #[test]
+    fn test_random_inputs() {
+        let inputs = random_inputs(50, 7);
+        assert_eq!(inputs, random_inputs(50, 7));
+        assert_ne!(inputs, random_inputs(50, 8));
+        assert!(inputs.iter().all(|input| input.len() <= MAX_RANDOM_INPUT));
+        assert!(inputs.iter().any(|input| input.len() > 1));
+    }
+}
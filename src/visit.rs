@@ -0,0 +1,212 @@
+use alloc::vec::Vec;
+
+use crate::Ast;
+use crate::ast::push_run;
+
+/// Read-only traversal over an [`Ast`].
+///
+/// Every method has a default implementation, so a pass only overrides the
+/// nodes it cares about. The defaults of `visit_loop` and `visit_block` walk
+/// into the children; call [`walk_nodes`] from an override to keep descending.
+pub trait Visitor {
+    /// Dispatches a node to the matching `visit_*` method.
+    fn visit(&mut self, ast: &Ast) {
+        walk(self, ast);
+    }
+
+    fn visit_inc(&mut self, _count: u32) {}
+
+    fn visit_dec(&mut self, _count: u32) {}
+
+    fn visit_move(&mut self, _offset: i32) {}
+
+    fn visit_output(&mut self) {}
+
+    fn visit_input(&mut self) {}
+
+    fn visit_loop(&mut self, body: &[Ast]) {
+        walk_nodes(self, body);
+    }
+
+    fn visit_block(&mut self, nodes: &[Ast]) {
+        walk_nodes(self, nodes);
+    }
+}
+
+/// Calls the `visit_*` method of `visitor` matching the kind of `ast`.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast) {
+    match ast {
+        Ast::Inc(count) => visitor.visit_inc(*count),
+        Ast::Dec(count) => visitor.visit_dec(*count),
+        Ast::Move(offset) => visitor.visit_move(*offset),
+        Ast::Output => visitor.visit_output(),
+        Ast::Input => visitor.visit_input(),
+        Ast::Loop(body) => visitor.visit_loop(body),
+        Ast::Block(nodes) => visitor.visit_block(nodes),
+    }
+}
+
+/// Visits every node of a sequence in order.
+pub fn walk_nodes<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[Ast]) {
+    for node in nodes {
+        visitor.visit(node);
+    }
+}
+
+/// Rewriting traversal over an [`Ast`].
+///
+/// Each `fold_*` method receives a node by value and returns its
+/// replacement. The defaults rebuild the node unchanged, folding children
+/// bottom-up through [`Fold::fold_nodes`].
+pub trait Fold {
+    /// Dispatches a node to the matching `fold_*` method.
+    fn fold(&mut self, ast: Ast) -> Ast {
+        walk_fold(self, ast)
+    }
+
+    fn fold_inc(&mut self, count: u32) -> Ast {
+        Ast::Inc(count)
+    }
+
+    fn fold_dec(&mut self, count: u32) -> Ast {
+        Ast::Dec(count)
+    }
+
+    fn fold_move(&mut self, offset: i32) -> Ast {
+        Ast::Move(offset)
+    }
+
+    fn fold_output(&mut self) -> Ast {
+        Ast::Output
+    }
+
+    fn fold_input(&mut self) -> Ast {
+        Ast::Input
+    }
+
+    fn fold_loop(&mut self, body: Vec<Ast>) -> Ast {
+        Ast::Loop(self.fold_nodes(body))
+    }
+
+    fn fold_block(&mut self, nodes: Vec<Ast>) -> Ast {
+        Ast::Block(self.fold_nodes(nodes))
+    }
+
+    /// Folds every node of a sequence in order.
+    fn fold_nodes(&mut self, nodes: Vec<Ast>) -> Vec<Ast> {
+        nodes.into_iter().map(|node| self.fold(node)).collect()
+    }
+}
+
+/// Calls the `fold_*` method of `folder` matching the kind of `ast`.
+pub fn walk_fold<F: Fold + ?Sized>(folder: &mut F, ast: Ast) -> Ast {
+    match ast {
+        Ast::Inc(count) => folder.fold_inc(count),
+        Ast::Dec(count) => folder.fold_dec(count),
+        Ast::Move(offset) => folder.fold_move(offset),
+        Ast::Output => folder.fold_output(),
+        Ast::Input => folder.fold_input(),
+        Ast::Loop(body) => folder.fold_loop(body),
+        Ast::Block(nodes) => folder.fold_block(nodes),
+    }
+}
+
+/// Pass that normalizes a tree after other rewrites.
+///
+/// Nested blocks are spliced into their parent, empty runs are dropped, and
+/// runs that became adjacent are merged again, so the result has the same
+/// shape [`parse_ast`](crate::parse_ast) would produce for its source.
+#[derive(Debug, Default)]
+pub struct MergeRuns;
+
+impl Fold for MergeRuns {
+    fn fold_nodes(&mut self, nodes: Vec<Ast>) -> Vec<Ast> {
+        let mut merged = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            match self.fold(node) {
+                Ast::Block(inner) => inner.into_iter().for_each(|n| push_run(&mut merged, n)),
+                node => push_run(&mut merged, node),
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval, lower, parse_ast};
+
+    /// User-defined analysis: the deepest loop nesting in a program.
+    #[derive(Default)]
+    struct MaxDepth {
+        depth: usize,
+        max: usize,
+    }
+
+    impl Visitor for MaxDepth {
+        fn visit_loop(&mut self, body: &[Ast]) {
+            self.depth += 1;
+            self.max = self.max.max(self.depth);
+            walk_nodes(self, body);
+            self.depth -= 1;
+        }
+    }
+
+    /// User-defined rewrite: drops clear loops at the start of the program,
+    /// where the current cell is known to be zero.
+    #[derive(Default)]
+    struct DropLeadingClears {
+        removed: usize,
+    }
+
+    impl Fold for DropLeadingClears {
+        fn fold_block(&mut self, nodes: Vec<Ast>) -> Ast {
+            let clear = Ast::Loop(vec![Ast::Dec(1)]);
+            let total = nodes.len();
+            let kept: Vec<Ast> = nodes.into_iter().skip_while(|n| *n == clear).collect();
+            self.removed += total - kept.len();
+            Ast::Block(self.fold_nodes(kept))
+        }
+    }
+
+    /// Test measuring loop nesting with a visitor.
+    #[test]
+    fn test_max_depth() {
+        let mut visitor = MaxDepth::default();
+        visitor.visit(&parse_ast("[[-]>[[+]]]<[.]").unwrap());
+
+        assert_eq!(visitor.max, 3);
+    }
+
+    /// Test that a user-defined rewrite still lowers to a working program.
+    #[test]
+    fn test_user_rewrite() {
+        let source_code = "[-][-]++++++++[>++++++++<-]>+.";
+        let ast = parse_ast(source_code).unwrap();
+
+        let mut pass = DropLeadingClears::default();
+        let rewritten = pass.fold(ast);
+        assert_eq!(pass.removed, 2);
+        assert_eq!(rewritten, parse_ast("++++++++[>++++++++<-]>+.").unwrap());
+
+        let mut writer = Vec::new();
+        eval(&lower(&rewritten), &[][..], &mut writer).unwrap();
+        assert_eq!(writer, b"A");
+    }
+
+    /// Test that runs separated by removed nodes are merged again.
+    #[test]
+    fn test_merge_runs() {
+        let ast = Ast::Block(vec![
+            Ast::Inc(2),
+            Ast::Block(vec![Ast::Inc(1), Ast::Move(0)]),
+            Ast::Inc(3),
+            Ast::Loop(vec![Ast::Move(1), Ast::Block(vec![]), Ast::Move(2)]),
+            Ast::Move(-1),
+            Ast::Move(-1),
+        ]);
+
+        assert_eq!(MergeRuns.fold(ast), parse_ast("++++++[>>>]<<").unwrap());
+    }
+}
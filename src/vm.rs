@@ -0,0 +1,1967 @@
+use alloc::collections::BTreeMap;
+#[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::blocks::{Block, Blocks, find_blocks};
+use crate::bounds::{LoopBounds, loop_bounds};
+use crate::handler::{read_utf8, write_utf8};
+#[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+use crate::jit::{Native, Registers};
+use crate::{
+    Bytecode, Cell, Command, CommandAddress, DecimalIo, Engine, EofBehavior, Error,
+    ExecutionReport, InstructionStats, Interpreter, IoError, IoHandler, IoMode, Observer,
+    OverflowPolicy, RuntimeError, Snapshot, SnapshotError, Tape, TapeError,
+};
+
+/// Enum describing why a [`Vm`] handed control back to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// An instruction was executed and the program can continue.
+    Running,
+    /// The program is waiting on `,`; supply a byte with [`Vm::provide_input`].
+    NeedsInput,
+    /// The program executed `.`; holds the low byte of the cell.
+    ProducedOutput(u8),
+    /// The program executed `#`; see [`Vm::debug_dump`].
+    DebugDump,
+    /// The program ran past its last command.
+    Halted,
+    /// [`Vm::run_for`] used up its fuel before anything else happened.
+    OutOfFuel,
+}
+
+/// Resumable Brainfuck virtual machine.
+///
+/// Unlike [`eval_on_tape`](crate::eval_on_tape), the VM never blocks on I/O:
+/// it stops at every `,` and `.` and lets the host decide what to do.
+#[derive(Debug, Clone)]
+pub struct Vm<'a, T = Vec<u8>> {
+    code: Code<'a>,
+    /// Handler and command at every address, with [`Engine::Threaded`].
+    handlers: Vec<(Handler<'a, T>, Command)>,
+    /// Machine code of the program, with [`Engine::Jit`].
+    #[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+    native: Option<Arc<Native>>,
+    /// Cells touched by the balanced loop at every bracket, see
+    /// [`Vm::run_bounded_loop`]. Empty for bytecode.
+    bounded_loops: Vec<Option<LoopBounds>>,
+    /// Straight-line runs of commands, see [`Vm::run_block`]. Empty for
+    /// bytecode.
+    blocks: Blocks,
+    tape: T,
+    data_pointer: usize,
+    instruction_pointer: usize,
+    report: ExecutionReport,
+    overflow_policy: OverflowPolicy,
+    eof_behavior: EofBehavior,
+    io_mode: IoMode,
+    /// Address of the `(` of every pbrain procedure defined so far.
+    procedures: BTreeMap<i64, CommandAddress>,
+    /// Address of the `:` of every procedure call that has not returned.
+    call_stack: Vec<CommandAddress>,
+    max_call_depth: usize,
+    /// State of the generator behind `?`.
+    random_state: u64,
+    /// Value of the cell written by the last `.`, so serving the output
+    /// does not read the cell a second time.
+    output_value: i64,
+}
+
+/// Code of one kind of command, see [`Engine::Threaded`].
+type Handler<'a, T> = fn(&mut Vm<'a, T>) -> Result<Status, RuntimeError>;
+
+/// Program a [`Vm`] runs, in either form.
+#[derive(Debug, Clone, Copy)]
+enum Code<'a> {
+    Commands(&'a [Command]),
+    Bytecode(&'a Bytecode),
+}
+
+impl Code<'_> {
+    #[inline]
+    fn get(self, address: usize) -> Option<Command> {
+        match self {
+            Code::Commands(commands) => commands.get(address).cloned(),
+            Code::Bytecode(bytecode) => bytecode.get(address),
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Code::Commands(commands) => commands.len(),
+            Code::Bytecode(bytecode) => bytecode.len(),
+        }
+    }
+}
+
+/// Number of pbrain procedure calls that may be active at the same time,
+/// unless set with [`Vm::with_max_call_depth`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// Number of cells on each side of the data pointer in a [`DebugDump`].
+pub const DEBUG_WINDOW: usize = 4;
+
+/// Tape contents around the data pointer when a `#` executes.
+///
+/// Displays as one line with the cells in decimal, then in hex, and the
+/// current cell in brackets:
+///
+/// ```text
+/// # instruction 7, pointer 2, cells 0..5: 0 0 [72] 101 0 | 00 00 [48] 65 00
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugDump {
+    /// Index of the `#` command.
+    pub instruction_index: usize,
+    pub data_pointer: usize,
+    /// Index of the first cell in `cells`.
+    pub window_start: usize,
+    /// Values of up to [`DEBUG_WINDOW`] cells on each side of the pointer.
+    pub cells: Vec<i64>,
+    /// Size of a cell in bytes, which sets the number of hex digits.
+    pub cell_bytes: usize,
+}
+
+impl fmt::Display for DebugDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let window_end = self.window_start + self.cells.len();
+        write!(
+            f,
+            "# instruction {}, pointer {}, cells {}..{}:",
+            self.instruction_index, self.data_pointer, self.window_start, window_end
+        )?;
+        let current = self.data_pointer - self.window_start;
+        for (i, value) in self.cells.iter().enumerate() {
+            if i == current {
+                write!(f, " [{value}]")?;
+            } else {
+                write!(f, " {value}")?;
+            }
+        }
+        f.write_str(" |")?;
+        let width = self.cell_bytes * 2;
+        let mask = u64::MAX >> (64 - 8 * self.cell_bytes);
+        for (i, &value) in self.cells.iter().enumerate() {
+            let bits = value as u64 & mask;
+            if i == current {
+                write!(f, " [{bits:0width$x}]")?;
+            } else {
+                write!(f, " {bits:0width$x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Number of commands [`Vm::run_with_timeout`] executes between two looks
+/// at the clock.
+pub const CLOCK_INTERVAL: u64 = 10_000;
+
+/// Check for [`Vm::run_metered`] that fails once `timeout` has passed since
+/// it was created.
+#[cfg(feature = "std")]
+pub(crate) fn timeout_check<T>(
+    timeout: Duration,
+) -> impl FnMut(&Vm<'_, T>) -> Result<(), RuntimeError> {
+    let start = Instant::now();
+    move |vm| {
+        let elapsed = start.elapsed();
+        if elapsed < timeout {
+            return Ok(());
+        }
+        Err(RuntimeError::Timeout {
+            instruction_index: vm.instruction_pointer,
+            elapsed,
+            steps: vm.report.steps,
+        })
+    }
+}
+
+/// Resource limits for [`Vm::run_metered`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limits {
+    /// Number of commands the program may execute.
+    pub(crate) max_steps: u64,
+    /// Number of bytes the program may write.
+    pub(crate) max_output: u64,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub(crate) const NONE: Limits = Limits {
+        max_steps: u64::MAX,
+        max_output: u64::MAX,
+    };
+}
+
+impl<'a> Vm<'a> {
+    /// Creates a VM with the default [`Interpreter`] settings.
+    pub fn new(commands: &'a [Command]) -> Self {
+        Interpreter::default().vm(commands)
+    }
+}
+
+impl<'a, T: Tape> Vm<'a, T> {
+    /// Creates a VM running on the given tape.
+    pub fn with_tape(commands: &'a [Command], tape: T, data_pointer: usize) -> Self {
+        Vm::with_code(Code::Commands(commands), tape, data_pointer)
+    }
+
+    /// Same as [`Vm::with_tape`], but runs a [`pack`](crate::pack)ed program.
+    pub fn with_bytecode(bytecode: &'a Bytecode, tape: T, data_pointer: usize) -> Self {
+        Vm::with_code(Code::Bytecode(bytecode), tape, data_pointer)
+    }
+
+    fn with_code(code: Code<'a>, tape: T, data_pointer: usize) -> Self {
+        let (bounded_loops, blocks) = match code {
+            Code::Commands(commands) => (loop_bounds(commands), find_blocks(commands)),
+            Code::Bytecode(_) => (Vec::new(), Blocks::default()),
+        };
+        Vm {
+            code,
+            handlers: Vec::new(),
+            #[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+            native: None,
+            bounded_loops,
+            blocks,
+            tape,
+            data_pointer,
+            instruction_pointer: 0,
+            report: ExecutionReport::new(data_pointer),
+            overflow_policy: OverflowPolicy::Wrap,
+            eof_behavior: EofBehavior::SetZero,
+            io_mode: IoMode::Bytes,
+            procedures: BTreeMap::new(),
+            call_stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            random_state: 0,
+            output_value: 0,
+        }
+    }
+
+    /// Sets how commands are dispatched. Defaults to [`Engine::Match`].
+    ///
+    /// [`Engine::Threaded`] and [`Engine::Jit`] translate the whole program
+    /// first, so set it once, before running.
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        #[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+        {
+            self.native = match (engine, self.code) {
+                (Engine::Jit, Code::Commands(commands)) => Native::compile(commands).map(Arc::new),
+                _ => None,
+            };
+        }
+        self.handlers = match engine {
+            Engine::Match | Engine::Jit => Vec::new(),
+            Engine::Threaded => (0..self.code.len())
+                .map(|address| {
+                    let command = self.code.get(address).unwrap();
+                    (Self::handler(&command), command)
+                })
+                .collect(),
+        };
+        self
+    }
+
+    /// Sets what `+` and `-` do at the ends of the cell range.
+    /// Defaults to [`OverflowPolicy::Wrap`].
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets what [`Vm::provide_eof`] stores in the current cell.
+    /// Defaults to [`EofBehavior::SetZero`].
+    pub fn with_eof_behavior(mut self, eof_behavior: EofBehavior) -> Self {
+        self.eof_behavior = eof_behavior;
+        self
+    }
+
+    /// Sets how the `run_with` family of methods exchanges cell values with
+    /// the handler. Defaults to [`IoMode::Bytes`].
+    pub fn with_io_mode(mut self, io_mode: IoMode) -> Self {
+        self.io_mode = io_mode;
+        self
+    }
+
+    /// Sets how many pbrain procedure calls may be active at the same time
+    /// before `:` fails with [`RuntimeError::CallStackOverflow`].
+    /// Defaults to [`DEFAULT_MAX_CALL_DEPTH`].
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Sets whether the report counts how often each kind of command runs,
+    /// in [`ExecutionReport::stats`]. Defaults to `false`.
+    ///
+    /// Counting runs one command at a time, without the shortcuts for
+    /// balanced loops, so only runs that ask for it are slowed down.
+    pub fn with_stats(mut self, collect: bool) -> Self {
+        self.report.stats = collect.then(InstructionStats::default);
+        self
+    }
+
+    /// Seeds the generator that `?` draws its bytes from, so runs with the
+    /// same seed produce the same bytes. Defaults to 0.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.random_state = seed;
+        self
+    }
+
+    /// Memory tape of the program.
+    pub fn tape(&self) -> &T {
+        &self.tape
+    }
+
+    /// Index of the current cell.
+    pub fn data_pointer(&self) -> usize {
+        self.data_pointer
+    }
+
+    /// Index of the next command to execute.
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Counters collected so far.
+    pub fn report(&self) -> &ExecutionReport {
+        &self.report
+    }
+
+    /// Consumes the VM and returns its tape.
+    pub fn into_tape(self) -> T {
+        self.tape
+    }
+
+    /// Executes a single command.
+    ///
+    /// A `,` is not executed until the input arrives, so stepping while the
+    /// VM waits on input keeps returning [`Status::NeedsInput`]. After an
+    /// error the VM stays on the failing command.
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        if self.report.stats.is_some() {
+            self.step_counted()
+        } else if self.handlers.is_empty() {
+            self.step_matched()
+        } else {
+            self.step_threaded()
+        }
+    }
+
+    /// Same as [`Vm::step`], but counts the command in the stats of the
+    /// report once it has run.
+    fn step_counted(&mut self) -> Result<Status, RuntimeError> {
+        let Some(command) = self.code.get(self.instruction_pointer) else {
+            return Ok(self.halt());
+        };
+        let zero = self.tape.get(self.data_pointer) == T::Cell::ZERO;
+        let status = if self.handlers.is_empty() {
+            self.step_matched()?
+        } else {
+            self.step_threaded()?
+        };
+        if let Some(stats) = &mut self.report.stats {
+            stats.record(&command, zero);
+        }
+        Ok(status)
+    }
+
+    #[inline(always)]
+    fn step_matched(&mut self) -> Result<Status, RuntimeError> {
+        let Some(command) = self.code.get(self.instruction_pointer) else {
+            return Ok(self.halt());
+        };
+        self.execute(&command)
+    }
+
+    #[inline(always)]
+    fn step_threaded(&mut self) -> Result<Status, RuntimeError> {
+        let Some(&(handler, _)) = self.handlers.get(self.instruction_pointer) else {
+            return Ok(self.halt());
+        };
+        handler(self)
+    }
+
+    /// Handler of the [`Engine::Threaded`] table for commands like `command`.
+    ///
+    /// Every handler calls [`Vm::execute`] with a command of its own kind,
+    /// built from the operands of the one at the instruction pointer, so
+    /// once `execute` is inlined the match folds away and only the code of
+    /// that kind is left.
+    fn handler(command: &Command) -> Handler<'a, T> {
+        use self::Command as C;
+
+        macro_rules! handler {
+            ($pattern:pat => $command:expr) => {
+                |vm| {
+                    let $pattern = vm.handlers[vm.instruction_pointer].1 else {
+                        unreachable!("handlers only get commands of their kind")
+                    };
+                    vm.execute(&$command)
+                }
+            };
+        }
+
+        match command {
+            C::IncrementDataPointer => |vm| vm.execute(&C::IncrementDataPointer),
+            C::DecrementDataPointer => |vm| vm.execute(&C::DecrementDataPointer),
+            C::Increment => |vm| vm.execute(&C::Increment),
+            C::Decrement => |vm| vm.execute(&C::Decrement),
+            C::WriteByte => |vm| vm.execute(&C::WriteByte),
+            C::ReadByte => |vm| vm.execute(&C::ReadByte),
+            C::DebugDump => |vm| vm.execute(&C::DebugDump),
+            C::Call => |vm| vm.execute(&C::Call),
+            C::Random => |vm| vm.execute(&C::Random),
+            C::JumpForwardIfZero(_) => {
+                handler!(C::JumpForwardIfZero(address) => C::JumpForwardIfZero(address))
+            }
+            C::JumpBackwardIfNonZero(_) => {
+                handler!(C::JumpBackwardIfNonZero(address) => C::JumpBackwardIfNonZero(address))
+            }
+            C::BeginProc(_) => handler!(C::BeginProc(end) => C::BeginProc(end)),
+            C::EndProc(_) => handler!(C::EndProc(start) => C::EndProc(start)),
+            C::Add(_) => handler!(C::Add(delta) => C::Add(delta)),
+            C::MovePointer(_) => handler!(C::MovePointer(offset) => C::MovePointer(offset)),
+            C::Set(_) => handler!(C::Set(value) => C::Set(value)),
+            C::MulAdd { .. } => handler!(
+                C::MulAdd { offset, factor } => C::MulAdd { offset, factor }
+            ),
+            C::ScanRight(_) => handler!(C::ScanRight(stride) => C::ScanRight(stride)),
+            C::ScanLeft(_) => handler!(C::ScanLeft(stride) => C::ScanLeft(stride)),
+            C::AddAt { .. } => handler!(C::AddAt { offset, value } => C::AddAt { offset, value }),
+            C::SetAt { .. } => handler!(C::SetAt { offset, value } => C::SetAt { offset, value }),
+            C::OutputAt(_) => handler!(C::OutputAt(offset) => C::OutputAt(offset)),
+            C::InputAt(_) => handler!(C::InputAt(offset) => C::InputAt(offset)),
+            C::ClearRange { .. } => handler!(C::ClearRange { len } => C::ClearRange { len }),
+        }
+    }
+
+    /// Executes `command`, which is the one at the instruction pointer.
+    #[inline(always)]
+    fn execute(&mut self, command: &Command) -> Result<Status, RuntimeError> {
+        use self::Command as C;
+
+        let tape = &mut self.tape;
+        let mut status = Status::Running;
+
+        match command {
+            C::IncrementDataPointer => self.move_right()?,
+            C::DecrementDataPointer => self.move_left()?,
+            C::MovePointer(offset) => self.move_by(i64::from(*offset))?,
+            C::MulAdd { offset, factor } => {
+                let value = tape.get(self.data_pointer);
+                if value != T::Cell::ZERO {
+                    let product = value.to_i64().wrapping_mul(i64::from(*factor));
+                    let index = self.cell_at(*offset)?;
+                    let target = self.tape.get(index);
+                    self.tape.set(index, target.wrapping_add_signed(product));
+                }
+            }
+            C::AddAt { offset, value } => {
+                let index = self.cell_at(*offset)?;
+                let Some(value) = self.overflow_policy.add(self.tape.get(index), *value) else {
+                    return Err(self.cell_overflow());
+                };
+                self.tape.set(index, value);
+            }
+            C::SetAt { offset, value } => {
+                let index = self.cell_at(*offset)?;
+                self.tape.set(index, T::Cell::from_byte(*value));
+            }
+            C::OutputAt(offset) => {
+                let index = self.cell_at(*offset)?;
+                self.report.bytes_written += 1;
+                let value = self.tape.get(index);
+                self.output_value = value.to_i64();
+                status = Status::ProducedOutput(value.low_byte());
+            }
+            C::InputAt(offset) => {
+                self.cell_at(*offset)?;
+                return Ok(Status::NeedsInput);
+            }
+            C::ClearRange { len } => self.clear_range(*len)?,
+            C::Increment => match self.overflow_policy {
+                OverflowPolicy::Wrap => tape.inc(self.data_pointer),
+                policy => {
+                    let Some(value) = policy.increment(tape.get(self.data_pointer)) else {
+                        return Err(self.cell_overflow());
+                    };
+                    self.tape.set(self.data_pointer, value);
+                }
+            },
+            C::Decrement => match self.overflow_policy {
+                OverflowPolicy::Wrap => tape.dec(self.data_pointer),
+                policy => {
+                    let Some(value) = policy.decrement(tape.get(self.data_pointer)) else {
+                        return Err(self.cell_overflow());
+                    };
+                    self.tape.set(self.data_pointer, value);
+                }
+            },
+            C::Add(delta) => {
+                let value = tape.get(self.data_pointer);
+                let Some(value) = self.overflow_policy.add(value, *delta) else {
+                    return Err(self.cell_overflow());
+                };
+                self.tape.set(self.data_pointer, value);
+            }
+            C::Set(value) => tape.set(self.data_pointer, T::Cell::from_byte(*value)),
+            C::WriteByte => {
+                self.report.bytes_written += 1;
+                let value = tape.get(self.data_pointer);
+                self.output_value = value.to_i64();
+                status = Status::ProducedOutput(value.low_byte());
+            }
+            C::ScanRight(stride) | C::ScanLeft(stride) => {
+                if tape.get(self.data_pointer) != T::Cell::ZERO {
+                    let stride = *stride as isize;
+                    let stride = if matches!(command, C::ScanRight(_)) {
+                        stride
+                    } else {
+                        -stride
+                    };
+                    match tape.find_zero(self.data_pointer, stride) {
+                        Some(pointer) => {
+                            self.data_pointer = pointer;
+                            self.report.max_pointer = self.report.max_pointer.max(pointer);
+                            self.report.min_pointer = self.report.min_pointer.min(pointer);
+                        }
+                        // Without a zero in sight, move one stride per step
+                        // and stay on the scan until the cell is zero.
+                        None => {
+                            self.move_by(stride as i64)?;
+                            if self.tape.get(self.data_pointer) != T::Cell::ZERO {
+                                self.report.steps += 1;
+                                return Ok(status);
+                            }
+                        }
+                    }
+                }
+            }
+            C::ReadByte => return Ok(Status::NeedsInput),
+            C::DebugDump => status = Status::DebugDump,
+            C::BeginProc(end) => {
+                let procedure = tape.get(self.data_pointer).to_i64();
+                self.procedures.insert(procedure, self.instruction_pointer);
+                self.instruction_pointer = *end;
+            }
+            // Reaching a `)` outside of a call, e.g. after jumping into the
+            // body of a hand-built program, just continues.
+            C::EndProc(_) => {
+                if let Some(call) = self.call_stack.pop() {
+                    self.instruction_pointer = call;
+                }
+            }
+            C::Call => {
+                let procedure = tape.get(self.data_pointer).to_i64();
+                let Some(&start) = self.procedures.get(&procedure) else {
+                    return Err(RuntimeError::UndefinedProcedure {
+                        instruction_index: self.instruction_pointer,
+                        procedure,
+                    });
+                };
+                if self.call_stack.len() == self.max_call_depth {
+                    return Err(RuntimeError::CallStackOverflow {
+                        instruction_index: self.instruction_pointer,
+                        limit: self.max_call_depth,
+                    });
+                }
+                self.call_stack.push(self.instruction_pointer);
+                self.instruction_pointer = start;
+            }
+            C::Random => {
+                let byte = self.next_random();
+                self.tape.set(self.data_pointer, T::Cell::from_byte(byte));
+            }
+            C::JumpForwardIfZero(address) => {
+                if tape.get(self.data_pointer) == T::Cell::ZERO {
+                    self.instruction_pointer = *address;
+                }
+            }
+            C::JumpBackwardIfNonZero(address) => {
+                if tape.get(self.data_pointer) != T::Cell::ZERO {
+                    self.instruction_pointer = *address;
+                }
+            }
+        };
+
+        self.instruction_pointer += 1;
+        self.report.steps += 1;
+        Ok(status)
+    }
+
+    fn move_right(&mut self) -> Result<(), RuntimeError> {
+        self.data_pointer = match self.tape.move_right(self.data_pointer) {
+            Ok(pointer) => pointer,
+            Err(e) => return Err(self.move_error(e, 1)),
+        };
+        self.report.max_pointer = self.report.max_pointer.max(self.data_pointer);
+        Ok(())
+    }
+
+    fn move_left(&mut self) -> Result<(), RuntimeError> {
+        let pointer = match self.tape.move_left(self.data_pointer) {
+            Ok(pointer) => pointer,
+            Err(e) => return Err(self.move_error(e, -1)),
+        };
+        // Cells added on the left move everything else to the right.
+        let shift = pointer + 1 - self.data_pointer;
+        self.report.max_pointer += shift;
+        self.report.min_pointer = (self.report.min_pointer + shift).min(pointer);
+        self.data_pointer = pointer;
+        Ok(())
+    }
+
+    /// Index of the cell `offset` away from the current one. Cells the
+    /// pointer has visited are indexed directly; the rest of the way is
+    /// moved from the farthest visited cell, so the tape fails or grows as
+    /// with `>` and `<`, and the pointer stops at the edge if it fails.
+    fn cell_at(&mut self, offset: i32) -> Result<usize, RuntimeError> {
+        let (low, high) = (self.report.min_pointer, self.report.max_pointer);
+        let target = self.data_pointer as i64 + i64::from(offset);
+        if (low as i64..=high as i64).contains(&target) {
+            return Ok(target as usize);
+        }
+
+        let start = self.data_pointer;
+        let edge = if offset > 0 { high } else { low };
+        self.data_pointer = edge;
+        self.move_by(target - edge as i64)?;
+        let index = self.data_pointer;
+        self.data_pointer = if offset > 0 {
+            start
+        } else {
+            // Cells added on the left move everything else to the right.
+            start + (self.report.max_pointer - high)
+        };
+        Ok(index)
+    }
+
+    /// Clears `len` cells from the current one on, going left for a
+    /// negative `len`, and moves the pointer to the last of them. If the
+    /// tape ends first, the cells up to its edge are cleared and the
+    /// pointer stops there, as with `[-]>[-]>[-]`.
+    fn clear_range(&mut self, len: i32) -> Result<(), RuntimeError> {
+        let high = self.report.max_pointer;
+        let start = self.data_pointer;
+        let reached = self.cell_at(len - len.signum());
+        let start = if len < 0 {
+            // Cells added on the left move everything else to the right.
+            start + (self.report.max_pointer - high)
+        } else {
+            start
+        };
+        let last = match reached {
+            Ok(index) => index,
+            Err(_) => self.data_pointer,
+        };
+        self.tape.clear_cells(start.min(last), start.max(last));
+        self.data_pointer = last;
+        reached.map(|_| ())
+    }
+
+    fn move_by(&mut self, offset: i64) -> Result<(), RuntimeError> {
+        for _ in 0..offset.unsigned_abs() {
+            if offset > 0 {
+                self.move_right()?;
+            } else {
+                self.move_left()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Next byte from the generator, which is SplitMix64: small, fast, and
+    /// good enough for games, but not for anything that needs secrecy.
+    fn next_random(&mut self) -> u8 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    /// Cells around the data pointer, for the `#` that was just executed.
+    ///
+    /// The window stops at the last cell the program has visited, since a
+    /// [`Tape`] does not know its length.
+    pub fn debug_dump(&self) -> DebugDump {
+        let window_start = self.data_pointer.saturating_sub(DEBUG_WINDOW);
+        let window_end = self
+            .data_pointer
+            .saturating_add(DEBUG_WINDOW)
+            .min(self.report.max_pointer);
+        DebugDump {
+            instruction_index: self.instruction_pointer.saturating_sub(1),
+            data_pointer: self.data_pointer,
+            window_start,
+            cells: (window_start..=window_end)
+                .map(|i| self.tape.get(i).to_i64())
+                .collect(),
+            cell_bytes: size_of::<T::Cell>(),
+        }
+    }
+
+    /// Records where the program stopped in the report.
+    fn halt(&mut self) -> Status {
+        self.report.final_pointer = self.data_pointer;
+        self.report.final_cell = self.tape.get(self.data_pointer).low_byte();
+        Status::Halted
+    }
+
+    fn cell_overflow(&self) -> RuntimeError {
+        RuntimeError::CellOverflow {
+            instruction_index: self.instruction_pointer,
+        }
+    }
+
+    fn move_error(&self, error: TapeError, offset: isize) -> RuntimeError {
+        match error {
+            TapeError::OutOfBounds => RuntimeError::PointerOutOfBounds {
+                instruction_index: self.instruction_pointer,
+                pointer: self.data_pointer as isize + offset,
+            },
+            TapeError::LimitReached { requested_cells } => RuntimeError::MemoryLimitExceeded {
+                instruction_index: self.instruction_pointer,
+                requested_cells,
+            },
+        }
+    }
+
+    /// Executes commands until the program needs input, produces output, or halts.
+    /// Never returns [`Status::Running`].
+    pub fn run(&mut self) -> Result<Status, RuntimeError> {
+        loop {
+            match self.run_for(u64::MAX)? {
+                Status::OutOfFuel => continue,
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Same as [`Vm::run`], but executes at most `fuel` commands.
+    ///
+    /// Every executed command costs one unit of fuel, jumps included. A `,`
+    /// costs nothing until its input arrives through [`Vm::provide_input`],
+    /// which is not metered. Returns [`Status::OutOfFuel`] when the fuel runs
+    /// out first; calling again continues exactly where execution stopped.
+    pub fn run_for(&mut self, fuel: u64) -> Result<Status, RuntimeError> {
+        // The engine is picked once here rather than in every step.
+        let status = if self.report.stats.is_some() {
+            self.run_counted(fuel)?
+        } else if let Some(status) = self.run_native(fuel) {
+            status?
+        } else if self.handlers.is_empty() {
+            self.run_steps(fuel, Self::step_matched)?
+        } else {
+            self.run_steps(fuel, Self::step_threaded)?
+        };
+        if status != Status::Running {
+            return Ok(status);
+        }
+
+        Ok(match self.code.get(self.instruction_pointer) {
+            None => self.halt(),
+            Some(Command::ReadByte | Command::InputAt(_)) => Status::NeedsInput,
+            Some(_) => Status::OutOfFuel,
+        })
+    }
+
+    /// Calls `step` until it returns something other than
+    /// [`Status::Running`], for at most `fuel` commands. Balanced loops are
+    /// run by [`Vm::run_bounded_loop`] and straight-line code by
+    /// [`Vm::run_block`] where they can.
+    #[inline(always)]
+    fn run_steps(
+        &mut self,
+        mut fuel: u64,
+        step: impl Fn(&mut Self) -> Result<Status, RuntimeError>,
+    ) -> Result<Status, RuntimeError> {
+        while fuel > 0 {
+            if let Some(&Some(block)) = self.blocks.at.get(self.instruction_pointer)
+                && block.len as u64 <= fuel
+                && self.run_block(block)
+            {
+                fuel -= block.len as u64;
+                continue;
+            }
+            if let Some(&Some(bounds)) = self.bounded_loops.get(self.instruction_pointer) {
+                let steps = self.run_bounded_loop(bounds, fuel)?;
+                if steps > 0 {
+                    fuel -= steps;
+                    continue;
+                }
+            }
+            fuel -= 1;
+            match step(self)? {
+                Status::Running => continue,
+                status => return Ok(status),
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Same as [`Vm::run_steps`], but in the machine code of [`Engine::Jit`],
+    /// which stops at every command it does not run itself for
+    /// [`Vm::step`] to run. Nothing runs, and the result is `None`, without
+    /// machine code, or on cells it does not run on: cells of more than a
+    /// byte, cells that do not wrap, or a tape that is not one slice.
+    #[cfg(all(feature = "jit", unix, target_arch = "x86_64"))]
+    fn run_native(&mut self, mut fuel: u64) -> Option<Result<Status, RuntimeError>> {
+        let native = self.native.clone()?;
+        if self.overflow_policy != OverflowPolicy::Wrap || size_of::<T::Cell>() != 1 {
+            return None;
+        }
+        self.tape.as_mut_slice()?;
+        Some(loop {
+            if fuel == 0 {
+                break Ok(Status::Running);
+            }
+            // The native code keeps the pointer on the tape, but does not
+            // check the one it starts from.
+            if self.instruction_pointer < native.len()
+                && let Some(cells) = self.tape.as_mut_slice()
+                && self.data_pointer < cells.len()
+            {
+                let mut registers = Registers {
+                    pointer: self.data_pointer,
+                    instruction_pointer: self.instruction_pointer,
+                    fuel,
+                    low: self.report.min_pointer,
+                    high: self.report.max_pointer,
+                };
+                native.run(cells, &mut registers);
+                self.report.steps += fuel - registers.fuel;
+                fuel = registers.fuel;
+                self.data_pointer = registers.pointer;
+                self.instruction_pointer = registers.instruction_pointer;
+                self.report.min_pointer = registers.low;
+                self.report.max_pointer = registers.high;
+                if fuel == 0 {
+                    continue;
+                }
+            }
+            fuel -= 1;
+            match self.step_matched() {
+                Ok(Status::Running) => {}
+                status => break status,
+            }
+        })
+    }
+
+    #[cfg(not(all(feature = "jit", unix, target_arch = "x86_64")))]
+    #[inline(always)]
+    fn run_native(&mut self, _fuel: u64) -> Option<Result<Status, RuntimeError>> {
+        None
+    }
+
+    /// Same as [`Vm::run_steps`], but one [`Vm::step_counted`] at a time.
+    fn run_counted(&mut self, mut fuel: u64) -> Result<Status, RuntimeError> {
+        while fuel > 0 {
+            fuel -= 1;
+            match self.step_counted()? {
+                Status::Running => continue,
+                status => return Ok(status),
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Runs the whole `block` at the instruction pointer in one step, and
+    /// returns whether it did.
+    ///
+    /// Every cell the block changes gets its net change, and the pointer
+    /// its net move, with the report as if the commands had run one by
+    /// one. Nothing runs if cells do not wrap, the tape is not one slice,
+    /// or the block reaches past it, so that [`Vm::step`] fails at the
+    /// right command instead.
+    fn run_block(&mut self, block: Block) -> bool {
+        if self.overflow_policy != OverflowPolicy::Wrap {
+            return false;
+        }
+        let Some(cells) = self.tape.as_mut_slice() else {
+            return false;
+        };
+        let start = self.data_pointer as i64;
+        if start + i64::from(block.low) < 0 || start + i64::from(block.high) >= cells.len() as i64 {
+            return false;
+        }
+
+        let (first, end) = block.changes;
+        for change in &self.blocks.changes[first..end] {
+            let cell = &mut cells[(start + i64::from(change.offset)) as usize];
+            if let Some(value) = change.set {
+                *cell = T::Cell::from_byte(value);
+            }
+            *cell = cell.wrapping_add_signed(change.add);
+        }
+        let low = (start + i64::from(block.low)) as usize;
+        let high = (start + i64::from(block.high)) as usize;
+        self.report.min_pointer = self.report.min_pointer.min(low);
+        self.report.max_pointer = self.report.max_pointer.max(high);
+        self.report.steps += block.len as u64;
+        self.data_pointer = (start + i64::from(block.shift)) as usize;
+        self.instruction_pointer += block.len;
+        true
+    }
+
+    /// Runs the balanced loop with a bracket at the instruction pointer
+    /// until it ends, for at most `fuel` commands, and returns how many it
+    /// ran.
+    ///
+    /// The loop only touches the cells `bounds` away from where the pointer
+    /// is now on every pass, so once those are known to be on the tape no
+    /// access needs another check. Returns 0 without running anything if
+    /// the tape is not one slice or they are not all on it, and leaves the
+    /// loop to [`Vm::step`], which fails at the right command. Everything
+    /// else, errors included, happens exactly as with `step`.
+    fn run_bounded_loop(
+        &mut self,
+        (low, high): LoopBounds,
+        fuel: u64,
+    ) -> Result<u64, RuntimeError> {
+        use self::Command as C;
+
+        let Code::Commands(commands) = self.code else {
+            return Ok(0);
+        };
+        let policy = self.overflow_policy;
+        let wrap = policy == OverflowPolicy::Wrap;
+        let Some(cells) = self.tape.as_mut_slice() else {
+            return Ok(0);
+        };
+        let start = self.data_pointer as i64;
+        if start + i64::from(low) < 0 || start + i64::from(high) >= cells.len() as i64 {
+            return Ok(0);
+        }
+        let end = match commands[self.instruction_pointer] {
+            C::JumpForwardIfZero(end) => end,
+            _ => self.instruction_pointer,
+        };
+
+        // SAFETY: every index is the pointer, which moves within `bounds`
+        // of where it started, plus an offset that keeps it there, as
+        // `loop_bounds` worked out, and those cells were checked above.
+        macro_rules! cell {
+            ($index:expr) => {
+                *unsafe { cells.get_unchecked_mut($index) }
+            };
+        }
+
+        let mut ip = self.instruction_pointer;
+        let mut pointer = self.data_pointer;
+        let (mut min, mut max) = (self.report.min_pointer, self.report.max_pointer);
+        let mut steps = 0;
+        let mut overflowed = false;
+        while ip <= end && steps < fuel {
+            match &commands[ip] {
+                C::IncrementDataPointer => {
+                    pointer += 1;
+                    max = max.max(pointer);
+                }
+                C::DecrementDataPointer => {
+                    pointer -= 1;
+                    min = min.min(pointer);
+                }
+                C::MovePointer(offset) => {
+                    pointer = pointer.wrapping_add_signed(*offset as isize);
+                    min = min.min(pointer);
+                    max = max.max(pointer);
+                }
+                C::MulAdd { offset, factor } => {
+                    let value = cell!(pointer);
+                    if value != T::Cell::ZERO {
+                        let product = value.to_i64().wrapping_mul(i64::from(*factor));
+                        let index = pointer.wrapping_add_signed(*offset as isize);
+                        min = min.min(index);
+                        max = max.max(index);
+                        cell!(index) = cell!(index).wrapping_add_signed(product);
+                    }
+                }
+                C::AddAt { offset, value } => {
+                    let index = pointer.wrapping_add_signed(*offset as isize);
+                    min = min.min(index);
+                    max = max.max(index);
+                    let Some(value) = policy.add(cell!(index), *value) else {
+                        overflowed = true;
+                        break;
+                    };
+                    cell!(index) = value;
+                }
+                C::SetAt { offset, value } => {
+                    let index = pointer.wrapping_add_signed(*offset as isize);
+                    min = min.min(index);
+                    max = max.max(index);
+                    cell!(index) = T::Cell::from_byte(*value);
+                }
+                C::Increment if wrap => cell!(pointer) = cell!(pointer).wrapping_inc(),
+                C::Decrement if wrap => cell!(pointer) = cell!(pointer).wrapping_dec(),
+                C::Increment => {
+                    let Some(value) = policy.increment(cell!(pointer)) else {
+                        overflowed = true;
+                        break;
+                    };
+                    cell!(pointer) = value;
+                }
+                C::Decrement => {
+                    let Some(value) = policy.decrement(cell!(pointer)) else {
+                        overflowed = true;
+                        break;
+                    };
+                    cell!(pointer) = value;
+                }
+                C::Add(delta) => {
+                    let Some(value) = policy.add(cell!(pointer), *delta) else {
+                        overflowed = true;
+                        break;
+                    };
+                    cell!(pointer) = value;
+                }
+                C::Set(value) => cell!(pointer) = T::Cell::from_byte(*value),
+                C::ClearRange { len } => {
+                    let last = pointer.wrapping_add_signed((len - len.signum()) as isize);
+                    let cleared = pointer.min(last)..=pointer.max(last);
+                    min = min.min(last);
+                    max = max.max(last);
+                    unsafe { cells.get_unchecked_mut(cleared) }.fill(T::Cell::ZERO);
+                    pointer = last;
+                }
+                C::JumpForwardIfZero(address) => {
+                    if cell!(pointer) == T::Cell::ZERO {
+                        ip = *address;
+                    }
+                }
+                C::JumpBackwardIfNonZero(address) => {
+                    if cell!(pointer) != T::Cell::ZERO {
+                        ip = *address;
+                    }
+                }
+                // Not part of a balanced loop; `step` takes it from here.
+                _ => break,
+            }
+            ip += 1;
+            steps += 1;
+        }
+
+        self.instruction_pointer = ip;
+        self.data_pointer = pointer;
+        self.report.steps += steps;
+        self.report.min_pointer = min;
+        self.report.max_pointer = max;
+        if overflowed {
+            return Err(self.cell_overflow());
+        }
+        Ok(steps)
+    }
+
+    /// Completes a pending `,` by storing `byte`, zero-extended, in the
+    /// current cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the VM is not waiting on input.
+    pub fn provide_input(&mut self, byte: u8) {
+        self.complete_input(T::Cell::from_byte(byte));
+        self.report.bytes_read += 1;
+    }
+
+    /// Completes a pending `,` when there is no more input.
+    /// The current cell is updated according to the [`EofBehavior`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the VM is not waiting on input.
+    pub fn provide_eof(&mut self) {
+        let value = match self.eof_behavior {
+            EofBehavior::SetZero => T::Cell::ZERO,
+            EofBehavior::SetMinusOne => T::Cell::MINUS_ONE,
+            EofBehavior::Unchanged => self.tape.get(self.input_cell()),
+        };
+        self.complete_input(value);
+    }
+
+    /// Index of the cell the pending `,` stores into.
+    fn input_cell(&self) -> usize {
+        match self.code.get(self.instruction_pointer) {
+            Some(Command::ReadByte) => self.data_pointer,
+            // Stepping onto the command has made sure the cell is there.
+            Some(Command::InputAt(offset)) => {
+                self.data_pointer.wrapping_add_signed(offset as isize)
+            }
+            _ => panic!("the program is not waiting on input"),
+        }
+    }
+
+    fn complete_input(&mut self, value: T::Cell) {
+        let index = self.input_cell();
+        self.tape.set(index, value);
+        self.instruction_pointer += 1;
+        self.report.steps += 1;
+    }
+
+    /// Completes a pending `,` with the next value from `handler`.
+    fn serve_input<H: IoHandler>(&mut self, handler: &mut H) -> Result<(), IoError> {
+        let value = match self.io_mode {
+            IoMode::Bytes => handler.input_value()?,
+            IoMode::Decimal { separator } => {
+                DecimalIo::new(&mut *handler, separator).input_value()?
+            }
+            IoMode::Utf8 => read_utf8(handler)?.map(u64::from),
+        };
+        match value {
+            Some(bits) => {
+                self.complete_input(T::Cell::from_bits(bits));
+                self.report.bytes_read += 1;
+            }
+            None => self.provide_eof(),
+        }
+        Ok(())
+    }
+
+    /// Passes the cell that `.` just wrote on to `handler`.
+    fn serve_output<H: IoHandler>(&mut self, handler: &mut H) -> Result<(), Error> {
+        let value = self.output_value;
+        match self.io_mode {
+            IoMode::Bytes => handler.output_value(value)?,
+            IoMode::Decimal { separator } => {
+                DecimalIo::new(&mut *handler, separator).output_value(value)?
+            }
+            IoMode::Utf8 => {
+                let ch = u32::try_from(value).ok().and_then(char::from_u32).ok_or(
+                    RuntimeError::InvalidScalarValue {
+                        instruction_index: self.instruction_pointer - 1,
+                        value,
+                    },
+                )?;
+                write_utf8(handler, ch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the program to completion, serving I/O through `handler`.
+    pub fn run_with<H: IoHandler>(&mut self, mut handler: H) -> Result<ExecutionReport, Error> {
+        loop {
+            match self.run()? {
+                Status::Running | Status::OutOfFuel => {}
+                Status::NeedsInput => self.serve_input(&mut handler)?,
+                Status::ProducedOutput(_) => self.serve_output(&mut handler)?,
+                Status::DebugDump => handler.debug_dump(&self.debug_dump())?,
+                Status::Halted => return Ok(self.report),
+            }
+        }
+    }
+
+    /// Same as [`Vm::run_with`], but fails with
+    /// [`RuntimeError::StepLimitExceeded`] once `max_steps` commands have
+    /// executed without the program halting.
+    pub fn run_with_limit<H: IoHandler>(
+        &mut self,
+        handler: H,
+        max_steps: u64,
+    ) -> Result<ExecutionReport, Error> {
+        let limits = Limits {
+            max_steps,
+            ..Limits::NONE
+        };
+        self.run_metered(handler, limits, |_| Ok(()))
+    }
+
+    /// Same as [`Vm::run_with`], but fails with [`RuntimeError::Timeout`]
+    /// once `timeout` has passed without the program halting.
+    ///
+    /// The clock is only read every [`CLOCK_INTERVAL`] commands, and not
+    /// while the handler blocks on I/O.
+    #[cfg(feature = "std")]
+    pub fn run_with_timeout<H: IoHandler>(
+        &mut self,
+        handler: H,
+        timeout: Duration,
+    ) -> Result<ExecutionReport, Error> {
+        self.run_metered(handler, Limits::NONE, timeout_check(timeout))
+    }
+
+    /// Runs the program to completion like [`Vm::run_with`], failing once it
+    /// goes past `limits` or as soon as `check` fails. `check` is called
+    /// every [`CLOCK_INTERVAL`] commands.
+    pub(crate) fn run_metered<H: IoHandler>(
+        &mut self,
+        mut handler: H,
+        limits: Limits,
+        mut check: impl FnMut(&Self) -> Result<(), RuntimeError>,
+    ) -> Result<ExecutionReport, Error> {
+        let Limits {
+            max_steps,
+            max_output,
+        } = limits;
+        let mut output_len = 0;
+        let mut next_check = self.report.steps.saturating_add(CLOCK_INTERVAL);
+        loop {
+            if self.report.steps >= next_check {
+                check(self)?;
+                next_check = self.report.steps.saturating_add(CLOCK_INTERVAL);
+            }
+            let fuel = max_steps.min(next_check).saturating_sub(self.report.steps);
+            match self.run_for(fuel)? {
+                Status::Running => {}
+                Status::OutOfFuel if self.report.steps < max_steps => {}
+                Status::NeedsInput if self.report.steps < max_steps => {
+                    self.serve_input(&mut handler)?
+                }
+                Status::NeedsInput | Status::OutOfFuel => {
+                    return Err(RuntimeError::StepLimitExceeded {
+                        instruction_index: self.instruction_pointer,
+                        steps: max_steps,
+                    }
+                    .into());
+                }
+                Status::ProducedOutput(_) if output_len == max_output => {
+                    return Err(RuntimeError::OutputLimitExceeded {
+                        instruction_index: self.instruction_pointer - 1,
+                        limit: max_output,
+                    }
+                    .into());
+                }
+                Status::ProducedOutput(_) => {
+                    self.serve_output(&mut handler)?;
+                    output_len += 1;
+                }
+                Status::DebugDump => handler.debug_dump(&self.debug_dump())?,
+                Status::Halted => return Ok(self.report),
+            }
+        }
+    }
+
+    /// Same as [`Vm::run_with`], but calls `observer` before every command.
+    /// Stops early, without an error, when the observer breaks.
+    pub fn run_observed<H: IoHandler, O: Observer<T::Cell>>(
+        &mut self,
+        mut handler: H,
+        mut observer: O,
+    ) -> Result<ExecutionReport, Error> {
+        while let Some(command) = self.code.get(self.instruction_pointer) {
+            let cell = self.tape.get(self.data_pointer);
+            if observer
+                .on_step(self.instruction_pointer, &command, self.data_pointer, cell)
+                .is_break()
+            {
+                break;
+            }
+            match self.step()? {
+                Status::Running | Status::OutOfFuel | Status::Halted => {}
+                Status::NeedsInput => self.serve_input(&mut handler)?,
+                Status::ProducedOutput(_) => self.serve_output(&mut handler)?,
+                Status::DebugDump => handler.debug_dump(&self.debug_dump())?,
+            }
+        }
+        if self.instruction_pointer == self.code.len() {
+            self.halt();
+        }
+        Ok(self.report)
+    }
+}
+
+impl<T: Tape<Cell = u8> + AsRef<[u8]> + AsMut<[u8]>> Vm<'_, T> {
+    /// Captures the tape and both pointers so execution can continue later
+    /// with [`Vm::restore`].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tape: self.tape.as_ref().to_vec(),
+            data_pointer: self.data_pointer,
+            instruction_pointer: self.instruction_pointer,
+            program_len: self.code.len(),
+        }
+    }
+
+    /// Continues from a [`Snapshot`] of the same program.
+    ///
+    /// The snapshot tape is copied into the VM tape, so both must have the
+    /// same length. The report starts over from the restored state.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let program_len = self.code.len();
+        if snapshot.program_len != program_len {
+            return Err(SnapshotError::ProgramMismatch {
+                expected: program_len,
+                found: snapshot.program_len,
+            });
+        }
+        if snapshot.instruction_pointer > program_len {
+            return Err(SnapshotError::InstructionPointerOutOfRange {
+                instruction_pointer: snapshot.instruction_pointer,
+                program_len,
+            });
+        }
+        let tape = self.tape.as_mut();
+        if snapshot.tape.len() != tape.len() {
+            return Err(SnapshotError::TapeMismatch {
+                expected: tape.len(),
+                found: snapshot.tape.len(),
+            });
+        }
+        if snapshot.data_pointer >= tape.len() {
+            return Err(SnapshotError::DataPointerOutOfRange {
+                data_pointer: snapshot.data_pointer,
+                tape_len: tape.len(),
+            });
+        }
+
+        tape.copy_from_slice(&snapshot.tape);
+        self.data_pointer = snapshot.data_pointer;
+        self.instruction_pointer = snapshot.instruction_pointer;
+        let stats = self.report.stats.map(|_| InstructionStats::default());
+        self.report = ExecutionReport {
+            stats,
+            ..ExecutionReport::new(snapshot.data_pointer)
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Streams, compile, compile_pbrain, compile_with_debug_dumps, compile_with_random, optimize,
+    };
+
+    /// Test driving the echo program by hand.
+    #[test]
+    fn test_step_through_io() {
+        let program = compile(",.").unwrap();
+        let mut vm = Vm::new(&program);
+
+        assert_eq!(vm.step().unwrap(), Status::NeedsInput);
+        assert_eq!(vm.step().unwrap(), Status::NeedsInput);
+        vm.provide_input(b'x');
+        assert_eq!(vm.step().unwrap(), Status::ProducedOutput(b'x'));
+        assert_eq!(vm.step().unwrap(), Status::Halted);
+        assert_eq!(vm.step().unwrap(), Status::Halted);
+    }
+
+    /// Test that `#` hands the cells around the pointer to the handler, and
+    /// is skipped by plain `compile`.
+    #[test]
+    fn test_debug_dump() {
+        struct Dumps(Vec<DebugDump>);
+        impl IoHandler for Dumps {
+            fn input(&mut self) -> Result<Option<u8>, IoError> {
+                Ok(None)
+            }
+            fn output(&mut self, _: u8) -> Result<(), IoError> {
+                Ok(())
+            }
+            fn debug_dump(&mut self, dump: &DebugDump) -> Result<(), IoError> {
+                self.0.push(dump.clone());
+                Ok(())
+            }
+        }
+
+        let source = "#+>>>>>>>>++>+++#<#";
+        let program = compile_with_debug_dumps(source).unwrap();
+        let mut dumps = Dumps(Vec::new());
+        Vm::new(&program).run_with(&mut dumps).unwrap();
+
+        let lines: Vec<_> = dumps.0.iter().map(|dump| dump.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "# instruction 0, pointer 0, cells 0..1: [0] | [00]",
+                "# instruction 16, pointer 9, cells 5..10: 0 0 0 2 [3] | 00 00 00 02 [03]",
+                "# instruction 18, pointer 8, cells 4..10: 0 0 0 0 [2] 3 | 00 00 00 00 [02] 03",
+            ]
+        );
+        assert_eq!(dumps.0[1].cells, [0, 0, 0, 2, 3]);
+
+        let mut vm = Vm::with_tape(&program, vec![0_u16; 16], 0);
+        assert_eq!(vm.run().unwrap(), Status::DebugDump);
+        vm.tape.set(0, 0xfffe);
+        assert_eq!(
+            vm.debug_dump().to_string(),
+            "# instruction 0, pointer 0, cells 0..1: [65534] | [fffe]"
+        );
+
+        let plain = compile(source).unwrap();
+        let mut dumps = Dumps(Vec::new());
+        Vm::new(&plain).run_with(&mut dumps).unwrap();
+        assert!(dumps.0.is_empty());
+    }
+
+    /// Test a recursive pbrain procedure. Procedure 1 prints the letter in
+    /// the third cell, moves on to the next letter, and calls itself until
+    /// the counter in the second cell runs out.
+    #[test]
+    fn test_pbrain_recursion() {
+        let source = "+(>>.+<-[<:>]<) >+++ >>++++++++++[<++++++++++>-]<--- <<:";
+        let program = compile_pbrain(source).unwrap();
+        let mut output = Vec::new();
+
+        let report = Vm::new(&program)
+            .run_with(Streams::new(&[][..], &mut output))
+            .unwrap();
+        assert_eq!(output, b"abc");
+        assert_eq!(report.final_pointer, 0);
+
+        output.clear();
+        let error = Vm::new(&program)
+            .with_max_call_depth(2)
+            .run_with(Streams::new(&[][..], &mut output))
+            .unwrap_err();
+        assert_eq!(output, b"ab");
+        assert!(matches!(
+            error,
+            Error::Runtime(RuntimeError::CallStackOverflow {
+                instruction_index: 10,
+                limit: 2
+            })
+        ));
+    }
+
+    /// Test that procedures are keyed by the cell value when `(` runs, and
+    /// that calling an unknown number fails at the `:`.
+    #[test]
+    fn test_pbrain_procedures() {
+        // Procedure 2 doubles the next cell, procedure 3 prints it, and
+        // procedure 4, defined after the first two calls, prints it twice.
+        let source = "++(>[->++<]>[-<+>]<<)+(>.<)>+++<-:+:+(>..<):";
+        let program = compile_pbrain(source).unwrap();
+        let mut output = Vec::new();
+        Vm::new(&program)
+            .run_with(Streams::new(&[][..], &mut output))
+            .unwrap();
+        assert_eq!(output, [6, 6, 6]);
+
+        let program = compile_pbrain("+(-)+++:").unwrap();
+        assert_eq!(
+            Vm::new(&program).run(),
+            Err(RuntimeError::UndefinedProcedure {
+                instruction_index: 7,
+                procedure: 4
+            })
+        );
+    }
+
+    /// Test that `?` repeats its bytes for the same seed only.
+    #[test]
+    fn test_random() {
+        let program = compile_with_random("?.>?.>?.>?.").unwrap();
+        let run = |seed| {
+            let mut output = Vec::new();
+            Vm::new(&program)
+                .with_seed(seed)
+                .run_with(Streams::new(&[][..], &mut output))
+                .unwrap();
+            output
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        assert_ne!(run(7)[..2], run(7)[2..]);
+        assert_eq!(compile("?").unwrap(), []);
+    }
+
+    /// Test that `run` stops at every output and finally halts.
+    #[test]
+    fn test_run_until_halt() {
+        let program = compile("+++[.-]").unwrap();
+        let mut vm = Vm::new(&program);
+        let mut output = Vec::new();
+
+        loop {
+            match vm.run().unwrap() {
+                Status::ProducedOutput(byte) => output.push(byte),
+                Status::Halted => break,
+                status => panic!("unexpected status {status:?}"),
+            }
+        }
+
+        assert_eq!(output, [3, 2, 1]);
+        assert_eq!(vm.instruction_pointer(), program.len());
+    }
+
+    /// Test that the VM works on a borrowed tape.
+    #[test]
+    fn test_borrowed_tape() {
+        let program = compile("[->+<]").unwrap();
+        let mut tape = [4_u8, 1];
+
+        let mut vm = Vm::with_tape(&program, &mut tape[..], 0);
+        assert_eq!(vm.run().unwrap(), Status::Halted);
+        assert_eq!(vm.data_pointer(), 0);
+
+        assert_eq!(tape, [0, 5]);
+    }
+
+    /// Test running hello world one command at a time.
+    #[test]
+    fn test_run_for_single_steps() {
+        let program = compile(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        )
+        .unwrap();
+        let mut vm = Vm::new(&program);
+        let mut output = Vec::new();
+        let mut calls = 0;
+
+        loop {
+            calls += 1;
+            match vm.run_for(1).unwrap() {
+                Status::OutOfFuel => {}
+                Status::ProducedOutput(byte) => output.push(byte),
+                Status::Halted => break,
+                status => panic!("unexpected status {status:?}"),
+            }
+        }
+
+        assert_eq!(output, b"Hello World!\n");
+
+        // Every call but the last one executed exactly one command.
+        let mut vm = Vm::new(&program);
+        let mut steps = 0;
+        while vm.step().unwrap() != Status::Halted {
+            steps += 1;
+        }
+        assert_eq!(calls - 1, steps);
+    }
+
+    /// Test that fuel is spent exactly, including inside loops.
+    #[test]
+    fn test_run_for_exact_fuel() {
+        // Five commands up to the first decrement, then two per iteration.
+        let program = compile("+++[-]").unwrap();
+        let mut vm = Vm::new(&program);
+
+        assert_eq!(vm.run_for(0).unwrap(), Status::OutOfFuel);
+        assert_eq!(vm.instruction_pointer(), 0);
+
+        assert_eq!(vm.run_for(5).unwrap(), Status::OutOfFuel);
+        assert_eq!(vm.instruction_pointer(), 5);
+        assert_eq!(vm.tape()[vm.data_pointer()], 2);
+
+        assert_eq!(vm.run_for(1).unwrap(), Status::OutOfFuel);
+        assert_eq!(vm.instruction_pointer(), 4);
+        assert_eq!(vm.tape()[vm.data_pointer()], 2);
+
+        assert_eq!(vm.run_for(3).unwrap(), Status::OutOfFuel);
+        assert_eq!(vm.instruction_pointer(), 5);
+        assert_eq!(vm.tape()[vm.data_pointer()], 0);
+
+        assert_eq!(vm.run_for(1).unwrap(), Status::Halted);
+        assert_eq!(vm.run_for(0).unwrap(), Status::Halted);
+    }
+
+    /// Test that a pending input is reported even without fuel.
+    #[test]
+    fn test_run_for_needs_input() {
+        let program = compile("+,").unwrap();
+        let mut vm = Vm::new(&program);
+
+        assert_eq!(vm.run_for(1).unwrap(), Status::NeedsInput);
+        vm.provide_input(7);
+        assert_eq!(vm.run_for(0).unwrap(), Status::Halted);
+    }
+
+    /// Test the exact counters of a tight loop.
+    #[test]
+    fn test_report_tight_loop() {
+        // Five increments, the first `[`, then `-` and `]` for each of five iterations.
+        let program = compile("+++++[-]").unwrap();
+        let mut vm = Vm::new(&program);
+
+        assert_eq!(vm.run().unwrap(), Status::Halted);
+        assert_eq!(vm.report().steps, 16);
+
+        // The `]` jumps right past the `[`, whose check is not repeated.
+        let mut vm = Vm::new(&program);
+        vm.run_for(7).unwrap();
+        assert_eq!(vm.instruction_pointer(), 7);
+        vm.step().unwrap();
+        assert_eq!(vm.instruction_pointer(), 6);
+
+        let program = compile("+[-[-]]").unwrap();
+        let mut vm = Vm::new(&program);
+        assert_eq!(vm.run().unwrap(), Status::Halted);
+        assert_eq!(vm.report().steps, 5);
+    }
+
+    /// Test that the report covers pointer extent and I/O.
+    #[test]
+    fn test_report_io_and_pointer() {
+        let program = compile(">>,<<<,.,.").unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 10], 5);
+        let start = vm.data_pointer();
+
+        assert_eq!(vm.run().unwrap(), Status::NeedsInput);
+        vm.provide_input(1);
+        assert_eq!(vm.run().unwrap(), Status::NeedsInput);
+        vm.provide_input(2);
+        assert_eq!(vm.run().unwrap(), Status::ProducedOutput(2));
+        assert_eq!(vm.run().unwrap(), Status::NeedsInput);
+        vm.provide_eof();
+        assert_eq!(vm.run().unwrap(), Status::ProducedOutput(0));
+        assert_eq!(vm.run().unwrap(), Status::Halted);
+
+        assert_eq!(
+            *vm.report(),
+            ExecutionReport {
+                steps: 10,
+                min_pointer: start - 1,
+                max_pointer: start + 2,
+                bytes_read: 2,
+                bytes_written: 2,
+                final_pointer: start - 1,
+                final_cell: 0,
+                stats: None,
+            }
+        );
+    }
+
+    /// Test the exact instruction mix of moving a value, with both engines.
+    #[test]
+    fn test_stats() {
+        let program = compile("[->+<]").unwrap();
+        let expected = InstructionStats {
+            decrements: 3,
+            right_moves: 3,
+            increments: 3,
+            left_moves: 3,
+            forward_jumps_not_taken: 1,
+            backward_jumps_taken: 2,
+            backward_jumps_not_taken: 1,
+            ..InstructionStats::default()
+        };
+        for engine in [Engine::Match, Engine::Threaded] {
+            let mut vm = Vm::with_tape(&program, vec![3_u8, 4], 0)
+                .with_engine(engine)
+                .with_stats(true);
+            assert_eq!(vm.run().unwrap(), Status::Halted);
+            assert_eq!(vm.report().stats, Some(expected));
+            assert_eq!(expected.total(), vm.report().steps);
+            assert_eq!(vm.into_tape(), [0, 7]);
+        }
+
+        // Single steps count too, and skipped loops count as taken jumps.
+        let program = compile("[-]+.").unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 2], 0).with_stats(true);
+        vm.step().unwrap();
+        assert_eq!(vm.report().stats.unwrap().forward_jumps_taken, 1);
+        assert_eq!(vm.run().unwrap(), Status::ProducedOutput(1));
+        let stats = vm.report().stats.unwrap();
+        assert_eq!((stats.increments, stats.writes, stats.total()), (1, 1, 3));
+
+        let optimized = optimize(&compile("++[->+<]").unwrap(), OverflowPolicy::Wrap);
+        let mut vm = Vm::new(&optimized).with_stats(true);
+        vm.run().unwrap();
+        let counted: Vec<_> = vm
+            .report()
+            .stats
+            .unwrap()
+            .counts()
+            .into_iter()
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        assert_eq!(counted, [("add", 1), ("set", 1), ("mul_add", 1)]);
+        let mut vm = Vm::new(&optimized);
+        vm.run().unwrap();
+        assert_eq!(vm.report().stats, None);
+    }
+
+    /// Test that moving off either end of the tape is an error.
+    #[test]
+    fn test_pointer_out_of_bounds() {
+        let program = compile("+<").unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 2], 0);
+
+        let error = RuntimeError::PointerOutOfBounds {
+            instruction_index: 1,
+            pointer: -1,
+        };
+        assert_eq!(vm.run(), Err(error.clone()));
+        assert_eq!(vm.step(), Err(error));
+        assert_eq!(vm.instruction_pointer(), 1);
+        assert_eq!(vm.data_pointer(), 0);
+
+        let program = compile(">>").unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 2], 0);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 1,
+                pointer: 2,
+            })
+        );
+    }
+
+    /// Test that input can only be provided while the VM waits for it.
+    #[test]
+    #[should_panic(expected = "not waiting on input")]
+    fn test_unexpected_input() {
+        let program = compile(".").unwrap();
+        Vm::new(&program).provide_input(0);
+    }
+
+    /// Test pausing hello world halfway through its output and finishing it
+    /// in a fresh VM.
+    #[test]
+    fn test_snapshot_restore() {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let program = compile(source_code).unwrap();
+
+        let mut first_half = Vec::new();
+        let mut vm = Vm::new(&program);
+        while first_half.len() < 6 {
+            if let Status::ProducedOutput(byte) = vm.run().unwrap() {
+                first_half.push(byte);
+            }
+        }
+        let snapshot = vm.snapshot();
+        drop(vm);
+
+        let mut second_half = Vec::new();
+        let mut vm = Vm::new(&program);
+        vm.restore(&snapshot).unwrap();
+        vm.run_with(Streams::new(&[][..], &mut second_half))
+            .unwrap();
+
+        first_half.extend(second_half);
+        assert_eq!(first_half, b"Hello World!\n");
+    }
+
+    /// Test that snapshots of other programs or tapes are rejected.
+    #[test]
+    fn test_restore_mismatch() {
+        let program = compile("+[>+]").unwrap();
+        let mut snapshot = Vm::new(&program).snapshot();
+
+        let other = compile("+").unwrap();
+        assert_eq!(
+            Vm::new(&other).restore(&snapshot),
+            Err(SnapshotError::ProgramMismatch {
+                expected: 1,
+                found: 5,
+            })
+        );
+
+        snapshot.instruction_pointer = 6;
+        assert_eq!(
+            Vm::new(&program).restore(&snapshot),
+            Err(SnapshotError::InstructionPointerOutOfRange {
+                instruction_pointer: 6,
+                program_len: 5,
+            })
+        );
+
+        snapshot.instruction_pointer = 2;
+        let mut vm = Vm::with_tape(&program, vec![0; 2], 0);
+        assert_eq!(
+            vm.restore(&snapshot),
+            Err(SnapshotError::TapeMismatch {
+                expected: 2,
+                found: 30_000,
+            })
+        );
+        assert_eq!(vm.instruction_pointer(), 0);
+    }
+
+    /// Tape of fixed length that does not hand out its cells, so the VM
+    /// checks every access.
+    #[derive(Debug, PartialEq)]
+    struct Checked(Vec<u8>);
+
+    impl Tape for Checked {
+        type Cell = u8;
+
+        fn get(&self, index: usize) -> u8 {
+            self.0[index]
+        }
+
+        fn set(&mut self, index: usize, value: u8) {
+            self.0[index] = value;
+        }
+
+        fn move_right(&mut self, index: usize) -> Result<usize, TapeError> {
+            self.0.move_right(index)
+        }
+
+        fn find_zero(&self, index: usize, stride: isize) -> Option<usize> {
+            self.0.find_zero(index, stride)
+        }
+    }
+
+    /// Everything a run of `program` does, fed `fuel` commands at a time.
+    fn trace<T: Tape>(
+        mut vm: Vm<'_, T>,
+        fuel: u64,
+    ) -> (Vec<Result<Status, RuntimeError>>, Vm<'_, T>) {
+        let mut statuses = Vec::new();
+        loop {
+            let status = vm.run_for(fuel);
+            statuses.push(status.clone());
+            match status {
+                Ok(Status::OutOfFuel | Status::ProducedOutput(_)) => continue,
+                _ => return (statuses, vm),
+            }
+        }
+    }
+
+    /// Test that balanced loops run without bounds checks exactly like
+    /// they do with them, at the edges of the tape and on overflow too.
+    #[test]
+    fn test_bounded_loops() {
+        let programs = [
+            (
+                "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.",
+                16,
+                0,
+            ),
+            ("+++[>+++[>++<-]<-]>>.", 3, 0),
+            ("+>+[>>+<<-]", 3, 0),
+            ("+[<<+>>-]", 3, 1),
+            ("+[->+<]>[-<<+>>]", 4, 0),
+            ("-[>+<-]>[>[-]+<-]", 3, 0),
+        ];
+        let policies = [
+            OverflowPolicy::Wrap,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Error,
+        ];
+        for (source, tape_len, start) in programs {
+            let compiled = compile(source).unwrap();
+            for policy in policies {
+                for program in [compiled.clone(), crate::optimize(&compiled, policy)] {
+                    for fuel in [1, 3, 100, u64::MAX] {
+                        let fast = Vm::with_tape(&program, vec![0_u8; tape_len], start)
+                            .with_overflow_policy(policy);
+                        if program == compiled {
+                            assert!(!fast.bounded_loops.is_empty(), "{source}");
+                        }
+                        let checked = Vm::with_tape(&program, Checked(vec![0; tape_len]), start)
+                            .with_overflow_policy(policy);
+                        let (fast_statuses, fast) = trace(fast, fuel);
+                        let (checked_statuses, checked) = trace(checked, fuel);
+
+                        let context = format!("{source} {policy:?} {fuel}");
+                        assert_eq!(fast_statuses, checked_statuses, "{context}");
+                        assert_eq!(fast.tape, checked.tape.0, "{context}");
+                        assert_eq!(fast.report, checked.report, "{context}");
+                        assert_eq!(fast.data_pointer, checked.data_pointer, "{context}");
+                        assert_eq!(
+                            fast.instruction_pointer, checked.instruction_pointer,
+                            "{context}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Test that straight-line blocks run in one step exactly like they
+    /// do one command at a time, at the edges of the tape and with too
+    /// little fuel too.
+    #[test]
+    fn test_blocks() {
+        let programs = [
+            ("+++>++>+<<-.>>>+++++<<<<", 5, 1),
+            ("++>+++<<+>", 2, 1),
+            ("+>>>>+", 4, 0),
+            (",[>+++>++<<-.,]>>>-", 4, 0),
+            ("+[->+++++>+++<<.]>[-]<+++", 3, 0),
+        ];
+        for (source, tape_len, start) in programs {
+            let compiled = compile(source).unwrap();
+            for program in [
+                compiled.clone(),
+                crate::optimize(&compiled, OverflowPolicy::Wrap),
+            ] {
+                let fast = Vm::with_tape(&program, vec![0_u8; tape_len], start);
+                assert!(fast.blocks.at.iter().any(Option::is_some), "{source}");
+                for fuel in [1, 2, 5, u64::MAX] {
+                    let fast = Vm::with_tape(&program, vec![0_u8; tape_len], start);
+                    let checked = Vm::with_tape(&program, Checked(vec![0; tape_len]), start);
+                    let (fast_statuses, fast) = trace(fast, fuel);
+                    let (checked_statuses, checked) = trace(checked, fuel);
+
+                    let context = format!("{source} {fuel}");
+                    assert_eq!(fast_statuses, checked_statuses, "{context}");
+                    assert_eq!(fast.tape, checked.tape.0, "{context}");
+                    assert_eq!(fast.report, checked.report, "{context}");
+                    assert_eq!(fast.data_pointer, checked.data_pointer, "{context}");
+                    assert_eq!(
+                        fast.instruction_pointer, checked.instruction_pointer,
+                        "{context}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Test that a long arithmetic program takes as many steps in blocks as
+    /// one command at a time, in less time.
+    #[test]
+    fn test_block_speed() {
+        // Every pass runs 1,300 additions and moves, and writes a byte, so
+        // the loop is not one that `run_bounded_loop` takes.
+        let body = "+>++>+++<<->".repeat(100) + "<".repeat(100).as_str();
+        let source = format!("-[{body}-.]");
+        let program = compile(&source).unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 128], 0);
+        let fast = vm.clone();
+        let started = Instant::now();
+        let statuses = trace(fast, u64::MAX).0;
+        let fused = started.elapsed();
+
+        let started = Instant::now();
+        let mut stepped = Vec::new();
+        loop {
+            match vm.step().unwrap() {
+                Status::Running => {}
+                Status::Halted => break,
+                status => stepped.push(Ok(status)),
+            }
+        }
+        let single = started.elapsed();
+
+        stepped.push(Ok(Status::Halted));
+        assert_eq!(statuses, stepped);
+        let steps = 255 * (body.len() + 3) as u64 + 2;
+        assert_eq!(vm.report().steps, steps);
+        assert!(
+            fused < single,
+            "{fused:?} in blocks, {single:?} one at a time"
+        );
+    }
+
+    /// Test that a loop whose cells are not all on the tape fails where
+    /// the checked run does.
+    #[test]
+    fn test_bounded_loop_edges() {
+        let program = compile("+[>>+<<-]").unwrap();
+        let mut vm = Vm::with_tape(&program, vec![0_u8; 3], 1);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::PointerOutOfBounds {
+                instruction_index: 3,
+                pointer: 3,
+            })
+        );
+
+        let program = compile("++++[>++++++++[>++++++++<-]<-]").unwrap();
+        let mut vm =
+            Vm::with_tape(&program, vec![0_u8; 3], 0).with_overflow_policy(OverflowPolicy::Error);
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::CellOverflow {
+                instruction_index: 23,
+            })
+        );
+        assert_eq!(vm.tape(), &[1, 1, 255]);
+        assert_eq!(vm.data_pointer(), 2);
+    }
+}
@@ -0,0 +1,69 @@
+//! WebAssembly bindings for running programs in the browser.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, Streams, Vm, compile};
+
+/// Compiles and runs `source`, returning everything it printed.
+///
+/// There is no stdin in the browser, so `,` reads the UTF-8 bytes of `input`
+/// and stores zero once they are used up. At most `max_steps` commands are
+/// executed; a program still running after that is reported as an error
+/// instead of freezing the page. Errors are returned as descriptive strings.
+#[wasm_bindgen]
+pub fn bf_run(source: &str, input: &str, max_steps: u32) -> Result<String, JsValue> {
+    run_limited(source, input.as_bytes(), max_steps.into())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn run_limited(source: &str, input: &[u8], max_steps: u64) -> Result<String, Error> {
+    let program = compile(source)?;
+    let mut output = Vec::new();
+    Vm::new(&program).run_with_limit(Streams::new(input, &mut output), max_steps)?;
+
+    Ok(String::from_utf8(output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that hello world runs within a generous step limit.
+    #[test]
+    fn test_hello_world() {
+        let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+            >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        assert_eq!(
+            run_limited(source_code, b"", 10_000).unwrap(),
+            "Hello World!\n"
+        );
+    }
+
+    /// Test that an infinite loop is cut off by the step limit.
+    #[test]
+    fn test_step_limit() {
+        assert_eq!(
+            run_limited("+[]", b"", 1000).unwrap_err().to_string(),
+            "runtime error: step limit of 1000 exceeded at instruction 2"
+        );
+        assert_eq!(
+            run_limited(",[.,]", b"abc", 3).unwrap_err().to_string(),
+            "runtime error: step limit of 3 exceeded at instruction 3"
+        );
+    }
+
+    /// Test that input comes from the string and reads past it store zero.
+    #[test]
+    fn test_input_and_eof() {
+        assert_eq!(run_limited(",[.,]", b"echo", 100).unwrap(), "echo");
+        assert_eq!(run_limited("+,.", b"", 100).unwrap(), "\0");
+        assert_eq!(
+            run_limited("+[", b"", 100).unwrap_err().to_string(),
+            "parse error: unmatched '[' at offset 1"
+        );
+    }
+}
@@ -0,0 +1,614 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::transpile::check;
+use crate::{Cell, Command, EofBehavior, Interpreter, OverflowPolicy, TranspileError};
+
+/// Bytes a wasm page holds.
+const PAGE_BYTES: u64 = 1 << 16;
+/// Pages a 32-bit wasm memory can have at most.
+const MAX_PAGES: u64 = 1 << 16;
+
+/// Value of the exported `error` global once a runtime error trapped.
+pub const WASM_LEFT_OF_TAPE: i32 = 1;
+/// Value of `error` when the data pointer moved past the last cell.
+pub const WASM_RIGHT_OF_TAPE: i32 = 2;
+/// Value of `error` after a cell overflowed under [`OverflowPolicy::Error`].
+pub const WASM_CELL_OVERFLOW: i32 = 3;
+
+// Indices of the functions, imports first.
+const READ_BYTE: u32 = 0;
+const WRITE_BYTE: u32 = 1;
+const RUN: u32 = 2;
+const AT: u32 = 3;
+const ADD: u32 = 4;
+const RANDOM: u32 = 5;
+
+// Indices of the globals.
+const RANDOM_STATE: u32 = 0;
+const ERROR: u32 = 1;
+const INSTRUCTION: u32 = 2;
+
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+
+/// Translates a program into a WebAssembly module that behaves like running
+/// it with `interpreter` on cells of type `C`, one of `u8`, `u16`, `u32`,
+/// and `i8`.
+///
+/// The tape is the exported `memory`, sized to [`Interpreter::tape_len`]
+/// cells with the initial contents in a data segment, and loops become
+/// blocks with `br_if`. The module imports `env.read_byte`, which returns
+/// the next byte or -1 at the end of the input, and `env.write_byte`, and
+/// exports `run`, which executes the program. A runtime error traps, with
+/// the kind in the exported `error` global ([`WASM_LEFT_OF_TAPE`],
+/// [`WASM_RIGHT_OF_TAPE`], or [`WASM_CELL_OVERFLOW`]) and the index of the
+/// command in `instruction`.
+///
+/// Fails for the same programs and settings as [`to_c`](crate::to_c), and
+/// for tapes larger than the 4 GiB a wasm memory can hold.
+pub fn to_wasm<C: Cell>(
+    commands: &[Command],
+    interpreter: &Interpreter,
+) -> Result<Vec<u8>, TranspileError> {
+    check(commands, interpreter)?;
+    let width = Width::of::<C>();
+    let tape_bytes = (interpreter.tape_len() as u64).saturating_mul(width.bytes);
+    let pages = tape_bytes.div_ceil(PAGE_BYTES);
+    if pages > MAX_PAGES {
+        return Err(TranspileError::TapeTooLong);
+    }
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    section(&mut module, 1, |types| {
+        uleb(types, 5);
+        for (params, results) in [
+            (&[][..], &[I32][..]),
+            (&[I32], &[]),
+            (&[], &[]),
+            (&[I32, I64, I32], &[I32]),
+            (&[I64, I64, I32], &[I32]),
+        ] {
+            types.push(0x60);
+            vector(types, params);
+            vector(types, results);
+        }
+    });
+    section(&mut module, 2, |imports| {
+        uleb(imports, 2);
+        for (name, ty) in [("read_byte", 0), ("write_byte", 1)] {
+            vector(imports, b"env");
+            vector(imports, name.as_bytes());
+            imports.extend([0x00, ty]);
+        }
+    });
+    section(&mut module, 3, |functions| {
+        // run, at, add, and random.
+        vector(functions, &[2, 3, 4, 0]);
+    });
+    section(&mut module, 5, |memories| {
+        uleb(memories, 1);
+        memories.push(0x01);
+        uleb(memories, pages);
+        uleb(memories, pages);
+    });
+    section(&mut module, 6, |globals| {
+        uleb(globals, 3);
+        globals.extend([I64, 0x01, 0x42]);
+        sleb(globals, interpreter.seed() as i64);
+        globals.push(0x0b);
+        for _ in [ERROR, INSTRUCTION] {
+            globals.extend([I32, 0x01, 0x41, 0x00, 0x0b]);
+        }
+    });
+    section(&mut module, 7, |exports| {
+        uleb(exports, 4);
+        for (name, kind, index) in [
+            ("memory", 0x02, 0),
+            ("run", 0x00, RUN),
+            ("error", 0x03, ERROR),
+            ("instruction", 0x03, INSTRUCTION),
+        ] {
+            vector(exports, name.as_bytes());
+            exports.push(kind);
+            uleb(exports, index.into());
+        }
+    });
+
+    let mut run = Body::new(width);
+    // The data pointer, a cell index, and a byte that was read.
+    run.code.extend([0x01, 0x03, I32]);
+    run.i32_const(interpreter.data_pointer() as i64);
+    run.code.push(0x21);
+    uleb(&mut run.code, 0);
+    for (address, command) in commands.iter().enumerate() {
+        run.command(address, command, interpreter.eof_behavior())?;
+    }
+    run.code.push(0x0b);
+    section(&mut module, 10, |code| {
+        uleb(code, 4);
+        for body in [
+            run.code,
+            at_body(interpreter.tape_len()),
+            add_body(width, interpreter.overflow_policy()),
+            random_body(),
+        ] {
+            vector(code, &body);
+        }
+    });
+
+    let (offset, data) = interpreter.tape_init();
+    if !data.is_empty() {
+        section(&mut module, 11, |segments| {
+            uleb(segments, 1);
+            segments.push(0x00);
+            segments.push(0x41);
+            sleb(segments, (offset as u64 * width.bytes) as i32 as i64);
+            segments.push(0x0b);
+            let mut bytes = Vec::new();
+            for &byte in data {
+                let value = C::from_byte(byte).to_i64().to_le_bytes();
+                bytes.extend(&value[..width.bytes as usize]);
+            }
+            vector(segments, &bytes);
+        });
+    }
+    Ok(module)
+}
+
+/// How cells of one type are laid out in memory.
+#[derive(Clone, Copy)]
+struct Width {
+    bytes: u64,
+    signed: bool,
+}
+
+impl Width {
+    fn of<C: Cell>() -> Self {
+        Width {
+            bytes: size_of::<C>() as u64,
+            signed: C::MINUS_ONE.to_i64() < 0,
+        }
+    }
+
+    /// `log2` of the size of a cell.
+    fn align(self) -> u32 {
+        self.bytes.trailing_zeros()
+    }
+}
+
+/// The code of a function, written one instruction at a time.
+struct Body {
+    code: Vec<u8>,
+    width: Width,
+}
+
+impl Body {
+    fn new(width: Width) -> Self {
+        Body {
+            code: Vec::new(),
+            width,
+        }
+    }
+
+    fn command(
+        &mut self,
+        address: usize,
+        command: &Command,
+        eof: EofBehavior,
+    ) -> Result<(), TranspileError> {
+        use self::Command as C;
+
+        match *command {
+            C::IncrementDataPointer => self.move_by(1, address),
+            C::DecrementDataPointer => self.move_by(-1, address),
+            C::MovePointer(offset) => self.move_by(offset.into(), address),
+            C::Increment => self.add(0, 1, address),
+            C::Decrement => self.add(0, -1, address),
+            C::Add(delta) => self.add(0, delta.into(), address),
+            C::AddAt { offset, value } => self.add(offset.into(), value.into(), address),
+            C::Set(value) => self.set(0, value, address),
+            C::SetAt { offset, value } => self.set(offset.into(), value, address),
+            C::WriteByte => self.output(0, address),
+            C::OutputAt(offset) => self.output(offset.into(), address),
+            C::ReadByte => self.input(0, address, eof),
+            C::InputAt(offset) => self.input(offset.into(), address, eof),
+            C::Random => {
+                self.current();
+                self.address();
+                self.call(RANDOM);
+                self.store();
+            }
+            // A loop is entered if the cell is not zero, and then repeated
+            // until it is.
+            C::JumpForwardIfZero(_) => {
+                self.code.extend([0x02, 0x40]);
+                self.load_current();
+                self.code.push(0x45);
+                self.code.extend([0x0d, 0x00]);
+                self.code.extend([0x03, 0x40]);
+            }
+            C::JumpBackwardIfNonZero(_) => {
+                self.load_current();
+                self.code.extend([0x0d, 0x00, 0x0b, 0x0b]);
+            }
+            C::ClearRange { len } => self.clear(len.into(), address),
+            C::ScanRight(stride) => self.scan(stride as i64, address),
+            C::ScanLeft(stride) => self.scan(-(stride as i64), address),
+            // The product wraps under every policy, as in the interpreter.
+            C::MulAdd { offset, factor } => {
+                self.load_current();
+                self.code.extend([0x04, 0x40]);
+                self.at(offset.into(), address);
+                self.code.extend([0x21, 0x01, 0x20, 0x01]);
+                self.address();
+                self.code.extend([0x20, 0x01]);
+                self.address();
+                self.load_i64();
+                self.current();
+                self.address();
+                self.load_i64();
+                self.i64_const(factor.into());
+                self.code.extend([0x7e, 0x7c, 0xa7]);
+                self.store();
+                self.code.push(0x0b);
+            }
+            C::DebugDump | C::BeginProc(_) | C::EndProc(_) | C::Call => {
+                return Err(TranspileError::UnsupportedCommand { address });
+            }
+        }
+        Ok(())
+    }
+
+    fn move_by(&mut self, offset: i64, address: usize) {
+        self.at(offset, address);
+        self.code.extend([0x21, 0x00]);
+    }
+
+    /// Pushes the index of the cell `offset` cells from the pointer.
+    fn at(&mut self, offset: i64, address: usize) {
+        self.current();
+        if offset != 0 {
+            self.i64_const(offset);
+            self.i32_const(address as i64);
+            self.call(AT);
+        }
+    }
+
+    fn add(&mut self, offset: i64, delta: i64, address: usize) {
+        self.at(offset, address);
+        self.code.extend([0x22, 0x01]);
+        self.address();
+        self.code.extend([0x20, 0x01]);
+        self.address();
+        self.load_i64();
+        self.i64_const(delta);
+        self.i32_const(address as i64);
+        self.call(ADD);
+        self.store();
+    }
+
+    fn set(&mut self, offset: i64, value: u8, address: usize) {
+        self.at(offset, address);
+        self.address();
+        self.i32_const(value.into());
+        self.store();
+    }
+
+    fn output(&mut self, offset: i64, address: usize) {
+        self.at(offset, address);
+        self.address();
+        self.load();
+        self.i32_const(0xff);
+        self.code.push(0x71);
+        self.call(WRITE_BYTE);
+    }
+
+    fn input(&mut self, offset: i64, address: usize, eof: EofBehavior) {
+        self.at(offset, address);
+        self.code.extend([0x21, 0x01]);
+        self.call(READ_BYTE);
+        self.code.extend([0x22, 0x02]);
+        self.i32_const(0);
+        self.code.extend([0x48, 0x04, 0x40]);
+        // Storing -1 truncates it to the cell, which is the minus one of
+        // every width.
+        let value = match eof {
+            EofBehavior::SetZero => Some(0),
+            EofBehavior::SetMinusOne => Some(-1),
+            EofBehavior::Unchanged => None,
+        };
+        if let Some(value) = value {
+            self.code.extend([0x20, 0x01]);
+            self.address();
+            self.i32_const(value);
+            self.store();
+        }
+        self.code.push(0x05);
+        self.code.extend([0x20, 0x01]);
+        self.address();
+        self.code.extend([0x20, 0x02]);
+        self.store();
+        self.code.push(0x0b);
+    }
+
+    /// Clears the cells up to the last one of the range, which `at` checks
+    /// first, one at a time, and leaves the pointer on it.
+    fn clear(&mut self, len: i64, address: usize) {
+        self.at(len - len.signum(), address);
+        self.code.extend([0x21, 0x01, 0x02, 0x40, 0x03, 0x40]);
+        self.set(0, 0, address);
+        self.current();
+        self.code.extend([0x20, 0x01, 0x46, 0x0d, 0x01]);
+        self.current();
+        self.i32_const(len.signum());
+        self.code.extend([0x6a, 0x21, 0x00, 0x0c, 0x00, 0x0b, 0x0b]);
+    }
+
+    fn scan(&mut self, stride: i64, address: usize) {
+        self.code.extend([0x02, 0x40, 0x03, 0x40]);
+        self.load_current();
+        self.code.extend([0x45, 0x0d, 0x01]);
+        self.move_by(stride, address);
+        self.code.extend([0x0c, 0x00, 0x0b, 0x0b]);
+    }
+
+    /// Pushes the data pointer.
+    fn current(&mut self) {
+        self.code.extend([0x20, 0x00]);
+    }
+
+    /// Pushes the current cell as an `i32`, see [`Body::load`].
+    fn load_current(&mut self) {
+        self.current();
+        self.address();
+        self.load();
+    }
+
+    /// Turns the cell index on the stack into a byte address.
+    fn address(&mut self) {
+        if self.width.align() > 0 {
+            self.i32_const(self.width.align().into());
+            self.code.push(0x74);
+        }
+    }
+
+    /// Loads the cell at the address on the stack as an `i32`, which is
+    /// only compared to zero or truncated to a byte.
+    fn load(&mut self) {
+        let opcode = match (self.width.bytes, self.width.signed) {
+            (1, true) => 0x2c,
+            (1, false) => 0x2d,
+            (2, _) => 0x2f,
+            _ => 0x28,
+        };
+        self.memory(opcode);
+    }
+
+    /// Loads the cell at the address on the stack as its value in an `i64`.
+    fn load_i64(&mut self) {
+        let opcode = match (self.width.bytes, self.width.signed) {
+            (1, true) => 0x30,
+            (1, false) => 0x31,
+            (2, _) => 0x33,
+            _ => 0x35,
+        };
+        self.memory(opcode);
+    }
+
+    /// Stores an `i32` into the cell, keeping its low bits.
+    fn store(&mut self) {
+        let opcode = match self.width.bytes {
+            1 => 0x3a,
+            2 => 0x3b,
+            _ => 0x36,
+        };
+        self.memory(opcode);
+    }
+
+    fn memory(&mut self, opcode: u8) {
+        self.code.push(opcode);
+        uleb(&mut self.code, self.width.align().into());
+        uleb(&mut self.code, 0);
+    }
+
+    fn call(&mut self, function: u32) {
+        self.code.push(0x10);
+        uleb(&mut self.code, function.into());
+    }
+
+    fn i32_const(&mut self, value: i64) {
+        self.code.push(0x41);
+        sleb(&mut self.code, value as i32 as i64);
+    }
+
+    fn i64_const(&mut self, value: i64) {
+        self.code.push(0x42);
+        sleb(&mut self.code, value);
+    }
+}
+
+/// `at(pointer, offset, instruction)`, which returns the index of the cell
+/// `offset` cells from `pointer`, or traps.
+fn at_body(tape_len: usize) -> Vec<u8> {
+    let mut body = Body::new(Width {
+        bytes: 1,
+        signed: false,
+    });
+    let code = &mut body.code;
+    code.extend([0x01, 0x01, I64]);
+    code.extend([0x20, 0x00, 0xad, 0x20, 0x01, 0x7c, 0x22, 0x03]);
+    body.i64_const(tape_len as i64);
+    let code = &mut body.code;
+    // Negative indices compare as huge ones.
+    code.extend([0x54, 0x04, I32, 0x20, 0x03, 0xa7, 0x05]);
+    code.extend([0x20, 0x02, 0x24]);
+    uleb(code, INSTRUCTION.into());
+    // The pointer moves one cell at a time, so only the direction matters.
+    body.i32_const(WASM_LEFT_OF_TAPE.into());
+    body.i32_const(WASM_RIGHT_OF_TAPE.into());
+    body.code.extend([0x20, 0x01]);
+    body.i64_const(0);
+    let code = &mut body.code;
+    code.extend([0x53, 0x1b, 0x24]);
+    uleb(code, ERROR.into());
+    code.extend([0x00, 0x0b, 0x0b]);
+    body.code
+}
+
+/// `add(value, delta, instruction)`, which returns the value of the cell
+/// after adding `delta` under `policy`, or traps.
+fn add_body(width: Width, policy: OverflowPolicy) -> Vec<u8> {
+    let bits = width.bytes * 8;
+    let (min, max) = if width.signed {
+        (-(1_i64 << (bits - 1)), (1_i64 << (bits - 1)) - 1)
+    } else {
+        (0, (1_i64 << bits) - 1)
+    };
+
+    let mut body = Body::new(width);
+    body.code.extend([0x01, 0x01, I64]);
+    body.code.extend([0x20, 0x00, 0x20, 0x01, 0x7c, 0x21, 0x03]);
+    match policy {
+        OverflowPolicy::Wrap => {}
+        OverflowPolicy::Saturate => {
+            for (limit, comparison) in [(min, 0x53), (max, 0x55)] {
+                body.i64_const(limit);
+                body.code.extend([0x20, 0x03, 0x20, 0x03]);
+                body.i64_const(limit);
+                body.code.extend([comparison, 0x1b, 0x21, 0x03]);
+            }
+        }
+        OverflowPolicy::Error => {
+            body.code.extend([0x20, 0x03]);
+            body.i64_const(min);
+            body.code.extend([0x53, 0x20, 0x03]);
+            body.i64_const(max);
+            body.code.extend([0x55, 0x72, 0x04, 0x40, 0x20, 0x02, 0x24]);
+            uleb(&mut body.code, INSTRUCTION.into());
+            body.i32_const(WASM_CELL_OVERFLOW.into());
+            body.code.push(0x24);
+            uleb(&mut body.code, ERROR.into());
+            body.code.extend([0x00, 0x0b]);
+        }
+    }
+    body.code.extend([0x20, 0x03, 0xa7, 0x0b]);
+    body.code
+}
+
+/// `random()`, which returns the next byte of SplitMix64, as in the
+/// interpreter.
+fn random_body() -> Vec<u8> {
+    let mut body = Body::new(Width {
+        bytes: 1,
+        signed: false,
+    });
+    body.code.extend([0x01, 0x01, I64, 0x23]);
+    uleb(&mut body.code, RANDOM_STATE.into());
+    body.i64_const(0x9e37_79b9_7f4a_7c15_u64 as i64);
+    body.code.extend([0x7c, 0x22, 0x00, 0x24]);
+    uleb(&mut body.code, RANDOM_STATE.into());
+    for (shift, factor) in [(30, 0xbf58_476d_1ce4_e5b9_u64), (27, 0x94d0_49bb_1331_11eb)] {
+        body.code.extend([0x20, 0x00, 0x20, 0x00]);
+        body.i64_const(shift);
+        body.code.extend([0x88, 0x85]);
+        body.i64_const(factor as i64);
+        body.code.extend([0x7e, 0x21, 0x00]);
+    }
+    body.code.extend([0x20, 0x00, 0x20, 0x00]);
+    body.i64_const(31);
+    body.code.extend([0x88, 0x85]);
+    body.i64_const(56);
+    body.code.extend([0x88, 0xa7, 0x0b]);
+    body.code
+}
+
+/// Appends a section with the contents written by `write`.
+fn section(module: &mut Vec<u8>, id: u8, write: impl FnOnce(&mut Vec<u8>)) {
+    let mut contents = Vec::new();
+    write(&mut contents);
+    module.push(id);
+    vector(module, &contents);
+}
+
+/// Appends `bytes` after their length.
+fn vector(out: &mut Vec<u8>, bytes: &[u8]) {
+    uleb(out, bytes.len() as u64);
+    out.extend(bytes);
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    /// Test the LEB128 encodings at the edges of their bytes.
+    #[test]
+    fn test_leb() {
+        for (value, expected) in [(0, &[0x00][..]), (127, &[0x7f]), (128, &[0x80, 0x01])] {
+            let mut out = Vec::new();
+            uleb(&mut out, value);
+            assert_eq!(out, expected);
+        }
+        for (value, expected) in [
+            (0, &[0x00][..]),
+            (-1, &[0x7f]),
+            (63, &[0x3f]),
+            (64, &[0xc0, 0x00]),
+            (-65, &[0xbf, 0x7f]),
+            (
+                i64::MIN,
+                &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f],
+            ),
+        ] {
+            let mut out = Vec::new();
+            sleb(&mut out, value);
+            assert_eq!(out, expected);
+        }
+    }
+
+    /// Test that the tape becomes the memory, a page at a time.
+    #[test]
+    fn test_memory() {
+        let interpreter = Interpreter::builder().tape_len(65_537).build().unwrap();
+        let module = to_wasm::<u8>(&compile("+").unwrap(), &interpreter).unwrap();
+        assert!(module.starts_with(b"\0asm\x01\0\0\0"));
+        let memory = [5, 4, 1, 0x01, 2, 2];
+        assert!(module.windows(6).any(|section| section == memory));
+
+        let interpreter = Interpreter::builder()
+            .tape_len((1 << 31) + 1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_wasm::<u16>(&[], &interpreter),
+            Err(TranspileError::TapeTooLong)
+        );
+        assert!(to_wasm::<u8>(&[], &interpreter).is_ok());
+    }
+}
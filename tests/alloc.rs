@@ -0,0 +1,67 @@
+//! Memory use of the parser, measured by an allocator that counts bytes.
+//! This is a test binary of its own so that no other test allocates while
+//! a measurement runs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use brainfuck_vm::{Ast, Command, compile, parse_ast};
+
+/// Allocator that keeps track of the most bytes ever live at once.
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(live, Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Runs `f` and returns its result with the most bytes it had allocated at
+/// once, on top of what was live before.
+fn peak_of<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = LIVE.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = f();
+    (result, PEAK.load(Ordering::SeqCst) - before)
+}
+
+/// Test that compiling 50 MB of source, mostly comments with multi-byte
+/// characters, takes memory for the commands alone.
+#[test]
+fn test_large_source() {
+    // 50 bytes with one command, and `ü`, `→`, and `😀` around it.
+    let line = "ünïcödé → comment 😀 is skipped bytewise+\n";
+    assert_eq!(line.len(), 50);
+    let source = line.repeat(1_000_000);
+    let commands = 1_000_000;
+
+    let started = Instant::now();
+    let (program, peak) = peak_of(|| compile(&source).unwrap());
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert_eq!(program.len(), commands);
+    assert!(program.iter().all(|command| *command == Command::Increment));
+    // The vector of commands at most doubles while it grows, and a copy of
+    // the source as `char`s would take four times its size on its own.
+    let command_bytes = commands * size_of::<Command>();
+    assert!(peak <= 2 * command_bytes.next_power_of_two(), "{peak}");
+    assert!(peak < source.len(), "{peak}");
+
+    // The tree merges the whole program into a single run.
+    let (ast, peak) = peak_of(|| parse_ast(&source).unwrap());
+    assert_eq!(ast, Ast::Block(vec![Ast::Inc(1_000_000)]));
+    assert!(peak < 1024, "{peak}");
+}
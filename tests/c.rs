@@ -0,0 +1,99 @@
+//! Compiles programs translated by `to_c` and checks that they behave like
+//! the interpreter. Skipped without a C compiler available as `cc`.
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use brainfuck_vm::{Cell, Interpreter, compile_with_random, optimize, to_c};
+
+use common::Backend;
+
+mod common;
+
+/// Builds `source_code` with `cc`, runs it on `input`, and returns its exit
+/// code, output, and error output, or `None` without a compiler.
+fn run_c<C: Cell>(
+    name: &str,
+    commands: &[brainfuck_vm::Command],
+    interpreter: &Interpreter,
+    input: &[u8],
+) -> Option<(Option<i32>, Vec<u8>, String)> {
+    let dir = env::temp_dir().join(format!("brainfuck_vm_c_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(format!("{name}.c"));
+    let binary = dir.join(name);
+    fs::write(&source, to_c::<C>(commands, interpreter).unwrap()).unwrap();
+
+    let status = Command::new("cc")
+        .args(["-std=c99", "-O2", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .ok()?;
+    assert!(status.success(), "{name} does not compile");
+
+    let mut child = Command::new(&binary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    Some((output.status.code(), output.stdout, stderr))
+}
+
+/// Runs `source_code` as is and optimized, through the interpreter and as
+/// C, and checks that both produce the same output and errors.
+fn assert_same<C: Cell>(name: &str, source_code: &str, interpreter: &Interpreter, input: &[u8]) {
+    let commands = compile_with_random(source_code).unwrap();
+    let policy = interpreter.overflow_policy();
+    for (suffix, commands) in [
+        ("", commands.clone()),
+        ("_opt", optimize(&commands, policy)),
+    ] {
+        let mut output = Vec::new();
+        let result = interpreter.run_with_cells::<C, _, _>(&commands, input, &mut output);
+        let expected = match result {
+            Ok(_) => (Some(0), output, String::new()),
+            Err(e) => (Some(1), output, format!("{e}\n")),
+        };
+        let name = format!("{name}{suffix}");
+        let Some(actual) = run_c::<C>(&name, &commands, interpreter, input) else {
+            eprintln!("no C compiler, skipping");
+            return;
+        };
+        assert_eq!(actual, expected, "{name}");
+    }
+}
+
+/// Translations to C, built with `cc`.
+struct Cc;
+
+impl Backend for Cc {
+    fn check<C: Cell>(
+        &mut self,
+        name: &str,
+        source_code: &str,
+        interpreter: &Interpreter,
+        input: &[u8],
+    ) {
+        assert_same::<C>(name, source_code, interpreter, input);
+    }
+}
+
+/// Test hello world and cat, the programs a C translation is made for.
+#[test]
+fn test_hello_and_cat() {
+    common::hello_and_cat(&mut Cc);
+}
+
+/// Test the programs that depend on the settings of the interpreter.
+#[test]
+fn test_settings() {
+    common::settings(&mut Cc);
+}
@@ -0,0 +1,1748 @@
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+/// Where the command line interpreter caches programs and output in tests,
+/// instead of the user's cache.
+const CACHE_DIR: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/cache");
+
+/// The command line interpreter, caching in [`CACHE_DIR`].
+fn brainfuck_vm() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_brainfuck_vm"));
+    command.env("BRAINFUCK_VM_CACHE_DIR", CACHE_DIR);
+    command
+}
+
+/// Runs the command line interpreter with the given arguments and no input.
+fn run(args: &[&str]) -> Output {
+    brainfuck_vm()
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// Runs the command line interpreter with the given arguments and `stdin`
+/// piped to it.
+fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    brainfuck_vm()
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(stdin)?;
+            child.wait_with_output()
+        })
+        .unwrap()
+}
+
+/// Test that the tape flags are passed on to the interpreter.
+#[test]
+fn test_tape_flags() {
+    let output = run(&["--tape-size", "10", "--pointer-start", "9", "<<<+++."]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3]);
+}
+
+/// Test that a start pointer outside the tape is rejected before running.
+#[test]
+fn test_pointer_start_out_of_range() {
+    let output = run(&["--pointer-start", "50", "--tape-size", "10", "+."]);
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "invalid configuration: data pointer 50 is outside of the 10-cell tape\n"
+    );
+}
+
+/// Test that the default tape has exactly 30,000 cells.
+#[test]
+fn test_default_tape_size() {
+    let last_cell = ">".repeat(29_999);
+
+    assert!(run(&[&format!("{last_cell}+")]).status.success());
+    assert!(!run(&[&format!("{last_cell}>")]).status.success());
+}
+
+/// Test that a malformed number is reported with the usage line.
+#[test]
+fn test_invalid_tape_size() {
+    let output = run(&["--tape-size", "lots", "+"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("--tape-size expects a number, got 'lots'\nUsage:"));
+}
+
+/// Test that leaving the tape is reported as a runtime error.
+#[test]
+fn test_pointer_out_of_bounds() {
+    let output = run(&["+.<"]);
+
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, [1]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: data pointer moved out of the tape to cell -1 at instruction 2\n"
+    );
+}
+
+/// Test that wide cells keep counting past 255.
+#[test]
+fn test_cell_size() {
+    // Prints one byte per value until the cell wraps to zero.
+    let counter = "+[+.]";
+
+    assert_eq!(run(&[counter]).stdout.len(), 255);
+    assert_eq!(run(&["--cell-size", "16", counter]).stdout.len(), 65_535);
+    assert!(!run(&["--cell-size", "12", counter]).status.success());
+}
+
+/// Test that running out of steps has its own exit code.
+#[test]
+fn test_max_steps() {
+    let output = run(&["--max-steps", "1000", "+[]"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: step limit of 1000 exceeded at instruction 2\n"
+    );
+    assert!(run(&["--max-steps", "1000", "+[-]"]).status.success());
+}
+
+/// Test that a timeout stops a spin loop and keeps the output so far.
+#[test]
+fn test_timeout() {
+    let start = Instant::now();
+    let output = run(&["--timeout", "100ms", "+.[]"]);
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(output.stdout, [1]);
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("runtime error: timed out after ")
+    );
+    assert!(!run(&["--timeout", "soon", "+"]).status.success());
+}
+
+/// Test that an endless printer is cut off at the output limit.
+#[test]
+fn test_max_output() {
+    let output = run(&["--max-output", "1024", "+[.]"]);
+
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(output.stdout, [1; 1024]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: output limit of 1024 bytes exceeded at instruction 2\n"
+    );
+}
+
+/// Test a huge sparse tape that would take gigabytes as a dense one.
+#[test]
+fn test_sparse_tape() {
+    let program = "+++.>-[>+++++++<-]>.";
+    let huge = usize::MAX.to_string();
+
+    let output = run(&["--tape", "sparse", "--tape-size", &huge, program]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3, 255u8.wrapping_mul(7)]);
+    assert!(!run(&["--tape", "tiny", program]).status.success());
+}
+
+/// Test preloading the tape from hex and from a file.
+#[test]
+fn test_tape_init() {
+    let output = run(&["--tape-init-hex", "4849", ".>."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"HI");
+
+    let path = std::env::temp_dir().join(format!("tape-init-{}", std::process::id()));
+    std::fs::write(&path, b"abc").unwrap();
+    let path = path.to_str().unwrap();
+    let args = [
+        "--tape-init",
+        path,
+        "--tape-init-offset",
+        "5",
+        "--init-pointer",
+        "2",
+        ".",
+    ];
+    let output = run(&args);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"c");
+
+    let args = [
+        "--tape-size",
+        "6",
+        "--tape-init",
+        path,
+        "--tape-init-offset",
+        "5",
+        ".",
+    ];
+    let output = run(&args);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "invalid configuration: initial tape data needs 8 cells, but the tape has 6\n"
+    );
+    std::fs::remove_file(path).unwrap();
+
+    assert!(!run(&["--tape-init-hex", "4g", "."]).status.success());
+}
+
+/// Test using the final cell as the exit code.
+#[test]
+fn test_exit_cell() {
+    let forty_two = "+".repeat(42);
+
+    assert_eq!(run(&["--exit-cell", &forty_two]).status.code(), Some(42));
+    assert_eq!(run(&["--exit-cell", "+++[-]"]).status.code(), Some(0));
+    assert_eq!(run(&[&forty_two]).status.code(), Some(0));
+    // Only the low byte of a wide cell fits into an exit code.
+    let wide = [
+        "--exit-cell",
+        "--cell-size",
+        "16",
+        "++++++++++++++++[>++++++++++++++++<-]>+",
+    ];
+    assert_eq!(run(&wide).status.code(), Some(1));
+    // Errors keep their own exit code.
+    assert_eq!(run(&["--exit-cell", "++<"]).status.code(), Some(1));
+}
+
+/// Test that strict mode rejects stray characters.
+#[test]
+fn test_strict() {
+    let output = run(&["--strict", "+++ ≻ ."]);
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unexpected character '≻' (U+227B) at offset 4\n"
+    );
+    assert_eq!(run(&["+++ ≻ ."]).stdout, [3]);
+    assert_eq!(run(&["--strict", "+++ # print three\n."]).stdout, [3]);
+}
+
+/// Test that `--raw` and `--echo` leave input that is not a terminal alone.
+#[test]
+fn test_raw_without_terminal() {
+    let output = brainfuck_vm()
+        .args(["--raw", "--echo", ",.,."])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(b"hi")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hi");
+}
+
+/// Test that `--numeric` prints cell values as decimal numbers.
+#[test]
+fn test_numeric() {
+    assert_eq!(run(&["--numeric", "++++++++++."]).stdout, b"10\n");
+    assert_eq!(run(&["++++++++++."]).stdout, b"\n");
+    assert_eq!(
+        run(&[
+            "--numeric",
+            "--separator",
+            "space",
+            "--cell-size",
+            "i8",
+            "-.-."
+        ])
+        .stdout,
+        b"-1 -2 "
+    );
+}
+
+/// Test that `--unicode` copies characters and needs wide cells.
+#[test]
+fn test_unicode() {
+    let output = brainfuck_vm()
+        .args(["--unicode", "--cell-size", "32", ",[.,]"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all("中文 🦀".as_bytes())?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, "中文 🦀".as_bytes());
+
+    let output = run(&["--unicode", ".", "--cell-size", "i8"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("--unicode needs --cell-size 16 or 32\n")
+    );
+}
+
+/// Test that `--newline` translates line endings in both directions.
+#[test]
+fn test_newline() {
+    let cat = |args: &[&str]| {
+        let child = brainfuck_vm()
+            .args(args)
+            .arg(",[.,]")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn();
+        let output = child
+            .and_then(|mut child| {
+                use std::io::Write;
+                child.stdin.take().unwrap().write_all(b"a\r\nb\nc\r")?;
+                child.wait_with_output()
+            })
+            .unwrap();
+        assert!(output.status.success());
+        output.stdout
+    };
+
+    assert_eq!(cat(&[]), b"a\r\nb\nc\r");
+    assert_eq!(cat(&["--newline", "lf"]), b"a\nb\nc\r");
+    assert_eq!(cat(&["--newline", "crlf"]), b"a\r\nb\r\nc\r");
+}
+
+/// Test that `--max-memory` stops a sparse tape and rejects a dense one.
+#[test]
+fn test_max_memory() {
+    let output = run(&[
+        "--tape",
+        "sparse",
+        "--tape-size",
+        "1000000",
+        "--max-memory",
+        "16KiB",
+        "+[>+]",
+    ]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("runtime error: tape would need 16384 cells")
+    );
+
+    assert_eq!(run(&["--max-memory", "1KiB", "+."]).status.code(), Some(6));
+    let output = run(&["--max-memory", "64KiB", "+."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1]);
+
+    let output = run(&["--max-memory", "64kb", "+."]);
+    assert!(!output.status.success());
+}
+
+/// Test that `--bang-input` feeds the text after `!` to `,` instead of stdin.
+#[test]
+fn test_bang_input() {
+    let output = run(&["--bang-input", ",[.,]!data"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"data");
+
+    assert!(run(&[",[.,]!data"]).stdout.is_empty());
+}
+
+/// Test that `--debug-ext` dumps the tape to stderr and leaves stdout alone.
+#[test]
+fn test_debug_ext() {
+    let program = "++>+++[>+<-]>.#";
+    // Instructions are counted in the program as written.
+    let output = run(&["-O0", "--debug-ext", program]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "# instruction 14, pointer 2, cells 0..3: 2 0 [3] | 02 00 [03]\n"
+    );
+
+    let output = run(&[program]);
+    assert_eq!(output.stdout, [3]);
+    assert!(output.stderr.is_empty());
+    assert!(!run(&["--debug-ext", "--strict", program]).status.success());
+}
+
+/// Test that `--dialect pbrain` runs procedures and limits their nesting.
+#[test]
+fn test_pbrain() {
+    // Procedure 1 prints the letter in the third cell and calls itself
+    // until the counter in the second cell reaches zero.
+    let program = "+(>>.+<-[<:>]<) >+++ >>++++++++++[<++++++++++>-]<--- <<:";
+
+    let output = run(&["--dialect", "pbrain", program]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"abc");
+
+    let output = run(&[
+        "-O0",
+        "--dialect",
+        "pbrain",
+        "--max-call-depth",
+        "2",
+        program,
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"ab");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: call depth limit of 2 exceeded at instruction 10\n"
+    );
+
+    let output = run(&["-O0", "--dialect", "pbrain", "+++:"]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: procedure 3 is not defined at instruction 3\n"
+    );
+    assert!(!run(&["--dialect", "pbrian", "+"]).status.success());
+}
+
+/// Test that `--dialect ook` translates the program before compiling it.
+#[test]
+fn test_ook() {
+    let output = run(&["--dialect", "ook", "Ook. Ook. Ook. Ook. Ook! Ook."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [2]);
+
+    let output = run(&["--dialect", "ook", "Ook. Ook. Ook?"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: incomplete pair starting at token 2\n"
+    );
+}
+
+/// Test that `--dialect-map` runs a program spelled with the given tokens.
+#[test]
+fn test_dialect_map() {
+    let map = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/dialect/emoji.toml");
+
+    let output = run(&["--dialect-map", map, "👍👍👍 text 📢"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3]);
+
+    let output = run(&["--strict", "--dialect-map", map, "👍👍👍 text 📢"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: text at offset 13 is not a token of the dialect\n"
+    );
+}
+
+/// Test that `--macros` expands definitions and points parse errors at the
+/// source as written.
+#[test]
+fn test_macros() {
+    let output = run(&["--macros", "@def three { +++ } @three @three ."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [6]);
+
+    let output = run(&["--macros", "@def open { [ } + @open ."]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unmatched '[' at offset 12\n"
+    );
+
+    let output = run(&["--macros", "@def a { @b } @def b { @a } @a"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: macro 'a' expands to itself: a -> b -> a\n"
+    );
+}
+
+/// Test that `--file` resolves `@include` relative to the including file,
+/// and that parse errors name the file they come from.
+#[test]
+fn test_include() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cli");
+
+    let output = run(&["--file", &format!("{dir}/main.b")]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hi\n");
+
+    let output = run(&["--file", &format!("{dir}/unbalanced.b")]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("parse error: unmatched '[' at offset 33 in {dir}/lib/open.b\n")
+    );
+}
+
+/// Test that a file including itself through another one is an error that
+/// names the whole chain.
+#[test]
+fn test_include_cycle() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cli");
+
+    let output = run(&["--file", &format!("{dir}/cycle_a.b")]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.lines().next().unwrap(),
+        format!("include cycle: {dir}/cycle_a.b -> {dir}/cycle_b.b -> {dir}/cycle_a.b")
+    );
+}
+
+/// Test that `--profile` bundles the settings programs expect.
+#[test]
+fn test_profile() {
+    // dbfi-style programs leave the cell alone at EOF.
+    let output = run(&["--profile", "dbfi", "+++,."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3]);
+
+    let output = run(&["--profile", "classic", "-."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [255]);
+
+    let output = run(&["--profile", "strict", "-."]);
+    assert!(!output.status.success());
+
+    // The pointer starts in the middle of the large tape.
+    let output = run(&["--profile", "large", "<<<<+."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1]);
+}
+
+/// Test that runs are folded unless `-O0` is given.
+#[test]
+fn test_optimize() {
+    let program = "++++++++++++.>>>>>>>>>>>>.<<<<<<<<<<<<.";
+    let output = run(&["--max-steps", "10", program]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [12, 0, 12]);
+
+    let output = run(&["-O0", "--max-steps", "10", program]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    let output = run(&["-O0", program]);
+    assert_eq!(output.stdout, [12, 0, 12]);
+}
+
+/// Test that `--emit-ir` prints the program after the passes, and that
+/// `--disable-pass` leaves one out.
+#[test]
+fn test_emit_ir() {
+    let output = run(&["--emit-ir", "+>[->++<]."]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0: inc\n1: right\n2: mul_add +1 2\n3: set 0\n4: out\n"
+    );
+
+    let output = run(&["-O1", "--disable-pass", "fold-runs", "--emit-ir", "++>"]);
+    assert_eq!(output.stdout, b"0: add 2\n1: right\n");
+    let output = run(&[
+        "-O1",
+        "--disable-pass",
+        "peephole",
+        "--disable-pass",
+        "fold-runs",
+        "--emit-ir",
+        "++>",
+    ]);
+    assert_eq!(output.stdout, b"0: inc\n1: inc\n2: right\n");
+
+    let output = run(&["--disable-pass", "everything", "+"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("unknown pass 'everything', expected one of fold-runs,"));
+}
+
+/// Test that `--verbose` reports the dead loops that were dropped.
+#[test]
+fn test_verbose() {
+    let output = run(&["--verbose", "--no-cache", "[comment]+[.-][->+<]>."]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1, 0]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "dead code elimination removed 2 of 11 instructions\n"
+    );
+
+    let output = run(&["--verbose", "--no-cache", "--tape-init-hex", "01", "[.-]"]);
+    assert_eq!(output.stdout, [1]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "dead code elimination removed 0 of 4 instructions\n"
+    );
+}
+
+/// Test that `--partial-eval` runs the start of a program ahead of time,
+/// also for a translation.
+#[test]
+fn test_partial_eval() {
+    let program = "++++++++[>++++++<-]>+.,.";
+    let output = brainfuck_vm()
+        .args(["--partial-eval", "--verbose", program])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(b"x")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"1x");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with("partial evaluation turned 8 instructions into 7\n"),
+        "{stderr}"
+    );
+
+    let output = run(&["compile", "--partial-eval", program]);
+    assert!(output.status.success());
+    let code = String::from_utf8(output.stdout).unwrap();
+    assert!(!code.contains("while"));
+    assert!(
+        run(&["compile", "--target", "wasm", "--partial-eval", program])
+            .status
+            .success()
+    );
+}
+
+/// Test that the output of a program without input is cached, and that
+/// runs that may differ are not.
+#[test]
+fn test_output_cache() {
+    let dir = std::env::temp_dir().join(format!("brainfuck_vm_cache_{}", std::process::id()));
+    let run = |args: &[&str]| {
+        brainfuck_vm()
+            .args(args)
+            .env("BRAINFUCK_VM_CACHE_DIR", &dir)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let hello = "tests/cli/hello.b";
+    let cached = "output taken from the cache\n";
+    let entries = || {
+        let entries = std::fs::read_dir(&dir).unwrap();
+        let is_output = |path: &std::path::Path| path.extension().is_some_and(|ext| ext == "out");
+        entries
+            .filter(|entry| is_output(&entry.as_ref().unwrap().path()))
+            .count()
+    };
+
+    let first = run(&["--verbose", hello]);
+    assert!(first.status.success());
+    assert_eq!(first.stdout, b"Hello World!\n");
+    assert!(!String::from_utf8(first.stderr).unwrap().contains(cached));
+    let second = run(&["--verbose", hello]);
+    assert_eq!(second.stdout, b"Hello World!\n");
+    assert!(String::from_utf8(second.stderr).unwrap().ends_with(cached));
+    assert_eq!(entries(), 1);
+
+    for args in [
+        &["--no-cache", hello][..],
+        &["--max-steps", "100000", hello],
+        &["--exit-cell", hello],
+        &["--random-ext", "?[-]+++."],
+        &["+++.,."],
+    ] {
+        for _ in 0..2 {
+            let output = run(&[&["--verbose"][..], args].concat());
+            assert!(!String::from_utf8(output.stderr).unwrap().contains(cached));
+        }
+    }
+    // Another cell width is another entry.
+    let output = run(&["--verbose", "--cell-size", "16", hello]);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains(cached));
+    assert_eq!(entries(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Test that a compiled program is kept between runs, also for programs
+/// that read input, and that an entry that does not decode is replaced.
+#[test]
+fn test_program_cache() {
+    let dir = std::env::temp_dir().join(format!("brainfuck_vm_programs_{}", std::process::id()));
+    let run = |args: &[&str]| {
+        brainfuck_vm()
+            .args(args)
+            .env("BRAINFUCK_VM_CACHE_DIR", &dir)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let programs = || -> Vec<_> {
+        let entries = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path());
+        entries
+            .filter(|path| path.extension().is_some_and(|ext| ext == "prog"))
+            .collect()
+    };
+    let program = "++++++[>++++++++<-]>+.+.<+++[>.-<-],[.,]";
+    let skipped = "compile: skipped";
+
+    let first = run(&["--time", program]);
+    assert!(first.status.success(), "{first:?}");
+    assert_eq!(first.stdout, b"12210");
+    assert!(!String::from_utf8(first.stderr).unwrap().contains(skipped));
+    assert_eq!(programs().len(), 1);
+    let second = run(&["--time", program]);
+    assert_eq!(second.stdout, first.stdout);
+    assert!(String::from_utf8(second.stderr).unwrap().contains(skipped));
+
+    // Other optimizations are another entry.
+    let output = run(&["--time", "-O0", program]);
+    assert_eq!(output.stdout, first.stdout);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains(skipped));
+    assert_eq!(programs().len(), 2);
+    let output = run(&["--time", "--no-cache", program]);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains(skipped));
+
+    // Every kind of command survives the round trip.
+    for args in [
+        &["--emit-ir", "--file", "tests/ir/digits.b"][..],
+        &["--emit-ir", "--file", "tests/ir/copy.b"],
+        &["--emit-ir", "--dialect", "pbrain", "+(>[>]<[<].,)::"],
+        &["--emit-ir", "--debug-ext", ">>+<<#[-]"],
+    ] {
+        let fresh = run(args);
+        assert!(fresh.status.success(), "{fresh:?}");
+        assert_eq!(run(args).stdout, fresh.stdout);
+    }
+
+    for path in programs() {
+        std::fs::write(path, b"\x02\0\0\0\0\0\0\0\x06\x07").unwrap();
+    }
+    let output = run(&["--time", program]);
+    assert_eq!(output.stdout, first.stdout);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains(skipped));
+    let output = run(&["--time", program]);
+    assert!(String::from_utf8(output.stderr).unwrap().contains(skipped));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Test that `--engine threaded` and `--engine jit` run like the default
+/// engine, and that the jit one needs its feature.
+#[test]
+fn test_engine() {
+    let program = "++++++[>++++++++<-]>+.+.<+++[>.-<-]";
+    let output = run(&["--engine", "threaded", program]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, run(&["--engine", "match", program]).stdout);
+    assert_eq!(output.stdout, b"12210");
+
+    let output = run(&["--engine", "jit", program]);
+    if cfg!(feature = "jit") {
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"12210");
+    } else {
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("the jit engine needs the 'jit' feature\nUsage:"));
+    }
+}
+
+/// Test that `gen` writes a program that prints the text or file it is given.
+#[test]
+fn test_gen() {
+    let output = run(&["gen", "Hi!"]);
+    assert!(output.status.success());
+    let program = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(run(&[&program]).stdout, b"Hi!");
+    let output = run(&["gen", "--", "-h"]);
+    assert!(output.status.success());
+    let program = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(run(&[&program]).stdout, b"-h");
+
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("gen-input-{}", std::process::id()));
+    let program = dir.join(format!("gen-program-{}", std::process::id()));
+    std::fs::write(&input, [0, 200, 255, 7]).unwrap();
+    let output = run(&[
+        "gen",
+        "--input-file",
+        input.to_str().unwrap(),
+        "-o",
+        program.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let output = run(&["--file", program.to_str().unwrap()]);
+    assert_eq!(output.stdout, [0, 200, 255, 7]);
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(program).unwrap();
+
+    assert!(!run(&["gen"]).status.success());
+}
+
+/// Test that `fmt` output runs the same, and that `--check` only passes
+/// on formatted files.
+#[test]
+fn test_fmt() {
+    let hello = "hello: ++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+        >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    let path = std::env::temp_dir().join(format!("fmt-{}.b", std::process::id()));
+    std::fs::write(&path, hello).unwrap();
+    let file = path.to_str().unwrap();
+    assert!(!run(&["fmt", "--check", file]).status.success());
+
+    let output = run(&["fmt", "--width", "30", "--indent", "4", file]);
+    assert!(output.status.success());
+    let formatted = String::from_utf8(output.stdout).unwrap();
+    assert!(formatted.starts_with("hello: ++++++++[\n    >++++[\n"));
+    assert!(formatted.lines().all(|line| line.len() <= 30));
+    assert_eq!(run(&[&formatted]).stdout, b"Hello World!\n");
+
+    let output = run(&["fmt", "--strip-comments", "-o", file, file]);
+    assert!(output.status.success());
+    let stripped = std::fs::read_to_string(&path).unwrap();
+    assert!(stripped.starts_with("++++++++[\n  >++++[\n"));
+    assert!(run(&["fmt", "--check", file]).status.success());
+    assert!(!run(&["fmt", "--check", "-o", file, file]).status.success());
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Test that a program minified at both levels prints the same, and that
+/// the savings are reported.
+#[test]
+fn test_minify() {
+    let original = run(&["--file", "tests/cli/commented.b"]);
+    assert_eq!(original.stdout, b"Hello World!\n");
+
+    for level in ["1", "2"] {
+        let output = run(&["minify", "--level", level, "tests/cli/commented.b"]);
+        assert!(output.status.success());
+        let minified = String::from_utf8(output.stdout).unwrap();
+        assert!(minified.chars().all(|ch| "+-<>.,[]\n".contains(ch)));
+        assert_eq!(run(&[minified.trim_end()]).stdout, original.stdout);
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let saved = std::fs::metadata("tests/cli/commented.b").unwrap().len() as usize
+            - minified.trim_end().len();
+        assert!(stderr.ends_with(&format!("saved {saved}\n")), "{stderr}");
+    }
+
+    let output = run(&["minify", "--level", "2", "tests/cli/commented.b"]);
+    let minified = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        minified,
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+        >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.\n"
+    );
+    assert!(
+        !run(&["minify", "--level", "3", "tests/cli/commented.b"])
+            .status
+            .success()
+    );
+    assert!(
+        !run(&["minify", "--level", "2", "tests/cli/lib/open.b"])
+            .status
+            .success()
+    );
+}
+
+/// Test that `export-html` writes a page with one span per bracket.
+#[test]
+fn test_export_html() {
+    let page = std::env::temp_dir().join(format!("export-{}.html", std::process::id()));
+    let output = run(&[
+        "export-html",
+        "tests/cli/commented.b",
+        "-o",
+        page.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let html = std::fs::read_to_string(&page).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>commented.b</title>"));
+    assert_eq!(html.matches(r#" data-pair="b"#).count(), 10);
+    std::fs::remove_file(page).unwrap();
+
+    let output = run(&["export-html", "tests/cli/lib/open.b"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("parse error:")
+    );
+}
+
+/// Test that `compile` writes C with the settings of a run, and rejects
+/// the flags that only a run has.
+#[test]
+fn test_compile() {
+    let code = std::env::temp_dir().join(format!("compile-{}.c", std::process::id()));
+    let output = run(&[
+        "compile",
+        "--target",
+        "c",
+        "--cell-size",
+        "16",
+        "--tape-size",
+        "64",
+        "tests/cli/commented.b",
+        "-o",
+        code.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let c = std::fs::read_to_string(&code).unwrap();
+    assert!(c.contains("#define TAPE_LEN 64ull\n"));
+    assert!(c.contains("typedef uint16_t cell;\n"));
+    std::fs::remove_file(code).unwrap();
+
+    let output = run(&["compile", "--eof", "minus-one", ",."]);
+    assert!(output.status.success());
+    let code = String::from_utf8(output.stdout).unwrap();
+    assert!(code.contains("        *value = 255;\n"));
+
+    let output = run(&["compile", "--target", "rust", "--cell-size", "i8", ",."]);
+    assert!(output.status.success());
+    let code = String::from_utf8(output.stdout).unwrap();
+    assert!(code.contains("type Cell = i8;\n"));
+    assert!(code.contains("    m.read(0, 0)?;\n    m.write(0, 1)?;\n"));
+
+    let output = run(&["compile", "--target", "wasm", "+."]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"\0asm"));
+
+    for (args, message) in [
+        (&["--target", "js", "+"][..], "unknown target 'js'\nUsage:"),
+        (
+            &["--max-steps", "5", "+"],
+            "--max-steps cannot be compiled\nUsage:",
+        ),
+        (
+            &["--debug-ext", "+#"],
+            "cannot compile: command 1 cannot be translated",
+        ),
+        (&["tests/cli/lib/open.b"], "parse error:"),
+        (
+            &["--target", "wasm", "--tape-size", "5000000000", "+"],
+            "cannot compile: the tape does not fit into wasm memory",
+        ),
+    ] {
+        let output = run(&[&["compile"][..], args].concat());
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with(message), "{stderr}");
+    }
+}
+
+/// Test that `build` makes an executable that behaves like the
+/// interpreter, and reports compiler errors. Skipped without a C compiler.
+#[test]
+fn test_build() {
+    let compiler = std::env::var("CC").unwrap_or("cc".into());
+    if !Command::new(&compiler)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+    {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("brainfuck_vm_build_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("cat.b");
+    std::fs::write(&program, ",[.,]").unwrap();
+    let build = |args: &[&str]| {
+        brainfuck_vm()
+            .arg("build")
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+    let pipe = |command: &mut Command, input: &[u8]| {
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(&mut child.stdin.take().unwrap(), input).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = build(&["--opt", program.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.join("target").join("bf").join("cat.c").is_file());
+    let input = b"piped\nthrough cat\n";
+    let native = pipe(
+        &mut Command::new(dir.join(format!("cat{}", std::env::consts::EXE_SUFFIX))),
+        input,
+    );
+    let interpreted = pipe(brainfuck_vm().arg(&program), input);
+    assert!(native.status.success());
+    assert_eq!(native.stdout, interpreted.stdout);
+    assert_eq!(native.stdout, input);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let fake = dir.join("fake-cc");
+        std::fs::write(
+            &fake,
+            "#!/bin/sh\nfor f; do :; done\necho \"$f:2:1: error: boom\" >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let output = brainfuck_vm()
+            .args(["build", "-o", "broken", "+."])
+            .env("CC", &fake)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("target/bf/broken.c:2:1: error: boom\n"),
+            "{stderr}"
+        );
+        assert!(
+            stderr.contains("generated line 2: /* Translated from Brainfuck"),
+            "{stderr}"
+        );
+    }
+
+    for (args, message) in [
+        (
+            &["+."][..],
+            "build needs -o for a program given inline\nUsage:",
+        ),
+        (
+            &["--timeout", "5s", "-o", "x", "+"],
+            "--timeout cannot be compiled\nUsage:",
+        ),
+    ] {
+        let output = build(args);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with(message), "{stderr}");
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Test that `--random-ext` makes `?` repeatable with `--seed`.
+#[test]
+fn test_random_ext() {
+    let program = "?.>?.>?.>?.";
+    let first = run(&["--random-ext", "--seed", "42", program]);
+    let again = run(&["--random-ext", "--seed", "42", program]);
+    let other = run(&["--random-ext", "--seed", "43", program]);
+    assert!(first.status.success());
+    assert_eq!(first.stdout.len(), 4);
+    assert_eq!(first.stdout, again.stdout);
+    assert_ne!(first.stdout, other.stdout);
+
+    // Without the flag `?` is a comment.
+    assert!(
+        run(&["--seed", "42", program])
+            .stdout
+            .iter()
+            .all(|&byte| byte == 0)
+    );
+}
+
+/// Test that `--comments` lets prose contain brackets, and that the default
+/// still runs them.
+#[test]
+fn test_comments() {
+    let program = "{ Adds two numbers, see [RFC 1]. } ++>+++[<+>-]<.";
+    let output = run(&["--comments", "braces", program]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, run(&["++>+++[<+>-]<."]).stdout);
+    assert_eq!(output.stdout, [5]);
+
+    let program = "++>+++[<+>-]<. ; prints [the sum]\n";
+    let output = run(&["--comments", "semicolon", program]);
+    assert_eq!(output.stdout, [5]);
+
+    let output = run(&["--comments", "braces", "+[{]}"]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unmatched '[' at offset 1\n"
+    );
+}
+
+/// Test that a file with a `#!` line runs as an executable script, even
+/// though the line would otherwise be code.
+#[cfg(unix)]
+#[test]
+fn test_shebang() {
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cli/hello.b");
+    let bin_dir = std::path::Path::new(env!("CARGO_BIN_EXE_brainfuck_vm"))
+        .parent()
+        .unwrap();
+    let path = std::env::join_paths(std::iter::once(bin_dir.to_path_buf()).chain(
+        std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+    ))
+    .unwrap();
+
+    let output = Command::new(script)
+        .env("PATH", path)
+        .env("BRAINFUCK_VM_CACHE_DIR", CACHE_DIR)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"Hello World!\n");
+
+    let output = run(&["--file", script]);
+    assert_eq!(output.stdout, b"Hello World!\n");
+}
+
+/// Test that flags after a program file still apply, and that nothing but
+/// flags may follow it.
+#[test]
+fn test_flags_after_file() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let cat = format!("{dir}/cat.b");
+    std::fs::write(&cat, ",[.,]").unwrap();
+    let input = format!("{dir}/cat-input.txt");
+    std::fs::write(&input, "from the file").unwrap();
+
+    let output = run(&[&cat, "--input", &input]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"from the file");
+
+    let output = run(&["tests/cli/hello.b", "--bogus"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stderr.starts_with(b"unknown flag '--bogus'\nUsage:"));
+
+    let output = run(&["tests/cli/hello.b", "extra"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        output
+            .stderr
+            .starts_with(b"unexpected argument 'extra'\nUsage:")
+    );
+}
+
+/// Test that a program file runs by its path, with a leading byte-order
+/// mark skipped, and that a file that cannot be read is named.
+#[test]
+fn test_program_file() {
+    let path = format!("{}/hello-bom.b", env!("CARGO_TARGET_TMPDIR"));
+    let hello = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\n\
+                 >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.\n";
+    std::fs::write(&path, format!("\u{feff}{hello}")).unwrap();
+
+    for args in [&[&path[..]][..], &["--file", &path]] {
+        let output = run(args);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(output.stdout, b"Hello World!\n");
+    }
+
+    let output = run(&["--file", "/nonexistent/hello.b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("cannot read '/nonexistent/hello.b': ")
+    );
+}
+
+/// Test that an argument that is not a file still runs as the program.
+#[test]
+fn test_inline_program() {
+    let output = run(&["++++++++[>++++++++<-]>+."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"A");
+}
+
+/// Test that `-` reads the program from stdin, leaving `,` to read from
+/// `--input` or find the end of its input.
+#[test]
+fn test_stdin_program() {
+    let input = format!("{}/stdin-program-input", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&input, "piped").unwrap();
+
+    // Bytes that are not UTF-8 are comments.
+    let output = run_with_stdin(&["--input", &input, "-"], b",[.,]\xff\xfe");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"piped");
+
+    let output = run_with_stdin(&["--eof", "minus-one", "-"], b",+.");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, [0]);
+
+    let output = run_with_stdin(&["-", "+."], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("'-' cannot be combined with --file or a program argument\n")
+    );
+}
+
+/// Test that `--input` and `--input-bytes` are what `,` reads, up to the
+/// end of input of the EOF behavior, and that a missing file is reported
+/// as an I/O error before anything is printed.
+#[test]
+fn test_input() {
+    let fixture = "tests/cli/commented.b";
+    let output = run(&["--input", fixture, ",[.,]"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, std::fs::read(fixture).unwrap());
+
+    let output = run(&["--eof", "minus-one", "--input-bytes", "ab", ",.,.,."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, [b'a', b'b', 255]);
+
+    let output = run(&["hotspots", "--input-bytes", "abc", "--json", ",[.,]"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.starts_with(b"{\"steps\":"));
+
+    // A missing file is not a malformed command line, so it comes without
+    // the usage.
+    for args in [
+        &["--emit-ir", "--input", "/nonexistent/input", "+."][..],
+        &["--input", "/nonexistent/input", "tests/cli/hello.b"],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "cannot read '/nonexistent/input': No such file or directory (os error 2)\n"
+        );
+    }
+
+    let output = run(&["--input-bytes", "a", "--input", fixture, ","]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+/// Test that `--output` gets every byte value as it is written, also from
+/// a run that fails, that `--append` adds to the file, and that a file
+/// that cannot be written fails without the usage.
+#[test]
+fn test_output() {
+    let path = format!("{}/output.bin", env!("CARGO_TARGET_TMPDIR"));
+    let output = run(&["--output", &path, "--time", ".+[.+]"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+    let all: Vec<u8> = (0..=255).collect();
+    assert_eq!(std::fs::read(&path).unwrap(), all);
+
+    // `\n` and `\r` are not translated.
+    let output = run(&["--output", &path, "--append", "++++++++++.+++.<"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(std::fs::read(&path).unwrap(), [&all[..], b"\n\r"].concat());
+
+    let output = run(&["--output", &path, "+."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(std::fs::read(&path).unwrap(), [1]);
+
+    let output = run(&["--append", "+."]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // A file that cannot be written is not a malformed command line.
+    let output = run(&["--output", "/nonexistent/output", "+."]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "cannot write '/nonexistent/output': No such file or directory (os error 2)\n"
+    );
+}
+
+/// Test every subcommand that works on a program, and that a program on
+/// its own still runs as with `run`.
+#[test]
+fn test_subcommands() {
+    let hello = "tests/cli/hello.b";
+    for args in [
+        &["run", hello][..],
+        &[hello],
+        &["run", "--tape-size", "100", hello],
+    ] {
+        let output = run(args);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(output.stdout, b"Hello World!\n");
+    }
+
+    let output = run(&["check", hello]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    let output = run(&["check", "+[>+]]"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unmatched ']' at offset 5\n"
+    );
+
+    let output = run(&["compile", "--target", "c", "+."]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("putchar")
+    );
+
+    let output = run(&["fmt", hello]);
+    assert!(output.status.success(), "{output:?}");
+    let formatted = String::from_utf8(output.stdout).unwrap();
+    assert!(formatted.trim_end().ends_with(">>+.>++."), "{formatted}");
+
+    // Entries go on from the cells and pointer the one before left, and a
+    // failing entry does not end the session.
+    let output = run_with_stdin(&["repl", "--eof", "unchanged", "+++"], b">++\n]\n.<.\n");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, [2, 3]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unmatched ']' at offset 0\n"
+    );
+}
+
+/// Test that `check` reports the errors of every file, valid or not, and
+/// all of them with `--json`, with the flags given anywhere.
+#[test]
+fn test_check() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let valid = "tests/cli/hello.b";
+    let unmatched = format!("{dir}/unmatched.b");
+    std::fs::write(&unmatched, "+[-]]\n").unwrap();
+    let two = format!("{dir}/two-problems.b");
+    std::fs::write(&two, "]\n+[->+<]\n[").unwrap();
+
+    let output = run(&["check", valid]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stderr.is_empty());
+
+    // Checking goes on past the file that fails.
+    let output = run(&["check", &unmatched, valid, "/nonexistent.b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "parse error: unmatched ']' at offset 4 in {unmatched}\n\
+             cannot read '/nonexistent.b': No such file or directory (os error 2)\n"
+        )
+    );
+
+    let output = run(&["check", "--json", &two, valid]);
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"file": two, "offset": 0, "line": 1, "column": 1, "message": "unmatched ']'"},
+            {"file": two, "offset": 10, "line": 3, "column": 1, "message": "unmatched '['"},
+        ])
+    );
+
+    let output = run(&["check", "--json", valid]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"[]\n");
+
+    // Flags of the run apply wherever they appear.
+    let output = run(&["check", valid, "--strict"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        output
+            .stderr
+            .starts_with(b"parse error: unexpected character 'P' (U+0050) at offset 50 in "),
+        "{output:?}"
+    );
+    let output = run(&["check", valid, "--cell-size", "16", valid]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+/// Test that `repl` runs entries across lines while brackets are open,
+/// takes meta-commands, and keeps the tape of failing entries as it was
+/// before them.
+#[test]
+fn test_repl() {
+    let script = b"++[\n>+++<-\n]\n:pointer 1\n.\n+>>>>>>>\n:dump\n:pointer 9\n:reset\n:dump\n\
+        :load tests/cli/hello.b\n:frobnicate\n:quit\n+.\n";
+    let output = run_with_stdin(&["repl", "--tape-size", "8"], script);
+    assert!(output.status.success(), "{output:?}");
+    let mut expected = vec![6];
+    expected.extend(
+        b"0:0 [1:6] 2:0 3:0 4:0 5:0 6:0 7:0\n[0:0] 1:0 2:0 3:0 4:0 5:0 6:0 7:0\nHello World!\n",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&expected)
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines.len(), 3, "{stderr}");
+    assert!(lines[0].starts_with("runtime error: "), "{stderr}");
+    assert_eq!(lines[1], "cell 9 is past the end of the tape of 8 cells");
+    assert!(lines[2].starts_with("unknown command ':frobnicate'"));
+
+    // An entry cut off with a bracket open is reported at the end.
+    let output = run_with_stdin(&["repl"], b"+[\n-");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "parse error: unmatched '[' at offset 1\n"
+    );
+}
+
+/// Test `--help` and `--version`, and that command lines that cannot be
+/// parsed exit with 2 rather than the 1 of failing programs and of files
+/// that cannot be read.
+#[test]
+fn test_usage() {
+    let output = run(&["--help"]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"Usage: brainfuck_vm [run] "));
+    let output = run(&["check", "--help"]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"Usage: brainfuck_vm check "));
+    let output = run(&["fmt", "--width", "40", "-h"]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"Usage: brainfuck_vm fmt "));
+    // Only flags ask for help, not the values of flags.
+    let output = run(&["--input-bytes", "-h", ",[.,]"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"-h");
+
+    let output = run(&["--version"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("brainfuck_vm {}\n", env!("CARGO_PKG_VERSION"))
+    );
+
+    for args in [
+        &["--bogus", "+"][..],
+        &["run", "-O3", "+"],
+        &["check", "--tape-size"],
+        &["compile", "--target", "cobol", "+"],
+        &["fmt", "--wide", "tests/cli/hello.b"],
+        &["repl", "--bogus"],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("\nUsage: brainfuck_vm "), "{stderr}");
+    }
+    let output = run(&["--bogus", "+"]);
+    assert!(output.stderr.starts_with(b"unknown flag '--bogus'\n"));
+
+    for (args, path) in [
+        (
+            &["--file", "/nonexistent/hello.b"][..],
+            "/nonexistent/hello.b",
+        ),
+        (
+            &["--tape-init", "/nonexistent/tape", "+"],
+            "/nonexistent/tape",
+        ),
+        (
+            &["--dialect-map", "/nonexistent/map", "+"],
+            "/nonexistent/map",
+        ),
+        (
+            &["gen", "--input-file", "/nonexistent/text"],
+            "/nonexistent/text",
+        ),
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(1), "{args:?}");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            format!("cannot read '{path}': No such file or directory (os error 2)\n")
+        );
+    }
+
+    // Help piped to a reader that has gone away is not a crash.
+    let mut child = brainfuck_vm()
+        .arg("--help")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    assert!(child.wait().unwrap().success());
+    // Programs may still start with `-`.
+    assert_eq!(run(&["---."]).stdout, [253]);
+}
+
+/// Test that `verify` finds no difference on the bundled programs, with
+/// every pass on and with one left out, and reports runs it cannot finish.
+#[test]
+fn test_verify() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests");
+    for file in ["cli/hello.b", "cli/commented.b", "ir/digits.b", "ir/copy.b"] {
+        let path = format!("{dir}/{file}");
+        for level in ["-O1", "-O2"] {
+            let args = ["verify", "--random-inputs", "8", level, "--file", &path];
+            let output = run(&args);
+            assert!(output.status.success(), "{file} {level}: {output:?}");
+            assert_eq!(output.stdout, b"verified 8 inputs\n");
+        }
+    }
+    let output = run(&["verify", "--disable-pass", "mul-loops", ",[->++<]>."]);
+    assert_eq!(output.stdout, b"verified 1 input\n");
+
+    let input = format!("{dir}/cli/hello.b");
+    let output = run(&["verify", "--input", &input, "--seed", "5", ",[.,]"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = run(&["verify", "--max-steps", "100", "+[]"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "cannot verify input 0: the unoptimized program did not finish within 100 steps\n"
+    );
+
+    let output = run(&["verify", "--input", "/nonexistent/input", "+"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("cannot read '/nonexistent/input'")
+    );
+}
+
+/// Test that `compile -o FILE.bfc` writes a program that runs like its
+/// source, and that files compiled differently or damaged are refused.
+#[test]
+fn test_bfc() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let hello = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cli/hello.b");
+    let path = format!("{dir}/hello.bfc");
+    let output = run(&["compile", "-o", &path, hello]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(std::fs::read(&path).unwrap().starts_with(b"\x7fBFC"));
+    let output = run(&[&path]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"Hello World!\n");
+    assert_eq!(run(&["--file", &path, "-O2"]).stdout, b"Hello World!\n");
+
+    for (args, expected) in [
+        (
+            &["--cell-size", "16"][..],
+            "compiled with --cell-size 8, not 16",
+        ),
+        (&["-O0"], "compiled with -O2, not -O0"),
+    ] {
+        let output = run(&[args, &[&path]].concat());
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(expected), "{stderr}");
+    }
+
+    let signed = format!("{dir}/signed.bfc");
+    let args = [
+        "compile",
+        "--target",
+        "bfc",
+        "--cell-size",
+        "i8",
+        "-O1",
+        "-o",
+        &signed,
+    ];
+    assert!(run(&[&args[..], &["-[-.]"]].concat()).status.success());
+    let output = run(&[&signed]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout.len(), 255);
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.truncate(bytes.len() - 3);
+    let broken = format!("{dir}/broken.bfc");
+    std::fs::write(&broken, &bytes).unwrap();
+    let output = run(&[&broken]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("'{broken}': the .bfc file is cut short\n")
+    );
+}
+
+/// Test that `disasm --range` lists part of the program at its depth, and
+/// that `--source-map` needs source it can map.
+#[test]
+fn test_disasm() {
+    let output = run(&["disasm", "-O0", "--range", "3..6", "+[>[-]<]"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"3    jz 5\n4      dec\n5    jnz 3\n");
+    let output = run(&["disasm", "--range", "5..", "+[-]"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["disasm", "--range", "5", "+"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("invalid range '5', expected e.g. 100..200\nUsage:"));
+    let output = run(&["disasm", "--source-map", "--dialect", "ook", "Ook. Ook."]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // Offsets point past the `#` comments of --strict and into the source
+    // before its macros are expanded.
+    let output = run(&["disasm", "--source-map", "--strict", "-O0", "# [+]\n+."]);
+    assert_eq!(output.stdout, b"0  @6  inc\n1  @7  out\n");
+    let args = [
+        "disasm",
+        "--source-map",
+        "--macros",
+        "-O0",
+        "@def two { ++ } @two .",
+    ];
+    assert_eq!(
+        run(&args).stdout,
+        b"0  @11  inc\n1  @12  inc\n2  @21  out\n"
+    );
+    // A compiled program lists like its source.
+    let hello = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cli/hello.b");
+    let path = format!("{}/disasm.bfc", env!("CARGO_TARGET_TMPDIR"));
+    assert!(run(&["compile", "-o", &path, hello]).status.success());
+    assert_eq!(
+        run(&["disasm", &path]).stdout,
+        run(&["disasm", hello]).stdout
+    );
+}
+
+/// Test the fields of the bench report, which hold the same steps on every
+/// run, and that a failing run fails the bench.
+#[test]
+fn test_bench() {
+    let input = format!("{}/bench-input", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&input, "abc").unwrap();
+    let args = [
+        "bench", "--runs", "3", "--warmup", "2", "--json", "--input", &input, ",[.,]",
+    ];
+    let output = run(&args);
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["runs"], 3);
+    assert_eq!(report["warmup"], 2);
+    // Two commands to start, then three for each byte.
+    assert_eq!(report["steps"], 11);
+    for field in [
+        "min_ns",
+        "median_ns",
+        "mean_ns",
+        "stddev_ns",
+        "steps_per_second",
+    ] {
+        assert!(report[field].is_u64(), "{field}: {report}");
+    }
+    assert!(report["min_ns"].as_u64() <= report["median_ns"].as_u64());
+
+    let output = run(&["bench", "--runs", "2", "+++[-]"]);
+    let table = String::from_utf8(output.stdout).unwrap();
+    let names: Vec<_> = table.lines().map(|line| line.split(' ').next()).collect();
+    let expected = ["runs", "steps", "min", "median", "mean", "stddev", "speed"];
+    assert_eq!(names, expected.map(Some));
+    assert!(table.starts_with("runs    2 (after 1 warmup)\nsteps   1 per run\n"));
+
+    let output = run(&["bench", "--runs", "0", "+"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        output
+            .stderr
+            .starts_with(b"--runs needs at least 1 run\nUsage: brainfuck_vm bench")
+    );
+    let output = run(&["bench", "--max-steps", "5", "+[]"]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: step limit of 5 exceeded at instruction 2\n"
+    );
+    assert!(output.stdout.is_empty());
+}
+
+/// Test that `--stats` counts every kind of command that ran.
+#[test]
+fn test_stats() {
+    let args = ["-O0", "--tape-init-hex", "0304", "--stats-json", "[->+<]>."];
+    let output = run(&args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, [7]);
+    let stats: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    let mut counted: Vec<_> = stats
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(name, count)| (name.as_str(), count.as_u64().unwrap()))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    counted.sort();
+    let expected = [
+        ("dec", 3),
+        ("inc", 3),
+        ("jnz_not_taken", 1),
+        ("jnz_taken", 2),
+        ("jz_not_taken", 1),
+        ("left", 3),
+        ("out", 1),
+        ("right", 4),
+        ("total", 18),
+    ];
+    assert_eq!(counted, expected);
+
+    let output = run(&["--stats", "+++."]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "out                      1   50.0%\nadd                      1   50.0%\n\
+         total                    2\n"
+    );
+}
+
+/// Test that `hotspots` ranks the loops and points them at the source.
+#[test]
+fn test_hotspots() {
+    let program = "++++[->++++[->+<]<]";
+    let output = run(&["hotspots", "-O0", "--json", program]);
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["steps"], 121);
+    let loops = report["loops"].as_array().unwrap();
+    let ranked: Vec<_> = loops
+        .iter()
+        .map(|profile| (profile["offset"].as_u64(), profile["steps"].as_u64()))
+        .collect();
+    assert_eq!(ranked, [(Some(4), Some(117)), (Some(11), Some(84))]);
+    // Without the outer loop's own steps, the inner loop does most of them.
+    let outer_only = loops[0]["steps"].as_u64().unwrap() - loops[1]["steps"].as_u64().unwrap();
+    assert!(loops[1]["steps"].as_u64().unwrap() > outer_only);
+    assert_eq!(loops[1]["entries"], 4);
+
+    // The offsets are those of the source as written, around its comments.
+    let output = run(&["hotspots", "-O0", "--top", "1", "ab++[-]"]);
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        table
+            .lines()
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .collect::<Vec<_>>(),
+        ["1", "2..4", "4", "5", "71.4%", "1"]
+    );
+    let output = run(&["hotspots", "--max-steps", "5", "+[]"]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "runtime error: step limit of 5 exceeded at instruction 2\n"
+    );
+}
@@ -0,0 +1,27 @@
+[ Prints "Hello World!" and a newline.
+
+  This whole loop is a comment: the first cell is still zero when it is
+  reached, so none of it runs, even the . and , in it. ]
+
+++++++++                set cell 0 to 8
+[
+    >++++               add 4 to cell 1
+    [                   and 4 times
+        >++ >+++ >+++ >+ add 2 3 3 and 1 to cells 2 to 5
+        <<<<-
+    ]
+    >+ >+ >- >>+        cells 2 3 4 and 6 get 1 1 minus 1 and 1
+    [<]                 back to the first zero cell which is cell 1
+    <-                  count down cell 0
+]
+[ cell 0 is zero here so this loop never runs either ]
+>>.                     H
+>---.                   e
++++++++..+++.           llo
+>>.                     space
+<-.                     W
+<.                      o
++++.------.--------.    rld
+>>+.                    !
+>++.                    newline
+<><>                    nothing at all
@@ -0,0 +1,4 @@
+#!/usr/bin/env -S brainfuck_vm --max-steps 100000
+Prints Hello World
+++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]
+>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.
@@ -0,0 +1,2 @@
+Opens a loop that nobody closes
++[
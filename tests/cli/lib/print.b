@@ -0,0 +1,2 @@
+Prints cells from the current one up to the next zero
+[.>]
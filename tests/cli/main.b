@@ -0,0 +1,8 @@
+Puts the letters H and i and a newline on the tape
+then prints them with the loop from the helper file
+
+>++++++++[<+++++++++>-]<
+>>++++++++++[<++++++++++>-]<+++++
+>++++++++++
+<<
+@include "lib/print.b"
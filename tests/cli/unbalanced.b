@@ -0,0 +1,2 @@
+@include "lib/open.b"
++.
@@ -0,0 +1,72 @@
+//! Programs and settings that every backend's translations are checked on.
+
+use brainfuck_vm::{Cell, EofBehavior, Interpreter, OverflowPolicy};
+
+/// Checks translations of programs against the interpreter.
+pub trait Backend {
+    /// Translates the program `name`, `source_code`, with cells `C`, and
+    /// checks that it does with `input` what `interpreter` does.
+    fn check<C: Cell>(
+        &mut self,
+        name: &str,
+        source_code: &str,
+        interpreter: &Interpreter,
+        input: &[u8],
+    );
+}
+
+/// Checks hello world and cat.
+pub fn hello_and_cat(backend: &mut impl Backend) {
+    let interpreter = Interpreter::default();
+    backend.check::<u8>(
+        "hello",
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+        >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        &interpreter,
+        b"",
+    );
+    backend.check::<u8>("cat", ",[.,]", &interpreter, b"cat\nthis back\n");
+}
+
+/// Checks that cell widths, overflow policies, EOF behaviors, and errors
+/// carry over.
+pub fn settings(backend: &mut impl Backend) {
+    let default = Interpreter::default();
+    let saturate = Interpreter::builder()
+        .overflow_policy(OverflowPolicy::Saturate)
+        .eof_behavior(EofBehavior::SetMinusOne)
+        .build()
+        .unwrap();
+    let strict = Interpreter::builder()
+        .tape_len(8)
+        .data_pointer(3)
+        .tape_init(2, *b"\x05\xff")
+        .overflow_policy(OverflowPolicy::Error)
+        .eof_behavior(EofBehavior::Unchanged)
+        .build()
+        .unwrap();
+    let wrap_around = "-.+.--.>+++[->------<]>.";
+    let saturating = "--.+++[>---------<-]>.,.,.+.";
+    let scan = ">>>+>+>+<<<<[>>[-]<+>>[>]<<<<<-]>>>>>.<.>";
+    let clear = "+>+>+>+<[-]<[-]<[-]>>[-]>[-]>[-]<<<<.>.>.>.>.";
+
+    backend.check::<u8>("wrap_u8", wrap_around, &default, b"");
+    backend.check::<u16>("wrap_u16", wrap_around, &default, b"");
+    backend.check::<u32>("wrap_u32", wrap_around, &default, b"");
+    backend.check::<i8>("wrap_i8", wrap_around, &default, b"");
+    backend.check::<i8>("saturate_i8", saturating, &saturate, b"x");
+    backend.check::<i8>("saturate_i8_negative", saturating, &saturate, b"\xc8");
+    backend.check::<u16>("saturate_u16", "-.,.+.", &saturate, b"");
+    backend.check::<u32>("saturate_u32", "-.,.+.", &saturate, b"");
+    backend.check::<u8>("random", "??.>?.", &default, b"");
+    backend.check::<u8>("scan", scan, &default, b"");
+    backend.check::<u8>("init", ".<.>>.,.,.", &strict, b"a");
+    backend.check::<u16>("init_u16", ".<.>>.,.,.", &strict, b"a");
+    backend.check::<u8>("overflow", "<-.>+.", &strict, b"");
+    backend.check::<u8>("underflow", ">>>>>-", &strict, b"");
+    backend.check::<u8>("left_edge", "+[.<]", &strict, b"");
+    backend.check::<u8>("right_edge", "+[>+]", &strict, b"");
+    backend.check::<u16>("clear", clear, &default, b"");
+    backend.check::<u8>("clear_right_edge", "+[-]>[-]>[-]>[-]>[-]>[-]", &strict, b"");
+    backend.check::<u8>("clear_left_edge", "[-]<[-]<[-]<[-]<[-]", &strict, b"");
+}
@@ -0,0 +1,33 @@
+//! Runs the programs in `tests/dialect` through their front ends.
+
+use brainfuck_vm::{DialectError, TokenMap, compile, eval, from_ook};
+
+/// Test that the Ook! hello world runs through `compile` and `eval`.
+/// It is a word-for-word translation of the usual Brainfuck hello world.
+#[test]
+fn test_ook_hello_world() {
+    let source = include_str!("dialect/hello.ook");
+    let program = compile(&from_ook(source).unwrap()).unwrap();
+    let mut output = Vec::new();
+
+    eval(&program, &[][..], &mut output).unwrap();
+
+    assert_eq!(output, b"Hello World!\n");
+}
+
+/// Test hello world in the emoji dialect from `tests/dialect/emoji.toml`.
+#[test]
+fn test_emoji_hello_world() {
+    let map = TokenMap::from_toml(include_str!("dialect/emoji.toml")).unwrap();
+    let source = include_str!("dialect/hello.emoji");
+    let program = compile(&map.translate_strict(source).unwrap()).unwrap();
+    let mut output = Vec::new();
+
+    eval(&program, &[][..], &mut output).unwrap();
+
+    assert_eq!(output, b"Hello World!\n");
+    assert_eq!(
+        map.translate_strict("👍👍 x"),
+        Err(DialectError::UnmatchedText { offset: 9 })
+    );
+}
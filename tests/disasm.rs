@@ -0,0 +1,52 @@
+//! Compares what `disasm` prints for the programs in `tests/disasm` with
+//! the listings next to them.
+//!
+//! A listing is what `brainfuck_vm disasm -O<level> [--source-map] --file
+//! <program>` prints; run that to update one after changing a pass on
+//! purpose.
+
+use std::process::Command;
+
+/// Program, flags, and the listing `disasm` prints for them.
+const LISTINGS: [(&str, &[&str], &str); 4] = [
+    (
+        "disasm/nested.b",
+        &["-O0"],
+        include_str!("disasm/nested.O0.lst"),
+    ),
+    (
+        "disasm/nested.b",
+        &["-O0", "--source-map"],
+        include_str!("disasm/nested.O0.map.lst"),
+    ),
+    ("cli/hello.b", &["-O2"], include_str!("disasm/hello.O2.lst")),
+    (
+        "cli/hello.b",
+        &["-O2", "--source-map"],
+        include_str!("disasm/hello.O2.map.lst"),
+    ),
+];
+
+/// Test that the listings stay the same.
+#[test]
+fn test_listings() {
+    for (file, flags, listing) in LISTINGS {
+        let path = format!("{}/tests/{file}", env!("CARGO_MANIFEST_DIR"));
+        let output = Command::new(env!("CARGO_BIN_EXE_brainfuck_vm"))
+            .arg("disasm")
+            .args(flags)
+            .args(["--file", &path])
+            .env(
+                "BRAINFUCK_VM_CACHE_DIR",
+                concat!(env!("CARGO_TARGET_TMPDIR"), "/cache"),
+            )
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            listing,
+            "{file} {flags:?}"
+        );
+    }
+}
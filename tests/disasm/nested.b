@@ -0,0 +1,2 @@
+[Multiplies two by three by four into the third cell]
+++[>+++[>++++<-]<-]
@@ -0,0 +1,39 @@
+//! Builds `tests/ffi/hello.c` against the cdylib and runs it.
+//! Requires a C compiler available as `cc`.
+#![cfg(all(feature = "ffi", unix))]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Test hello world from a C program linked against the library.
+#[test]
+fn test_c_hello_world() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // The test binary lives next to the cdylib in `target/<profile>/deps`.
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let binary = deps_dir.join("ffi_hello");
+
+    let status = Command::new("cc")
+        .arg(manifest_dir.join("tests/ffi/hello.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&deps_dir)
+        .arg("-lbrainfuck_vm")
+        .arg(format!("-Wl,-rpath,{}", deps_dir.display()))
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Cargo puts `target/<profile>` on the library path, whose copy of the
+    // cdylib may come from a build without the `ffi` feature.
+    let output = Command::new(&binary)
+        .env("LD_LIBRARY_PATH", &deps_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"Hello World!\n");
+}
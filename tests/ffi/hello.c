@@ -0,0 +1,31 @@
+/* Runs hello world through the C interface and prints its output. */
+#include <stdio.h>
+
+#include "brainfuck_vm.h"
+
+int main(void) {
+    const char *source =
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]"
+        ">>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    bf_program *program = NULL;
+    bf_output output = {NULL, 0};
+
+    int status = bf_compile(source, &program);
+    if (status != BF_OK) {
+        fprintf(stderr, "bf_compile failed with %d\n", status);
+        return 1;
+    }
+
+    status = bf_run(program, NULL, 0, &output);
+    if (status != BF_OK) {
+        fprintf(stderr, "bf_run failed with %d\n", status);
+        bf_free_program(program);
+        return 1;
+    }
+
+    fwrite(output.data, 1, output.len, stdout);
+
+    bf_free_output(&output);
+    bf_free_program(program);
+    return 0;
+}
@@ -0,0 +1,51 @@
+//! Compares the optimized programs in `tests/ir` with their listings.
+//!
+//! A listing is what `brainfuck_vm -O<level> --emit-ir --file <program>`
+//! prints; run that to update one after changing a pass on purpose.
+
+use brainfuck_vm::{OptLevel, Pipeline, compile, to_ir};
+
+/// Every program with a listing at every level.
+const PROGRAMS: [(&str, &str, [&str; 3]); 2] = [
+    (
+        "digits",
+        include_str!("ir/digits.b"),
+        [
+            include_str!("ir/digits.O0.ir"),
+            include_str!("ir/digits.O1.ir"),
+            include_str!("ir/digits.O2.ir"),
+        ],
+    ),
+    (
+        "copy",
+        include_str!("ir/copy.b"),
+        [
+            include_str!("ir/copy.O0.ir"),
+            include_str!("ir/copy.O1.ir"),
+            include_str!("ir/copy.O2.ir"),
+        ],
+    ),
+];
+
+/// Test that every level turns the programs into the listed commands.
+#[test]
+fn test_listings() {
+    let levels = [OptLevel::O0, OptLevel::O1, OptLevel::O2];
+    for (name, source_code, listings) in PROGRAMS {
+        let program = compile(source_code).unwrap();
+        for (level, listing) in levels.into_iter().zip(listings) {
+            let optimized = Pipeline::new(level).run(&program);
+            assert_eq!(to_ir(&optimized), listing, "{name} at {level:?}");
+        }
+    }
+}
+
+/// Test that the listings get shorter with every level.
+#[test]
+fn test_listing_lengths() {
+    for (name, _, [o0, o1, o2]) in PROGRAMS {
+        let lines = |listing: &str| listing.lines().count();
+        assert!(lines(o0) > lines(o1), "{name}");
+        assert!(lines(o1) > lines(o2), "{name}");
+    }
+}
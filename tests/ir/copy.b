@@ -0,0 +1,2 @@
+[Copies an input byte twice and prints it after clearing cells and scanning back]
+>,[>+>+<<-]>>[<<+>>-]>[-]>[-]+<<<[<]>.
@@ -0,0 +1,2 @@
+[Prints the digits from 0 to 9]
+>++++++[<++++++++>-]>++++++++++[<<.+>>-]
@@ -0,0 +1,363 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use brainfuck_vm::{
+    Command, Engine, Error, Interpreter, OptLevel, OverflowPolicy, ParsingError, Pass, Pipeline,
+    RuntimeError, compile, eliminate_dead_code, eval, optimize, validate,
+};
+
+/// Reader that hands out its bytes one at a time.
+struct ByteByByte(Vec<u8>);
+
+impl Read for ByteByByte {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0.remove(0);
+        Ok(1)
+    }
+}
+
+/// Writer that accepts a fixed number of bytes and then fails.
+struct Limited {
+    written: Vec<u8>,
+    capacity: usize,
+}
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written.len() == self.capacity {
+            return Err(io::Error::other("writer is full"));
+        }
+        let n = buf.len().min(self.capacity - self.written.len());
+        self.written.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Test that compiled output can be inspected through the public `Command` type.
+#[test]
+fn test_compiled_commands() {
+    let program = compile("+[.]").unwrap();
+
+    assert_eq!(
+        program,
+        [
+            Command::Increment,
+            Command::JumpForwardIfZero(3),
+            Command::WriteByte,
+            Command::JumpBackwardIfNonZero(1),
+        ]
+    );
+}
+
+/// Test running an endless printer against a custom writer that stops it.
+#[test]
+fn test_custom_writer() {
+    let program = compile("+[.]").unwrap();
+    let mut writer = Limited {
+        written: Vec::new(),
+        capacity: 3,
+    };
+
+    let result = eval(&program, io::empty(), &mut writer);
+
+    assert!(result.is_err());
+    assert_eq!(writer.written, [1, 1, 1]);
+}
+
+/// Test running the echo program against a custom reader.
+#[test]
+fn test_custom_reader() {
+    let program = compile(",[.,]").unwrap();
+    let reader = ByteByByte(b"abc".to_vec());
+    let mut writer = Vec::new();
+
+    eval(&program, reader, &mut writer).unwrap();
+
+    assert_eq!(writer, b"abc");
+}
+
+/// Test that unmatched brackets are reported through the public error type.
+#[test]
+fn test_unmatched_bracket() {
+    assert_eq!(
+        compile("+]"),
+        Err(ParsingError::UnmatchedBracket {
+            offset: 1,
+            bracket: ']'
+        })
+    );
+    assert_eq!(
+        compile("[[]"),
+        Err(ParsingError::UnmatchedBracket {
+            offset: 0,
+            bracket: '['
+        })
+    );
+}
+
+/// Test that leaving either end of the default tape is an error, not a panic.
+/// The checks do not rely on overflow checks, so release builds agree.
+#[test]
+fn test_pointer_out_of_bounds() {
+    let run = |source_code: &str| eval(&compile(source_code).unwrap(), io::empty(), io::sink());
+
+    assert!(matches!(
+        run("<"),
+        Err(Error::Runtime(RuntimeError::PointerOutOfBounds {
+            instruction_index: 0,
+            pointer: -1
+        }))
+    ));
+    assert!(matches!(
+        run(&">".repeat(30_000)),
+        Err(Error::Runtime(RuntimeError::PointerOutOfBounds {
+            instruction_index: 29_999,
+            pointer: 30_000
+        }))
+    ));
+}
+
+/// Test that the report says where the program stopped.
+#[test]
+fn test_final_cell() {
+    let program = compile("++>+++++<[->+<]>").unwrap();
+
+    let report = eval(&program, io::empty(), io::sink()).unwrap();
+
+    assert_eq!(report.final_pointer, 1);
+    assert_eq!(report.final_cell, 7);
+}
+
+/// I/O call seen by [`Terminal`].
+#[derive(Debug, PartialEq)]
+enum Event {
+    Write(u8),
+    Flush,
+    Read(u8),
+}
+
+/// Reader and writer pair standing in for an interactive terminal that
+/// records every call in order.
+#[derive(Clone)]
+struct Terminal {
+    log: Rc<RefCell<Vec<Event>>>,
+    keys: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Read for Terminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut keys = self.keys.borrow_mut();
+        if keys.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = keys.remove(0);
+        self.log.borrow_mut().push(Event::Read(buf[0]));
+        Ok(1)
+    }
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = self.log.borrow_mut();
+        log.extend(buf.iter().map(|&byte| Event::Write(byte)));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.log.borrow_mut().push(Event::Flush);
+        Ok(())
+    }
+}
+
+/// Test that each key is echoed right after it is read, between the prompt
+/// and the program's own output.
+#[test]
+fn test_echo_input() {
+    let terminal = Terminal {
+        log: Rc::default(),
+        keys: Rc::new(RefCell::new(b"ab".to_vec())),
+    };
+    // Prints '>', reads two keys, and prints the first one plus one.
+    let program = compile("++++++[>++++++++++<-]>++.,>,<+.").unwrap();
+    let interpreter = Interpreter::builder()
+        .tape_len(3)
+        .echo_input(true)
+        .max_output(2)
+        .build()
+        .unwrap();
+
+    let report = interpreter
+        .run(&program, terminal.clone(), terminal.clone())
+        .unwrap();
+
+    assert_eq!(
+        *terminal.log.borrow(),
+        [
+            Event::Write(b'>'),
+            Event::Flush,
+            Event::Read(b'a'),
+            Event::Write(b'a'),
+            Event::Flush,
+            Event::Read(b'b'),
+            Event::Write(b'b'),
+            Event::Flush,
+            Event::Write(b'b'),
+        ]
+    );
+    assert_eq!(report.bytes_written, 2);
+}
+
+/// Programs with their input and overflow policy, for tests that run them
+/// in different ways.
+fn corpus() -> Vec<(&'static str, &'static [u8], OverflowPolicy)> {
+    // Leaves out the `#!` line, which the CLI blanks.
+    let file = |text: &'static str| {
+        text.strip_prefix("#!")
+            .map_or(text, |t| &t[t.find('\n').unwrap()..])
+    };
+    vec![
+        (file(include_str!("cli/hello.b")), b"", OverflowPolicy::Wrap),
+        (
+            file(include_str!("cli/commented.b")),
+            b"",
+            OverflowPolicy::Wrap,
+        ),
+        (
+            file(include_str!("cli/lib/print.b")),
+            b"",
+            OverflowPolicy::Wrap,
+        ),
+        (",[.,]", b"echo this back", OverflowPolicy::Wrap),
+        (">,[>,]<[<]>[.>]", b"round trip", OverflowPolicy::Wrap),
+        (">,>,[<.>-]<<.>.", b"\x03\x05", OverflowPolicy::Wrap),
+        (
+            "++++++[>++++++++<-]>[>+>+<<-]>[->[.+]<]",
+            b"",
+            OverflowPolicy::Wrap,
+        ),
+        (
+            "-[>-[>>+<<-]<-]>>>[-<+>]<[>+<-]>.",
+            b"",
+            OverflowPolicy::Wrap,
+        ),
+        ("+[>[-]+<[<]>>+]", b"", OverflowPolicy::Wrap),
+        ("+[-<+]", b"", OverflowPolicy::Wrap),
+        (
+            "++[>+++<-]>[<+>>++<-]<[>>>-<<<-]>>>.",
+            b"",
+            OverflowPolicy::Saturate,
+        ),
+        ("+++[>++<-]>>-", b"", OverflowPolicy::Error),
+        (
+            "[a comment, with. commands]+++[>++<-][->+<]>.[<]>[+.]+[>+]",
+            b"",
+            OverflowPolicy::Wrap,
+        ),
+    ]
+}
+
+/// Test that every engine runs every program in the corpus the same way,
+/// with and without optimization, including where and how they fail.
+#[test]
+fn test_engines_agree() {
+    let engines = [Engine::Threaded, Engine::Jit];
+    for (source_code, input, overflow_policy) in corpus() {
+        let commands = compile(source_code).unwrap();
+        for commands in [optimize(&commands, overflow_policy), commands] {
+            let run = |engine| {
+                let interpreter = Interpreter::builder()
+                    .overflow_policy(overflow_policy)
+                    .engine(engine)
+                    .max_steps(1_000_000)
+                    .build()
+                    .unwrap();
+                let mut output = Vec::new();
+                let result = interpreter.run(&commands, input, &mut output);
+                (format!("{result:?}"), output)
+            };
+            let matched = run(Engine::Match);
+            for &engine in &engines {
+                assert_eq!(run(engine), matched, "{source_code} with {engine:?}");
+            }
+        }
+    }
+}
+
+/// Test that dropping dead loops changes neither the output nor the kind
+/// of error of any program in the corpus.
+#[test]
+fn test_dead_code_elimination() {
+    let mut removed = 0;
+    for (source_code, input, overflow_policy) in corpus() {
+        let interpreter = Interpreter::builder()
+            .overflow_policy(overflow_policy)
+            .max_steps(1_000_000)
+            .build()
+            .unwrap();
+        let run = |commands: &[Command]| {
+            let mut output = Vec::new();
+            let result = interpreter.run(commands, input, &mut output);
+            let kind = result.map(|_| ()).map_err(|e| match e {
+                Error::Runtime(e) => Some(std::mem::discriminant(&e)),
+                _ => None,
+            });
+            (kind, output)
+        };
+
+        let commands = compile(source_code).unwrap();
+        for commands in [optimize(&commands, overflow_policy), commands] {
+            let live = eliminate_dead_code(&commands, true);
+            assert_eq!(validate(&live), Ok(()), "{source_code}");
+            assert_eq!(run(&commands), run(&live), "{source_code}");
+            removed += commands.len() - live.len();
+        }
+    }
+    assert!(removed > 0);
+}
+
+/// Test that every level, and every level without each of its passes,
+/// runs the corpus like the unoptimized program.
+#[test]
+fn test_opt_levels() {
+    for (source_code, input, overflow_policy) in corpus() {
+        let interpreter = Interpreter::builder()
+            .overflow_policy(overflow_policy)
+            .max_steps(1_000_000)
+            .build()
+            .unwrap();
+        let run = |commands: &[Command]| {
+            let mut output = Vec::new();
+            let result = interpreter.run(commands, input, &mut output);
+            let kind = result.map(|_| ()).map_err(|e| match e {
+                Error::Runtime(e) => Some(std::mem::discriminant(&e)),
+                _ => None,
+            });
+            (kind, output)
+        };
+
+        let commands = compile(source_code).unwrap();
+        let expected = run(&commands);
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let pipeline = Pipeline::new(level).with_overflow_policy(overflow_policy);
+            for pass in Pass::ALL {
+                let pipeline = pipeline.clone().without(pass);
+                let optimized = pipeline.run(&commands);
+                assert_eq!(validate(&optimized), Ok(()), "{source_code}");
+                assert_eq!(
+                    run(&optimized),
+                    expected,
+                    "{source_code} at {level:?} without {}",
+                    pass.name()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Builds programs translated by `to_rust` with Cargo and checks that they
+//! behave like the interpreter. Skipped if Cargo cannot be started.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use brainfuck_vm::{Cell, Interpreter, compile_with_random, optimize, to_rust};
+
+use common::Backend;
+
+mod common;
+
+/// A translated program with its input and what the interpreter made of it.
+struct Case {
+    name: String,
+    code: String,
+    input: Vec<u8>,
+    /// Exit code, output, and error output.
+    expected: (Option<i32>, Vec<u8>, String),
+}
+
+/// Translates `source_code` as is and optimized, and runs both through the
+/// interpreter.
+fn cases<C: Cell>(
+    name: &str,
+    source_code: &str,
+    interpreter: &Interpreter,
+    input: &[u8],
+) -> [Case; 2] {
+    let commands = compile_with_random(source_code).unwrap();
+    let optimized = optimize(&commands, interpreter.overflow_policy());
+    [("", commands), ("_opt", optimized)].map(|(suffix, commands)| {
+        let mut output = Vec::new();
+        let result = interpreter.run_with_cells::<C, _, _>(&commands, input, &mut output);
+        let expected = match result {
+            Ok(_) => (Some(0), output, String::new()),
+            Err(e) => (Some(1), output, format!("{e}\n")),
+        };
+        Case {
+            name: format!("{name}{suffix}"),
+            code: to_rust::<C>(&commands, interpreter).unwrap(),
+            input: input.to_vec(),
+            expected,
+        }
+    })
+}
+
+/// Builds every case as a binary of one crate in `dir`, runs them, and
+/// checks their results.
+fn assert_same(dir: &str, cases: impl IntoIterator<Item = Case>) {
+    let dir = env::temp_dir().join(format!("brainfuck_vm_{dir}_{}", std::process::id()));
+    let bin = dir.join("src").join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"translated\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    )
+    .unwrap();
+    let cases: Vec<Case> = cases.into_iter().collect();
+    for case in &cases {
+        fs::write(bin.join(format!("{}.rs", case.name)), &case.code).unwrap();
+    }
+
+    let Ok(status) = Command::new(env!("CARGO"))
+        .args(["build", "--offline", "--quiet", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .env("RUSTFLAGS", "-D warnings")
+        .status()
+    else {
+        eprintln!("cannot start cargo, skipping");
+        return;
+    };
+    assert!(status.success(), "translations do not build");
+
+    for case in cases {
+        let actual = run(
+            &dir.join("target").join("debug").join(format!(
+                "{}{}",
+                case.name,
+                env::consts::EXE_SUFFIX
+            )),
+            &case.input,
+        );
+        assert_eq!(actual, case.expected, "{}", case.name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Runs `binary` on `input` and returns its exit code, output, and error
+/// output.
+fn run(binary: &Path, input: &[u8]) -> (Option<i32>, Vec<u8>, String) {
+    let mut child = Command::new(binary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (output.status.code(), output.stdout, stderr)
+}
+
+/// Cases are collected to build them all as one crate.
+impl Backend for Vec<Case> {
+    fn check<C: Cell>(
+        &mut self,
+        name: &str,
+        source_code: &str,
+        interpreter: &Interpreter,
+        input: &[u8],
+    ) {
+        self.extend(cases::<C>(name, source_code, interpreter, input));
+    }
+}
+
+/// Test hello world and cat.
+#[test]
+fn test_hello_and_cat() {
+    let mut cases = Vec::new();
+    common::hello_and_cat(&mut cases);
+    assert_same("rust_hello", cases);
+}
+
+/// Test the programs that depend on the settings of the interpreter.
+#[test]
+fn test_settings() {
+    let mut cases = Vec::new();
+    common::settings(&mut cases);
+    assert_same("rust_settings", cases);
+}
@@ -0,0 +1,28 @@
+//! Runs the exported bindings inside a WebAssembly runtime with
+//! `wasm-bindgen-test-runner` as the wasm32 runner and the `wasm` feature.
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use brainfuck_vm::bf_run;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Test hello world through the JavaScript-facing function.
+#[wasm_bindgen_test]
+fn test_hello_world() {
+    let source_code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]\
+        >>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    assert_eq!(bf_run(source_code, "", 10_000).unwrap(), "Hello World!\n");
+}
+
+/// Test that an infinite loop returns an error instead of hanging.
+#[wasm_bindgen_test]
+fn test_infinite_loop() {
+    let error = bf_run("+[]", "", 1000).unwrap_err();
+
+    assert!(
+        error
+            .as_string()
+            .unwrap()
+            .contains("step limit of 1000 exceeded")
+    );
+}
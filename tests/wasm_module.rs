@@ -0,0 +1,134 @@
+//! Checks the modules written by `to_wasm` with wasmparser, and runs them
+//! under Node.js, skipped if it is not installed, to compare them with the
+//! interpreter.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use brainfuck_vm::{
+    Cell, Error, Interpreter, RuntimeError, WASM_CELL_OVERFLOW, WASM_LEFT_OF_TAPE,
+    WASM_RIGHT_OF_TAPE, compile_with_random, optimize, to_wasm,
+};
+
+use common::Backend;
+
+mod common;
+
+/// Runs the module named on the command line with stdin as its input, and
+/// reports a trap as the `error` and `instruction` globals on stderr.
+const RUNNER: &str = r#"const fs = require("fs");
+const input = fs.readFileSync(0);
+let position = 0;
+const output = [];
+const env = {
+  read_byte: () => (position < input.length ? input[position++] : -1),
+  write_byte: (byte) => output.push(byte),
+};
+const compiled = new WebAssembly.Module(fs.readFileSync(process.argv[2]));
+const instance = new WebAssembly.Instance(compiled, { env });
+let status = 0;
+try {
+  instance.exports.run();
+} catch (e) {
+  const { error, instruction } = instance.exports;
+  process.stderr.write(`${error.value} ${instruction.value}\n`);
+  status = 1;
+}
+process.stdout.write(Buffer.from(output));
+process.exitCode = status;
+"#;
+
+/// Translates `source_code` as is and optimized, validates both modules,
+/// and checks that they behave like the interpreter.
+fn assert_same<C: Cell>(name: &str, source_code: &str, interpreter: &Interpreter, input: &[u8]) {
+    let commands = compile_with_random(source_code).unwrap();
+    let policy = interpreter.overflow_policy();
+    for (suffix, commands) in [
+        ("", commands.clone()),
+        ("_opt", optimize(&commands, policy)),
+    ] {
+        let name = format!("{name}{suffix}");
+        let module = to_wasm::<C>(&commands, interpreter).unwrap();
+        wasmparser::validate(&module).unwrap_or_else(|e| panic!("{name}: {e}"));
+
+        let mut output = Vec::new();
+        let result = interpreter.run_with_cells::<C, _, _>(&commands, input, &mut output);
+        let expected = match result {
+            Ok(_) => (Some(0), output, String::new()),
+            Err(e) => {
+                let (kind, instruction) = match e {
+                    Error::Runtime(RuntimeError::PointerOutOfBounds {
+                        instruction_index,
+                        pointer,
+                    }) if pointer < 0 => (WASM_LEFT_OF_TAPE, instruction_index),
+                    Error::Runtime(RuntimeError::PointerOutOfBounds {
+                        instruction_index, ..
+                    }) => (WASM_RIGHT_OF_TAPE, instruction_index),
+                    Error::Runtime(RuntimeError::CellOverflow { instruction_index }) => {
+                        (WASM_CELL_OVERFLOW, instruction_index)
+                    }
+                    e => panic!("{name}: {e}"),
+                };
+                (Some(1), output, format!("{kind} {instruction}\n"))
+            }
+        };
+        let Some(actual) = run_node(&name, &module, input) else {
+            eprintln!("no Node.js, skipping");
+            return;
+        };
+        assert_eq!(actual, expected, "{name}");
+    }
+}
+
+/// Runs `module` on `input` and returns the exit code, output, and error
+/// output of the runner, or `None` without Node.js.
+fn run_node(name: &str, module: &[u8], input: &[u8]) -> Option<(Option<i32>, Vec<u8>, String)> {
+    let dir = env::temp_dir().join(format!("brainfuck_vm_wasm_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let runner = dir.join("run.cjs");
+    let path = dir.join(format!("{name}.wasm"));
+    fs::write(&runner, RUNNER).unwrap();
+    fs::write(&path, module).unwrap();
+
+    let mut child = Command::new("node")
+        .arg(&runner)
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    Some((output.status.code(), output.stdout, stderr))
+}
+
+/// Modules run under Node.js.
+struct Wasm;
+
+impl Backend for Wasm {
+    fn check<C: Cell>(
+        &mut self,
+        name: &str,
+        source_code: &str,
+        interpreter: &Interpreter,
+        input: &[u8],
+    ) {
+        assert_same::<C>(name, source_code, interpreter, input);
+    }
+}
+
+/// Test hello world and cat.
+#[test]
+fn test_hello_and_cat() {
+    common::hello_and_cat(&mut Wasm);
+}
+
+/// Test the programs that depend on the settings of the interpreter.
+#[test]
+fn test_settings() {
+    common::settings(&mut Wasm);
+}